@@ -0,0 +1,208 @@
+//! The append-only audit log of what the tool does in a repository, one JSON object per line
+
+use std::{
+    fs::{OpenOptions, create_dir_all},
+    io::Write,
+    path::PathBuf,
+    process,
+};
+
+use anyhow::{Context, Result};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::types::HookEvent;
+
+/// Version of the schema of the records, raised when a field is removed or changes its meaning
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A record of the log
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    /// The version of the schema the record follows
+    pub schema_version: u32,
+    /// When the action was taken, in RFC 3339 format
+    pub timestamp: String,
+    /// The process that took it, telling the records of concurrent processes apart
+    pub pid: u32,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// An action of the tool, tagged with its kind in the `action` field
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum Action {
+    /// A hook event was received
+    EventReceived {
+        /// The name of the event, e.g., `PostToolUse`
+        event: String,
+        session_id: String,
+        /// The tool used, for a `PostToolUse` event
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tool: Option<String>,
+    },
+    /// Files were staged to be committed
+    FilesStaged {
+        /// Their paths relative to the repository root
+        paths: Vec<String>,
+    },
+    /// A commit was created
+    CommitCreated {
+        sha: String,
+        /// The first line of the commit message
+        subject: String,
+        /// The author, as `Name <email>`
+        author: String,
+    },
+    /// Changes were left uncommitted
+    CommitSkipped { reason: SkipReason },
+    /// A commit message was generated
+    MessageGenerated {
+        /// The generator command
+        backend: String,
+        /// How long generating took, in milliseconds
+        latency_ms: u64,
+        /// Whether the fallback message was used, as generating failed or gave an invalid message
+        fallback: bool,
+    },
+}
+
+impl Action {
+    /// Creates the action of receiving the hook event
+    pub fn received(event: &HookEvent) -> Self {
+        Self::EventReceived {
+            event: event.name().to_string(),
+            session_id: event.session_id().to_string(),
+            tool: match event {
+                HookEvent::PostToolUse { tool_name, .. } => Some(tool_name.to_string()),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Why changes were left uncommitted
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// The staged changes contain likely secrets
+    Secrets,
+    /// The staged changes contain unresolved conflict markers
+    ConflictMarkers,
+    /// The rate limit is reached, deferring the commit
+    RateLimited,
+    /// HEAD is on a protected branch, which nothing is committed on
+    ProtectedBranch,
+    /// The changes were made in plan mode
+    PlanMode,
+    /// A rebase, bisect, or `git am` is in progress
+    BlockingOperation,
+    /// A merge, revert, or cherry-pick is in progress, skipped by config or with conflicts left
+    PendingOperation,
+    /// The commit was proposed for review instead, in review mode
+    Review,
+}
+
+/// Per-repository audit log, only ever appended to
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    /// Creates a log stored in the given file
+    ///
+    /// # Arguments
+    /// * `path` - The log file; created on first write
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Appends a record of the action, warning if it can't, as the action is taken anyway
+    pub fn record(&self, action: Action) {
+        if let Err(e) = self.append(action) {
+            warn!("Failed to write the audit log: {e:#}");
+        }
+    }
+
+    /// Appends a record of the action as one line
+    fn append(&self, action: Action) -> Result<()> {
+        let record = Record {
+            schema_version: SCHEMA_VERSION,
+            timestamp: Timestamp::now().to_string(),
+            pid: process::id(),
+            action,
+        };
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+        // A single write to a file opened for appending lands whole at its end, so that the lines
+        // of concurrent processes never interleave
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("Failed to append to {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{read_to_string, remove_dir_all},
+        sync::Arc,
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn concurrent_records_stay_whole_lines() {
+        let dir = temp_dir().join(format!("ccc-test-audit-{}", process::id()));
+        let _ = remove_dir_all(&dir);
+        let log = Arc::new(AuditLog::new(dir.join("audit.jsonl")));
+
+        let threads = (0..8)
+            .map(|thread| {
+                let log = Arc::clone(&log);
+                thread::spawn(move || {
+                    for file in 0..25 {
+                        log.record(Action::FilesStaged {
+                            paths: vec![format!("src/{thread}/{file}.rs"); 20],
+                        });
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        threads.into_iter().for_each(|thread| thread.join().unwrap());
+
+        let content = read_to_string(dir.join("audit.jsonl")).unwrap();
+        let records = content
+            .lines()
+            .map(|line| serde_json::from_str::<Record>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records.len(), 200);
+        assert!(records.iter().all(|record| record.schema_version == SCHEMA_VERSION));
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn record_is_tagged_with_its_action() {
+        let record = Record {
+            schema_version: SCHEMA_VERSION,
+            timestamp: "2026-01-02T03:04:05Z".to_string(),
+            pid: 42,
+            action: Action::CommitSkipped { reason: SkipReason::RateLimited },
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"schema_version":1,"timestamp":"2026-01-02T03:04:05Z","pid":42,"action":"commit-skipped","reason":"rate-limited"}"#
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! The manifest of the pending batch of edits with `granularity = "batched"`, shared by the
+//! background processes handling the edits, so that the process of the last one commits the batch
+
+use std::{
+    fs::{create_dir_all, read_to_string, remove_file, rename, write},
+    io::ErrorKind,
+    path::PathBuf,
+    process,
+};
+
+use anyhow::{Context, Result};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::{file_lock::FileLock, instances::Instance};
+
+/// The pending batch of edits, as recorded in its manifest
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    /// The process of the latest edit, which commits the batch unless another edit follows
+    pub owner: Instance,
+    /// Identifies the latest edit, by the ID of its process and the time it was made
+    pub token: String,
+    /// The files edited in the batch, relative to the working directory, in the order first
+    /// edited
+    pub files: Vec<String>,
+    /// When the first edit of the batch was made, in RFC 3339 format
+    pub started: String,
+}
+
+/// The pending batch of edits of a repository, in one manifest file
+pub struct Batch {
+    path: PathBuf,
+}
+
+impl Batch {
+    /// Creates the batch recorded in the given file
+    ///
+    /// # Arguments
+    /// * `path` - The JSON manifest of the batch, created by its first edit, next to which a lock
+    ///   file serializes processes updating it
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Gets the manifest of the pending batch, if any
+    pub fn read(&self) -> Option<Manifest> {
+        let content = read_to_string(&self.path).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Adds the edited files to the pending batch, starting one if there is none, and makes this
+    /// process its owner
+    ///
+    /// # Arguments
+    /// * `files` - The files the edit staged, relative to the working directory
+    ///
+    /// # Returns
+    /// The manifest of the batch with the edit
+    pub fn add(&self, files: impl IntoIterator<Item = String>) -> Result<Manifest> {
+        let _lock = self.lock()?;
+        let now = Timestamp::now();
+        let (mut edited, started) = match self.read() {
+            Some(manifest) => (manifest.files, manifest.started),
+            None => (Vec::new(), now.to_string()),
+        };
+        for file in files {
+            if !edited.contains(&file) {
+                edited.push(file);
+            }
+        }
+        let manifest = Manifest {
+            owner: Instance::current("PostToolUse"),
+            token: format!("{}-{}", process::id(), now.as_nanosecond()),
+            files: edited,
+            started,
+        };
+        self.write(&manifest)?;
+        Ok(manifest)
+    }
+
+    /// Checks whether the edit of the token is still the latest of the pending batch
+    pub fn is_latest(&self, token: &str) -> bool {
+        self.read().is_some_and(|manifest| manifest.token == token)
+    }
+
+    /// Ends the batch once its files are committed: removes the manifest, unless an edit made
+    /// meanwhile took the batch over, from which the files committed are then dropped
+    ///
+    /// # Arguments
+    /// * `committed` - The manifest of the batch as committed
+    pub fn finish(&self, committed: &Manifest) -> Result<()> {
+        let _lock = self.lock()?;
+        match self.read() {
+            Some(mut manifest) if manifest.token != committed.token => {
+                manifest.files.retain(|file| !committed.files.contains(file));
+                self.write(&manifest)
+            }
+            _ => self.remove(),
+        }
+    }
+
+    /// Removes the manifest, e.g., once the end of the session committed all changes
+    pub fn clear(&self) -> Result<()> {
+        let _lock = self.lock()?;
+        self.remove()
+    }
+
+    /// Locks the manifest, so that no other process's edit is lost between reading and writing it
+    fn lock(&self) -> Result<FileLock> {
+        FileLock::acquire(&self.path.with_extension("lock"))
+    }
+
+    /// Removes the manifest, if any
+    fn remove(&self) -> Result<()> {
+        match remove_file(&self.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the manifest aside and renames it, so that other processes never read it half
+    /// written
+    fn write(&self, manifest: &Manifest) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+        let temp = self.path.with_extension(format!("{}.tmp", process::id()));
+        write(&temp, serde_json::to_string(manifest)?)?;
+        rename(&temp, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_dir_all, sync::Arc, thread};
+
+    use super::*;
+
+    /// Creates a batch in a fresh temporary directory
+    fn batch(name: &str) -> (PathBuf, Batch) {
+        let dir = temp_dir().join(format!("ccc-test-batch-{name}-{}", process::id()));
+        let _ = remove_dir_all(&dir);
+        let batch = Batch::new(dir.join("batch.json"));
+        (dir, batch)
+    }
+
+    #[test]
+    fn edits_join_the_batch_and_take_it_over() {
+        let (dir, batch) = batch("join");
+
+        let first = batch.add(Some("src/main.rs".to_string())).unwrap();
+        let second = batch
+            .add(["src/lib.rs".to_string(), "src/main.rs".to_string()])
+            .unwrap();
+
+        assert_eq!(second.files, ["src/main.rs", "src/lib.rs"]);
+        assert_eq!(second.started, first.started);
+        assert!(second.owner.is_alive());
+        assert!(!batch.is_latest(&first.token));
+        assert!(batch.is_latest(&second.token));
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn finishing_keeps_the_edits_made_meanwhile() {
+        let (dir, batch) = batch("finish");
+        let committed = batch.add(Some("src/main.rs".to_string())).unwrap();
+        let later = batch.add(Some("src/lib.rs".to_string())).unwrap();
+
+        batch.finish(&committed).unwrap();
+        let manifest = batch.read().unwrap();
+        assert_eq!(manifest.token, later.token);
+        assert_eq!(manifest.files, ["src/lib.rs"]);
+
+        batch.finish(&later).unwrap();
+        assert!(batch.read().is_none());
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_edits_keep_each_others_files() {
+        let (dir, batch) = batch("concurrent");
+        let batch = Arc::new(batch);
+
+        let threads = (0..20)
+            .map(|n| {
+                let batch = Arc::clone(&batch);
+                thread::spawn(move || batch.add(Some(format!("file{n}.txt"))).unwrap())
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(batch.read().unwrap().files.len(), 20);
+        assert!(!dir.join("batch.lock").exists());
+        remove_dir_all(dir).unwrap();
+    }
+}
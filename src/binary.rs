@@ -0,0 +1,113 @@
+//! Detection of binary files, which `binary_policy` keeps out of commits or notes
+
+use std::{
+    fmt::{Display, Formatter},
+    fs::File,
+    io::Read,
+    path::Path,
+};
+
+/// Number of leading bytes sniffed, as many as git looks at
+const SNIFF_LEN: usize = 8000;
+
+/// What the content of a file looks like
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Content {
+    Text,
+    /// UTF-16 text, which git diffs as binary and is therefore handled as such
+    Utf16Text,
+    Binary,
+}
+
+impl Content {
+    /// Sniffs the leading bytes of the content
+    ///
+    /// # Arguments
+    /// * `bytes` - The content, of which only the first 8000 bytes are looked at
+    ///
+    /// # Returns
+    /// Binary if a NUL byte occurs, as git decides, except for UTF-16 text starting with a byte
+    /// order mark or made of ASCII characters only
+    pub fn sniff(bytes: &[u8]) -> Self {
+        let bytes = &bytes[..bytes.len().min(SNIFF_LEN)];
+        if bytes.starts_with(&[0xff, 0xfe]) || bytes.starts_with(&[0xfe, 0xff]) {
+            return Self::Utf16Text;
+        }
+        if !bytes.contains(&0) {
+            return Self::Text;
+        }
+        // Without a byte order mark, ASCII text in UTF-16 has every other byte zero
+        let units = bytes.chunks_exact(2);
+        let ascii_le = units
+            .clone()
+            .all(|unit| unit[0] != 0 && unit[0] < 0x80 && unit[1] == 0);
+        let ascii_be = units
+            .clone()
+            .all(|unit| unit[0] == 0 && unit[1] != 0 && unit[1] < 0x80);
+        if bytes.len().is_multiple_of(2) && (ascii_le || ascii_be) {
+            Self::Utf16Text
+        } else {
+            Self::Binary
+        }
+    }
+
+    /// Sniffs the leading bytes of the file
+    ///
+    /// # Returns
+    /// The kind of content, or `None` if the file can't be read, e.g., as it was deleted
+    pub fn of_file(path: &Path) -> Option<Self> {
+        let mut bytes = Vec::with_capacity(SNIFF_LEN);
+        File::open(path)
+            .ok()?
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut bytes)
+            .ok()?;
+        Some(Self::sniff(&bytes))
+    }
+
+    /// Checks whether the content is handled as binary
+    pub fn is_binary(self) -> bool {
+        self != Self::Text
+    }
+}
+
+impl Display for Content {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Text => "text",
+            Self::Utf16Text => "UTF-16 text, handled as binary",
+            Self::Binary => "binary",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nul_bytes_make_content_binary() {
+        assert_eq!(Content::sniff(b"fn main() {}\n"), Content::Text);
+        assert_eq!(Content::sniff("日本語\n".as_bytes()), Content::Text);
+        assert_eq!(Content::sniff(b""), Content::Text);
+        assert_eq!(Content::sniff(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Content::Binary);
+        assert_eq!(Content::sniff(b"\x7fELF\x02\x01\x01\0"), Content::Binary);
+    }
+
+    #[test]
+    fn utf16_text_is_told_apart() {
+        let le = "\u{feff}Notes\r\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>();
+        let be = "\u{feff}Notes\r\n"
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect::<Vec<_>>();
+        assert_eq!(Content::sniff(&le), Content::Utf16Text);
+        assert_eq!(Content::sniff(&be), Content::Utf16Text);
+        assert_eq!(Content::sniff(&le[2..]), Content::Utf16Text);
+        assert_eq!(Content::sniff(&be[2..]), Content::Utf16Text);
+        assert!(Content::Utf16Text.is_binary());
+    }
+}
@@ -0,0 +1,98 @@
+use std::{
+    fs::{create_dir_all, read_dir, read_to_string, remove_file, write},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use git2::{ObjectType, Oid};
+
+/// On-disk cache of generated commit messages, keyed by a hash of the generation inputs
+pub struct MessageCache {
+    dir: PathBuf,
+    max_entries: usize,
+    max_age: Duration,
+}
+
+/// Number and total size of the entries in a message cache
+pub struct CacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+impl MessageCache {
+    /// Creates a cache stored in the given directory
+    ///
+    /// # Arguments
+    /// * `dir` - The directory holding one file per cached message; created on first write
+    /// * `max_entries` - The number of entries kept when evicting
+    /// * `max_age` - The age after which entries are ignored and evicted
+    pub fn new(dir: PathBuf, max_entries: usize, max_age: Duration) -> Self {
+        Self { dir, max_entries, max_age }
+    }
+
+    /// Computes the cache key for the given inputs
+    ///
+    /// # Arguments
+    /// * `parts` - The inputs the generated message depends on
+    pub fn key(parts: &[&str]) -> String {
+        Oid::hash_object(ObjectType::Blob, parts.join("\0").as_bytes())
+            .map(|oid| oid.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Gets the cached message for the key, unless it is missing or expired
+    pub fn get(&self, key: &str) -> Option<String> {
+        let path = self.dir.join(key);
+        let age = path.metadata().ok()?.modified().ok()?.elapsed().unwrap_or_default();
+        if age > self.max_age {
+            return None;
+        }
+        read_to_string(path).ok()
+    }
+
+    /// Stores a message under the key, then evicts expired and excess entries
+    pub fn put(&self, key: &str, message: &str) -> Result<()> {
+        create_dir_all(&self.dir)?;
+        write(self.dir.join(key), message)?;
+        self.evict()
+    }
+
+    /// Gets the number and total size of the entries currently in the cache
+    pub fn stats(&self) -> CacheStats {
+        self.entries()
+            .iter()
+            .fold(CacheStats { entries: 0, bytes: 0 }, |stats, (path, _)| CacheStats {
+                entries: stats.entries + 1,
+                bytes: stats.bytes + path.metadata().map(|m| m.len()).unwrap_or_default(),
+            })
+    }
+
+    /// Removes expired entries and, beyond `max_entries`, the oldest ones
+    fn evict(&self) -> Result<()> {
+        let now = SystemTime::now();
+        let mut entries = self.entries();
+        entries.sort_by_key(|(_, modified)| std::cmp::Reverse(*modified));
+
+        for (i, (path, modified)) in entries.iter().enumerate() {
+            let expired = now.duration_since(*modified).unwrap_or_default() > self.max_age;
+            if expired || i >= self.max_entries {
+                remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the entry files with their modification times
+    fn entries(&self) -> Vec<(PathBuf, SystemTime)> {
+        read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let modified = entry.metadata().ok()?.modified().ok()?;
+                Some((entry.path(), modified))
+            })
+            .collect()
+    }
+}
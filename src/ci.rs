@@ -0,0 +1,70 @@
+//! Detection of CI and other non-interactive environments, where the hook writes nothing to git
+
+/// Environment variables CI services set, checked before the configured ones: `CI` by most of
+/// them, then GitHub Actions, GitLab CI, and Azure Pipelines
+pub const CI_VARIABLES: &[&str] = &["CI", "GITHUB_ACTIONS", "GITLAB_CI", "TF_BUILD"];
+
+/// Finds the environment variable telling that the process runs in CI
+///
+/// # Arguments
+/// * `extra` - Names of further variables to check, after the built-in ones
+/// * `var` - Looks up the value of an environment variable
+///
+/// # Returns
+/// The first variable of `CI_VARIABLES`, then of `extra`, set to a value other than an empty
+/// string, "0", or "false", or `None` if none is
+pub fn detect(extra: &[String], var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    CI_VARIABLES
+        .iter()
+        .copied()
+        .chain(extra.iter().map(String::as_str))
+        .find(|name| {
+            var(name).is_some_and(|value| {
+                let value = value.trim();
+                !value.is_empty() && value != "0" && !value.eq_ignore_ascii_case("false")
+            })
+        })
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Detects CI in an environment of the given variables
+    fn detect_in(extra: &[&str], vars: &[(&str, &str)]) -> Option<String> {
+        let extra = extra.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        detect(&extra, |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn builtin_variables_come_first() {
+        assert_eq!(detect_in(&[], &[]), None);
+        assert_eq!(detect_in(&[], &[("CI", "true")]).as_deref(), Some("CI"));
+        assert_eq!(detect_in(&[], &[("TF_BUILD", "True")]).as_deref(), Some("TF_BUILD"));
+        assert_eq!(
+            detect_in(&["JENKINS_URL"], &[("JENKINS_URL", "http://ci"), ("GITLAB_CI", "true")])
+                .as_deref(),
+            Some("GITLAB_CI")
+        );
+        assert_eq!(
+            detect_in(&["JENKINS_URL"], &[("JENKINS_URL", "http://ci")]).as_deref(),
+            Some("JENKINS_URL")
+        );
+    }
+
+    #[test]
+    fn false_values_are_no_ci() {
+        for value in ["", "0", "false", "FALSE", " "] {
+            assert_eq!(detect_in(&[], &[("CI", value)]), None, "{value:?}");
+        }
+        assert_eq!(
+            detect_in(&[], &[("CI", "false"), ("GITHUB_ACTIONS", "true")]).as_deref(),
+            Some("GITHUB_ACTIONS")
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! Typed model of the hooks in a Claude Code settings file, keeping everything else as is
+
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+
+/// A Claude Code settings file
+///
+/// Only the hooks are modeled; the other settings, and the keys of the hooks this binary doesn't
+/// know, are kept in `extra` maps in the order of the file, so that writing the settings back
+/// changes nothing but the hooks.
+#[derive(Debug, Default, PartialEq)]
+pub struct ClaudeSettings {
+    /// The matcher groups of each event, if the file has `hooks`
+    pub hooks: Option<Hooks>,
+    /// The other settings, in which `hooks` is only a placeholder keeping its position
+    pub extra: Map<String, Value>,
+}
+
+/// The `hooks` of a settings file: the matcher groups of each event, in the order of the file
+#[derive(Debug, Default, PartialEq)]
+pub struct Hooks(Vec<(String, Vec<MatcherGroup>)>);
+
+/// A group of hooks run for the tools matched by `matcher`, or for every use of the event
+#[derive(Debug, Default, PartialEq)]
+pub struct MatcherGroup {
+    /// The matcher of the tools triggering the hooks, for the tool events
+    pub matcher: Option<String>,
+    /// The hooks of the group
+    pub hooks: Vec<HookCommand>,
+    /// The other keys of the group, in which the modeled ones are only placeholders
+    pub extra: Map<String, Value>,
+}
+
+/// A hook of a matcher group
+#[derive(Debug, Default, PartialEq)]
+pub struct HookCommand {
+    /// The command run by a `command` hook
+    pub command: Option<String>,
+    /// The seconds Claude Code waits for the hook, if set
+    pub timeout: Option<u64>,
+    /// The other keys of the hook, such as `type`, in which the modeled ones are only placeholders
+    pub extra: Map<String, Value>,
+}
+
+impl ClaudeSettings {
+    /// Reads the settings from the content of a settings file
+    ///
+    /// # Returns
+    /// * `Result<Self>` - The settings, or an error naming the first value of an unexpected type
+    pub fn from_value(value: Value) -> Result<Self> {
+        let mut extra = expect_object(value, "the settings")?;
+        let hooks = match extra.get_mut("hooks").map(Value::take) {
+            Some(hooks) => Some(Hooks::from_value(hooks)?),
+            None => None,
+        };
+        Ok(Self { hooks, extra })
+    }
+
+    /// Converts the settings to the content of a settings file
+    pub fn to_value(&self) -> Value {
+        let mut map = self.extra.clone();
+        set(&mut map, "hooks", self.hooks.as_ref().map(Hooks::to_value));
+        Value::Object(map)
+    }
+
+    /// Gets the hooks, added to the end of the settings if they have none
+    pub fn hooks_mut(&mut self) -> &mut Hooks {
+        self.hooks.get_or_insert_default()
+    }
+
+    /// Iterates over the hooks of every event
+    ///
+    /// # Returns
+    /// * `impl Iterator<Item = (&str, &HookCommand)>` - The name of the event and the hook
+    pub fn commands(&self) -> impl Iterator<Item = (&str, &HookCommand)> {
+        self.hooks.iter().flat_map(|hooks| {
+            hooks.0.iter().flat_map(|(event, groups)| {
+                groups
+                    .iter()
+                    .flat_map(|group| &group.hooks)
+                    .map(move |hook| (event.as_str(), hook))
+            })
+        })
+    }
+}
+
+impl Hooks {
+    /// Reads the `hooks` of a settings file
+    fn from_value(value: Value) -> Result<Self> {
+        expect_object(value, "hooks")?
+            .into_iter()
+            .map(|(event, groups)| {
+                let path = format!("hooks.{event}");
+                let groups = expect_array(groups, &path)?
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, group)| MatcherGroup::from_value(group, &format!("{path}[{i}]")))
+                    .collect::<Result<_>>()?;
+                Ok((event, groups))
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+
+    /// Converts the hooks to the `hooks` of a settings file
+    fn to_value(&self) -> Value {
+        Value::Object(
+            self.0
+                .iter()
+                .map(|(event, groups)| {
+                    (event.clone(), groups.iter().map(MatcherGroup::to_value).collect())
+                })
+                .collect(),
+        )
+    }
+
+    /// Gets the matcher groups of the event, empty if it has none
+    pub fn event(&self, event: &str) -> &[MatcherGroup] {
+        self.0
+            .iter()
+            .find(|(name, _)| name == event)
+            .map_or(&[], |(_, groups)| groups)
+    }
+
+    /// Gets the matcher groups of the event, added to the end of the hooks if it has none
+    pub fn event_mut(&mut self, event: &str) -> &mut Vec<MatcherGroup> {
+        let index = match self.0.iter().position(|(name, _)| name == event) {
+            Some(index) => index,
+            None => {
+                self.0.push((event.to_string(), Vec::new()));
+                self.0.len() - 1
+            }
+        };
+        &mut self.0[index].1
+    }
+
+    /// Iterates over the matcher groups of every event
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut Vec<MatcherGroup>)> {
+        self.0.iter_mut().map(|(event, groups)| (event.as_str(), groups))
+    }
+
+    /// Removes the events without any matcher group
+    pub fn remove_empty(&mut self) {
+        self.0.retain(|(_, groups)| !groups.is_empty());
+    }
+}
+
+impl MatcherGroup {
+    /// Creates a group of one hook
+    ///
+    /// # Arguments
+    /// * `matcher` - The matcher of the tools triggering the hook, for the tool events
+    /// * `hook` - The hook
+    pub fn new(matcher: Option<&str>, hook: HookCommand) -> Self {
+        Self {
+            matcher: matcher.map(str::to_string),
+            hooks: vec![hook],
+            extra: Map::new(),
+        }
+    }
+
+    /// Reads a matcher group
+    ///
+    /// # Arguments
+    /// * `value` - The group
+    /// * `path` - The path of the group in the settings, for errors
+    fn from_value(value: Value, path: &str) -> Result<Self> {
+        let mut extra = expect_object(value, path)?;
+        let matcher = take_string(&mut extra, "matcher", path)?;
+        let hooks = match extra.get_mut("hooks").map(Value::take) {
+            Some(hooks) => expect_array(hooks, &format!("{path}.hooks"))?
+                .into_iter()
+                .enumerate()
+                .map(|(i, hook)| HookCommand::from_value(hook, &format!("{path}.hooks[{i}]")))
+                .collect::<Result<_>>()?,
+            None => Vec::new(),
+        };
+        Ok(Self { matcher, hooks, extra })
+    }
+
+    /// Converts the group to its entry in a settings file
+    fn to_value(&self) -> Value {
+        let mut map = self.extra.clone();
+        set(&mut map, "matcher", self.matcher.clone().map(Value::from));
+        // A group without `hooks` is left without them
+        let hooks = (!self.hooks.is_empty() || map.contains_key("hooks"))
+            .then(|| self.hooks.iter().map(HookCommand::to_value).collect());
+        set(&mut map, "hooks", hooks);
+        Value::Object(map)
+    }
+}
+
+impl HookCommand {
+    /// Creates a `command` hook
+    ///
+    /// # Arguments
+    /// * `command` - The command to run
+    /// * `timeout` - The seconds Claude Code waits for it
+    pub fn new(command: &str, timeout: u64) -> Self {
+        Self {
+            command: Some(command.to_string()),
+            timeout: Some(timeout),
+            extra: Map::from_iter([("type".to_string(), Value::from("command"))]),
+        }
+    }
+
+    /// Reads a hook
+    ///
+    /// # Arguments
+    /// * `value` - The hook
+    /// * `path` - The path of the hook in the settings, for errors
+    fn from_value(value: Value, path: &str) -> Result<Self> {
+        let mut extra = expect_object(value, path)?;
+        let command = take_string(&mut extra, "command", path)?;
+        let timeout = match extra.get_mut("timeout").map(Value::take) {
+            Some(timeout) => match timeout.as_u64() {
+                Some(timeout) => Some(timeout),
+                None => bail!(
+                    "expected {path}.timeout to be a whole number of seconds, found {}",
+                    type_name(&timeout)
+                ),
+            },
+            None => None,
+        };
+        Ok(Self { command, timeout, extra })
+    }
+
+    /// Converts the hook to its entry in a settings file
+    fn to_value(&self) -> Value {
+        let mut map = self.extra.clone();
+        set(&mut map, "command", self.command.clone().map(Value::from));
+        set(&mut map, "timeout", self.timeout.map(Value::from));
+        Value::Object(map)
+    }
+}
+
+/// Sets the key of the map in place, appending it if it's new, or removes it
+fn set(map: &mut Map<String, Value>, key: &str, value: Option<Value>) {
+    match value {
+        Some(value) => {
+            map.insert(key.to_string(), value);
+        }
+        None => {
+            map.shift_remove(key);
+        }
+    }
+}
+
+/// Takes the string at the key of the map, leaving a placeholder keeping its position
+fn take_string(map: &mut Map<String, Value>, key: &str, path: &str) -> Result<Option<String>> {
+    match map.get_mut(key).map(Value::take) {
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(value) => bail!("expected {path}.{key} to be a string, found {}", type_name(&value)),
+        None => Ok(None),
+    }
+}
+
+/// Unwraps the object, or fails naming the path of the value
+fn expect_object(value: Value, path: &str) -> Result<Map<String, Value>> {
+    match value {
+        Value::Object(map) => Ok(map),
+        value => bail!("expected {path} to be an object, found {}", type_name(&value)),
+    }
+}
+
+/// Unwraps the array, or fails naming the path of the value
+fn expect_array(value: Value, path: &str) -> Result<Vec<Value>> {
+    match value {
+        Value::Array(values) => Ok(values),
+        value => bail!("expected {path} to be an array, found {}", type_name(&value)),
+    }
+}
+
+/// Gets the JSON type of the value, for errors
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{from_str, json};
+
+    use super::*;
+
+    /// Settings with unknown keys at every level, in an order other than the modeled one
+    const SETTINGS: &str = r#"{
+        "permissions": { "allow": ["Bash(cargo test:*)"] },
+        "hooks": {
+            "PreToolUse": [
+                {
+                    "hooks": [
+                        { "command": "guard", "type": "command", "zzFuture": [1, 2] },
+                        { "type": "prompt", "prompt": "Is this safe?" }
+                    ],
+                    "matcher": "Bash",
+                    "zzGroup": true
+                }
+            ],
+            "SessionStart": [{ "hooks": [{ "timeout": 5, "type": "command", "command": "hi" }] }]
+        },
+        "zzUnknown": { "b": 1, "a": [true, null] }
+    }"#;
+
+    #[test]
+    fn unknown_content_round_trips() {
+        let value = from_str::<Value>(SETTINGS).unwrap();
+        let settings = ClaudeSettings::from_value(value.clone()).unwrap();
+        let round_tripped = settings.to_value();
+        assert_eq!(round_tripped, value);
+        assert_eq!(
+            serde_json::to_string(&round_tripped).unwrap(),
+            serde_json::to_string(&value).unwrap()
+        );
+    }
+
+    #[test]
+    fn commands_are_modeled() {
+        let settings = ClaudeSettings::from_value(from_str(SETTINGS).unwrap()).unwrap();
+        let commands = settings
+            .commands()
+            .map(|(event, hook)| (event, hook.command.as_deref(), hook.timeout))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            [
+                ("PreToolUse", Some("guard"), None),
+                ("PreToolUse", None, None),
+                ("SessionStart", Some("hi"), Some(5))
+            ]
+        );
+        let hooks = settings.hooks.as_ref().unwrap();
+        assert_eq!(hooks.event("PreToolUse")[0].matcher.as_deref(), Some("Bash"));
+        assert!(hooks.event("Stop").is_empty());
+    }
+
+    #[test]
+    fn added_hooks_are_appended() {
+        let mut settings =
+            ClaudeSettings::from_value(json!({ "model": "opus", "env": {} })).unwrap();
+        settings
+            .hooks_mut()
+            .event_mut("PostToolUse")
+            .push(MatcherGroup::new(Some("Edit"), HookCommand::new("ccc", 60)));
+        assert_eq!(
+            serde_json::to_string(&settings.to_value()).unwrap(),
+            r#"{"model":"opus","env":{},"hooks":{"PostToolUse":[{"matcher":"Edit","hooks":[{"type":"command","command":"ccc","timeout":60}]}]}}"#
+        );
+    }
+
+    #[test]
+    fn unexpected_types_are_named() {
+        for (settings, error) in [
+            (json!([]), "expected the settings to be an object, found array"),
+            (json!({ "hooks": [] }), "expected hooks to be an object, found array"),
+            (
+                json!({ "hooks": { "SessionStart": {} } }),
+                "expected hooks.SessionStart to be an array, found object",
+            ),
+            (
+                json!({ "hooks": { "Stop": [{ "hooks": {} }] } }),
+                "expected hooks.Stop[0].hooks to be an array, found object",
+            ),
+            (
+                json!({ "hooks": { "PostToolUse": [{ "matcher": 1, "hooks": [] }] } }),
+                "expected hooks.PostToolUse[0].matcher to be a string, found number",
+            ),
+            (
+                json!({ "hooks": { "Stop": [{ "hooks": [{ "command": "ccc", "timeout": "10" }] }] } }),
+                "expected hooks.Stop[0].hooks[0].timeout to be a whole number of seconds, found \
+                 string",
+            ),
+        ] {
+            assert_eq!(ClaudeSettings::from_value(settings).unwrap_err().to_string(), error);
+        }
+    }
+}
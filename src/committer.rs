@@ -1,65 +1,379 @@
-use std::{env::set_current_dir, path::Path};
+use std::{
+    ffi::OsStr,
+    fs::{metadata, read_link},
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result, anyhow, bail};
+use git2::Oid;
+use jiff::{Timestamp, Zoned};
+use tracing::{Span, debug, error, field, info, info_span, instrument, warn};
+use unicode_normalization::UnicodeNormalization;
 
 use crate::{
-    commit_message_generator::CommitMessageGenerator,
+    audit_log::{Action, AuditLog, SkipReason},
+    batch::Batch,
+    binary::Content,
+    cache::MessageCache,
+    commit_message_generator::{
+        CommitContext, CommitMessageGenerator, MessageGenerator, PromptVariables,
+    },
+    config::{
+        BinaryPolicy, BlockingPolicy, CONFIG, CommitTrigger, Config, Granularity, Language, Mode,
+        NotifyMethod, PendingPolicy, ProtectedPolicy, TranscriptTrailer, UserStagedPolicy,
+    },
+    conflicts::find_markers,
+    daemon_log::DaemonLog,
+    debounce::Debouncer,
     git_ops::{
-        create_commit, create_session_branch, get_current_branch, get_staged_diff, stage_all_files,
-        stage_file,
+        AddedLine, FileStat, PendingOperation, StagedDiff, commit_tree, count_commits_since,
+        create_commit, create_session_branch, get_author_identity, get_base_branch,
+        get_blocking_operation, get_current_branch, get_diff_since, get_file_stats_since,
+        get_pending_operation, get_recent_subjects, get_staged_added_lines, get_staged_content,
+        get_staged_diff, get_staged_file_stats, get_staged_paths, get_staged_summary,
+        get_subjects_since, get_tree_patch, restage, stage_all_files, stage_file, stash_file,
+        state_dir, without_staged_paths,
     },
-    types::{HookEvent, HookEvent::*, Repository, SessionStartSource, ToolName},
+    hook_output::Committed,
+    instances::Instances,
+    invocation_log::InvocationLog,
+    locale::Strings,
+    notify::{Notification, Notifier},
+    proposals::{Proposal, Proposals},
+    rate_limit::RateLimiter,
+    redact::Redactor,
+    secrets::SecretScanner,
+    sessions::{Session, Sessions},
+    shutdown::{self, Interrupted},
+    staged_paths::StagedPaths,
+    template::render,
+    trailers::{FALLBACK_TRAILER, SESSION_TRAILER, TRANSCRIPT_TRAILER, append_trailers},
+    transcript::{shorten_home, user_messages},
+    types::{HookEvent, HookEvent::*, PermissionMode, Repository, ToolName},
+    watchdog::Phase,
+    webhook::{Delivery, WebhookClient},
 };
 
+/// Maximum number of files listed in the prompt; the rest are summarized as a count
+const MAX_LISTED_FILES: usize = 50;
+
+/// Tells why a changed file is left unstaged
+///
+/// # Arguments
+/// * `relative_path` - The path of the file relative to the working directory
+/// * `path` - The path the file is read from
+///
+/// # Returns
+/// The reason, or `None` if the file is staged, including when it was deleted unless excluded
+fn unstaged_reason(relative_path: &Path, path: &Path) -> Option<String> {
+    if let Some(reason) = CONFIG.paths.exclusion(relative_path) {
+        return Some(reason.to_string());
+    }
+    let size = metadata(path).ok()?.len();
+    if CONFIG.is_oversized(relative_path, size) {
+        return Some(format!(
+            "{size} bytes, above max_file_size ({} bytes); add it to large_files to commit it",
+            CONFIG.max_file_size
+        ));
+    }
+    if CONFIG.binary_policy == BinaryPolicy::Skip {
+        return Content::of_file(path)
+            .filter(|content| content.is_binary())
+            .map(|content| format!("{content}, with binary_policy = \"skip\""));
+    }
+    None
+}
+
+/// Maximum number of paths listed in the `{files}` placeholder of the default commit message
+const MAX_DEFAULT_MESSAGE_FILES: usize = 5;
+
 /// Handles git commit operations for auto-commit functionality
 pub struct Committer {
     repo: Repository,
+    generator: Option<Box<dyn MessageGenerator>>,
+    /// Whether commit messages end with a `Signed-off-by` trailer of the author
+    signoff: bool,
+    /// The client of `[webhook]`, kept for all the commits of the Committer
+    webhook: Option<WebhookClient>,
+    /// The time to wait after a per-file edit for a newer edit of the same file
+    debounce: Duration,
 }
 
 impl Committer {
-    /// Creates a new Committer instance with a default repository
-    pub fn new() -> Self {
-        Self { repo: Repository::default() }
+    /// Creates a Committer for the repository containing the directory
+    ///
+    /// # Arguments
+    /// * `dir` - A directory in the repository, e.g., the working directory of the hook event
+    ///
+    /// # Returns
+    /// The Committer, or an error if the directory isn't in a repository
+    pub fn new(dir: &Path) -> Result<Self> {
+        Ok(Self {
+            repo: Repository::discover_from(dir)?,
+            generator: None,
+            signoff: CONFIG.message.signoff,
+            webhook: WebhookClient::new(&CONFIG.webhook),
+            debounce: Duration::ZERO,
+        })
+    }
+
+    /// Generates the commit messages with the given generator instead of the configured command
+    ///
+    /// # Arguments
+    /// * `generator` - The generator of every commit message
+    pub fn with_generator(mut self, generator: impl MessageGenerator + 'static) -> Self {
+        self.generator = Some(Box::new(generator));
+        self
+    }
+
+    /// Ends commit messages with a `Signed-off-by` trailer of the author, or not, whatever
+    /// `message.signoff` says
+    ///
+    /// # Arguments
+    /// * `signoff` - Whether to add the trailer
+    pub fn with_signoff(mut self, signoff: bool) -> Self {
+        self.signoff = signoff;
+        self
+    }
+
+    /// Waits after each per-file edit for a newer edit of the same file, by this process or
+    /// another, which commits it instead; only worth it in the background, as nothing waits for
+    /// the process there
+    ///
+    /// # Arguments
+    /// * `debounce` - The time to wait, e.g., `debounce_secs`
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
     }
 
     /// Handles different types of hook events and performs appropriate git operations
     ///
     /// # Arguments
-    /// * `hook_event` - The hook event to process (SessionStart or PostToolUse)
+    /// * `hook_event` - The hook event to process (SessionStart, PostToolUse, or Stop)
+    /// * `language` - Language to use for generating commit messages, resolved against the config
+    ///   and the system locale
+    ///
+    /// # Returns
+    /// `Ok(())` on success, or an error if any git operation fails or a shutdown is requested
+    /// before committing, in which case the index is restored to its state before the event
+    pub fn handle_event(&self, hook_event: HookEvent, language: &Language) -> Result<()> {
+        if self.waits_for_operation(std::slice::from_ref(&hook_event))? {
+            return Ok(());
+        }
+        let result = self.restoring_index_if_interrupted(|| self.dispatch(hook_event, language));
+        notifier(&self.repo).flush();
+        result
+    }
+
+    /// Stages the files of several edits and commits them together, as the worker does for edits
+    /// queued up while it was busy
+    ///
+    /// # Arguments
+    /// * `edits` - The `PostToolUse` events of the edits, in order
     /// * `language` - Language to use for generating commit messages
     ///
     /// # Returns
-    /// `Ok(())` on success, or an error if any git operation fails
-    pub fn handle_event(&self, hook_event: HookEvent, language: &str) -> Result<()> {
-        match hook_event {
-            SessionStart { session_id, source, cwd, .. } => {
-                let current_branch = get_current_branch(&self.repo)?;
+    /// `Ok(())` on success, or an error as `handle_event` returns it
+    pub fn handle_edits(&self, edits: &[HookEvent], language: &Language) -> Result<()> {
+        // As `dispatch` leaves them; batched edits are those the worker queues up within
+        // `quiet_period_secs`
+        if CONFIG.granularity == Granularity::Session {
+            info!("Leaving {} edits to be committed when the session ends", edits.len());
+            return Ok(());
+        }
+        if self.waits_for_operation(edits)? {
+            return Ok(());
+        }
+        let result = self.restoring_index_if_interrupted(|| {
+            let language = language.resolve();
+            let (mut last, mut user_modified) = (None, false);
+            for edit in edits {
+                if let PostToolUse {
+                    session_id,
+                    cwd,
+                    permission_mode,
+                    tool_name,
+                    tool_input,
+                    tool_response,
+                    ..
+                } = edit
+                    && let Some(file_path) =
+                        CONFIG.committed_file(tool_name, tool_input, tool_response)
+                    && !self.changed_in_plan_mode(tool_name, file_path, *permission_mode)
+                {
+                    self.stage_edited_file(cwd, file_path)?;
+                    last = Some((session_id, tool_name));
+                    user_modified |= tool_response.user_modified();
+                }
+            }
+            let Some((session_id, tool_name)) = last else {
+                return Ok(());
+            };
+            if !self.leave_protected_branch(session_id)? {
+                return Ok(());
+            }
+            if edits.len() == 1 {
+                self.commit_staged(
+                    session_id,
+                    Some(tool_name),
+                    &language.name,
+                    CommitContext::PerFile,
+                    user_modified,
+                )
+            } else {
+                info!("Committing {} edits together", edits.len());
+                self.commit_staged(
+                    session_id,
+                    None,
+                    &language.name,
+                    CommitContext::Batch,
+                    user_modified,
+                )
+            }
+        });
+        notifier(&self.repo).flush();
+        result
+    }
+
+    /// Checks whether a rebase, bisect, or `git am` is in progress, which writing to git would
+    /// derail, stashing the files the edits changed with `pending_operations.on_blocking = "stash"`
+    ///
+    /// # Arguments
+    /// * `events` - The hook events to handle
+    ///
+    /// # Returns
+    /// Whether such an operation is in progress, in which case the events are handled no further
+    fn waits_for_operation(&self, events: &[HookEvent]) -> Result<bool> {
+        let Some(operation) = get_blocking_operation(&self.repo) else {
+            return Ok(false);
+        };
+        info!("Leaving git alone during the {operation} in progress");
+        self.audit(Action::CommitSkipped { reason: SkipReason::BlockingOperation });
+        if CONFIG.pending_operations.on_blocking != BlockingPolicy::Stash {
+            return Ok(true);
+        }
+        let workdir = self
+            .repo
+            .workdir()
+            .ok_or_else(|| anyhow!("Repository has no working directory (bare repo?)"))?;
+        for event in events {
+            let PostToolUse { cwd, tool_name, tool_input, tool_response, .. } = event else {
+                continue;
+            };
+            let Some(file_path) = CONFIG.committed_file(tool_name, tool_input, tool_response)
+            else {
+                continue;
+            };
+            let path = normalize(&cwd.join(file_path));
+            match contained_path(workdir, &path)
+                .and_then(|relative_path| stash_file(&self.repo, &relative_path))
+            {
+                Ok(oid) => warn!(
+                    "Stashed the change to {} as {oid}; `git stash pop` restores it once the \
+                     {operation} is done",
+                    path.display()
+                ),
+                Err(e) => warn!("Left {} in the working tree: {e:#}", path.display()),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Runs the git operations, restoring the index to its state before them if they stop early
+    /// on a shutdown request
+    ///
+    /// # Arguments
+    /// * `operations` - The git operations
+    fn restoring_index_if_interrupted(
+        &self,
+        operations: impl FnOnce() -> Result<()>,
+    ) -> Result<()> {
+        // Fails with conflicts in the index, which nothing here stages over anyway
+        let snapshot = self.repo.index().and_then(|mut index| index.write_tree()).ok();
+        let result = operations();
+        if let Err(e) = &result
+            && e.is::<Interrupted>()
+        {
+            if let Some(tree) = snapshot {
+                let mut index = self.repo.index()?;
+                index.read_tree(&self.repo.find_tree(tree)?)?;
+                index.write()?;
+            }
+            return result.context("Left the index as it was before the event");
+        }
+        result
+    }
 
+    /// Performs the git operations for the hook event
+    ///
+    /// # Arguments
+    /// * `hook_event` - The hook event to process
+    /// * `language` - Language to use for generating commit messages
+    fn dispatch(&self, hook_event: HookEvent, language: &Language) -> Result<()> {
+        let language = language.resolve();
+        info!("Generating commit messages in {} ({})", language.name, language.source);
+        let language = &language.name;
+        if CONFIG.granularity == Granularity::Batched {
+            self.recover_batch(hook_event.session_id(), language)?;
+        }
+        match hook_event {
+            SessionStart { session_id, source, permission_mode, .. } => {
                 // If the `source` indicates the end of the previous session, commit changes
                 if let Some(ref source_value) = source
-                    && matches!(
-                        source_value,
-                        SessionStartSource::Clear
-                            | SessionStartSource::Compact
-                            | SessionStartSource::Resume
-                    )
+                    && CONFIG.commits_on(source_value)
                 {
-                    self.handle_session_end(&cwd, language)?;
+                    if permission_mode == Some(PermissionMode::Plan) {
+                        info!("Skipping the commit of the previous session in plan mode");
+                        self.audit(Action::CommitSkipped { reason: SkipReason::PlanMode });
+                        return Ok(());
+                    }
+                    self.handle_session_end(&session_id, language)?;
                 }
 
-                // Then handle new session creation
-                if matches!(current_branch.as_str(), "main" | "master" | "develop") {
+                // Then handle new session creation, unless committing left a protected branch
+                // for a session branch already
+                let current_branch = get_current_branch(&self.repo)?;
+                if CONFIG.branches_from(&current_branch) {
                     create_session_branch(&self.repo, &session_id)?;
                 }
             }
             PostToolUse {
+                session_id,
                 cwd,
-                tool_name: ToolName::Edit | ToolName::MultiEdit | ToolName::Write,
+                permission_mode,
+                tool_name,
                 tool_input,
                 tool_response,
                 ..
-            } if tool_response.success => {
-                self.handle_file_commit(&cwd, &tool_input.file_path, language)?;
+            } if tool_response.success() => {
+                let Some(file_path) =
+                    CONFIG.committed_file(&tool_name, &tool_input, &tool_response)
+                else {
+                    return Ok(());
+                };
+                if self.changed_in_plan_mode(&tool_name, file_path, permission_mode) {
+                    return Ok(());
+                }
+                match CONFIG.granularity {
+                    Granularity::PerFile => self.handle_file_commit(
+                        &cwd,
+                        &session_id,
+                        &tool_name,
+                        file_path,
+                        tool_response.user_modified(),
+                        language,
+                    )?,
+                    Granularity::Batched => {
+                        self.handle_batched_edit(&cwd, &session_id, file_path, language)?
+                    }
+                    // The changes are committed when the session ends
+                    Granularity::Session => {}
+                }
+            }
+            Stop { session_id, .. } if CONFIG.commit_on.contains(&CommitTrigger::Stop) => {
+                self.handle_session_end(&session_id, language)?;
             }
             _ => {}
         }
@@ -67,38 +381,1335 @@ impl Committer {
         Ok(())
     }
 
-    fn handle_session_end(&self, cwd: &str, language: &str) -> Result<()> {
-        set_current_dir(cwd)?;
-        stage_all_files(&self.repo)?;
-        if !get_staged_diff(&self.repo)?.is_empty() {
-            create_commit(
-                &self.repo,
-                &CommitMessageGenerator::new(language)?.generate(&get_staged_diff(&self.repo)?),
-            )?;
+    /// Commits the changes already staged, with a generated message, as `ccc commit` does
+    ///
+    /// # Arguments
+    /// * `language` - Language to use for generating the commit message
+    ///
+    /// # Returns
+    /// `Ok(())` on success, including when nothing is staged or the commit is held back, e.g.,
+    /// for secrets in the changes
+    pub fn commit_staged_changes(&self, language: &Language) -> Result<()> {
+        let language = language.resolve();
+        self.restoring_index_if_interrupted(|| {
+            self.commit_index("", None, &language.name, CommitContext::SessionEnd, false)
+        })
+    }
+
+    fn handle_session_end(&self, session_id: &str, language: &str) -> Result<()> {
+        if !self.leave_protected_branch(session_id)? {
+            return Ok(());
         }
-        Ok(())
+        Phase::Staging.enter();
+        info_span!("staging").in_scope(|| self.stage_changed_files())?;
+        self.commit_staged(session_id, None, language, CommitContext::SessionEnd, false)?;
+        // The pending batch is committed along with the rest
+        batch(&self.repo).clear()
+    }
+
+    fn handle_file_commit(
+        &self,
+        cwd: &Path,
+        session_id: &str,
+        tool_name: &ToolName,
+        file_path: &Path,
+        user_modified: bool,
+        language: &str,
+    ) -> Result<()> {
+        let mut user_modified = user_modified;
+        if !self.debounce.is_zero() {
+            let debouncer = debouncer(&self.repo, self.debounce);
+            let claim = debouncer.claim(&normalize(&cwd.join(file_path)), user_modified)?;
+            match debouncer.wait(&claim)? {
+                Some(absorbed) => user_modified = absorbed,
+                None => {
+                    info!("Not committing {}: superseded by a newer edit", file_path.display());
+                    return Ok(());
+                }
+            }
+        }
+        if !self.leave_protected_branch(session_id)? {
+            return Ok(());
+        }
+        self.stage_edited_file(cwd, file_path)?;
+        self.commit_staged(
+            session_id,
+            Some(tool_name),
+            language,
+            CommitContext::PerFile,
+            user_modified,
+        )
     }
 
-    fn handle_file_commit(&self, cwd: &str, file_path: &str, language: &str) -> Result<()> {
-        set_current_dir(cwd)?;
+    /// Stages the edited file and adds it to the pending batch, committing everything staged once
+    /// no other edit follows within the quiet period, or at once when the batch reaches
+    /// `max_batch_files`
+    ///
+    /// # Arguments
+    /// * `cwd` - The working directory of the hook event
+    /// * `session_id` - The Claude Code session the commit is made for
+    /// * `file_path` - The edited file, absolute or relative to `cwd`
+    /// * `language` - Language to use for generating commit messages
+    fn handle_batched_edit(
+        &self,
+        cwd: &Path,
+        session_id: &str,
+        file_path: &Path,
+        language: &str,
+    ) -> Result<()> {
+        let staged = self.stage_edited_file(cwd, file_path)?;
 
-        let relative_path = if Path::new(file_path).is_absolute() {
-            Path::new(file_path)
-                .strip_prefix(cwd)
-                .map(|p| p.to_string_lossy().to_string())
-                .unwrap_or_else(|_| file_path.to_string())
+        // Each edit takes the batch over; only the process of the last one commits it
+        let batch = batch(&self.repo);
+        let manifest = batch.add(staged)?;
+        if CONFIG.max_batch_files > 0 && manifest.files.len() >= CONFIG.max_batch_files {
+            info!("Committing the batch of {} files at once", manifest.files.len());
         } else {
-            file_path.to_string()
+            shutdown::sleep(Duration::from_secs(CONFIG.quiet_period_secs));
+            if !batch.is_latest(&manifest.token) {
+                return Ok(());
+            }
+        }
+        if self.leave_protected_branch(session_id)? {
+            self.commit_staged(session_id, None, language, CommitContext::Batch, false)?;
+        }
+        batch.finish(&manifest)
+    }
+
+    /// Commits the pending batch left behind by a process that exited before committing it, e.g.,
+    /// stopped by `deadline_secs`, restaging its files in case they were unstaged meanwhile
+    ///
+    /// # Arguments
+    /// * `session_id` - The Claude Code session the commit is made for
+    /// * `language` - Language to use for generating commit messages
+    fn recover_batch(&self, session_id: &str, language: &str) -> Result<()> {
+        let batch = batch(&self.repo);
+        let Some(manifest) = batch.read().filter(|manifest| !manifest.owner.is_alive()) else {
+            return Ok(());
         };
+        warn!(
+            "Committing the batch of {} files left behind by process {}",
+            manifest.files.len(),
+            manifest.owner.pid
+        );
+        let workdir = self
+            .repo
+            .workdir()
+            .ok_or_else(|| anyhow!("Repository has no working directory (bare repo?)"))?;
+        for file in &manifest.files {
+            self.stage_edited_file(workdir, Path::new(file))?;
+        }
+        if self.leave_protected_branch(session_id)? {
+            self.commit_staged(session_id, None, language, CommitContext::Batch, false)?;
+        }
+        batch.finish(&manifest)
+    }
+
+    /// Switches to a new session branch if HEAD is on a protected branch, as
+    /// `branching.on_protected` says
+    ///
+    /// # Arguments
+    /// * `session_id` - The Claude Code session the session branch is named after
+    ///
+    /// # Returns
+    /// Whether committing may go ahead, which it may not while staying on a protected branch
+    fn leave_protected_branch(&self, session_id: &str) -> Result<bool> {
+        let branch = get_current_branch(&self.repo)?;
+        if !CONFIG.is_protected(&branch) {
+            return Ok(true);
+        }
+        if CONFIG.branching.enabled && CONFIG.branching.on_protected == ProtectedPolicy::Switch {
+            info!("Switching from the protected branch {branch} to a session branch");
+            create_session_branch(&self.repo, session_id)?;
+            return Ok(true);
+        }
+        warn!(
+            "Not committing on the protected branch {branch}; enable branching with \
+             branching.on_protected = \"switch\", or switch to another branch"
+        );
+        self.audit(Action::CommitSkipped { reason: SkipReason::ProtectedBranch });
+        Ok(false)
+    }
+
+    /// Checks whether the tool changed the file in plan mode, where nothing should change on
+    /// disk, warning that it is left uncommitted
+    fn changed_in_plan_mode(
+        &self,
+        tool_name: &ToolName,
+        file_path: &Path,
+        permission_mode: Option<PermissionMode>,
+    ) -> bool {
+        let in_plan_mode = permission_mode == Some(PermissionMode::Plan);
+        if in_plan_mode {
+            let file_path = file_path.display();
+            warn!(
+                "{tool_name} changed {file_path} in plan mode; leaving it uncommitted for review"
+            );
+            self.audit(Action::CommitSkipped { reason: SkipReason::PlanMode });
+        }
+        in_plan_mode
+    }
+
+    /// Appends a record of the action to the audit log, if `audit.enabled`
+    fn audit(&self, action: Action) {
+        if let Some(log) = audit_log(&self.repo) {
+            log.record(action);
+        }
+    }
+
+    /// Stages the file an edit tool changed
+    ///
+    /// # Arguments
+    /// * `cwd` - The working directory of the hook event
+    /// * `file_path` - The edited file, absolute or relative to `cwd`
+    ///
+    /// # Returns
+    /// The path of the file relative to the working directory, or `None` if it is left unstaged
+    #[instrument(name = "staging", skip(self, cwd))]
+    fn stage_edited_file(&self, cwd: &Path, file_path: &Path) -> Result<Option<String>> {
+        Phase::Staging.enter();
 
+        let workdir = self
+            .repo
+            .workdir()
+            .ok_or_else(|| anyhow!("Repository has no working directory (bare repo?)"))?;
+        // Resolved against the directory of the event, not that of the process
+        let path = normalize(&cwd.join(file_path));
+        let relative_path = match contained_path(workdir, &path) {
+            Ok(relative_path) => relative_path,
+            Err(e) => {
+                warn!("Not staging {}: {e}", path.display());
+                return Ok(None);
+            }
+        };
+        if let Some(reason) = unstaged_reason(&relative_path, &path) {
+            warn!("Not staging {}: {reason}", relative_path.display());
+            return Ok(None);
+        }
+        debug!(relative_path = %relative_path.display(), "Staging");
         stage_file(&self.repo, &relative_path)?;
-        let diff = get_staged_diff(&self.repo)?;
-        if diff.is_empty() {
+        let relative_path = relative_path.to_string_lossy().into_owned();
+        self.audit(Action::FilesStaged { paths: vec![relative_path.clone()] });
+        staged_paths(&self.repo).add([relative_path.clone()])?;
+        Ok(Some(relative_path))
+    }
+
+    /// Stages all changed files but those left out by `max_file_size` or `binary_policy`, listing
+    /// the ones left unstaged
+    fn stage_changed_files(&self) -> Result<()> {
+        let workdir = self
+            .repo
+            .workdir()
+            .ok_or_else(|| anyhow!("Repository has no working directory (bare repo?)"))?;
+        let user_staged = match CONFIG.user_staged {
+            UserStagedPolicy::Leave => self.user_staged_paths()?,
+            UserStagedPolicy::Commit => Vec::new(),
+        };
+        let mut unstaged = Vec::new();
+        stage_all_files(&self.repo, |path| {
+            if user_staged.iter().any(|staged| staged == path) {
+                return true;
+            }
+            let reason = unstaged_reason(path, &workdir.join(path));
+            if let Some(reason) = &reason {
+                unstaged.push(format!("{} ({reason})", path.display()));
+            }
+            reason.is_some()
+        })?;
+        if !unstaged.is_empty() {
+            warn!("Not staging {} files: {}", unstaged.len(), unstaged.join(", "));
+        }
+        let staged = get_staged_paths(&self.repo)?;
+        let paths = staged
+            .iter()
+            .filter(|path| !user_staged.contains(path))
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        if !paths.is_empty() {
+            self.audit(Action::FilesStaged { paths: paths.clone() });
+        }
+        staged_paths(&self.repo).add(paths)
+    }
+
+    /// Lists the paths with changes staged by the user rather than the tool, which commits leave
+    /// out
+    ///
+    /// # Returns
+    /// The paths, none while a merge, revert, or cherry-pick is in progress, as the changes
+    /// staged to conclude it are committed all together
+    fn user_staged_paths(&self) -> Result<Vec<PathBuf>> {
+        if get_pending_operation(&self.repo)?.is_some() || self.repo.index()?.has_conflicts() {
+            return Ok(Vec::new());
+        }
+        let own = staged_paths(&self.repo).list();
+        Ok(get_staged_paths(&self.repo)?
+            .into_iter()
+            .filter(|path| !own.contains(&*path.to_string_lossy()))
+            .collect())
+    }
+
+    /// Lists the staged binary files, for `binary_policy = "warn"`
+    ///
+    /// # Arguments
+    /// * `stats` - The statistics of the staged files
+    ///
+    /// # Returns
+    /// The path of each binary file, followed by the kind of content unless plainly binary
+    fn staged_binary_files(&self, stats: &[FileStat]) -> Result<Vec<String>> {
+        let mut binaries = Vec::new();
+        for stat in stats {
+            let Some(content) = get_staged_content(&self.repo, Path::new(&stat.path))? else {
+                continue;
+            };
+            match Content::sniff(&content) {
+                Content::Text => {}
+                Content::Binary => binaries.push(stat.path.clone()),
+                kind => binaries.push(format!("{} ({kind})", stat.path)),
+            }
+        }
+        Ok(binaries)
+    }
+
+    /// Commits the changes the tool staged with a generated message, leaving those the user staged
+    /// out of the commit and staged
+    ///
+    /// # Arguments
+    /// * `session_id` - The Claude Code session the commit is made for
+    /// * `tool_name` - The tool whose use triggered the commit, if any
+    /// * `language` - Language to use for generating commit messages
+    /// * `context` - The situation the commit is made in
+    /// * `user_modified` - Whether the user hand-edited a change before it was applied, noted in
+    ///   the message
+    fn commit_staged(
+        &self,
+        session_id: &str,
+        tool_name: Option<&ToolName>,
+        language: &str,
+        context: CommitContext,
+        user_modified: bool,
+    ) -> Result<()> {
+        let user_staged = self.user_staged_paths()?;
+        if !user_staged.is_empty() {
+            info!(
+                "Leaving {} files you staged out of the commit: {}",
+                user_staged.len(),
+                user_staged
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        let result = without_staged_paths(&self.repo, &user_staged, || {
+            self.commit_index(session_id, tool_name, language, context, user_modified)
+        });
+        // Committed paths are no longer staged, and the user may stage them again
+        let staged = get_staged_paths(&self.repo)?;
+        staged_paths(&self.repo)
+            .retain(|path| staged.iter().any(|staged| staged.to_string_lossy() == path))?;
+        result
+    }
+
+    /// Commits the changes staged in the index with a generated message, doing nothing if nothing
+    /// is staged
+    ///
+    /// # Arguments
+    /// * `session_id` - The Claude Code session the commit is made for
+    /// * `tool_name` - The tool whose use triggered the commit, if any
+    /// * `language` - Language to use for generating commit messages
+    /// * `context` - The situation the commit is made in
+    /// * `user_modified` - Whether the user hand-edited a change before it was applied, noted in
+    ///   the message
+    fn commit_index(
+        &self,
+        session_id: &str,
+        tool_name: Option<&ToolName>,
+        language: &str,
+        context: CommitContext,
+        user_modified: bool,
+    ) -> Result<()> {
+        let diff = self.staged_diff(language)?;
+        if diff.content.is_empty() {
             return Ok(());
         }
+        let added = if CONFIG.secrets.enabled || CONFIG.conflict_markers.enabled {
+            get_staged_added_lines(&self.repo)?
+        } else {
+            Vec::new()
+        };
+        let skipped = if self.stages_secrets(&added)? {
+            Some(SkipReason::Secrets)
+        } else if stages_conflict_markers(&added) {
+            Some(SkipReason::ConflictMarkers)
+        } else if self.is_throttled() {
+            Some(SkipReason::RateLimited)
+        } else {
+            None
+        };
+        if let Some(reason) = skipped {
+            self.audit(Action::CommitSkipped { reason });
+            return Ok(());
+        }
+
+        shutdown::check()?;
+        Phase::Generating.enter();
+        let stats = get_staged_file_stats(&self.repo)?;
+        let mut variables = self.prompt_variables(diff.content, &stats, session_id, language)?;
+        if context == CommitContext::SessionEnd {
+            variables.conversation_summary = self.conversation_summary(session_id);
+        }
+        let configured;
+        let generator = match &self.generator {
+            Some(generator) => generator.as_ref(),
+            None => {
+                configured = self.configured_generator(&stats, tool_name, language)?;
+                &configured as &dyn MessageGenerator
+            }
+        };
+        if let Some(operation) = get_pending_operation(&self.repo)? {
+            return self.conclude_operation(operation, generator, &variables, context);
+        }
+
+        let mut message = generator.generate(&variables, context);
+        if let Some(model) = generator.model() {
+            info!("Commit message generated by {model}");
+        }
+        let fallback = generator.used_fallback();
+        let strings = Strings::new(language, &CONFIG.localization);
+        if user_modified {
+            message = format!("{}\n\n{}", message.trim_end(), strings.user_modified_note);
+        }
+        if CONFIG.binary_policy == BinaryPolicy::Warn {
+            let binaries = self.staged_binary_files(&stats)?;
+            if !binaries.is_empty() {
+                warn!("Committing binary files: {}", binaries.join(", "));
+                let note = render(&strings.binary_files_note, &[("files", &binaries.join(", "))]);
+                message = format!("{}\n\n{note}", message.trim_end());
+            }
+        }
+        if fallback {
+            message = append_trailers(&message, &[(FALLBACK_TRAILER, "true".to_string())]);
+        }
+        self.commit(&message, &[], session_id)
+    }
 
-        create_commit(&self.repo, &CommitMessageGenerator::new(language)?.generate(&diff))?;
+    /// Checks the rate limiter, reporting when the limit is reached; the commit is taken from it
+    /// only once created
+    ///
+    /// # Returns
+    /// Whether the commit has to wait, in which case the changes stay staged for the next one
+    fn is_throttled(&self) -> bool {
+        let Some(throttle) = rate_limiter(&self.repo).check() else {
+            return false;
+        };
+        warn!(
+            "Deferring the commit, as the limit of {} commits per {} is reached; the changes stay \
+             staged for the next commit, allowed in {} s",
+            throttle.max,
+            throttle.window,
+            throttle.wait.as_secs().max(1)
+        );
+        self.notify(
+            "rate-limited",
+            String::new(),
+            format!("The limit of {} commits per {} is reached", throttle.max, throttle.window),
+            None,
+        );
+        true
+    }
+
+    /// Scans the staged changes for secrets, reporting each one found
+    ///
+    /// # Arguments
+    /// * `added` - The lines the staged changes add
+    ///
+    /// # Returns
+    /// Whether any were found, in which case nothing may be committed
+    #[instrument(name = "secrets", skip_all)]
+    fn stages_secrets(&self, added: &[AddedLine]) -> Result<bool> {
+        if !CONFIG.secrets.enabled {
+            return Ok(false);
+        }
+        let findings = SecretScanner::new(&CONFIG.secrets)?.scan(added);
+        if findings.is_empty() {
+            return Ok(false);
+        }
+        error!(
+            "NOT COMMITTED: the staged changes contain {} likely secrets; they are left staged for \
+             you to remove",
+            findings.len()
+        );
+        for finding in &findings {
+            error!(
+                "  {}:{}: {} (fingerprint {})",
+                finding.path, finding.line, finding.rule, finding.fingerprint
+            );
+        }
+        error!(
+            "If they are no secrets, add their paths or fingerprints to `secrets.allow` in \
+             .claude/auto-commit.toml"
+        );
+        self.notify(
+            "secrets",
+            String::new(),
+            format!("The staged changes contain {} likely secrets", findings.len()),
+            None,
+        );
+        Ok(true)
+    }
 
+    /// Commits the staged changes to conclude a merge, revert, or cherry-pick in progress
+    ///
+    /// # Arguments
+    /// * `operation` - The operation in progress
+    /// * `generator` - The generator for the optional body of a merge message
+    /// * `variables` - The values for the prompt template placeholders
+    /// * `context` - The situation the commit is made in
+    ///
+    /// # Returns
+    /// `Ok(())` without committing if the operation is skipped by config, isn't supported, or
+    /// still has conflicts
+    fn conclude_operation(
+        &self,
+        operation: PendingOperation,
+        generator: &dyn MessageGenerator,
+        variables: &PromptVariables,
+        context: CommitContext,
+    ) -> Result<()> {
+        if CONFIG.pending_operations.policy == PendingPolicy::Skip
+            || matches!(operation, PendingOperation::Other(_))
+        {
+            info!("Skipping the commit while a {operation} is in progress");
+            self.audit(Action::CommitSkipped { reason: SkipReason::PendingOperation });
+            return Ok(());
+        }
+        if self.repo.index()?.has_conflicts() {
+            info!("Skipping the commit while the {operation} has unresolved conflicts");
+            self.audit(Action::CommitSkipped { reason: SkipReason::PendingOperation });
+            return Ok(());
+        }
+
+        let name = operation.to_string();
+        let (message, heads) = match operation {
+            PendingOperation::Merge { heads, message } => {
+                let message = if CONFIG.pending_operations.generate_merge_body {
+                    let body = generator.generate(variables, context);
+                    format!("{message}\n\n{body}")
+                } else {
+                    message
+                };
+                (message, heads)
+            }
+            PendingOperation::Revert { message } | PendingOperation::CherryPick { message } => {
+                (message, Vec::new())
+            }
+            PendingOperation::Other(_) => unreachable!("skipped above"),
+        };
+        info!("Concluding the {name} with the message prepared by git");
+        self.commit(&message, &heads, &variables.session_id)
+    }
+
+    /// Commits the staged changes
+    ///
+    /// # Arguments
+    /// * `message` - The commit message
+    /// * `merge_heads` - Parents in addition to HEAD, for a merge commit
+    /// * `session_id` - The Claude Code session the commit is made for, if any
+    #[instrument(name = "commit", skip_all, fields(sha = field::Empty))]
+    fn commit(&self, message: &str, merge_heads: &[Oid], session_id: &str) -> Result<()> {
+        // Once committing, a shutdown request waits for the commit to finish
+        shutdown::check()?;
+        Phase::Committing.enter();
+        let message = &self.with_trailers(message, session_id)?;
+        if CONFIG.mode == Mode::Review {
+            self.audit(Action::CommitSkipped { reason: SkipReason::Review });
+            return self.propose(message, merge_heads);
+        }
+        let oid = create_commit(&self.repo, message, merge_heads, CONFIG.committer_identity())?;
+        Span::current().record("sha", oid.to_string());
+        info!("Committed");
+        if let Err(err) = rate_limiter(&self.repo).record() {
+            warn!("Failed to record the commit in the rate limiter: {err:#}");
+        }
+        Phase::Finishing.enter();
+        if audit_log(&self.repo).is_some() {
+            let (name, email) = get_author_identity(&self.repo)?;
+            self.audit(Action::CommitCreated {
+                sha: oid.to_string(),
+                subject: message.lines().next().unwrap_or_default().to_string(),
+                author: format!("{name} <{email}>"),
+            });
+        }
+        if (CONFIG.notify.method != NotifyMethod::Off || self.webhook.is_some())
+            && let Some(committed) = Committed::of(&self.repo, oid)
+        {
+            if let Some(webhook) = &self.webhook {
+                webhook.send(&Delivery {
+                    sha: oid.to_string(),
+                    subject: committed.subject.clone(),
+                    branch: get_current_branch(&self.repo).unwrap_or_default(),
+                    repo: self.project_name(),
+                    session_id: session_id.to_string(),
+                    files: committed.files,
+                });
+            }
+            self.notify("commit", oid.to_string(), committed.subject, committed.files);
+        }
+        Ok(())
+    }
+
+    /// Notifies the user as configured in `[notify]`
+    ///
+    /// # Arguments
+    /// * `event` - What happened, as described by `Notification::event`
+    /// * `sha` - The ID of the commit, empty unless committed
+    /// * `subject` - The first line of the commit message, or else why nothing was committed
+    /// * `files` - The number of files committed, or `None` for those left staged
+    fn notify(
+        &self,
+        event: &'static str,
+        sha: String,
+        subject: String,
+        files: impl Into<Option<usize>>,
+    ) {
+        if CONFIG.notify.method == NotifyMethod::Off {
+            return;
+        }
+        let files = files
+            .into()
+            .unwrap_or_else(|| get_staged_paths(&self.repo).map_or(0, |paths| paths.len()));
+        notifier(&self.repo).notify(&Notification {
+            event: event.to_string(),
+            sha,
+            subject,
+            branch: get_current_branch(&self.repo).unwrap_or_default(),
+            files,
+        });
+    }
+
+    /// Appends the configured trailers to the commit message, after those it has already
+    ///
+    /// # Arguments
+    /// * `message` - The commit message
+    /// * `session_id` - The Claude Code session the commit is made for, linked by
+    ///   `message.transcript_trailer`; empty for none
+    fn with_trailers(&self, message: &str, session_id: &str) -> Result<String> {
+        let mut trailers = Vec::new();
+        if CONFIG.message.transcript_trailer != TranscriptTrailer::Off && !session_id.is_empty() {
+            trailers.push((SESSION_TRAILER, session_id.to_string()));
+            if CONFIG.message.transcript_trailer == TranscriptTrailer::Path {
+                match sessions(&self.repo).get(session_id) {
+                    Some(session) => {
+                        trailers.push((TRANSCRIPT_TRAILER, shorten_home(&session.transcript_path)))
+                    }
+                    None => debug!("The transcript of the session isn't recorded"),
+                }
+            }
+        }
+        if self.signoff {
+            let (name, email) = get_author_identity(&self.repo)?;
+            trailers.push(("Signed-off-by", format!("{name} <{email}>")));
+        }
+        if trailers.is_empty() {
+            return Ok(message.to_string());
+        }
+        Ok(append_trailers(message, &trailers))
+    }
+
+    /// Records the staged changes as a proposal for a human to apply, leaving them staged, in
+    /// place of the proposals on the same base whose changes they include
+    ///
+    /// # Arguments
+    /// * `message` - The commit message
+    /// * `merge_heads` - Parents in addition to HEAD, for a merge commit
+    fn propose(&self, message: &str, merge_heads: &[Oid]) -> Result<()> {
+        let tree = self.repo.index()?.write_tree()?;
+        let base = self.repo.head().ok().and_then(|head| head.target());
+        let proposals = proposals(&self.repo);
+        for superseded in proposals.list() {
+            if superseded.base == base.map(|oid| oid.to_string()) {
+                info!("Superseding proposal {}", superseded.id);
+                proposals.remove(&superseded.id)?;
+            }
+        }
+        let id = tree.to_string()[..7].to_string();
+        proposals.save(&Proposal {
+            id: id.clone(),
+            message: message.to_string(),
+            base: base.map(|oid| oid.to_string()),
+            tree: tree.to_string(),
+            merge_heads: merge_heads.iter().map(Oid::to_string).collect(),
+            branch: get_current_branch(&self.repo)?,
+            created: Timestamp::now().to_string(),
+            diff: get_tree_patch(&self.repo, base, tree)?,
+        })?;
+        info!("Proposed commit {id}; `ccc review apply {id}` commits it");
         Ok(())
     }
+
+    /// Commits a proposal made in review mode, or stages its changes again onto HEAD for another
+    /// review if HEAD moved since, as its tree would undo the commits made in between
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the proposal, as `ccc review` lists it
+    ///
+    /// # Returns
+    /// The ID of the commit, or `None` if the proposal was re-staged instead. Returns an error if
+    /// there is no such proposal, or its changes conflict with those committed since.
+    pub fn apply_proposal(&self, id: &str) -> Result<Option<Oid>> {
+        let proposals = proposals(&self.repo);
+        let mut proposal = proposals.get(id)?;
+        let base = proposal.base.as_deref().map(Oid::from_str).transpose()?;
+        let tree = Oid::from_str(&proposal.tree)?;
+        let head = self.repo.head().ok().and_then(|head| head.target());
+        if head == base {
+            let merge_heads = proposal
+                .merge_heads
+                .iter()
+                .map(|oid| Oid::from_str(oid))
+                .collect::<Result<Vec<_>, _>>()?;
+            let oid = commit_tree(
+                &self.repo,
+                &proposal.message,
+                tree,
+                &merge_heads,
+                CONFIG.committer_identity(),
+            )?;
+            proposals.remove(id)?;
+            info!("Committed proposal {id} as {oid}");
+            return Ok(Some(oid));
+        }
+
+        let tree = restage(&self.repo, base, tree).with_context(|| {
+            format!("Failed to re-stage proposal {id}; `ccc review discard {id}` drops it")
+        })?;
+        let moved = format!(
+            "HEAD moved from {} to {} since proposal {id} was made",
+            proposal.base.as_deref().unwrap_or("an unborn branch"),
+            head.map_or("an unborn branch".to_string(), |oid| oid.to_string())
+        );
+        if head.is_some_and(|head| {
+            self.repo
+                .find_commit(head)
+                .is_ok_and(|commit| commit.tree_id() == tree)
+        }) {
+            proposals.remove(id)?;
+            warn!("{moved}, and its changes are all committed already; dropped it");
+            return Ok(None);
+        }
+        proposal.base = head.map(|oid| oid.to_string());
+        proposal.tree = tree.to_string();
+        proposal.diff = get_tree_patch(&self.repo, head, tree)?;
+        proposal.created = Timestamp::now().to_string();
+        proposals.save(&proposal)?;
+        warn!(
+            "{moved}; re-staged its changes onto HEAD instead of committing them. Review them \
+             with `ccc review show {id}` and apply it again."
+        );
+        Ok(None)
+    }
+
+    /// Creates the message generator running the configured command for the staged changes
+    ///
+    /// # Arguments
+    /// * `stats` - The statistics of the staged files
+    /// * `tool_name` - The tool whose use triggered the commit, if any
+    /// * `language` - Language to use for generating commit messages
+    fn configured_generator(
+        &self,
+        stats: &[FileStat],
+        tool_name: Option<&ToolName>,
+        language: &str,
+    ) -> Result<CommitMessageGenerator<'static>> {
+        let mut generator = CommitMessageGenerator::new(&CONFIG, language)?
+            .with_default_message(default_message(&CONFIG, stats, tool_name, language));
+        if let Some(primary) = stats.iter().max_by_key(|stat| stat.insertions + stat.deletions)
+            && let Some(stem) = Path::new(&primary.path).file_stem()
+        {
+            generator = generator.with_primary_file_stem(stem.to_string_lossy().to_string());
+        }
+        if CONFIG.cache.enabled {
+            generator = generator.with_cache(message_cache(&self.repo));
+        }
+        if let Some(workdir) = self.repo.workdir() {
+            generator = generator.in_dir(workdir.to_path_buf());
+        }
+        if let Some(log) = audit_log(&self.repo) {
+            generator = generator.with_audit_log(log);
+        }
+        Ok(generator.with_log(invocation_log(&self.repo)))
+    }
+
+    /// Gets the repository the committer operates on
+    pub fn repo(&self) -> &Repository {
+        &self.repo
+    }
+
+    /// Gets the staged changes as included in the prompt
+    ///
+    /// # Arguments
+    /// * `language` - Language of the marker appended when the changes are truncated
+    ///
+    /// # Returns
+    /// The patch text, or a per-file summary when the patch exceeds the configured threshold,
+    /// without the files `paths` excludes, which a note counts instead
+    #[instrument(name = "diff", skip_all)]
+    pub fn staged_diff(&self, language: &str) -> Result<StagedDiff> {
+        let strings = Strings::new(language, &CONFIG.localization);
+        let marker = &strings.truncation_marker;
+        let excluded = |path: &Path| CONFIG.paths.excludes(path);
+        let mut diff = get_staged_diff(&self.repo, CONFIG.max_diff_chars(), marker, excluded)?;
+        if diff.total <= CONFIG.diff.summary_threshold {
+            debug!(total = diff.total, dropped = diff.dropped, "Got the staged patch");
+        } else {
+            debug!(total = diff.total, "Summarizing the staged changes, as the patch is too large");
+            diff = get_staged_summary(
+                &self.repo,
+                CONFIG.diff.summary_full_patches,
+                CONFIG.max_diff_chars(),
+                marker,
+                excluded,
+            )?;
+        }
+        if diff.omitted > 0 {
+            debug!(omitted = diff.omitted, "Left excluded files out of the diff");
+            let note = render(&strings.excluded_files, &[("count", &diff.omitted.to_string())]);
+            diff.content = format!("{}\n\n{note}", diff.content).trim_start().to_string();
+        }
+        Ok(diff)
+    }
+
+    /// Collects the values for the prompt template placeholders from the repository state
+    ///
+    /// # Arguments
+    /// * `diff_content` - The staged diff text to include in the prompt
+    /// * `stats` - The statistics of the staged files
+    /// * `session_id` - The Claude Code session the commit is made for, if any
+    /// * `language` - Language of the fallback commits left out of the recent commits
+    pub fn prompt_variables(
+        &self,
+        diff_content: String,
+        stats: &[FileStat],
+        session_id: &str,
+        language: &str,
+    ) -> Result<PromptVariables> {
+        let branch = get_current_branch(&self.repo)?;
+        let base_branch = get_base_branch(&self.repo, &branch, &CONFIG.base_branches);
+        Ok(PromptVariables {
+            diff_content,
+            file_list: format_file_list(stats),
+            commit_count: count_commits_since(&self.repo, &base_branch),
+            base_branch,
+            branch,
+            session_id: session_id.to_string(),
+            recent_commits: get_recent_subjects(
+                &self.repo,
+                CONFIG.history.recent_commits,
+                fixed_default_message(&CONFIG, language).as_deref(),
+            )
+            .map(|subjects| subjects.join("\n"))
+            .unwrap_or_default(),
+            project_name: self.project_name(),
+            ..Default::default()
+        })
+    }
+
+    /// Generates the title and body of a pull request of the current branch from its changes
+    /// since it diverged from the base branch
+    ///
+    /// # Arguments
+    /// * `base_branch` - The branch the pull request is merged into, e.g., `main` or `origin/main`
+    /// * `language` - Language to use for the title and body
+    ///
+    /// # Returns
+    /// The title, which is the subject of the generated message, and the body, which is its body
+    /// followed by the subjects of the commits on the branch; or an error if it has none
+    pub fn describe_branch(&self, base_branch: &str, language: &str) -> Result<(String, String)> {
+        let head = self.repo.head()?.peel_to_commit()?.id();
+        let base = self
+            .repo
+            .revparse_single(base_branch)
+            .and_then(|object| object.peel_to_commit())
+            .with_context(|| format!("No base branch {base_branch}"))?
+            .id();
+        let merge_base = self.repo.merge_base(head, base)?;
+        let subjects = get_subjects_since(&self.repo, merge_base)?;
+        if subjects.is_empty() {
+            bail!("The branch has no commits since {base_branch}");
+        }
+
+        let strings = Strings::new(language, &CONFIG.localization);
+        let diff = get_diff_since(
+            &self.repo,
+            merge_base,
+            CONFIG.max_diff_chars(),
+            &strings.truncation_marker,
+            |path| CONFIG.paths.excludes(path),
+        )?;
+        let stats = get_file_stats_since(&self.repo, merge_base)?;
+        let mut variables = self.prompt_variables(diff.content, &stats, "", language)?;
+        variables.base_branch = base_branch.to_string();
+        let configured;
+        let generator = match &self.generator {
+            Some(generator) => generator.as_ref(),
+            None => {
+                configured = self.configured_generator(&stats, None, language)?;
+                &configured as &dyn MessageGenerator
+            }
+        };
+        let message = generator.generate(&variables, CommitContext::SessionEnd);
+
+        let (title, description) = message.split_once('\n').unwrap_or((&message, ""));
+        let commits = subjects
+            .iter()
+            .map(|subject| format!("- {subject}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let body = format!("{}\n\n## Commits\n\n{commits}", description.trim());
+        Ok((title.trim().to_string(), body.trim_start().to_string()))
+    }
+
+    /// Gets the name of the directory of the repository
+    fn project_name(&self) -> String {
+        self.repo
+            .workdir()
+            .and_then(|workdir| workdir.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    /// Lists the last messages the user typed in the session, read from its transcript, if
+    /// `conversation.enabled`
+    ///
+    /// # Arguments
+    /// * `session_id` - The session whose changes are committed; if it has no messages, as when
+    ///   `/clear` just started it, those of the session recorded before it are read
+    ///
+    /// # Returns
+    /// The messages, one list item each, with likely secrets redacted; empty if there are none or
+    /// the transcript can't be read, which never keeps the changes from being committed
+    fn conversation_summary(&self, session_id: &str) -> String {
+        if !CONFIG.conversation.enabled {
+            return String::new();
+        }
+        let read = |session: Session| {
+            user_messages(
+                Path::new(&session.transcript_path),
+                CONFIG.conversation.max_messages,
+                CONFIG.conversation.max_chars,
+            )
+        };
+        let sessions = sessions(&self.repo);
+        let messages = sessions
+            .get(session_id)
+            .map(read)
+            .filter(|messages| !messages.is_empty())
+            .or_else(|| sessions.latest_except(session_id).map(read))
+            .unwrap_or_default();
+        if messages.is_empty() {
+            debug!("Found no messages of the session for the conversation summary");
+            return String::new();
+        }
+        let summary = messages
+            .iter()
+            .map(|message| format!("- {}", message.replace('\n', "\n  ")))
+            .collect::<Vec<_>>()
+            .join("\n");
+        match Redactor::new(&CONFIG.redaction.patterns) {
+            Ok(redactor) => {
+                let (redacted, count) = redactor.redact(&summary);
+                if count > 0 {
+                    info!("Redacted {count} likely secrets from the conversation summary");
+                }
+                redacted.into_owned()
+            }
+            Err(e) => {
+                warn!("Leaving the conversation summary out of the prompt: {e:#}");
+                String::new()
+            }
+        }
+    }
+}
+
+/// Checks the staged changes for unresolved conflict markers, reporting each one found
+///
+/// # Arguments
+/// * `added` - The lines the staged changes add
+///
+/// # Returns
+/// Whether any were found, in which case nothing may be committed
+fn stages_conflict_markers(added: &[AddedLine]) -> bool {
+    if !CONFIG.conflict_markers.enabled {
+        return false;
+    }
+    let markers = find_markers(added, &CONFIG.conflict_markers.allow);
+    if markers.is_empty() {
+        return false;
+    }
+    error!(
+        "NOT COMMITTED: the staged changes contain {} unresolved conflict markers; they are left \
+         staged for you to resolve",
+        markers.len()
+    );
+    for marker in &markers {
+        error!("  {}:{}: {}", marker.path, marker.line, marker.content);
+    }
+    error!(
+        "If the markers belong in the files, add their paths to `conflict_markers.allow` in \
+         .claude/auto-commit.toml"
+    );
+    true
+}
+
+/// Creates the message cache of the repository as configured
+pub fn message_cache(repo: &git2::Repository) -> MessageCache {
+    MessageCache::new(
+        state_dir(repo).join("cache"),
+        CONFIG.cache.max_entries,
+        Duration::from_secs(CONFIG.cache.max_age_days * 24 * 60 * 60),
+    )
+}
+
+/// Creates the rate limiter of the commits in the repository as configured
+pub fn rate_limiter(repo: &git2::Repository) -> RateLimiter {
+    RateLimiter::new(
+        state_dir(repo).join("rate-limit.json"),
+        CONFIG.rate_limit.per_minute,
+        CONFIG.rate_limit.per_hour,
+    )
+}
+
+/// Creates the log of generator command invocations of the repository
+pub fn invocation_log(repo: &git2::Repository) -> InvocationLog {
+    InvocationLog::new(state_dir(repo).join("generator.log"))
+}
+
+/// Creates the audit log of the repository, if enabled by `audit.enabled`
+pub fn audit_log(repo: &git2::Repository) -> Option<AuditLog> {
+    if !CONFIG.audit.enabled {
+        return None;
+    }
+    let path = audit_path(&state_dir(repo), &CONFIG.audit.path);
+    if path.is_none() {
+        warn!("Keeping no audit log: `audit.path` ({:?}) leaves its directory", CONFIG.audit.path);
+    }
+    path.map(AuditLog::new)
+}
+
+/// Gets the file of the audit log at `audit.path`, or `None` if the path goes up a directory, as
+/// the log would otherwise be appended to any file a relative path reaches
+///
+/// # Arguments
+/// * `state_dir` - The directory of a relative path
+/// * `path` - The setting
+fn audit_path(state_dir: &Path, path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if path.components().any(|component| component == Component::ParentDir) {
+        return None;
+    }
+    Some(state_dir.join(path))
+}
+
+/// Creates the log of the background processes handling hook events in the repository
+pub fn daemon_log(repo: &git2::Repository) -> DaemonLog {
+    DaemonLog::new(state_dir(repo).join("daemon.log"))
+}
+
+/// Creates the commits proposed in the repository in review mode
+pub fn proposals(repo: &git2::Repository) -> Proposals {
+    Proposals::new(state_dir(repo).join("pending"))
+}
+
+/// Creates the notifier of the repository as configured
+fn notifier(repo: &git2::Repository) -> Notifier<'static> {
+    Notifier::new(&CONFIG.notify, state_dir(repo).join("notified.json"))
+}
+
+/// Creates the metadata of the Claude Code sessions seen in the repository
+pub fn sessions(repo: &git2::Repository) -> Sessions {
+    Sessions::new(state_dir(repo).join("sessions"))
+}
+
+/// Creates the record of the paths the tool staged and hasn't committed in the repository
+fn staged_paths(repo: &git2::Repository) -> StagedPaths {
+    StagedPaths::new(state_dir(repo).join("staged.json"))
+}
+
+/// Creates the pending batch of edits of the repository
+fn batch(repo: &git2::Repository) -> Batch {
+    Batch::new(state_dir(repo).join("batch.json"))
+}
+
+/// Creates the intents to commit the edited files of the repository, debounced by the window
+fn debouncer(repo: &git2::Repository, window: Duration) -> Debouncer {
+    Debouncer::new(state_dir(repo).join("debounce"), window)
+}
+
+/// Creates the PID files of the background processes handling hook events in the repository
+pub fn instances(repo: &git2::Repository) -> Instances {
+    Instances::new(state_dir(repo).join("instances"))
+}
+
+/// Formats the changed files as a list for the prompt, capped at `MAX_LISTED_FILES` entries
+fn format_file_list(stats: &[FileStat]) -> String {
+    let mut lines = stats
+        .iter()
+        .take(MAX_LISTED_FILES)
+        .map(|stat| format!("- {} (+{}/-{})", stat.path, stat.insertions, stat.deletions))
+        .collect::<Vec<_>>();
+    if stats.len() > MAX_LISTED_FILES {
+        lines.push(format!("- +{} more", stats.len() - MAX_LISTED_FILES));
+    }
+    lines.join("\n")
+}
+
+/// Renders the default commit message template for the staged changes
+///
+/// # Arguments
+/// * `config` - The configuration providing the template and the localized strings
+/// * `stats` - The statistics of the staged files, for `{files}` and `{stats}`
+/// * `tool_name` - The tool whose use triggered the commit, for `{tool}`
+/// * `language` - Language of the fallback subject used when no template is configured
+fn default_message(
+    config: &Config,
+    stats: &[FileStat],
+    tool_name: Option<&ToolName>,
+    language: &str,
+) -> String {
+    let mut files = stats
+        .iter()
+        .take(MAX_DEFAULT_MESSAGE_FILES)
+        .map(|stat| stat.path.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if stats.len() > MAX_DEFAULT_MESSAGE_FILES {
+        files.push_str(&format!(", +{} more", stats.len() - MAX_DEFAULT_MESSAGE_FILES));
+    }
+    let insertions = stats.iter().map(|stat| stat.insertions).sum::<usize>();
+    let deletions = stats.iter().map(|stat| stat.deletions).sum::<usize>();
+
+    render(
+        &default_message_template(config, language),
+        &[
+            ("files", &files),
+            ("stats", &format!("+{insertions}/-{deletions}")),
+            ("date", &Zoned::now().strftime("%Y-%m-%d").to_string()),
+            ("tool", &tool_name.map(ToString::to_string).unwrap_or_default()),
+        ],
+    )
+}
+
+/// Gets the configured default commit message template, or the localized fallback subject
+fn default_message_template(config: &Config, language: &str) -> String {
+    config
+        .generator
+        .default_commit_message
+        .clone()
+        .unwrap_or_else(|| Strings::new(language, &config.localization).fallback_subject)
+}
+
+/// Gets the default commit message if it has no placeholders, and so is the subject of every
+/// fallback commit, including those made before they had `FALLBACK_TRAILER`
+fn fixed_default_message(config: &Config, language: &str) -> Option<String> {
+    let message = default_message_template(config, language);
+    (!message.is_empty() && !message.contains('{')).then_some(message)
+}
+
+/// Makes the path of an edited file relative to the working directory of the repository, making
+/// sure that staging it can't reach outside: the path the model reported may lead anywhere
+///
+/// # Arguments
+/// * `workdir` - The working directory of the repository
+/// * `path` - The absolute path of the edited file, without `.` and `..` components
+///
+/// # Returns
+/// The relative path, or an error saying how the file escapes the repository: by lying outside
+/// `workdir`, by resolving to a file outside through a symbolic link, or by lying in a repository
+/// nested in it
+fn contained_path(workdir: &Path, path: &Path) -> Result<PathBuf> {
+    let relative = relative_path(workdir, path)
+        .with_context(|| format!("it is outside the repository {}", workdir.display()))?;
+    if relative.as_os_str().is_empty() {
+        bail!("it is the working directory of the repository");
+    }
+
+    // Links are resolved up to the file, or its directory if deleted, and then the file itself if
+    // it is a link, which may be dangling
+    let root = workdir.canonicalize()?;
+    let parent = path.parent().context("it has no parent directory")?;
+    let file_name = path.file_name().context("it has no file name")?;
+    let mut resolved = parent.canonicalize()?.join(file_name);
+    if resolved
+        .symlink_metadata()
+        .is_ok_and(|metadata| metadata.is_symlink())
+    {
+        resolved = resolved.canonicalize().unwrap_or_else(|_| {
+            let target = read_link(&resolved).unwrap_or_default();
+            normalize(&resolved.parent().unwrap_or(&root).join(target))
+        });
+    }
+    if !resolved.starts_with(&root) {
+        bail!(
+            "it resolves to {} outside the repository through a symbolic link",
+            resolved.display()
+        );
+    }
+
+    let mut dir = workdir.to_path_buf();
+    for component in relative.parent().into_iter().flat_map(Path::components) {
+        dir.push(component);
+        if dir.join(".git").exists() {
+            bail!("it is in the nested repository {}", dir.display());
+        }
+    }
+    Ok(relative)
+}
+
+/// Resolves the `.` and `..` components of a path lexically, without following links, as the
+/// shell does for `cd`
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Makes the path of an edited file relative to the working directory of the repository
+///
+/// The components are compared as they are, without converting them to UTF-8. On Windows, the
+/// path may differ from the working directory in the case of the drive letter and in using `/` or
+/// `\` as separators; on macOS, in the Unicode normalization of the names. Where either is reached
+/// through a symbolic link, e.g., `/tmp` on macOS, they are compared again with links resolved.
+///
+/// # Arguments
+/// * `workdir` - The working directory of the repository
+/// * `path` - The absolute path of the edited file
+///
+/// # Returns
+/// The relative path, or `None` if the file is outside `workdir`
+fn relative_path(workdir: &Path, path: &Path) -> Option<PathBuf> {
+    let same = |a: Component, b: Component| match (a, b) {
+        (Component::Prefix(a), Component::Prefix(b)) => {
+            a.as_os_str().eq_ignore_ascii_case(b.as_os_str())
+        }
+        (Component::Normal(a), Component::Normal(b)) => same_name(a, b),
+        _ => a == b,
+    };
+    let strip = |workdir: &Path, path: &Path| {
+        let mut components = path.components();
+        workdir
+            .components()
+            .all(|base| components.next().is_some_and(|c| same(c, base)))
+            .then(|| components.as_path().to_path_buf())
+    };
+    strip(workdir, path).or_else(|| {
+        // The file itself may be deleted, so only its directory is resolved
+        let dir = path.parent()?.canonicalize().ok()?;
+        strip(&workdir.canonicalize().ok()?, &dir.join(path.file_name()?))
+    })
+}
+
+/// Checks whether the file names are the same, which on macOS they are whatever their Unicode
+/// normalization, e.g., `é` as one character or as `e` and a combining accent
+fn same_name(a: &OsStr, b: &OsStr) -> bool {
+    a == b
+        || cfg!(target_os = "macos")
+            && a.to_str().zip(b.to_str()).is_some_and(|(a, b)| a.nfc().eq(b.nfc()))
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::Delta;
+    use toml::from_str;
+
+    use super::*;
+
+    /// Gets the embedded default configuration with the default commit message template
+    fn config(template: Option<&str>) -> Config {
+        let mut config: Config = from_str(include_str!("../assets/commit-config.toml")).unwrap();
+        config.generator.default_commit_message = template.map(str::to_string);
+        config
+    }
+
+    /// Creates the statistics of a modified file
+    fn stat(path: &str, insertions: usize, deletions: usize) -> FileStat {
+        FileStat {
+            path: path.to_string(),
+            old_path: None,
+            status: Delta::Modified,
+            insertions,
+            deletions,
+        }
+    }
+
+    #[test]
+    fn audit_path_stays_in_its_directory() {
+        let state_dir = Path::new("/repo/.git/claude-auto-commit");
+
+        for (path, expected) in [
+            ("audit.jsonl", Some("/repo/.git/claude-auto-commit/audit.jsonl")),
+            ("logs/audit.jsonl", Some("/repo/.git/claude-auto-commit/logs/audit.jsonl")),
+            ("/var/log/ccc.jsonl", Some("/var/log/ccc.jsonl")),
+            ("../../.bashrc", None),
+            ("logs/../../hooks/pre-commit", None),
+            ("/home/u/../u/.bashrc", None),
+        ] {
+            assert_eq!(audit_path(state_dir, path).as_deref(), expected.map(Path::new), "{path}");
+        }
+    }
+
+    #[test]
+    fn per_file_default_message_names_the_tool_and_the_file() {
+        let config = config(Some("chore({tool}): update {files} ({stats})"));
+
+        let message =
+            default_message(&config, &[stat("src/main.rs", 3, 1)], Some(&ToolName::Write), "en");
+
+        assert_eq!(message, "chore(Write): update src/main.rs (+3/-1)");
+        // Its fallback commits are told apart by their trailer alone
+        assert_eq!(fixed_default_message(&config, "en"), None);
+        assert_eq!(fixed_default_message(&self::config(Some("")), "en"), None);
+    }
+
+    #[test]
+    fn session_end_default_message_lists_the_first_files() {
+        let config = config(Some("WARNING: {files} ({stats}){tool}"));
+        let stats = (1..=7)
+            .map(|n| stat(&format!("src/{n}.rs"), n, 1))
+            .collect::<Vec<_>>();
+
+        let message = default_message(&config, &stats, None, "en");
+
+        assert_eq!(
+            message,
+            "WARNING: src/1.rs, src/2.rs, src/3.rs, src/4.rs, src/5.rs, +2 more (+28/-7)"
+        );
+    }
+
+    #[test]
+    fn default_message_is_localized_without_a_template() {
+        let config = config(None);
+        let stats = [stat("src/main.rs", 3, 1)];
+
+        for (language, expected) in [
+            ("en", "WARNING: commit message generation failure"),
+            ("ja", "警告: コミットメッセージの生成に失敗しました"),
+            ("Japanese", "警告: コミットメッセージの生成に失敗しました"),
+            ("fr", "WARNING: commit message generation failure"),
+        ] {
+            for tool_name in [Some(&ToolName::Edit), None] {
+                assert_eq!(default_message(&config, &stats, tool_name, language), expected);
+            }
+            assert_eq!(fixed_default_message(&config, language).as_deref(), Some(expected));
+        }
+    }
 }
@@ -0,0 +1,2204 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env::{var, vars},
+    fmt::{Display, Formatter},
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+    sync::{LazyLock, OnceLock},
+};
+
+use anyhow::{Context, Result, bail};
+use clap::parser::ValueSource;
+use directories::{BaseDirs, ProjectDirs};
+use globset::GlobBuilder;
+use schemars::{JsonSchema, Schema, generate::SchemaSettings, transform::RecursiveTransform};
+use serde::Deserialize;
+use serde_json::json;
+use toml::{Table, Value, from_str};
+use tracing::warn;
+
+use crate::{
+    cache::MessageCache,
+    commit_message_generator::CommitContext,
+    git_ops::state_dir,
+    locale::{LocalizedStrings, language_of_locale, normalize},
+    types::{HookEvent, SessionStartSource, ToolInput, ToolName, ToolResponse},
+};
+
+/// Configuration embedded from `assets/commit-config.toml`, with the config files merged onto it
+#[derive(Deserialize, JsonSchema)]
+pub struct Config {
+    /// Whether the hook acts in the repository, best set in the repository-level file; `ccc
+    /// enable` and `ccc disable` take precedence
+    pub enabled: Option<bool>,
+    /// Whether the hook acts only in repositories opted in with `enabled = true` or `ccc enable`
+    pub require_opt_in: bool,
+    /// Whether the hook writes to git when running in CI
+    pub allow_in_ci: bool,
+    /// Environment variables telling that the hook runs in CI, besides those CI services set
+    pub ci_variables: Vec<String>,
+    /// Whether commits are made, or only proposed for a human to apply
+    pub mode: Mode,
+    /// Language to use for commit messages, unless given by the `--language` flag
+    pub language: Option<String>,
+    /// Branches a session branch is created from, in order of preference as the base branch
+    pub base_branches: Vec<String>,
+    /// Glob patterns of branches nothing is committed on directly, `base_branches` if unset
+    pub protected_branches: Option<Vec<String>>,
+    /// How many edits go into one commit
+    pub granularity: Granularity,
+    /// Seconds without edits after which a batch of edits is committed
+    pub quiet_period_secs: u64,
+    /// Number of files in a batch of edits at which it is committed without waiting for the quiet
+    /// period, or 0 for no limit
+    pub max_batch_files: usize,
+    /// Seconds a background process waits after a per-file edit for a newer edit of the same file,
+    /// which commits it instead
+    pub debounce_secs: u64,
+    /// Seconds after which the hook gives up when running in the foreground
+    pub foreground_deadline_secs: u64,
+    /// Seconds after which a background process handling a hook event is stopped
+    pub deadline_secs: u64,
+    /// Size in bytes above which changed files are left unstaged, or 0 for no limit
+    pub max_file_size: u64,
+    /// Glob patterns over paths in the repository of files staged whatever their size
+    pub large_files: Vec<String>,
+    /// How changed binary files are handled
+    pub binary_policy: BinaryPolicy,
+    /// What happens to the changes the user staged before the tool staged its own
+    pub user_staged: UserStagedPolicy,
+    /// Events on which the changes of the session are committed
+    pub commit_on: Vec<CommitTrigger>,
+    pub branching: Branching,
+    pub repos: Repos,
+    pub paths: Paths,
+    /// Whether a use of the tool of each name commits the file it changed
+    pub triggers: BTreeMap<String, bool>,
+    /// MCP tools treated as native edit tools, by glob patterns over their names
+    pub mcp_tools: BTreeMap<String, McpMapping>,
+    pub prompt: Prompt,
+    pub generator: Generator,
+    pub validation: Validation,
+    pub message: Message,
+    pub history: History,
+    pub conversation: Conversation,
+    pub cache: Cache,
+    pub rate_limit: RateLimit,
+    pub notify: Notify,
+    pub webhook: Webhook,
+    pub audit: Audit,
+    pub diff: DiffConfig,
+    #[serde(default)]
+    pub post_process: PostProcess,
+    pub redaction: Redaction,
+    pub secrets: SecretScanning,
+    pub conflict_markers: ConflictMarkers,
+    #[serde(default)]
+    pub localization: HashMap<String, LocalizedStrings>,
+    pub pending_operations: PendingOperations,
+    pub committer: CommitterIdentity,
+    pub install: Install,
+    pub worker: Worker,
+    pub pull_request: PullRequestConfig,
+}
+
+impl Config {
+    /// Gets the number of diff characters included in the prompt, preferring the generator's own
+    /// limit over the general one
+    pub fn max_diff_chars(&self) -> usize {
+        self.generator.max_diff_chars.unwrap_or(self.diff.max_chars)
+    }
+
+    /// Gets the name and email commits are committed by, or `None` to commit them as the author
+    pub fn committer_identity(&self) -> Option<(&str, &str)> {
+        self.committer
+            .bot
+            .then_some((&self.committer.name, &self.committer.email))
+    }
+
+    /// Checks whether a session starting from the source commits the changes of the previous one
+    pub fn commits_on(&self, source: &SessionStartSource) -> bool {
+        let trigger = match source {
+            SessionStartSource::Clear => CommitTrigger::Clear,
+            SessionStartSource::Compact => CommitTrigger::Compact,
+            SessionStartSource::Resume => CommitTrigger::Resume,
+            SessionStartSource::Startup | SessionStartSource::Unknown => return false,
+        };
+        self.commit_on.contains(&trigger)
+    }
+
+    /// Checks whether handling the hook event can do any git work, so that other events end
+    /// before forking a background process
+    pub fn acts_on(&self, event: &HookEvent) -> bool {
+        match event {
+            // Even without committing, it may switch to a session branch
+            HookEvent::SessionStart { .. } => true,
+            HookEvent::PostToolUse { tool_name, tool_input, tool_response, .. } => {
+                tool_response.success()
+                    && self.committed_file(tool_name, tool_input, tool_response).is_some()
+                    && self.granularity != Granularity::Session
+            }
+            HookEvent::Stop { .. } => self.commit_on.contains(&CommitTrigger::Stop),
+        }
+    }
+
+    /// Checks whether a use of the tool commits the file it changed
+    pub fn triggers_commit(&self, tool_name: &ToolName) -> bool {
+        self.triggers.get(&tool_name.to_string()).copied().unwrap_or(false)
+    }
+
+    /// Gets the file a use of the tool changed, if the tool commits it: a native tool in
+    /// `triggers`, or an MCP tool mapped in `mcp_tools` to one
+    ///
+    /// # Arguments
+    /// * `tool_name` - The tool used
+    /// * `tool_input` - Its input, holding the path of the file
+    /// * `tool_response` - Its response, whose `filePath` is preferred over the input's
+    ///
+    /// # Returns
+    /// The path of the file, absolute or relative to the working directory of the event, or `None`
+    /// if the tool doesn't commit it or names no file
+    pub fn committed_file<'a>(
+        &self,
+        tool_name: &ToolName,
+        tool_input: &'a ToolInput,
+        tool_response: &'a ToolResponse,
+    ) -> Option<&'a Path> {
+        let (as_tool, path_field) = match tool_name {
+            ToolName::Mcp(_) => {
+                let name = tool_name.to_string();
+                let mapping = self.mcp_tools.iter().find_map(|(pattern, mapping)| {
+                    let glob = GlobBuilder::new(pattern).build().ok()?;
+                    glob.compile_matcher().is_match(&name).then_some(mapping)
+                })?;
+                (mapping.as_tool.tool_name(), mapping.path_field.as_str())
+            }
+            tool_name => (tool_name.clone(), "file_path"),
+        };
+        if !self.triggers_commit(&as_tool) {
+            return None;
+        }
+        tool_response
+            .file_path()
+            .or_else(|| tool_input.get(path_field))
+            .map(Path::new)
+    }
+
+    /// Gets the seconds handling a hook event may take without being wedged: the generator
+    /// command timing out, then once more when regenerating, the post-processing command timing
+    /// out, and waiting for the quiet period of batched edits or the debounce window of per-file
+    /// ones
+    pub fn min_deadline_secs(&self) -> u64 {
+        let generations = match self.validation.on_failure {
+            FailurePolicy::RegenerateOnce => 2,
+            _ => 1,
+        } + u64::from(self.post_process.command.is_some());
+        let quiet_period = match self.granularity {
+            Granularity::Batched => self.quiet_period_secs,
+            Granularity::PerFile => self.debounce_secs,
+            Granularity::Session => 0,
+        };
+        generations * self.generator.timeout_secs + quiet_period
+    }
+
+    /// Checks whether the repository is opted in, as far as `enabled` and `require_opt_in` go
+    ///
+    /// # Arguments
+    /// * `recorded` - Whether `ccc enable` or `ccc disable` was run in the repository last, if
+    ///   either was
+    ///
+    /// # Returns
+    /// `None` if the repository is opted in, the reason why not otherwise
+    pub fn opt_out_reason(&self, recorded: Option<bool>) -> Option<&'static str> {
+        match (recorded, self.enabled) {
+            (Some(true), _) | (None, Some(true)) => None,
+            (Some(false), _) => Some("was disabled with `ccc disable`"),
+            (None, Some(false)) => Some("sets enabled = false"),
+            (None, None) if self.require_opt_in => Some(
+                "isn't opted in with `ccc enable` or enabled = true in .claude/auto-commit.toml, \
+                 as require_opt_in is set",
+            ),
+            (None, None) => None,
+        }
+    }
+
+    /// Checks whether a changed file is too large to be staged
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file relative to the root of the repository
+    /// * `size` - The size of the file in bytes
+    ///
+    /// # Returns
+    /// Whether the file exceeds `max_file_size` without matching a `large_files` pattern
+    pub fn is_oversized(&self, path: &Path, size: u64) -> bool {
+        self.max_file_size != 0
+            && size > self.max_file_size
+            && !self.large_files.iter().any(|pattern| {
+                GlobBuilder::new(pattern)
+                    .literal_separator(true)
+                    .build()
+                    .is_ok_and(|glob| glob.compile_matcher().is_match(path))
+            })
+    }
+
+    /// Checks whether the branch matches a pattern of `protected_branches`, or is one of
+    /// `base_branches` if unset
+    pub fn is_protected(&self, branch: &str) -> bool {
+        let patterns = self.protected_branches.as_ref().unwrap_or(&self.base_branches);
+        patterns.iter().any(|pattern| {
+            GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .is_ok_and(|glob| glob.compile_matcher().is_match(branch))
+        })
+    }
+
+    /// Checks whether a session starting on the branch switches to a new session branch
+    pub fn branches_from(&self, branch: &str) -> bool {
+        self.branching.enabled
+            && (!self.branching.protect_current || self.base_branches.iter().any(|b| b == branch))
+    }
+}
+
+/// Repositories the hook acts in, matched by the path of their root directory
+#[derive(Deserialize, JsonSchema)]
+pub struct Repos {
+    /// Glob patterns of the repositories to act in; an empty list allows every repository
+    pub allow: Vec<String>,
+    /// Glob patterns of the repositories to ignore, even if allowed
+    pub deny: Vec<String>,
+}
+
+impl Repos {
+    /// Checks whether the hook acts in the repository at the given root
+    ///
+    /// # Arguments
+    /// * `root` - The root directory of the repository
+    ///
+    /// # Returns
+    /// `None` if the repository is enabled, the reason why not otherwise, or an error if a pattern
+    /// is invalid
+    pub fn disabled_reason(&self, root: &Path) -> Result<Option<String>> {
+        let root = root.components().as_path();
+        if let Some(pattern) = first_match(&self.deny, root)? {
+            return Ok(Some(format!("matches repos.deny pattern {pattern:?}")));
+        }
+        if !self.allow.is_empty() && first_match(&self.allow, root)?.is_none() {
+            return Ok(Some("matches no repos.allow pattern".to_string()));
+        }
+        Ok(None)
+    }
+}
+
+/// Finds the first glob pattern matching the path, with a leading `~/` standing for the home
+/// directory
+pub fn first_match<'a>(patterns: &'a [String], path: &Path) -> Result<Option<&'a str>> {
+    let home = BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    for pattern in patterns {
+        let expanded = match (pattern.strip_prefix("~/"), &home) {
+            (Some(rest), Some(home)) => home.join(rest).to_string_lossy().to_string(),
+            _ => pattern.clone(),
+        };
+        let glob = GlobBuilder::new(&expanded)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("Invalid repository pattern {pattern:?}"))?;
+        if glob.compile_matcher().is_match(path) {
+            return Ok(Some(pattern));
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Branching {
+    /// Whether session branches are created at all
+    pub enabled: bool,
+    /// Whether sessions starting on a branch outside `base_branches` stay on it
+    pub protect_current: bool,
+    /// What happens to a commit due on a protected branch
+    pub on_protected: ProtectedPolicy,
+}
+
+/// What happens to a commit due on a branch of `protected_branches`
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProtectedPolicy {
+    /// Switch to a new session branch and commit there, unless branching is disabled
+    Switch,
+    /// Leave the changes uncommitted, with a warning in the log
+    Skip,
+}
+
+/// How the use of an MCP tool is handled
+#[derive(Deserialize, JsonSchema)]
+pub struct McpMapping {
+    /// The native tool whose use the MCP tool's is handled as, as enabled in `triggers`
+    #[serde(rename = "as")]
+    pub as_tool: EditTool,
+    /// The field of the tool input holding the path of the changed file
+    pub path_field: String,
+}
+
+/// A native tool changing a single file
+#[derive(Deserialize, JsonSchema, Clone, Copy)]
+pub enum EditTool {
+    Edit,
+    Write,
+}
+
+impl EditTool {
+    /// Gets the tool as named in hook events
+    pub fn tool_name(self) -> ToolName {
+        match self {
+            EditTool::Edit => ToolName::Edit,
+            EditTool::Write => ToolName::Write,
+        }
+    }
+}
+
+/// An event ending a session, on which its changes can be committed
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommitTrigger {
+    /// A session started by `/clear`
+    Clear,
+    /// A session started by `/compact` or automatic compaction
+    Compact,
+    /// A session resumed by `--resume` or `/resume`
+    Resume,
+    /// The end of each response, if the Stop hook is installed
+    Stop,
+}
+
+/// What happens to a commit once its message is generated
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Commit it
+    Commit,
+    /// Record it as a proposal under `.git/claude-auto-commit/pending/`, leaving the changes
+    /// staged, for `ccc review apply` to commit
+    Review,
+}
+
+/// How edits are grouped into commits
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Granularity {
+    /// Commit each edited file right after the edit
+    PerFile,
+    /// Stage each edited file and commit them together once no edits are made for a while
+    Batched,
+    /// Commit only when the session ends
+    Session,
+}
+
+/// What happens to the changes the user staged, e.g., with `git add -p`, which the tool didn't
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UserStagedPolicy {
+    /// Leave them out of every commit, staged as they are
+    Leave,
+    /// Leave them out of per-file commits, and stage the files as they are in the working tree
+    /// for the commit at the end of the session
+    Commit,
+}
+
+/// How changed binary files are handled
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BinaryPolicy {
+    /// Commit them like text files
+    Commit,
+    /// Leave them unstaged, with a warning in the log
+    Skip,
+    /// Commit them, with a warning in the log and a note in the commit message
+    Warn,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Prompt {
+    pub template: String,
+    pub per_file_template: Option<String>,
+    pub session_template: Option<String>,
+    /// Templates replacing `template` for a language, keyed by its name or code in any case
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+    pub subject_only_instruction: String,
+    pub data_instruction: String,
+}
+
+impl Prompt {
+    /// Gets the template for the context, falling back to the one for the language and then to
+    /// the general one
+    ///
+    /// The template keyed by the language as given is preferred over the first one, in the order
+    /// of the keys, whose key normalizes to the same language.
+    ///
+    /// # Arguments
+    /// * `context` - The situation the commit is made in
+    /// * `language` - The language messages are generated in, e.g., "Japanese" or "ja"
+    pub fn template_for(&self, context: CommitContext, language: &str) -> &str {
+        let code = normalize(language);
+        match context {
+            CommitContext::PerFile => self.per_file_template.as_deref(),
+            CommitContext::SessionEnd => self.session_template.as_deref(),
+            CommitContext::Batch | CommitContext::Standalone => None,
+        }
+        .or_else(|| self.templates.get(language).map(String::as_str))
+        .or_else(|| {
+            self.templates
+                .iter()
+                .find(|(key, _)| normalize(key) == code)
+                .map(|(_, template)| template.as_str())
+        })
+        .unwrap_or(&self.template)
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Generator {
+    pub enabled: bool,
+    pub command: String,
+    pub args: Vec<String>,
+    pub prompt_via: PromptVia,
+    pub output_format: OutputFormat,
+    pub timeout_secs: u64,
+    pub candidates: usize,
+    pub scoring: Scoring,
+    pub max_diff_chars: Option<usize>,
+    pub max_prompt_tokens: Option<usize>,
+    pub model: Option<String>,
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    pub env_allowlist: Option<Vec<String>>,
+    pub default_commit_message: Option<String>,
+    pub fallback: Fallback,
+}
+
+impl Generator {
+    /// Environment variable through which the claude CLI takes the maximum number of output tokens
+    pub const MAX_TOKENS_ENV: &str = "CLAUDE_CODE_MAX_OUTPUT_TOKENS";
+
+    /// Gets the arguments of the generator command, with `model` translated into the claude CLI
+    /// flag and `extra_args` appended
+    ///
+    /// # Returns
+    /// The arguments, or an error if a flag given by `model` or `extra_args` is also in `args`,
+    /// or `max_tokens` is also set in `env`
+    pub fn resolved_args(&self) -> Result<Vec<String>> {
+        let has_flag = |args: &[String], flag: &str| {
+            args.iter().any(|arg| {
+                arg == flag || arg.strip_prefix(flag).is_some_and(|rest| rest.starts_with('='))
+            })
+        };
+
+        let mut args = self.args.clone();
+        if let Some(model) = &self.model {
+            if has_flag(&self.args, "--model") {
+                bail!("`generator.model` is set, but `generator.args` already contains --model");
+            }
+            args.extend(["--model".to_string(), model.clone()]);
+        }
+        for arg in self.extra_args.iter().filter(|arg| arg.starts_with('-')) {
+            let flag = arg.split_once('=').map_or(arg.as_str(), |(flag, _)| flag);
+            if has_flag(&args, flag) {
+                bail!("`generator.extra_args` contains {flag}, which is already set");
+            }
+        }
+        args.extend(self.extra_args.iter().cloned());
+
+        if self.max_tokens.is_some() && self.env.contains_key(Self::MAX_TOKENS_ENV) {
+            bail!(
+                "`generator.max_tokens` is set, but `generator.env` already sets {}",
+                Self::MAX_TOKENS_ENV
+            );
+        }
+        Ok(args)
+    }
+}
+
+/// How the stdout of the generator command is read
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// The whole stdout is the message
+    Text,
+    /// The message is the `result` of the JSON envelope printed by `claude --output-format json`;
+    /// output that isn't JSON is taken as plain text
+    Json,
+}
+
+/// Weights for picking the best of multiple generated candidates
+#[derive(Deserialize, JsonSchema)]
+pub struct Scoring {
+    /// Added when the message passes validation
+    pub valid: f64,
+    /// Multiplied by the number of characters in the subject line
+    pub subject_length: f64,
+    /// Added when the subject line mentions the stem of the most changed file
+    pub mentions_primary_file: f64,
+}
+
+/// How the rendered prompt is handed to the generator command
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PromptVia {
+    /// Write the prompt to the command's stdin and close it
+    Stdin,
+    /// Append the prompt as the last command line argument
+    Arg,
+}
+
+/// How the message is built when generation fails or is disabled
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fallback {
+    /// Derive a message from the shape of the diff
+    Heuristic,
+    /// Use `default_commit_message` as is
+    Default,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Validation {
+    pub pattern: String,
+    pub on_failure: FailurePolicy,
+    pub allowed_types: Vec<String>,
+    pub corrections: HashMap<String, String>,
+}
+
+/// What to do when a generated message fails validation
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailurePolicy {
+    /// Use the default commit message as the subject and the generated message as the body
+    PrependDefault,
+    /// Use the fallback message
+    UseDefault,
+    /// Generate again once, prepending the default commit message if that fails as well
+    RegenerateOnce,
+    /// Use the generated message anyway
+    AcceptAnyway,
+}
+
+impl Display for FailurePolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FailurePolicy::PrependDefault => "prepend-default",
+            FailurePolicy::UseDefault => "use-default",
+            FailurePolicy::RegenerateOnce => "regenerate-once",
+            FailurePolicy::AcceptAnyway => "accept-anyway",
+        })
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Message {
+    pub body: BodyPolicy,
+    pub preserved_trailers: Vec<String>,
+    pub signoff: bool,
+    pub transcript_trailer: TranscriptTrailer,
+}
+
+/// Which trailers link a commit to the Claude Code session it was made in
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TranscriptTrailer {
+    /// None
+    Off,
+    /// `Claude-Session`, the session ID
+    Session,
+    /// `Claude-Session` and `Claude-Transcript`, the path of the transcript
+    Path,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct History {
+    pub recent_commits: usize,
+}
+
+/// The messages of the user read from the session transcript for `{conversation_summary}`
+#[derive(Deserialize, JsonSchema)]
+pub struct Conversation {
+    /// Whether session-end prompts get the messages
+    pub enabled: bool,
+    /// The maximum number of messages, the latest ones kept
+    pub max_messages: usize,
+    /// The maximum number of characters of the messages together
+    pub max_chars: usize,
+}
+
+#[derive(Deserialize, JsonSchema, Default)]
+pub struct PostProcess {
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct PendingOperations {
+    pub policy: PendingPolicy,
+    pub generate_merge_body: bool,
+    pub on_blocking: BlockingPolicy,
+}
+
+/// What to do when changes are made while a merge, revert, or cherry-pick is in progress
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PendingPolicy {
+    /// Conclude the operation with the message git prepared, once no conflicts are left
+    Complete,
+    /// Leave the commit to the user
+    Skip,
+}
+
+/// What to do with edits made while a rebase, bisect, or `git am` is in progress, during which
+/// nothing is written to git
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BlockingPolicy {
+    /// Leave them in the working tree
+    Skip,
+    /// Stash the edited tracked files, for `git stash pop` to restore once the operation is done
+    Stash,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Install {
+    /// Arguments appended to the hook command written by `install`
+    pub extra_args: Vec<String>,
+}
+
+/// The persistent process handling the hook events of a repository one after another
+#[derive(Deserialize, JsonSchema)]
+pub struct Worker {
+    /// Whether hook invocations forward their events to the worker instead of forking a process
+    /// each, on Unix
+    pub enabled: bool,
+    /// Seconds without events after which the worker exits
+    pub idle_timeout_secs: u64,
+}
+
+/// How `ccc pr` publishes a session branch as a GitHub pull request
+#[derive(Deserialize, JsonSchema)]
+pub struct PullRequestConfig {
+    pub method: PullRequestMethod,
+    /// The remote on GitHub the branch is pushed to
+    pub remote: String,
+    /// The URL of the REST API, e.g., of a GitHub Enterprise Server
+    pub api_url: String,
+    /// The token of the REST API, `GH_TOKEN` or `GITHUB_TOKEN` if unset
+    pub token: Option<String>,
+}
+
+/// How a pull request is created
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PullRequestMethod {
+    /// With `gh` if it is installed, or else the REST API
+    Auto,
+    /// With `gh pr create`
+    Gh,
+    /// With the REST API
+    Api,
+}
+
+/// The identity commits are committed by, while the user is their author
+#[derive(Deserialize, JsonSchema)]
+pub struct CommitterIdentity {
+    /// Whether to commit as this identity rather than as the author
+    pub bot: bool,
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Redaction {
+    pub enabled: bool,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+/// The scan of the staged changes for secrets, which leaves them uncommitted on a hit
+#[derive(Deserialize, JsonSchema)]
+pub struct SecretScanning {
+    pub enabled: bool,
+    /// Additional rules, by name, as regular expressions matched against each added line
+    #[serde(default)]
+    pub patterns: BTreeMap<String, String>,
+    /// Glob patterns of paths, or fingerprints of findings, that hold no secrets
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+/// The check of the staged changes for unresolved conflict markers, which leaves them uncommitted
+/// on a hit
+#[derive(Deserialize, JsonSchema)]
+pub struct ConflictMarkers {
+    pub enabled: bool,
+    /// Glob patterns of paths of files that may contain conflict markers
+    pub allow: Vec<String>,
+}
+
+/// The append-only log of every action, for compliance records
+#[derive(Deserialize, JsonSchema)]
+pub struct Audit {
+    pub enabled: bool,
+    /// The log file, relative to the state directory `.git/claude-auto-commit` unless absolute
+    pub path: String,
+}
+
+/// The notifications of commits and of changes left uncommitted for likely secrets or the rate
+/// limit
+#[derive(Deserialize, JsonSchema)]
+pub struct Notify {
+    pub method: NotifyMethod,
+    /// The program run with `method = "command"`
+    pub command: Option<String>,
+    /// Arguments of the command, with `{event}`, `{sha}`, `{subject}`, `{branch}`, `{files}`, and
+    /// `{coalesced}` replaced
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Seconds after a notification during which others are left out, and counted in the next; the
+    /// last one left out is sent after them if nothing else is
+    pub coalesce_secs: u64,
+}
+
+/// The webhook posted each commit, e.g., a relay to a chat channel
+#[derive(Deserialize, JsonSchema)]
+pub struct Webhook {
+    /// The URL posted to, or unset for no webhook
+    pub url: Option<String>,
+    /// Headers of the request besides `Content-Type: application/json`, e.g., a token
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// The JSON body, whose strings have `{sha}`, `{subject}`, `{branch}`, `{repo}`,
+    /// `{session_id}`, and `{files}` replaced
+    pub body: serde_json::Value,
+    /// Seconds after which a request is given up
+    pub timeout_secs: u64,
+    /// Whether a failed request is retried once
+    pub retry: bool,
+}
+
+/// How the user is notified
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyMethod {
+    /// Not at all
+    Off,
+    /// With a desktop notification
+    Desktop,
+    /// By running `command`
+    Command,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct DiffConfig {
+    pub max_chars: usize,
+    pub summary_threshold: usize,
+    pub summary_full_patches: usize,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct Cache {
+    pub enabled: bool,
+    pub max_entries: usize,
+    pub max_age_days: u64,
+}
+
+/// Files left out of commits and of the diff the message is generated from, by glob patterns over
+/// their paths relative to the repository root
+#[derive(Deserialize, JsonSchema)]
+pub struct Paths {
+    /// Vendored and generated files, built in; set to an empty list to exclude none
+    pub default_exclude: Vec<String>,
+    /// Files excluded whatever `include` says
+    pub exclude: Vec<String>,
+    /// Files committed even though `default_exclude` matches them
+    pub include: Vec<String>,
+}
+
+impl Paths {
+    /// Checks whether a changed file is excluded, `exclude` first, then `default_exclude` unless
+    /// `include` matches the file
+    ///
+    /// # Arguments
+    /// * `path` - The path of the file relative to the root of the repository
+    ///
+    /// # Returns
+    /// Why the file is excluded, or `None` if it isn't
+    pub fn exclusion(&self, path: &Path) -> Option<&'static str> {
+        let matches = |patterns: &[String]| {
+            patterns.iter().any(|pattern| {
+                GlobBuilder::new(pattern)
+                    .literal_separator(true)
+                    .build()
+                    .is_ok_and(|glob| glob.compile_matcher().is_match(path))
+            })
+        };
+        if matches(&self.exclude) {
+            Some("matches paths.exclude")
+        } else if matches(&self.default_exclude) && !matches(&self.include) {
+            Some("matches paths.default_exclude; add it to paths.include to commit it")
+        } else {
+            None
+        }
+    }
+
+    /// Checks whether a changed file is excluded
+    pub fn excludes(&self, path: &Path) -> bool {
+        self.exclusion(path).is_some()
+    }
+}
+
+/// Limits on the commits made in a repository, against sessions looping on an edit
+#[derive(Deserialize, JsonSchema)]
+pub struct RateLimit {
+    /// The maximum number of commits per minute, 0 for no limit
+    pub per_minute: u32,
+    /// The maximum number of commits per hour, 0 for no limit
+    pub per_hour: u32,
+}
+
+/// Controls in which contexts generated messages may contain a body
+#[derive(Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BodyPolicy {
+    Never,
+    SessionEndOnly,
+    Always,
+}
+
+impl BodyPolicy {
+    pub fn allows_body(self, context: CommitContext) -> bool {
+        match self {
+            BodyPolicy::Never => false,
+            BodyPolicy::SessionEndOnly => context != CommitContext::PerFile,
+            BodyPolicy::Always => true,
+        }
+    }
+}
+
+/// Prefix of the environment variables overriding settings
+const ENV_PREFIX: &str = "CC_AUTO_COMMIT_";
+
+/// Environment variables read as command line arguments rather than as settings
+const ARGUMENT_ENVS: &[&str] = &[
+    "CC_AUTO_COMMIT_LANGUAGE",
+    "CC_AUTO_COMMIT_PROFILE",
+    "CC_AUTO_COMMIT_FOREGROUND",
+    crate::logging::LOG_ENV,
+];
+
+/// Settings that were renamed, by their former and current dotted paths; the former ones are
+/// still read from the config files and environment variables, with a warning
+const RENAMED_SETTINGS: &[(&str, &str)] = &[("branches.base", "base_branches")];
+
+/// Tables mapping free names to values, which environment variables can't set
+const MAP_SETTINGS: &[&str] = &[
+    "generator.env",
+    "validation.corrections",
+    "localization",
+    "triggers",
+    "mcp_tools",
+    "secrets.patterns",
+    "prompt.templates",
+    "webhook.headers",
+    "webhook.body",
+];
+
+/// The configuration embedded in the binary, which every other layer is merged onto
+const DEFAULT_CONFIG: &str = include_str!("../assets/commit-config.toml");
+
+static LOADED: OnceLock<LoadedConfig> = OnceLock::new();
+
+/// The effective configuration, as loaded by `init`
+pub static CONFIG: LazyLock<&'static Config> = LazyLock::new(|| &loaded().config);
+
+/// A configuration file or other source of settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// The defaults embedded in the binary
+    Default,
+    /// The user-level config file at the given path
+    User(PathBuf),
+    /// The profile of the given name in the user-level config file at the given path
+    Profile(String, PathBuf),
+    /// The repository-level config file at the given path
+    Repo(PathBuf),
+    /// The environment variable of the given name
+    Environment(String),
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::User(path) => write!(f, "user config {}", path.display()),
+            Origin::Profile(name, path) => {
+                write!(f, "profile {name} in user config {}", path.display())
+            }
+            Origin::Repo(path) => write!(f, "repository config {}", path.display()),
+            Origin::Environment(name) => write!(f, "environment variable {name}"),
+        }
+    }
+}
+
+/// A setting emptied by a higher layer, hiding the value of a lower one
+pub struct Shadowing {
+    /// The dotted path of the setting
+    pub key: String,
+    /// The layer setting the empty string
+    pub origin: Origin,
+    /// The hidden value
+    pub value: Value,
+    /// The layer the hidden value comes from
+    pub shadowed: Origin,
+}
+
+/// The effective configuration along with where each setting comes from
+///
+/// The layers are merged in this order, each overriding the previous ones: the embedded defaults,
+/// the user-level config file, the selected profile in it, the repository-level config file, and
+/// the environment variables.
+/// The `--language` flag is applied on top by `Language::resolve`.
+pub struct LoadedConfig {
+    pub config: Config,
+    /// The merged settings, as written in the config files
+    pub values: Table,
+    /// The origin of every setting, keyed by its dotted path, e.g., `generator.timeout_secs`
+    pub origins: BTreeMap<String, Origin>,
+    /// Settings a layer set to an empty string while a lower layer had a value
+    pub shadowings: Vec<Shadowing>,
+    /// The name of the applied profile, if any
+    pub profile: Option<String>,
+}
+
+impl LoadedConfig {
+    /// Location of the repository-level config file, relative to the working tree root
+    pub const REPO_PATH: &str = ".claude/auto-commit.toml";
+
+    /// Gets the path of the user-level config file, e.g., `$XDG_CONFIG_HOME/c/config.toml` on
+    /// Linux, or `None` if the home directory can't be determined
+    pub fn user_path() -> Option<PathBuf> {
+        ProjectDirs::from("", "", "c").map(|dirs| dirs.config_dir().join("config.toml"))
+    }
+
+    /// Loads the embedded defaults with the user-level config file, the selected profile, and the
+    /// repository-level config file merged onto them
+    ///
+    /// The command settings of the repository-level file are ignored unless trusted, as the file
+    /// comes with the repository and may have been written by anyone.
+    ///
+    /// # Arguments
+    /// * `repo` - The repository to load the repository-level config file of, if any
+    /// * `profile` - The `[profile.<name>]` of the user-level file to apply, overriding the one the
+    ///   repository-level file pins, if any
+    ///
+    /// # Returns
+    /// The effective configuration, or an error if a config file can't be read or parsed, or the
+    /// profile isn't defined
+    pub fn load(repo: Option<&git2::Repository>, profile: Option<&str>) -> Result<Self> {
+        let mut loaded = Self::defaults();
+        let mut layers = Vec::new();
+        let mut profiles = Table::new();
+        if let Some(path) = Self::user_path()
+            && let Some(mut layer) = read_layer(&path)?
+        {
+            if let Some(value) = layer.remove("profile") {
+                let Value::Table(table) = value else {
+                    bail!("`profile` in {} must be a table of profiles", path.display());
+                };
+                profiles = table;
+            }
+            layers.push((layer, Origin::User(path)));
+        }
+
+        let mut repo_layer = None;
+        let mut pinned = None;
+        if let Some(repo) = repo
+            && let Some(workdir) = repo.workdir()
+        {
+            let path = workdir.join(Self::REPO_PATH);
+            if let Some(mut layer) = read_layer(&path)? {
+                let settings = command_settings(&layer);
+                let trusted = read_to_string(trust_path(repo))
+                    .is_ok_and(|record| record.trim() == fingerprint(&settings));
+                if !settings.is_empty() && !trusted {
+                    warn!(
+                        "Ignoring {} in {}; run `ccc trust` to allow it",
+                        settings.keys().cloned().collect::<Vec<_>>().join(", "),
+                        path.display()
+                    );
+                    for key in settings.keys() {
+                        let (section, name) = key.split_once('.').expect("keys are dotted");
+                        if let Some(Value::Table(section)) = layer.get_mut(section) {
+                            section.remove(name);
+                        }
+                    }
+                }
+                // A repository can't let itself in
+                if layer.remove("repos").is_some() {
+                    warn!("Ignoring [repos] in {}; set it in the user config", path.display());
+                }
+                if let Some(value) = layer.remove("profile") {
+                    let Value::String(name) = value else {
+                        bail!("`profile` in {} must be the name of a profile", path.display());
+                    };
+                    pinned = Some(name);
+                }
+                repo_layer = Some((layer, Origin::Repo(path)));
+            }
+        }
+
+        // The profile overrides the rest of the user-level file, but not the repository's settings
+        loaded.profile = profile.map(str::to_string).or(pinned);
+        if let Some(name) = &loaded.profile {
+            let Some(Value::Table(settings)) = profiles.remove(name) else {
+                bail!(
+                    "Unknown profile {name:?}; available profiles: {}",
+                    match profiles.keys().cloned().collect::<Vec<_>>().join(", ") {
+                        names if names.is_empty() => "none".to_string(),
+                        names => names,
+                    }
+                );
+            };
+            let path = Self::user_path().expect("profiles come from the user-level file");
+            layers.push((settings, Origin::Profile(name.clone(), path)));
+        }
+        layers.extend(repo_layer);
+        loaded.resolve(layers, vars())?;
+
+        for name in loaded.config.triggers.keys() {
+            match name.parse::<ToolName>() {
+                Ok(ToolName::Mcp(_)) => {
+                    warn!("Ignoring MCP tool {name:?} in triggers; map it in mcp_tools instead")
+                }
+                Ok(_) => {}
+                Err(_) => warn!("Ignoring unknown tool {name:?} in triggers"),
+            }
+        }
+        for pattern in loaded.config.protected_branches.iter().flatten() {
+            if let Err(e) = GlobBuilder::new(pattern).build() {
+                warn!("Ignoring invalid pattern {pattern:?} in protected_branches: {e}");
+            }
+        }
+        for pattern in &loaded.config.large_files {
+            if let Err(e) = GlobBuilder::new(pattern).build() {
+                warn!("Ignoring invalid pattern {pattern:?} in large_files: {e}");
+            }
+        }
+        for pattern in &loaded.config.conflict_markers.allow {
+            if let Err(e) = GlobBuilder::new(pattern).build() {
+                warn!("Ignoring invalid pattern {pattern:?} in conflict_markers.allow: {e}");
+            }
+        }
+        let paths = &loaded.config.paths;
+        for (key, patterns) in [
+            ("default_exclude", &paths.default_exclude),
+            ("exclude", &paths.exclude),
+            ("include", &paths.include),
+        ] {
+            for pattern in patterns {
+                if let Err(e) = GlobBuilder::new(pattern).build() {
+                    warn!("Ignoring invalid pattern {pattern:?} in paths.{key}: {e}");
+                }
+            }
+        }
+        for pattern in loaded.config.mcp_tools.keys() {
+            if let Err(e) = GlobBuilder::new(pattern).build() {
+                warn!("Ignoring invalid pattern {pattern:?} in mcp_tools: {e}");
+            }
+        }
+        Ok(loaded)
+    }
+
+    /// Merges the layers of settings onto these ones in order, then the environment variables
+    ///
+    /// # Arguments
+    /// * `layers` - The config files, or parts of them, with where they come from
+    /// * `vars` - The environment variables; those not starting with `ENV_PREFIX` are ignored
+    ///
+    /// # Returns
+    /// `Ok(())` on success, or an error naming the layer or variable with an invalid setting
+    fn resolve(
+        &mut self,
+        layers: Vec<(Table, Origin)>,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<()> {
+        for (mut layer, origin) in layers {
+            rename_settings(&mut layer, &origin);
+            let mut leaves = Vec::new();
+            collect_leaves(&layer, "", &mut leaves);
+            for (key, value) in leaves {
+                self.check_shadowing(&key, value, &origin);
+            }
+            merge(&mut self.values, layer, "", &origin, &mut self.origins);
+            self.config = Value::Table(self.values.clone())
+                .try_into()
+                .with_context(|| format!("Invalid setting in {origin}"))?;
+        }
+
+        // The language variable is read through the `--language` argument, as it ranks below the
+        // config files, and the profile one through `--profile`
+        let mut vars = vars
+            .into_iter()
+            .filter(|(name, _)| {
+                name.starts_with(ENV_PREFIX) && !ARGUMENT_ENVS.contains(&name.as_str())
+            })
+            .collect::<Vec<_>>();
+        for (old, new) in RENAMED_SETTINGS
+            .iter()
+            .map(|(old, new)| (env_name(old), env_name(new)))
+        {
+            if let Some(position) = vars.iter().position(|(name, _)| *name == old) {
+                warn!("{old} is now {new}; rename it");
+                let (_, raw) = vars.remove(position);
+                if !vars.iter().any(|(name, _)| *name == new) {
+                    vars.push((new, raw));
+                }
+            }
+        }
+        vars.sort();
+        for (name, raw) in vars {
+            self.apply_env(&name, &raw)?;
+        }
+        Ok(())
+    }
+
+    /// Overrides the setting named by an environment variable, e.g.,
+    /// `CC_AUTO_COMMIT_DIFF_MAX_CHARS` for `diff.max_chars`
+    ///
+    /// The value is parsed as the type of the current value of the setting: integers and floats as
+    /// such, booleans from 1/true/yes or 0/false/no, and arrays as comma-separated items. For
+    /// settings without a value, the first type the configuration accepts is used.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the variable, starting with `ENV_PREFIX`
+    /// * `raw` - The value of the variable
+    ///
+    /// # Returns
+    /// `Ok(())` on success, or an error naming the variable if the value is invalid
+    fn apply_env(&mut self, name: &str, raw: &str) -> Result<()> {
+        let path = env_path(&self.values, &name[ENV_PREFIX.len()..].to_lowercase());
+        let candidates = match get_path(&self.values, &path) {
+            Some(current) => vec![parse_env_value(name, raw, current)?],
+            None => [
+                Value::Integer(0),
+                Value::Float(0.0),
+                Value::Boolean(false),
+                Value::String(String::new()),
+                Value::Array(Vec::new()),
+            ]
+            .iter()
+            .filter_map(|like| parse_env_value(name, raw, like).ok())
+            .collect(),
+        };
+
+        let mut error = None;
+        for value in candidates {
+            let mut values = self.values.clone();
+            set_path(&mut values, &path, value);
+            match Value::Table(values.clone()).try_into() {
+                Ok(config) => {
+                    let origin = Origin::Environment(name.to_string());
+                    if let Some(value) = get_path(&values, &path) {
+                        self.check_shadowing(&path.join("."), value, &origin);
+                    }
+                    self.config = config;
+                    self.values = values;
+                    self.origins.insert(path.join("."), origin);
+                    return Ok(());
+                }
+                Err(e) => error = Some(e),
+            }
+        }
+        match error {
+            Some(e) => Err(e).with_context(|| format!("Invalid value of {name}")),
+            None => bail!("Invalid value of {name}: {raw:?}"),
+        }
+    }
+
+    /// Records a shadowing if the layer empties a setting a lower layer has a value for
+    ///
+    /// # Arguments
+    /// * `key` - The dotted path of the setting
+    /// * `value` - The value the layer sets
+    /// * `origin` - The layer
+    fn check_shadowing(&mut self, key: &str, value: &Value, origin: &Origin) {
+        if value.as_str() != Some("") {
+            return;
+        }
+        let path = key.split('.').map(str::to_string).collect::<Vec<_>>();
+        if let Some(current) = get_path(&self.values, &path)
+            && current.as_str().is_none_or(|current| !current.is_empty())
+            && let Some(shadowed) = self.origins.get(key)
+        {
+            self.shadowings.push(Shadowing {
+                key: key.to_string(),
+                origin: origin.clone(),
+                value: current.clone(),
+                shadowed: shadowed.clone(),
+            });
+        }
+    }
+
+    /// Gets the embedded defaults alone
+    fn defaults() -> Self {
+        let values: Table =
+            from_str(DEFAULT_CONFIG).expect("Failed to parse embedded commit-config.toml");
+        let mut origins = BTreeMap::new();
+        record_origins(&values, "", &Origin::Default, &mut origins);
+        Self {
+            config: Value::Table(values.clone())
+                .try_into()
+                .expect("Failed to parse embedded commit-config.toml"),
+            values,
+            origins,
+            shadowings: Vec::new(),
+            profile: None,
+        }
+    }
+
+    /// Lists every setting as a `key = value` line in dotted notation, sorted by key
+    ///
+    /// # Arguments
+    /// * `with_origin` - Whether to append the origin of each setting as a comment
+    pub fn lines(&self, with_origin: bool) -> Vec<String> {
+        let mut leaves = Vec::new();
+        collect_leaves(&self.values, "", &mut leaves);
+        leaves.sort_by(|(a, _), (b, _)| a.cmp(b));
+        leaves
+            .into_iter()
+            .map(|(key, value)| match self.origins.get(&key).filter(|_| with_origin) {
+                Some(origin) => format!("{key} = {value}  # {origin}"),
+                None => format!("{key} = {value}"),
+            })
+            .collect()
+    }
+}
+
+/// Loads the configuration for this process, reporting errors in config files
+///
+/// Call this before the first use of `CONFIG`. Otherwise, only the embedded defaults are used.
+///
+/// # Arguments
+/// * `repo` - The repository to load the repository-level config file of, if any
+/// * `profile` - The profile selected by the `--profile` flag, if any
+pub fn init(
+    repo: Option<&git2::Repository>,
+    profile: Option<&str>,
+) -> Result<&'static LoadedConfig> {
+    if let Some(loaded) = LOADED.get() {
+        return Ok(loaded);
+    }
+    let loaded = LoadedConfig::load(repo, profile)?;
+    Ok(LOADED.get_or_init(|| loaded))
+}
+
+/// Gets the JSON Schema of the config files, for editors to validate them against
+///
+/// Every setting is optional in the schema, as each file only overrides some of them. `profile` is
+/// a table of profiles in the user-level file, and the name of one in the repository-level file.
+pub fn schema() -> Schema {
+    let mut schema = SchemaSettings::draft07()
+        .with_transform(RecursiveTransform(|schema: &mut Schema| {
+            schema.remove("required");
+        }))
+        .into_generator()
+        .into_root_schema_for::<Config>();
+    if let Some(properties) = schema.get_mut("properties").and_then(|p| p.as_object_mut()) {
+        properties.insert(
+            "profile".to_string(),
+            json!({
+                "description": "Profiles of settings by name, applied with `--profile`, or the \
+                    name of the one the repository applies",
+                "anyOf": [
+                    { "type": "string" },
+                    { "type": "object", "additionalProperties": { "$ref": "#" } },
+                ],
+            }),
+        );
+    }
+    schema
+}
+
+/// Gets the loaded configuration, falling back to the embedded defaults if `init` wasn't called
+pub fn loaded() -> &'static LoadedConfig {
+    LOADED.get_or_init(LoadedConfig::defaults)
+}
+
+/// Maps the lowercase name of an environment variable without `ENV_PREFIX` to a setting path
+///
+/// Underscores separate sections from keys where a section of that name exists, preferring the
+/// longest one, so that `generator_max_tokens` maps to `generator.max_tokens`. Tables mapping free
+/// names to values, such as `generator.env`, can't be set this way.
+fn env_path(values: &Table, name: &str) -> Vec<String> {
+    let mut table = values;
+    let mut path: Vec<String> = Vec::new();
+    let mut rest = name;
+    while table.get(rest).is_none_or(Value::is_table) {
+        let section = table
+            .iter()
+            .filter_map(|(key, value)| Some((key, value.as_table()?)))
+            .filter(|(key, _)| {
+                let dotted = path.iter().chain([*key]).cloned().collect::<Vec<_>>().join(".");
+                rest.strip_prefix(key.as_str()).is_some_and(|r| r.starts_with('_'))
+                    && !MAP_SETTINGS.contains(&dotted.as_str())
+            })
+            .max_by_key(|(key, _)| key.len());
+        let Some((key, section)) = section else {
+            break;
+        };
+        path.push(key.clone());
+        rest = &rest[key.len() + 1..];
+        table = section;
+    }
+    path.push(rest.to_string());
+    path
+}
+
+/// Parses the value of an environment variable as the type of the given value
+fn parse_env_value(name: &str, raw: &str, like: &Value) -> Result<Value> {
+    Ok(match like {
+        Value::String(_) => Value::String(raw.to_string()),
+        Value::Integer(_) => Value::Integer(
+            raw.trim()
+                .parse()
+                .with_context(|| format!("{name} must be an integer, got {raw:?}"))?,
+        ),
+        Value::Float(_) => Value::Float(
+            raw.trim()
+                .parse()
+                .with_context(|| format!("{name} must be a number, got {raw:?}"))?,
+        ),
+        Value::Boolean(_) => Value::Boolean(match raw.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" => true,
+            "0" | "false" | "no" => false,
+            _ => bail!("{name} must be a boolean (1/true/yes or 0/false/no), got {raw:?}"),
+        }),
+        Value::Array(items) => Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| match items.first() {
+                    Some(like) => parse_env_value(name, item, like),
+                    None => Ok(Value::String(item.to_string())),
+                })
+                .collect::<Result<_>>()?,
+        ),
+        Value::Datetime(_) | Value::Table(_) => bail!("{name} can't set a table"),
+    })
+}
+
+/// Moves the settings of a layer that were renamed to their current paths, unless the layer sets
+/// those too
+fn rename_settings(layer: &mut Table, origin: &Origin) {
+    for (old, new) in RENAMED_SETTINGS {
+        let old = old.split('.').map(str::to_string).collect::<Vec<_>>();
+        let (last, sections) = old.split_last().expect("paths are not empty");
+        let Some(section) = sections
+            .iter()
+            .try_fold(&mut *layer, |table, section| table.get_mut(section)?.as_table_mut())
+        else {
+            continue;
+        };
+        let Some(value) = section.remove(last) else {
+            continue;
+        };
+        if let Some(first) = sections.first()
+            && layer
+                .get(first)
+                .and_then(Value::as_table)
+                .is_some_and(Table::is_empty)
+        {
+            layer.remove(first);
+        }
+        warn!("`{}` in {origin} is now `{new}`; rename it", old.join("."));
+        let new = new.split('.').map(str::to_string).collect::<Vec<_>>();
+        if get_path(layer, &new).is_none() {
+            set_path(layer, &new, value);
+        }
+    }
+}
+
+/// Gets the environment variable overriding the setting, e.g., `CC_AUTO_COMMIT_DIFF_MAX_CHARS`
+/// for `diff.max_chars`
+fn env_name(key: &str) -> String {
+    format!("{ENV_PREFIX}{}", key.replace('.', "_").to_uppercase())
+}
+
+/// Gets the value at a setting path
+fn get_path<'a>(values: &'a Table, path: &[String]) -> Option<&'a Value> {
+    let (last, sections) = path.split_last()?;
+    let mut table = values;
+    for section in sections {
+        table = table.get(section)?.as_table()?;
+    }
+    table.get(last)
+}
+
+/// Sets the value at a setting path, creating the sections on the way
+fn set_path(values: &mut Table, path: &[String], value: Value) {
+    let Some((last, sections)) = path.split_last() else {
+        return;
+    };
+    let mut table = values;
+    for section in sections {
+        let entry = table
+            .entry(section.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        if !entry.is_table() {
+            *entry = Value::Table(Table::new());
+        }
+        table = entry.as_table_mut().expect("just made a table");
+    }
+    table.insert(last.clone(), value);
+}
+
+/// Merges a layer of settings onto the base, recording the origin of every setting it sets
+///
+/// Tables are merged key by key; any other value, including arrays, replaces the base value.
+fn merge(
+    base: &mut Table,
+    layer: Table,
+    prefix: &str,
+    origin: &Origin,
+    origins: &mut BTreeMap<String, Origin>,
+) {
+    for (key, value) in layer {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(layer)) => {
+                merge(base, layer, &path, origin, origins)
+            }
+            (_, value) => {
+                match &value {
+                    Value::Table(table) => record_origins(table, &path, origin, origins),
+                    _ => {
+                        origins.insert(path, origin.clone());
+                    }
+                }
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Records the origin of every setting in the table
+fn record_origins(
+    table: &Table,
+    prefix: &str,
+    origin: &Origin,
+    origins: &mut BTreeMap<String, Origin>,
+) {
+    let mut leaves = Vec::new();
+    collect_leaves(table, prefix, &mut leaves);
+    for (key, _) in leaves {
+        origins.insert(key, origin.clone());
+    }
+}
+
+/// Collects the settings in the table that aren't tables themselves, with their dotted paths
+fn collect_leaves<'a>(table: &'a Table, prefix: &str, leaves: &mut Vec<(String, &'a Value)>) {
+    for (key, value) in table {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{prefix}.{key}") };
+        match value {
+            Value::Table(table) => collect_leaves(table, &path, leaves),
+            value => leaves.push((path, value)),
+        }
+    }
+}
+
+/// Settings of the repository-level config file that make the tool run commands, send data and
+/// credentials elsewhere, or write files outside the repository, which are honored only once the
+/// file is trusted with `ccc trust`
+const COMMAND_SETTINGS: &[&str] = &[
+    "generator.command",
+    "generator.args",
+    "generator.extra_args",
+    "generator.env",
+    "post_process.command",
+    "post_process.args",
+    "install.extra_args",
+    "notify.method",
+    "notify.command",
+    "notify.args",
+    "webhook.url",
+    "webhook.headers",
+    "pull_request.api_url",
+    "pull_request.token",
+    "audit.path",
+];
+
+/// Extracts the command settings from a layer of settings
+fn command_settings(layer: &Table) -> Table {
+    let mut settings = Table::new();
+    for key in COMMAND_SETTINGS {
+        let (section, name) = key.split_once('.').expect("command settings are in a section");
+        if let Some(value) = layer.get(section).and_then(|s| s.get(name)) {
+            settings.insert(key.to_string(), value.clone());
+        }
+    }
+    settings
+}
+
+/// Gets the file recording the trusted command settings of the repository-level config file
+fn trust_path(repo: &git2::Repository) -> PathBuf {
+    state_dir(repo).join("trusted-config")
+}
+
+/// Computes the fingerprint of the command settings recorded when trusting them
+fn fingerprint(settings: &Table) -> String {
+    MessageCache::key(&[&settings.to_string()])
+}
+
+/// Trusts the command settings currently in the repository-level config file
+///
+/// # Arguments
+/// * `repo` - The repository whose config file to trust
+///
+/// # Returns
+/// The trusted settings as dotted keys, empty if there are none. Returns an error if the file
+/// can't be parsed or the record can't be written.
+pub fn trust(repo: &git2::Repository) -> Result<Vec<String>> {
+    let workdir = repo
+        .workdir()
+        .context("Repository has no working directory (bare repo?)")?;
+    let layer = read_layer(&workdir.join(LoadedConfig::REPO_PATH))?.unwrap_or_default();
+    let settings = command_settings(&layer);
+    create_dir_all(state_dir(repo))?;
+    write(trust_path(repo), fingerprint(&settings))?;
+    Ok(settings.keys().cloned().collect())
+}
+
+/// Reads a config file as a layer of settings
+///
+/// # Returns
+/// The settings, `None` if the file doesn't exist, or an error if it can't be read or parsed
+fn read_layer(path: &Path) -> Result<Option<Table>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    from_str(&read_to_string(path)?)
+        .map(Some)
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Where the effective commit message language was taken from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LanguageSource {
+    /// The `language` key of the repository-level config file at the given path
+    RepoConfig(PathBuf),
+    /// The `language` key of the user-level config file at the given path
+    UserConfig(PathBuf),
+    /// The `--language` command line flag, e.g., baked into the installed hook command
+    CommandLine,
+    /// The `CC_AUTO_COMMIT_LANGUAGE` environment variable
+    Environment,
+    /// The system locale in the environment variable of the given name, e.g., `LANG`
+    Locale(String),
+    /// The built-in default
+    Default,
+}
+
+impl Display for LanguageSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LanguageSource::RepoConfig(path) => write!(f, "repository config {}", path.display()),
+            LanguageSource::UserConfig(path) => write!(f, "user config {}", path.display()),
+            LanguageSource::CommandLine => write!(f, "--language flag"),
+            LanguageSource::Environment => write!(f, "CC_AUTO_COMMIT_LANGUAGE"),
+            LanguageSource::Locale(name) => write!(f, "locale in {name}"),
+            LanguageSource::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// The language to use for commit messages, along with where it was taken from
+#[derive(Debug, Clone)]
+pub struct Language {
+    pub name: String,
+    pub source: LanguageSource,
+}
+
+impl Language {
+    /// The language used when nothing else gives one
+    const DEFAULT: &str = "English";
+
+    /// Variables of the system locale, in order of precedence
+    const LOCALE_VARS: &[&str] = &["LC_ALL", "LC_MESSAGES", "LANG"];
+
+    /// Creates a language from the parsed `--language` argument
+    ///
+    /// # Arguments
+    /// * `name` - The parsed value of the argument, if any
+    /// * `value_source` - Where clap took the value from
+    pub fn from_arg(name: Option<&str>, value_source: Option<ValueSource>) -> Self {
+        let source = match value_source {
+            Some(ValueSource::CommandLine) => LanguageSource::CommandLine,
+            Some(ValueSource::EnvVariable) => LanguageSource::Environment,
+            _ => LanguageSource::Default,
+        };
+        Self {
+            name: name.unwrap_or(Self::DEFAULT).to_string(),
+            source,
+        }
+    }
+
+    /// Resolves the effective language, taking the first of the `--language` flag, the
+    /// repository-level config file, the user-level config file, the `CC_AUTO_COMMIT_LANGUAGE`
+    /// environment variable, the system locale, and English
+    ///
+    /// # Returns
+    /// The effective language
+    pub fn resolve(&self) -> Self {
+        self.resolve_in(loaded(), |name| var(name).ok())
+    }
+
+    /// Resolves the effective language as `resolve` does, from the given settings and locale
+    ///
+    /// # Arguments
+    /// * `loaded` - The settings, along with the files they were taken from
+    /// * `var` - Gets the value of an environment variable of the system locale
+    fn resolve_in(&self, loaded: &LoadedConfig, var: impl Fn(&str) -> Option<String>) -> Self {
+        if self.source == LanguageSource::CommandLine {
+            return self.clone();
+        }
+        if let Some(name) = &loaded.config.language {
+            match loaded.origins.get("language") {
+                Some(Origin::Repo(path)) => {
+                    return Self {
+                        name: name.clone(),
+                        source: LanguageSource::RepoConfig(path.clone()),
+                    };
+                }
+                Some(Origin::User(path) | Origin::Profile(_, path)) => {
+                    return Self {
+                        name: name.clone(),
+                        source: LanguageSource::UserConfig(path.clone()),
+                    };
+                }
+                _ => {}
+            }
+        }
+        if self.source == LanguageSource::Environment {
+            return self.clone();
+        }
+        Self::from_locale(var).unwrap_or_else(|| Self {
+            name: Self::DEFAULT.to_string(),
+            source: LanguageSource::Default,
+        })
+    }
+
+    /// Detects the language from the first set variable of the system locale
+    fn from_locale(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        let (name, locale) = Self::LOCALE_VARS
+            .iter()
+            .find_map(|name| Some((*name, var(name).filter(|value| !value.is_empty())?)))?;
+        Some(Self {
+            name: language_of_locale(&locale)?.to_string(),
+            source: LanguageSource::Locale(name.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gets a value of the type of the setting, different for each layer, or the default itself
+    /// where no other value is valid, e.g., for a choice among names
+    ///
+    /// # Arguments
+    /// * `key` - The dotted path of the setting
+    /// * `default` - The embedded default of the setting
+    /// * `layer` - The number of the layer, from 1
+    fn layered(key: &str, default: &Value, layer: i64) -> Value {
+        let value = match default {
+            Value::Integer(n) => Value::Integer(n + layer),
+            Value::Float(x) => Value::Float(x + layer as f64 / 4.0),
+            Value::Boolean(b) => Value::Boolean(*b ^ (layer % 2 == 1)),
+            Value::String(_) => Value::String(format!("{key}-{layer}")),
+            Value::Array(items) => Value::Array(vec![match items.first() {
+                Some(item) => layered(key, item, layer),
+                None => Value::String(format!("{key}-{layer}")),
+            }]),
+            value => value.clone(),
+        };
+        let mut values = LoadedConfig::defaults().values;
+        set_path(&mut values, &path(key), value.clone());
+        match Value::Table(values).try_into::<Config>() {
+            Ok(_) => value,
+            Err(_) => default.clone(),
+        }
+    }
+
+    /// Splits the dotted path of a setting
+    fn path(key: &str) -> Vec<String> {
+        key.split('.').map(str::to_string).collect()
+    }
+
+    /// Creates a layer setting only the setting to the value
+    fn layer(key: &str, value: &Value) -> Table {
+        let mut layer = Table::new();
+        set_path(&mut layer, &path(key), value.clone());
+        layer
+    }
+
+    /// Gets the environment variable of the setting, with the value in its syntax
+    fn env_var(key: &str, value: &Value) -> (String, String) {
+        fn raw(value: &Value) -> String {
+            match value {
+                Value::String(s) => s.clone(),
+                Value::Array(items) => items.iter().map(raw).collect::<Vec<_>>().join(","),
+                value => value.to_string(),
+            }
+        }
+        (env_name(key), raw(value))
+    }
+
+    /// Checks whether an environment variable can set the setting, i.e., it isn't in a table
+    /// mapping free names to values
+    fn settable_by_env(key: &str) -> bool {
+        !MAP_SETTINGS
+            .iter()
+            .any(|map| key.strip_prefix(map).is_some_and(|rest| rest.starts_with('.')))
+    }
+
+    /// The embedded defaults as dotted paths and values
+    fn default_leaves() -> Vec<(String, Value)> {
+        let defaults = LoadedConfig::defaults();
+        let mut leaves = Vec::new();
+        collect_leaves(&defaults.values, "", &mut leaves);
+        leaves.into_iter().map(|(key, value)| (key, value.clone())).collect()
+    }
+
+    #[test]
+    fn every_setting_is_taken_from_each_layer() {
+        let user = Origin::User(PathBuf::from("/home/dev/.config/c/config.toml"));
+        let repo = Origin::Repo(PathBuf::from("/project/.claude/auto-commit.toml"));
+        for (key, default) in default_leaves() {
+            for (number, origin) in [(1, &user), (2, &repo)] {
+                let value = layered(&key, &default, number);
+                let mut loaded = LoadedConfig::defaults();
+                loaded
+                    .resolve(vec![(layer(&key, &value), origin.clone())], [])
+                    .unwrap_or_else(|e| panic!("{key}: {e:#}"));
+                assert_eq!(get_path(&loaded.values, &path(&key)), Some(&value), "{key}");
+                assert_eq!(loaded.origins.get(&key), Some(origin), "{key}");
+            }
+
+            if settable_by_env(&key) {
+                let value = layered(&key, &default, 3);
+                let (name, raw) = env_var(&key, &value);
+                let mut loaded = LoadedConfig::defaults();
+                loaded
+                    .resolve(Vec::new(), [(name.clone(), raw)])
+                    .unwrap_or_else(|e| panic!("{name}: {e:#}"));
+                assert_eq!(get_path(&loaded.values, &path(&key)), Some(&value), "{name}");
+                assert_eq!(loaded.origins.get(&key), Some(&Origin::Environment(name)));
+            }
+        }
+    }
+
+    #[test]
+    fn higher_layers_override_lower_ones() {
+        let user = Origin::User(PathBuf::from("/home/dev/.config/c/config.toml"));
+        let repo = Origin::Repo(PathBuf::from("/project/.claude/auto-commit.toml"));
+        for (key, default) in default_leaves() {
+            let layers = vec![
+                (layer(&key, &layered(&key, &default, 1)), user.clone()),
+                (layer(&key, &layered(&key, &default, 2)), repo.clone()),
+            ];
+            let (value, origin, vars) = if settable_by_env(&key) {
+                let value = layered(&key, &default, 3);
+                let (name, raw) = env_var(&key, &value);
+                (value, Origin::Environment(name.clone()), vec![(name, raw)])
+            } else {
+                (layered(&key, &default, 2), repo.clone(), Vec::new())
+            };
+
+            let mut loaded = LoadedConfig::defaults();
+            loaded
+                .resolve(layers, vars)
+                .unwrap_or_else(|e| panic!("{key}: {e:#}"));
+            assert_eq!(get_path(&loaded.values, &path(&key)), Some(&value), "{key}");
+            assert_eq!(loaded.origins.get(&key), Some(&origin), "{key}");
+        }
+    }
+
+    #[test]
+    fn untouched_settings_keep_their_defaults() {
+        let mut loaded = LoadedConfig::defaults();
+        loaded
+            .resolve(
+                vec![(
+                    layer("diff.max_chars", &Value::Integer(1000)),
+                    Origin::Repo(PathBuf::from("/project/.claude/auto-commit.toml")),
+                )],
+                [("CC_AUTO_COMMIT_DIFF_SUMMARY_THRESHOLD".to_string(), "5".to_string())],
+            )
+            .unwrap();
+
+        for (key, default) in default_leaves() {
+            if key == "diff.max_chars" || key == "diff.summary_threshold" {
+                continue;
+            }
+            assert_eq!(get_path(&loaded.values, &path(&key)), Some(&default), "{key}");
+            assert_eq!(loaded.origins.get(&key), Some(&Origin::Default), "{key}");
+        }
+        assert_eq!(loaded.config.diff.max_chars, 1000);
+        assert_eq!(loaded.config.diff.summary_threshold, 5);
+    }
+
+    /// Creates the default generator settings with the arguments, model, and extra arguments
+    fn generator(args: &[&str], model: Option<&str>, extra_args: &[&str]) -> Generator {
+        let mut generator = LoadedConfig::defaults().config.generator;
+        generator.args = args.iter().map(|arg| arg.to_string()).collect();
+        generator.model = model.map(str::to_string);
+        generator.extra_args = extra_args.iter().map(|arg| arg.to_string()).collect();
+        generator
+    }
+
+    #[test]
+    fn model_and_extra_args_are_appended() {
+        for (args, model, extra_args, expected) in [
+            (&["-p"][..], None, &[][..], &["-p"][..]),
+            (&["-p"], Some("opus"), &[], &["-p", "--model", "opus"]),
+            (&["-p"], None, &["--verbose"], &["-p", "--verbose"]),
+            (
+                &["-p"],
+                Some("opus"),
+                &["--max-turns", "3"],
+                &["-p", "--model", "opus", "--max-turns", "3"],
+            ),
+            // Values are not flags, and flags are told apart from those they start like
+            (&["-p", "3"], None, &["--max-turns", "3"], &["-p", "3", "--max-turns", "3"]),
+            (&["--models"], Some("opus"), &[], &["--models", "--model", "opus"]),
+        ] {
+            let resolved = generator(args, model, extra_args).resolved_args().unwrap();
+            assert_eq!(resolved, expected, "{args:?} {model:?} {extra_args:?}");
+        }
+    }
+
+    #[test]
+    fn flags_given_twice_are_rejected() {
+        for (args, model, extra_args) in [
+            (&["-p", "--model", "sonnet"][..], Some("opus"), &[][..]),
+            (&["-p", "--model=sonnet"], Some("opus"), &[]),
+            (&["-p", "--verbose"], None, &["--verbose"]),
+            (&["-p", "--max-turns", "3"], None, &["--max-turns=5"]),
+            (&["-p", "--max-turns=3"], None, &["--max-turns", "5"]),
+            (&["-p"], Some("opus"), &["--model", "sonnet"]),
+        ] {
+            let resolved = generator(args, model, extra_args).resolved_args();
+            assert!(resolved.is_err(), "{args:?} {model:?} {extra_args:?}");
+        }
+    }
+
+    #[test]
+    fn max_tokens_set_twice_is_rejected() {
+        let mut generator = generator(&["-p"], None, &[]);
+        generator.max_tokens = Some(1024);
+        assert!(generator.resolved_args().is_ok());
+
+        generator
+            .env
+            .insert(Generator::MAX_TOKENS_ENV.to_string(), "2048".to_string());
+        assert!(generator.resolved_args().is_err());
+    }
+
+    #[test]
+    fn language_is_taken_from_the_first_source_giving_one() {
+        let (repo, user) =
+            (PathBuf::from("/repo/.claude/auto-commit.toml"), PathBuf::from("/user"));
+        let flag = Language::from_arg(Some("French"), Some(ValueSource::CommandLine));
+        let env = Language::from_arg(Some("Korean"), Some(ValueSource::EnvVariable));
+        let default = Language::from_arg(None, Some(ValueSource::DefaultValue));
+        let configured = |origin: Option<Origin>| {
+            let mut loaded = LoadedConfig::defaults();
+            if let Some(origin) = origin {
+                loaded.config.language = Some("German".to_string());
+                loaded.origins.insert("language".to_string(), origin);
+            }
+            loaded
+        };
+        let locale = |vars: &'static [(&str, &str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        for (language, origin, vars, name, source) in [
+            (
+                &flag,
+                Some(Origin::Repo(repo.clone())),
+                &[("LANG", "ja_JP.UTF-8")][..],
+                "French",
+                LanguageSource::CommandLine,
+            ),
+            (
+                &env,
+                Some(Origin::Repo(repo.clone())),
+                &[],
+                "German",
+                LanguageSource::RepoConfig(repo.clone()),
+            ),
+            (
+                &env,
+                Some(Origin::User(user.clone())),
+                &[],
+                "German",
+                LanguageSource::UserConfig(user.clone()),
+            ),
+            (
+                &env,
+                Some(Origin::Profile("work".to_string(), user.clone())),
+                &[],
+                "German",
+                LanguageSource::UserConfig(user.clone()),
+            ),
+            (&env, None, &[("LANG", "ja_JP.UTF-8")], "Korean", LanguageSource::Environment),
+            (
+                &default,
+                Some(Origin::Default),
+                &[("LANG", "ja_JP.UTF-8")],
+                "Japanese",
+                LanguageSource::Locale("LANG".to_string()),
+            ),
+            (
+                &default,
+                None,
+                &[("LC_ALL", "de_DE.UTF-8"), ("LANG", "ja_JP.UTF-8")],
+                "German",
+                LanguageSource::Locale("LC_ALL".to_string()),
+            ),
+            (
+                &default,
+                None,
+                &[("LC_ALL", ""), ("LC_MESSAGES", "fr_FR"), ("LANG", "ja_JP")],
+                "French",
+                LanguageSource::Locale("LC_MESSAGES".to_string()),
+            ),
+            (
+                &default,
+                None,
+                &[("LANG", "C")],
+                "English",
+                LanguageSource::Locale("LANG".to_string()),
+            ),
+            (&default, None, &[("LANG", "xx_YY")], "English", LanguageSource::Default),
+            (&default, None, &[], "English", LanguageSource::Default),
+        ] {
+            let resolved = language.resolve_in(&configured(origin.clone()), locale(vars));
+            assert_eq!(resolved.name, name, "{:?} {origin:?} {vars:?}", language.source);
+            assert_eq!(resolved.source, source, "{:?} {origin:?} {vars:?}", language.source);
+        }
+    }
+
+    /// Creates the default prompt settings with the templates of each context and a Japanese one
+    fn prompt(per_file: Option<&str>, session: Option<&str>) -> Prompt {
+        let mut prompt = LoadedConfig::defaults().config.prompt;
+        prompt.template = "general".to_string();
+        prompt.per_file_template = per_file.map(str::to_string);
+        prompt.session_template = session.map(str::to_string);
+        prompt.templates.insert("ja".to_string(), "japanese".to_string());
+        prompt
+    }
+
+    #[test]
+    fn template_of_the_context_takes_precedence() {
+        let prompt = prompt(Some("per-file"), Some("session"));
+
+        for language in ["English", "Japanese"] {
+            assert_eq!(prompt.template_for(CommitContext::PerFile, language), "per-file");
+            assert_eq!(prompt.template_for(CommitContext::SessionEnd, language), "session");
+        }
+        assert_eq!(prompt.template_for(CommitContext::Batch, "English"), "general");
+        assert_eq!(prompt.template_for(CommitContext::Standalone, "English"), "general");
+        assert_eq!(prompt.template_for(CommitContext::Batch, "Japanese"), "japanese");
+    }
+
+    #[test]
+    fn template_falls_back_to_the_language_then_the_general_one() {
+        let prompt = prompt(None, None);
+
+        for context in [
+            CommitContext::PerFile,
+            CommitContext::SessionEnd,
+            CommitContext::Batch,
+            CommitContext::Standalone,
+        ] {
+            for language in ["ja", "Japanese", "ja_JP.UTF-8", "日本語"] {
+                assert_eq!(prompt.template_for(context, language), "japanese", "{language}");
+            }
+            for language in ["en", "English", "French"] {
+                assert_eq!(prompt.template_for(context, language), "general", "{language}");
+            }
+        }
+    }
+
+    #[test]
+    fn template_of_the_language_is_found_in_any_case() {
+        let mut prompt = prompt(None, None);
+        prompt.templates.clear();
+        prompt
+            .templates
+            .insert("JAPANESE".to_string(), "japanese".to_string());
+        prompt.templates.insert("Fr".to_string(), "french".to_string());
+
+        for (language, expected) in [
+            ("Japanese", "japanese"),
+            ("japanese", "japanese"),
+            ("JA", "japanese"),
+            ("ja_JP.UTF-8", "japanese"),
+            ("fr", "french"),
+            ("FR", "french"),
+            ("French", "general"),
+            ("English", "general"),
+        ] {
+            assert_eq!(
+                prompt.template_for(CommitContext::PerFile, language),
+                expected,
+                "{language}"
+            );
+        }
+    }
+
+    #[test]
+    fn template_keyed_by_the_language_as_given_is_preferred() {
+        let mut prompt = prompt(None, None);
+        prompt.templates.insert("JA".to_string(), "upper".to_string());
+        prompt.templates.insert("Japanese".to_string(), "name".to_string());
+
+        for (language, expected) in [
+            ("ja", "japanese"),
+            ("JA", "upper"),
+            ("Japanese", "name"),
+            // The first key in order, as none is the language as given
+            ("japanese", "upper"),
+        ] {
+            assert_eq!(
+                prompt.template_for(CommitContext::PerFile, language),
+                expected,
+                "{language}"
+            );
+        }
+    }
+
+    #[test]
+    fn renamed_settings_are_still_read() {
+        let origin = Origin::User(PathBuf::from("/user"));
+        let resolved = |layer: &str, vars: &[(&str, &str)]| {
+            let mut loaded = LoadedConfig::defaults();
+            let vars = vars.iter().map(|(name, raw)| (name.to_string(), raw.to_string()));
+            loaded
+                .resolve(vec![(from_str(layer).unwrap(), origin.clone())], vars)
+                .unwrap();
+            loaded
+        };
+
+        let loaded = resolved("[branches]\nbase = [\"trunk\"]", &[]);
+        assert_eq!(loaded.config.base_branches, ["trunk"]);
+        assert_eq!(loaded.origins.get("base_branches"), Some(&origin));
+        assert!(!loaded.values.contains_key("branches"));
+
+        let loaded = resolved("base_branches = [\"main\"]\n[branches]\nbase = [\"trunk\"]", &[]);
+        assert_eq!(loaded.config.base_branches, ["main"]);
+
+        let loaded = resolved("", &[("CC_AUTO_COMMIT_BRANCHES_BASE", "trunk,main")]);
+        assert_eq!(loaded.config.base_branches, ["trunk", "main"]);
+        assert_eq!(
+            loaded.origins.get("base_branches"),
+            Some(&Origin::Environment("CC_AUTO_COMMIT_BASE_BRANCHES".to_string()))
+        );
+
+        let loaded = resolved(
+            "",
+            &[("CC_AUTO_COMMIT_BRANCHES_BASE", "trunk"), ("CC_AUTO_COMMIT_BASE_BRANCHES", "main")],
+        );
+        assert_eq!(loaded.config.base_branches, ["main"]);
+    }
+
+    #[test]
+    fn populated_config_validates_against_the_schema() {
+        let mut values: Table = from_str(DEFAULT_CONFIG).unwrap();
+        let populated: Table = from_str(
+            r#"
+            language = "Japanese"
+            protected_branches = ["main", "release/*"]
+
+            [generator]
+            model = "sonnet"
+            max_tokens = 1024
+            default_commit_message = "WARNING: {files} ({stats})"
+
+            [prompt]
+            per_file_template = "{diff_content}"
+            session_template = "{conversation_summary}\n{diff_content}"
+
+            [prompt.templates]
+            ja = "{diff_content}"
+
+            [notify]
+            method = "command"
+            command = "notify-send"
+            args = ["--urgency=low", "{subject}"]
+            coalesce_secs = 30
+
+            [webhook]
+            url = "https://hooks.example.com/commits"
+            timeout_secs = 10
+            retry = true
+
+            [webhook.headers]
+            Authorization = "Bearer 0123abcd"
+
+            [webhook.body]
+            text = "{subject} on {branch}"
+
+            [profile.work]
+            language = "English"
+            base_branches = ["trunk"]
+
+            [profile.work.generator]
+            model = "opus"
+            "#,
+        )
+        .unwrap();
+        merge(&mut values, populated, "", &Origin::Default, &mut BTreeMap::new());
+        let schema = serde_json::to_value(schema()).unwrap();
+        let validator = jsonschema::draft7::new(&schema).unwrap();
+
+        // The settings, and where the schema finds them invalid, if it does
+        for (instance, invalid) in [
+            (values.clone(), None),
+            (from_str(r#"profile = "work""#).unwrap(), None),
+            (from_str("[notify]\nmethod = \"email\"").unwrap(), Some("/notify/method")),
+            (from_str("[webhook.headers]\nRetries = 3").unwrap(), Some("/webhook/headers/Retries")),
+            // Reported on the profile, as neither of its shapes matches
+            (from_str("[profile.work]\ngranularity = 1").unwrap(), Some("/profile")),
+        ] {
+            let instance = serde_json::to_value(&instance).unwrap();
+            let errors = validator
+                .iter_errors(&instance)
+                .map(|e| e.instance_path().to_string())
+                .collect::<Vec<_>>();
+            match invalid {
+                None => assert!(errors.is_empty(), "{errors:?}"),
+                Some(path) => assert!(errors.iter().any(|e| e == path), "{path}: {errors:?}"),
+            }
+        }
+        values.remove("profile");
+        assert!(Value::Table(values).try_into::<Config>().is_ok());
+    }
+}
@@ -0,0 +1,128 @@
+//! Detection of conflict markers left unresolved in the staged changes
+
+use std::collections::HashSet;
+
+use globset::GlobBuilder;
+
+use crate::git_ops::AddedLine;
+
+/// Length of the runs of `<`, `|`, `=`, and `>` git writes as conflict markers
+const MARKER_LEN: usize = 7;
+
+/// A conflict marker in an added line of the staged changes
+#[derive(Debug, PartialEq)]
+pub struct Marker {
+    /// The file the line is added to
+    pub path: String,
+    /// The number of the line in the new file
+    pub line: u32,
+    /// The line
+    pub content: String,
+}
+
+/// Finds the conflict markers in the added lines, except in files matching an allowed pattern
+///
+/// A line of `=======` alone is a marker only in a file with an opening or closing marker among
+/// the added lines too, as Markdown and reStructuredText underline headings with it.
+///
+/// # Arguments
+/// * `lines` - The lines the staged changes add, without those of binary files
+/// * `allow` - Glob patterns over the paths of files that may contain markers
+///
+/// # Returns
+/// The markers, in the order of the lines
+pub fn find_markers(lines: &[AddedLine], allow: &[String]) -> Vec<Marker> {
+    let allowed = |path: &str| {
+        allow.iter().any(|pattern| {
+            GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .is_ok_and(|glob| glob.compile_matcher().is_match(path))
+        })
+    };
+    let conflicted = lines
+        .iter()
+        .filter(|line| is_marker(&line.content, '<') || is_marker(&line.content, '>'))
+        .map(|line| line.path.as_str())
+        .collect::<HashSet<_>>();
+    lines
+        .iter()
+        .filter(|line| conflicted.contains(line.path.as_str()) && !allowed(&line.path))
+        .filter(|line| {
+            ['<', '|', '>'].iter().any(|&c| is_marker(&line.content, c))
+                || line.content == "=".repeat(MARKER_LEN)
+        })
+        .map(|line| Marker {
+            path: line.path.clone(),
+            line: line.line,
+            content: line.content.clone(),
+        })
+        .collect()
+}
+
+/// Checks whether the line is a marker of the character, such as `<<<<<<< HEAD`: seven of them,
+/// followed by nothing or a space and a label
+fn is_marker(content: &str, c: char) -> bool {
+    content
+        .strip_prefix(&c.to_string().repeat(MARKER_LEN))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates the added lines of the file, numbered from 1
+    fn added(path: &str, content: &str) -> Vec<AddedLine> {
+        content
+            .lines()
+            .zip(1..)
+            .map(|(content, line)| AddedLine {
+                path: path.to_string(),
+                line,
+                content: content.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unresolved_conflict_is_found() {
+        let lines = added(
+            "src/lib.rs",
+            "fn main() {\n<<<<<<< HEAD\n    run();\n||||||| base\n=======\n    start();\n>>>>>>> feature\n}\n",
+        );
+        let markers = find_markers(&lines, &[]);
+        assert_eq!(markers.iter().map(|marker| marker.line).collect::<Vec<_>>(), [2, 4, 5, 7]);
+        assert_eq!(markers[0].path, "src/lib.rs");
+        assert_eq!(markers[0].content, "<<<<<<< HEAD");
+    }
+
+    #[test]
+    fn leftover_closing_marker_is_found() {
+        let lines = added("src/lib.rs", "    start();\n>>>>>>> feature\n");
+        assert_eq!(find_markers(&lines, &[]).len(), 1);
+    }
+
+    #[test]
+    fn heading_underlines_are_no_markers() {
+        let lines = added("README.md", "Title\n=======\n\nSection\n-------\n");
+        assert_eq!(find_markers(&lines, &[]), []);
+        // A separator counts only in the file with the opening marker
+        let mut lines = added("README.md", "Title\n=======\n");
+        lines.extend(added("src/lib.rs", "<<<<<<< HEAD\n"));
+        assert_eq!(find_markers(&lines, &[]).len(), 1);
+    }
+
+    #[test]
+    fn longer_runs_and_quotes_are_no_markers() {
+        let lines = added("notes.md", "<<<<<<<<\n>>>>>>>>> deep quote\n<<<<<<<HEAD\n");
+        assert_eq!(find_markers(&lines, &[]), []);
+    }
+
+    #[test]
+    fn allowed_paths_are_skipped() {
+        let lines = added("tests/fixtures/conflict.txt", "<<<<<<< ours\n=======\n>>>>>>> theirs\n");
+        assert_eq!(find_markers(&lines, &["tests/fixtures/**".to_string()]), []);
+        assert_eq!(find_markers(&lines, &["tests/*.txt".to_string()]).len(), 3);
+    }
+}
@@ -0,0 +1,62 @@
+use std::{
+    fs::{File, OpenOptions, create_dir_all, metadata, read_to_string, rename},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+
+/// Size in bytes beyond which the log is moved to `<log>.1` before a run, replacing the previous
+/// one
+const MAX_BYTES: u64 = 1024 * 1024;
+
+/// Log of the stdout and stderr of the background processes handling hook events, which would be
+/// lost otherwise
+pub struct DaemonLog {
+    path: PathBuf,
+}
+
+impl DaemonLog {
+    /// Creates a log stored in the given file
+    ///
+    /// # Arguments
+    /// * `path` - The log file; created on first run
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Gets the path of the log file
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Opens the log for appending the output of a run, rotating it first if it grew too large
+    pub fn open(&self) -> Result<File> {
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+        if metadata(&self.path).is_ok_and(|metadata| metadata.len() > MAX_BYTES) {
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(".1");
+            rename(&self.path, rotated)?;
+        }
+        Ok(OpenOptions::new().create(true).append(true).open(&self.path)?)
+    }
+
+    /// Gets the last lines of the log
+    ///
+    /// # Arguments
+    /// * `count` - The number of lines
+    ///
+    /// # Returns
+    /// The lines, or `None` if the log doesn't exist or is empty
+    pub fn tail(&self, count: usize) -> Option<Vec<String>> {
+        let content = read_to_string(&self.path).ok()?;
+        let lines = content.lines().collect::<Vec<_>>();
+        (!lines.is_empty()).then(|| {
+            lines[lines.len().saturating_sub(count)..]
+                .iter()
+                .map(|l| l.to_string())
+                .collect()
+        })
+    }
+}
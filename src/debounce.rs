@@ -0,0 +1,271 @@
+//! Debouncing of bursts of edits of the same file by the background processes handling them:
+//! each edit records an intent to commit the file, and only the process of the latest intent
+//! commits it, once the window passed without a newer one
+
+use std::{
+    fs::{create_dir_all, read_dir, read_to_string, remove_file, rename, write},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::Result;
+use git2::{ObjectType, Oid};
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::shutdown;
+
+/// Age after which an intent is taken as left behind by a process that died before committing,
+/// and removed rather than absorbed
+const STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Number of the next claim of this process, telling apart its claims recorded at the same time
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// The intent of a process to commit an edited file, as recorded in its file
+#[derive(Serialize, Deserialize)]
+struct Intent {
+    /// The ID of the process
+    pid: u32,
+    /// Whether the user modified the edit before accepting it
+    user_modified: bool,
+    /// Whether the process committed the file, having absorbed the older intents; kept so that
+    /// those still waiting see it as newer
+    #[serde(default)]
+    settled: bool,
+}
+
+/// The intents to commit the edited files of a repository, in one directory per file holding one
+/// file per intent, named after the time it was recorded so that the latest sorts last
+pub struct Debouncer {
+    dir: PathBuf,
+    /// The time without a newer edit of a file after which it is committed
+    window: Duration,
+}
+
+/// The intent of this process to commit a file
+pub struct Claim {
+    /// The directory of the intents for the file
+    dir: PathBuf,
+    /// The name of the file of the intent
+    name: String,
+}
+
+impl Debouncer {
+    /// Creates the intents stored in the given directory
+    ///
+    /// # Arguments
+    /// * `dir` - The directory of the intents; created on first claim
+    /// * `window` - The time without a newer edit of a file after which it is committed
+    pub fn new(dir: PathBuf, window: Duration) -> Self {
+        Self { dir, window }
+    }
+
+    /// Records the intent of this process to commit the file, superseding the intents of other
+    /// processes pending for it
+    ///
+    /// # Arguments
+    /// * `file` - The absolute path of the edited file
+    /// * `user_modified` - Whether the user modified the edit before accepting it
+    pub fn claim(&self, file: &Path, user_modified: bool) -> Result<Claim> {
+        let key = Oid::hash_object(ObjectType::Blob, file.as_os_str().as_encoded_bytes())?;
+        let dir = self.dir.join(key.to_string());
+        create_dir_all(&dir)?;
+        let pid = process::id();
+        let name = format!(
+            "{:039}-{pid}-{}.json",
+            Timestamp::now().as_nanosecond(),
+            SEQUENCE.fetch_add(1, Ordering::Relaxed)
+        );
+        record(&dir, &name, &Intent { pid, user_modified, settled: false })?;
+
+        let pending = intents(&dir)?;
+        for (_, intent) in pending
+            .iter()
+            .filter(|(other, intent)| *other < name && !intent.settled)
+        {
+            info!("Superseding the pending commit of {} by process {}", file.display(), intent.pid);
+        }
+        Ok(Claim { dir, name })
+    }
+
+    /// Waits for the window to pass, then settles the claim
+    ///
+    /// # Returns
+    /// As `settle` does, or an error if a shutdown is requested meanwhile
+    pub fn wait(&self, claim: &Claim) -> Result<Option<bool>> {
+        shutdown::sleep(self.window);
+        shutdown::check()?;
+        settle(claim)
+    }
+}
+
+/// Decides whether this process commits the file of the claim: it does if no newer intent was
+/// recorded for the file, absorbing the older ones, whose processes leave the file to it
+///
+/// # Returns
+/// Whether the user modified any of the edits absorbed, if this process commits the file, or
+/// `None` if a newer edit superseded the claim
+pub fn settle(claim: &Claim) -> Result<Option<bool>> {
+    let intents = intents(&claim.dir)?;
+    if intents.iter().any(|(other, _)| *other > claim.name) {
+        debug!("Leaving the commit to the process of a newer edit");
+        return Ok(None);
+    }
+    let mut user_modified = false;
+    let mut own = None;
+    for (name, intent) in intents {
+        if !intent.settled {
+            user_modified |= intent.user_modified;
+        }
+        if name == claim.name {
+            own = Some(intent);
+        } else {
+            remove_intent(&claim.dir.join(name))?;
+        }
+    }
+    if let Some(intent) = own {
+        record(&claim.dir, &claim.name, &Intent { settled: true, ..intent })?;
+    }
+    Ok(Some(user_modified))
+}
+
+/// Writes the intent to its file aside and renames it, so that other processes never read it
+/// half written
+fn record(dir: &Path, name: &str, intent: &Intent) -> Result<()> {
+    let temp = dir.join(format!(".{name}"));
+    write(&temp, serde_json::to_string(intent)?)?;
+    rename(&temp, dir.join(name))?;
+    Ok(())
+}
+
+/// Reads the intents in the directory of a file, oldest first, removing the stale ones
+fn intents(dir: &Path) -> Result<Vec<(String, Intent)>> {
+    let now = Timestamp::now().as_nanosecond();
+    let mut intents = Vec::new();
+    for entry in read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        // Skips the files being written
+        let Some(recorded) = name
+            .split_once('-')
+            .and_then(|(recorded, _)| recorded.parse::<i128>().ok())
+        else {
+            continue;
+        };
+        if now - recorded > STALE_AFTER.as_nanos() as i128 {
+            remove_intent(&path)?;
+            continue;
+        }
+        // Removed by the process committing the file meanwhile
+        let Ok(content) = read_to_string(&path) else {
+            continue;
+        };
+        if let Ok(intent) = serde_json::from_str(&content) {
+            intents.push((name.to_string(), intent));
+        }
+    }
+    intents.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(intents)
+}
+
+/// Removes the file of an intent, unless another process removed it already
+fn remove_intent(path: &Path) -> Result<()> {
+    match remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_dir_all, sync::Barrier, thread::scope};
+
+    use super::*;
+
+    /// Creates a debouncer in a fresh temporary directory
+    fn debouncer(name: &str) -> Debouncer {
+        let dir = temp_dir().join(format!("ccc-test-debounce-{name}-{}", process::id()));
+        let _ = remove_dir_all(&dir);
+        Debouncer::new(dir, Duration::from_millis(50))
+    }
+
+    #[test]
+    fn newer_edit_supersedes_the_pending_one() {
+        let debouncer = debouncer("supersede");
+        let file = Path::new("/project/src/main.rs");
+
+        let older = debouncer.claim(file, true).unwrap();
+        let newer = debouncer.claim(file, false).unwrap();
+
+        assert_eq!(debouncer.wait(&older).unwrap(), None);
+        // The newer one absorbs the modification of the user in the older edit
+        assert_eq!(debouncer.wait(&newer).unwrap(), Some(true));
+        // Kept settled, so that nothing is absorbed from it
+        let newest = debouncer.claim(file, false).unwrap();
+        assert_eq!(debouncer.wait(&newest).unwrap(), Some(false));
+        assert_eq!(intents(&newest.dir).unwrap().len(), 1);
+        remove_dir_all(debouncer.dir).unwrap();
+    }
+
+    #[test]
+    fn edits_of_other_files_are_independent() {
+        let debouncer = debouncer("independent");
+
+        let main = debouncer.claim(Path::new("/project/src/main.rs"), false).unwrap();
+        let lib = debouncer.claim(Path::new("/project/src/lib.rs"), false).unwrap();
+
+        assert_eq!(debouncer.wait(&main).unwrap(), Some(false));
+        assert_eq!(debouncer.wait(&lib).unwrap(), Some(false));
+        remove_dir_all(debouncer.dir).unwrap();
+    }
+
+    #[test]
+    fn one_of_racing_processes_commits() {
+        let debouncer = debouncer("race");
+        let file = Path::new("/project/src/main.rs");
+        let racers = 16;
+        let claimed = Barrier::new(racers);
+
+        // Each thread stands for the process of an edit, the first one modified by the user
+        let results = scope(|scope| {
+            let handles = (0..racers)
+                .map(|racer| {
+                    let (debouncer, claimed) = (&debouncer, &claimed);
+                    scope.spawn(move || {
+                        let claim = debouncer.claim(file, racer == 0).unwrap();
+                        claimed.wait();
+                        settle(&claim).unwrap()
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert_eq!(results.iter().flatten().collect::<Vec<_>>(), [&true]);
+        remove_dir_all(debouncer.dir).unwrap();
+    }
+
+    #[test]
+    fn stale_intents_are_removed_unabsorbed() {
+        let debouncer = debouncer("stale");
+        let file = Path::new("/project/src/main.rs");
+        let claim = debouncer.claim(file, false).unwrap();
+        let recorded = Timestamp::now().as_nanosecond() - 2 * STALE_AFTER.as_nanos() as i128;
+        let stale = claim.dir.join(format!("{recorded:039}-1-0.json"));
+        write(&stale, r#"{"pid":1,"user_modified":true}"#).unwrap();
+
+        assert_eq!(debouncer.wait(&claim).unwrap(), Some(false));
+        assert!(!stale.exists());
+        remove_dir_all(debouncer.dir).unwrap();
+    }
+}
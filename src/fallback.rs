@@ -0,0 +1,176 @@
+use std::path::Path;
+
+/// Changes to a single file, as parsed from a unified diff
+#[derive(Default)]
+struct FileChange {
+    path: String,
+    /// The path before a rename
+    old_path: Option<String>,
+    added: bool,
+    deleted: bool,
+    insertions: usize,
+    deletions: usize,
+}
+
+impl FileChange {
+    fn is_test(&self) -> bool {
+        let path = self.path.to_lowercase();
+        path.split('/')
+            .any(|part| matches!(part, "test" | "tests" | "spec" | "__tests__"))
+            || ["_test.", ".test.", "_spec.", ".spec."]
+                .iter()
+                .any(|s| path.contains(s))
+            || Path::new(&path)
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with("test_"))
+    }
+
+    fn is_docs(&self) -> bool {
+        let path = self.path.to_lowercase();
+        path.starts_with("docs/")
+            || path.contains("/docs/")
+            || [".md", ".rst", ".adoc", ".txt"].iter().any(|ext| path.ends_with(ext))
+    }
+}
+
+/// Builds a conventional commit message from the shape of a diff, without any AI
+///
+/// The type is derived from the changed files (test files → `test`, documentation → `docs`, a
+/// new primary file → `feat`, only renames → `chore` with "rename", only deletions → `chore` with
+/// "remove", otherwise `chore` with "update"), the scope is the file stem of the primary (most
+/// changed) file, and the subject names that file along with the total line counts. The result
+/// depends only on the diff.
+///
+/// # Arguments
+/// - `diff_content` - A unified diff as produced by `git diff`
+///
+/// # Returns
+/// The message, or `None` if the diff contains no file changes
+pub fn heuristic_message(diff_content: &str) -> Option<String> {
+    let changes = parse_diff(diff_content);
+    let primary = changes.iter().rev().max_by_key(|c| c.insertions + c.deletions)?;
+    let insertions = changes.iter().map(|c| c.insertions).sum::<usize>();
+    let deletions = changes.iter().map(|c| c.deletions).sum::<usize>();
+
+    let (commit_type, verb) = if changes.iter().all(FileChange::is_test) {
+        ("test", "update")
+    } else if changes.iter().all(FileChange::is_docs) {
+        ("docs", "update")
+    } else if primary.added {
+        ("feat", "add")
+    } else if changes
+        .iter()
+        .all(|c| c.old_path.is_some() && c.insertions + c.deletions == 0)
+    {
+        ("chore", "rename")
+    } else if changes
+        .iter()
+        .all(|c| c.deleted || c.insertions == 0 && c.old_path.is_none())
+    {
+        ("chore", "remove")
+    } else {
+        ("chore", "update")
+    };
+
+    let scope = Path::new(&primary.path)
+        .file_stem()
+        .map(|stem| format!("({})", stem.to_string_lossy()))
+        .unwrap_or_default();
+    let others = match changes.len() - 1 {
+        0 => String::new(),
+        1 => " and 1 more file".to_string(),
+        n => format!(" and {n} more files"),
+    };
+
+    let path = match &primary.old_path {
+        Some(old_path) if verb == "rename" => format!("{old_path} to {}", primary.path),
+        _ => primary.path.clone(),
+    };
+    Some(format!("{commit_type}{scope}: {verb} {path}{others} (+{insertions}/-{deletions})"))
+}
+
+/// Splits a unified diff into per-file changes with their line counts
+fn parse_diff(diff_content: &str) -> Vec<FileChange> {
+    let mut changes: Vec<FileChange> = Vec::new();
+    let mut in_hunk = false;
+
+    for line in diff_content.lines() {
+        if let Some(header) = line.strip_prefix("diff --git ") {
+            in_hunk = false;
+            let path = header.rsplit_once(" b/").map(|(_, path)| path).unwrap_or(header);
+            changes.push(FileChange { path: path.to_string(), ..Default::default() });
+            continue;
+        }
+        let Some(change) = changes.last_mut() else {
+            continue;
+        };
+
+        if line.starts_with("@@") {
+            in_hunk = true;
+        } else if !in_hunk {
+            if line.starts_with("new file mode") || line == "--- /dev/null" {
+                change.added = true;
+            } else if line.starts_with("deleted file mode") || line == "+++ /dev/null" {
+                change.deleted = true;
+            } else if let Some(old_path) = line.strip_prefix("rename from ") {
+                change.old_path = Some(old_path.to_string());
+            }
+        } else if line.starts_with('+') {
+            change.insertions += 1;
+        } else if line.starts_with('-') {
+            change.deletions += 1;
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_follows_the_shape_of_the_diff() {
+        for (diff, message) in [
+            (
+                "diff --git a/src/parser.rs b/src/parser.rs\nnew file mode 100644\n--- /dev/null\n\
+                 +++ b/src/parser.rs\n@@ -0,0 +1,2 @@\n+fn parse() {}\n+fn lex() {}\n",
+                "feat(parser): add src/parser.rs (+2/-0)",
+            ),
+            (
+                "diff --git a/src/old.rs b/src/old.rs\ndeleted file mode 100644\n--- a/src/old.rs\n\
+                 +++ /dev/null\n@@ -1,2 +0,0 @@\n-fn old() {}\n-fn older() {}\n",
+                "chore(old): remove src/old.rs (+0/-2)",
+            ),
+            (
+                "diff --git a/src/lex.rs b/src/lexer.rs\nsimilarity index 100%\n\
+                 rename from src/lex.rs\nrename to src/lexer.rs\n",
+                "chore(lexer): rename src/lex.rs to src/lexer.rs (+0/-0)",
+            ),
+            (
+                "diff --git a/README.md b/README.md\n--- a/README.md\n+++ b/README.md\n\
+                 @@ -1 +1 @@\n-# Old\n+# New\ndiff --git a/docs/usage.md b/docs/usage.md\n\
+                 --- a/docs/usage.md\n+++ b/docs/usage.md\n@@ -1 +1,2 @@\n # Usage\n+Run it.\n",
+                "docs(README): update README.md and 1 more file (+2/-1)",
+            ),
+            (
+                "diff --git a/tests/parser.rs b/tests/parser.rs\n--- a/tests/parser.rs\n\
+                 +++ b/tests/parser.rs\n@@ -1 +1 @@\n-#[test]\n+#[test] // parses\n",
+                "test(parser): update tests/parser.rs (+1/-1)",
+            ),
+            (
+                "diff --git a/src/main.rs b/src/main.rs\n--- a/src/main.rs\n+++ b/src/main.rs\n\
+                 @@ -1 +1 @@\n-fn main() {}\n+fn main() { run() }\n",
+                "chore(main): update src/main.rs (+1/-1)",
+            ),
+        ] {
+            assert_eq!(heuristic_message(diff).as_deref(), Some(message), "{diff}");
+        }
+    }
+
+    #[test]
+    fn diff_without_files_has_no_message() {
+        assert_eq!(heuristic_message(""), None);
+        assert_eq!(heuristic_message("not a diff\n"), None);
+    }
+}
@@ -0,0 +1,71 @@
+//! Lock files serializing the processes handling hook events that update the same state file,
+//! e.g., the rate limit buckets, so that none of them loses the updates of another
+
+use std::{
+    fs::{OpenOptions, create_dir_all, metadata, remove_file},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{Context, Result, bail};
+
+/// How long to wait for another process to release the lock
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often to check whether the lock was released
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Age after which a lock is taken to be left behind by a process that was killed holding it
+const STALE_LOCK_AGE: Duration = Duration::from_secs(30);
+
+/// A lock file held by one process at a time, removed when dropped
+pub struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Creates the lock file, waiting for another process holding it to remove it, or taking it
+    /// over once stale
+    ///
+    /// # Arguments
+    /// * `path` - The lock file, next to the file it serializes the updates of
+    pub fn acquire(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+        let deadline = Instant::now() + LOCK_TIMEOUT;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(path) {
+                Ok(_) => return Ok(Self { path: path.to_path_buf() }),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let stale = metadata(path).and_then(|metadata| metadata.modified()).is_ok_and(
+                        |modified| {
+                            SystemTime::now()
+                                .duration_since(modified)
+                                .is_ok_and(|age| age > STALE_LOCK_AGE)
+                        },
+                    );
+                    if stale {
+                        let _ = remove_file(path);
+                        continue;
+                    }
+                    if Instant::now() >= deadline {
+                        bail!("Timed out waiting for the lock {}", path.display());
+                    }
+                    thread::sleep(LOCK_POLL_INTERVAL);
+                }
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to create {}", path.display()));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.path);
+    }
+}
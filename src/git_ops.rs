@@ -1,53 +1,307 @@
-use std::path::Path;
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::{copy, create_dir_all, remove_file},
+    path::{Path, PathBuf},
+    process,
+};
 
-use anyhow::{Context, Result};
-use git2::{DiffFormat, DiffOptions, Signature, Time};
+use anyhow::{Context, Result, bail};
+use git2::{
+    Delta, Diff, DiffDelta, DiffFormat, DiffOptions, Index, Oid, Patch, RepositoryState, Signature,
+    StashSaveOptions, Time,
+};
 use jiff::Zoned;
 
-use crate::types::Repository;
+use crate::{
+    trailers::{FALLBACK_TRAILER, find_trailer},
+    types::Repository,
+};
+
+/// Prefix of the names of the session branches the tool creates
+pub const SESSION_BRANCH_PREFIX: &str = "session/";
+
+/// Gets the directory where the tool keeps its per-repository state
+///
+/// # Arguments
+/// * `repo` - The git repository
+///
+/// # Returns
+/// The path of `claude-auto-commit` inside the git directory. It may not exist yet.
+pub fn state_dir(repo: &git2::Repository) -> PathBuf {
+    repo.path().join("claude-auto-commit")
+}
 
 /// Stages a single file for the next commit
 ///
 /// # Arguments
 /// * `repo` - The git repository
 /// * `file_path` - Path to the file to stage
-pub fn stage_file(repo: &Repository, file_path: &str) -> Result<()> {
+pub fn stage_file(repo: &Repository, file_path: &Path) -> Result<()> {
     let mut index = repo.index()?;
     index
-        .add_path(Path::new(file_path))
-        .with_context(|| format!("Failed to add file to index: {}", file_path))?;
+        .add_path(file_path)
+        .with_context(|| format!("Failed to add file to index: {}", file_path.display()))?;
     index.write()?;
     Ok(())
 }
 
-/// Stages all modified files in the working directory
+/// Stages all modified files in the working directory, except those the filter skips
 ///
 /// # Arguments
 /// * `repo` - The git repository
-pub fn stage_all_files(repo: &Repository) -> Result<()> {
+/// * `skip` - Decides from its path relative to the working directory whether a file is left
+///   unstaged
+pub fn stage_all_files(repo: &Repository, mut skip: impl FnMut(&Path) -> bool) -> Result<()> {
     let mut index = repo.index()?;
-    index.add_all(["."], git2::IndexAddOption::DEFAULT, None)?;
+    let mut callback = |path: &Path, _: &[u8]| i32::from(skip(path));
+    index.add_all(["."], git2::IndexAddOption::DEFAULT, Some(&mut callback))?;
     index.write()?;
     Ok(())
 }
 
-/// Gets the diff content for currently staged changes
+/// Lists the paths whose staged content differs from HEAD, both the old and the new path of a
+/// renamed file included
+///
+/// # Arguments
+/// * `repo` - The git repository
+pub fn get_staged_paths(repo: &Repository) -> Result<Vec<PathBuf>> {
+    let head = repo.head().ok().and_then(|head| head.peel_to_tree().ok());
+    let diff = repo.diff_tree_to_index(head.as_ref(), Some(&repo.index()?), None)?;
+    let mut paths = Vec::new();
+    for delta in diff.deltas() {
+        for file in [delta.old_file(), delta.new_file()] {
+            if let Some(path) = file.path()
+                && !paths.iter().any(|known| known == path)
+            {
+                paths.push(path.to_path_buf());
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Runs the function with `repo.index()` returning a copy of the index in which the paths are as
+/// in HEAD, so that what it reads and commits leaves their staged changes out, while the index
+/// itself keeps them
 ///
 /// # Arguments
 /// * `repo` - The git repository
+/// * `paths` - The paths left out, relative to the working directory
+/// * `operations` - What to run on the copy
 ///
 /// # Returns
-/// The diff as a string, truncated to 5000 characters if too long.
-/// Returns an error if the diff cannot be generated.
-pub fn get_staged_diff(repo: &Repository) -> Result<String> {
+/// What the function returns, with the index of the repository switched back either way
+pub fn without_staged_paths<T>(
+    repo: &Repository,
+    paths: &[PathBuf],
+    operations: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    if paths.is_empty() {
+        return operations();
+    }
+    let index_path = repo.path().join("index");
+    // One per process, as the processes of other events may switch to theirs meanwhile
+    let copy_path = state_dir(repo).join(format!("index.{}", process::id()));
+    create_dir_all(state_dir(repo))?;
+    copy(&index_path, &copy_path)
+        .with_context(|| format!("Failed to copy {}", index_path.display()))?;
+    repo.set_index(&mut Index::open(&copy_path)?)?;
+    let head = repo
+        .head()
+        .ok()
+        .and_then(|head| head.peel(git2::ObjectType::Commit).ok());
+    let result = repo
+        .reset_default(head.as_ref(), paths)
+        .map_err(anyhow::Error::from)
+        .and_then(|()| operations());
+    repo.set_index(&mut Index::open(&index_path)?)?;
+    let _ = remove_file(&copy_path);
+    result
+}
+
+/// Gets the content of a file as staged
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `path` - Path of the file relative to the working directory
+///
+/// # Returns
+/// The content, or `None` if the file isn't in the index
+pub fn get_staged_content(repo: &Repository, path: &Path) -> Result<Option<Vec<u8>>> {
+    let index = repo.index()?;
+    let Some(entry) = index.get_path(path, 0) else {
+        return Ok(None);
+    };
+    Ok(Some(repo.find_blob(entry.id)?.content().to_vec()))
+}
+
+/// Insertion and deletion counts of a single staged file
+pub struct FileStat {
+    pub path: String,
+    /// The path before a rename or copy, if different from `path`
+    pub old_path: Option<String>,
+    pub status: Delta,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+impl FileStat {
+    /// Gets the one-letter status code of the change, as in `git diff --name-status`
+    pub fn status_code(&self) -> char {
+        match self.status {
+            Delta::Added => 'A',
+            Delta::Deleted => 'D',
+            Delta::Renamed => 'R',
+            Delta::Copied => 'C',
+            Delta::Typechange => 'T',
+            _ => 'M',
+        }
+    }
+}
+
+/// Computes the diff between HEAD and the index, with renames detected
+fn diff_staged(repo: &Repository) -> Result<Diff<'_>> {
     let head = repo.head()?.peel_to_tree()?;
     let index = repo.index()?;
     let mut opts = DiffOptions::new();
     opts.force_text(false);
-    let diff = repo.diff_tree_to_index(Some(&head), Some(&index), Some(&mut opts))?;
+    let mut diff = repo.diff_tree_to_index(Some(&head), Some(&index), Some(&mut opts))?;
+    diff.find_similar(None)?;
+    Ok(diff)
+}
+
+/// A line the staged changes add to a file
+pub struct AddedLine {
+    /// The file, relative to the working directory
+    pub path: String,
+    /// The number of the line in the new file
+    pub line: u32,
+    /// The line, without its line break; lines that aren't UTF-8 are left out
+    pub content: String,
+}
+
+/// Gets the lines the staged changes add, leaving out binary files
+///
+/// # Arguments
+/// * `repo` - The git repository
+pub fn get_staged_added_lines(repo: &Repository) -> Result<Vec<AddedLine>> {
+    let diff = diff_staged(repo)?;
+    let mut lines = Vec::new();
+    diff.print(DiffFormat::Patch, |delta, _, line| {
+        if line.origin() == '+'
+            && let (Some(path), Some(number), Ok(content)) =
+                (delta.new_file().path(), line.new_lineno(), std::str::from_utf8(line.content()))
+        {
+            lines.push(AddedLine {
+                path: path.to_string_lossy().to_string(),
+                line: number,
+                content: content.trim_end_matches(['\n', '\r']).to_string(),
+            });
+        }
+        true
+    })?;
+    Ok(lines)
+}
+
+/// Gets the per-file statistics for currently staged changes
+///
+/// # Arguments
+/// * `repo` - The git repository
+///
+/// # Returns
+/// One entry per changed file, in diff order. Binary files are reported with zero counts.
+pub fn get_staged_file_stats(repo: &Repository) -> Result<Vec<FileStat>> {
+    let diff = diff_staged(repo)?;
+    (0..diff.deltas().len())
+        .map(|idx| file_stat(&diff, idx, Patch::from_diff(&diff, idx)?.as_ref()))
+        .collect()
+}
+
+/// Builds the statistics of the file at `idx` in the diff from its patch, if it has one
+fn file_stat(diff: &Diff, idx: usize, patch: Option<&Patch>) -> Result<FileStat> {
+    let delta = diff.get_delta(idx).expect("delta index is in range");
+    let to_string = |p: &Path| p.to_string_lossy().to_string();
+    let path = delta
+        .new_file()
+        .path()
+        .or_else(|| delta.old_file().path())
+        .map(to_string);
+    let old_path = delta
+        .old_file()
+        .path()
+        .map(to_string)
+        .filter(|old| Some(old) != path.as_ref());
+    let (insertions, deletions) = match patch {
+        Some(patch) => {
+            let (_, insertions, deletions) = patch.line_stats()?;
+            (insertions, deletions)
+        }
+        None => (0, 0),
+    };
+    Ok(FileStat {
+        path: path.unwrap_or_default(),
+        old_path,
+        status: delta.status(),
+        insertions,
+        deletions,
+    })
+}
+
+/// Diff text of the staged changes, possibly truncated
+pub struct StagedDiff {
+    /// The diff text, ending with a truncation marker if characters were dropped
+    pub content: String,
+    /// The number of characters dropped from the end of the diff
+    pub dropped: usize,
+    /// The number of characters in the complete diff
+    pub total: usize,
+    /// The number of files left out of the diff
+    pub omitted: usize,
+}
+
+/// Gets the path of the file a delta changes, its new path unless deleted
+fn delta_path<'a>(delta: &DiffDelta<'a>) -> Option<&'a Path> {
+    delta.new_file().path().or_else(|| delta.old_file().path())
+}
+
+/// Gets the diff content for currently staged changes
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `max_chars` - The number of characters kept before the rest of the diff is dropped
+/// * `marker` - The text appended when the diff is truncated
+/// * `omit` - Decides from its path relative to the working directory whether a file is left out
+///
+/// # Returns
+/// The diff, truncated to `max_chars` characters if too long.
+/// Returns an error if the diff cannot be generated.
+pub fn get_staged_diff(
+    repo: &Repository,
+    max_chars: usize,
+    marker: &str,
+    omit: impl Fn(&Path) -> bool,
+) -> Result<StagedDiff> {
+    let diff = diff_staged(repo)?;
+    let omitted = diff
+        .deltas()
+        .filter(|delta| delta_path(delta).is_some_and(&omit))
+        .count();
+
+    let diff_text = patch_text(&diff, omit)?;
 
+    Ok(StagedDiff {
+        omitted,
+        ..truncate(diff_text.trim(), max_chars, marker)
+    })
+}
+
+/// Gets the patch text of the diff, without the files `omit` decides to leave out
+fn patch_text(diff: &Diff, omit: impl Fn(&Path) -> bool) -> Result<String> {
     let mut diff_text = String::new();
-    diff.print(DiffFormat::Patch, |_, _, line| {
+    diff.print(DiffFormat::Patch, |delta, _, line| {
+        if delta_path(&delta).is_some_and(&omit) {
+            return true;
+        }
         if let Ok(content) = std::str::from_utf8(line.content()) {
             match line.origin() {
                 '+' | '-' | ' ' => diff_text.push_str(&format!("{}{content}", line.origin())),
@@ -56,43 +310,384 @@ pub fn get_staged_diff(repo: &Repository) -> Result<String> {
         }
         true
     })?;
+    Ok(diff_text)
+}
 
-    let diff_text = diff_text.trim();
-    Ok(if diff_text.len() > 5000 {
-        format!("{}\\n\\n[... truncated ...]", &diff_text[..5000])
-    } else {
-        diff_text.to_string()
+/// Gets the patch text of the changes from a commit to a tree
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `base` - The commit the changes are made on, or `None` for an unborn branch
+/// * `tree` - The tree with the changes
+pub fn get_tree_patch(repo: &Repository, base: Option<Oid>, tree: Oid) -> Result<String> {
+    let base_tree = base.map(|oid| repo.find_commit(oid)?.tree()).transpose()?;
+    let mut diff =
+        repo.diff_tree_to_tree(base_tree.as_ref(), Some(&repo.find_tree(tree)?), None)?;
+    diff.find_similar(None)?;
+    Ok(patch_text(&diff, |_| false)?.trim().to_string())
+}
+
+/// Computes the diff from a commit to HEAD, with renames detected
+fn diff_since(repo: &Repository, base: Oid) -> Result<Diff<'_>> {
+    let base_tree = repo.find_commit(base)?.tree()?;
+    let head = repo.head()?.peel_to_tree()?;
+    let mut diff = repo.diff_tree_to_tree(Some(&base_tree), Some(&head), None)?;
+    diff.find_similar(None)?;
+    Ok(diff)
+}
+
+/// Gets the patch text of the changes HEAD made since a commit, e.g., the merge base of its
+/// branch, as included in the prompt
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `base` - The commit to diff from
+/// * `max_chars` - Maximum number of characters of the patch text
+/// * `marker` - Text appended when the patch is truncated
+/// * `omit` - Decides which files are left out of the patch, counted in `omitted`
+pub fn get_diff_since(
+    repo: &Repository,
+    base: Oid,
+    max_chars: usize,
+    marker: &str,
+    omit: impl Fn(&Path) -> bool,
+) -> Result<StagedDiff> {
+    let diff = diff_since(repo, base)?;
+    let omitted = diff
+        .deltas()
+        .filter(|delta| delta_path(delta).is_some_and(&omit))
+        .count();
+    let diff_text = patch_text(&diff, omit)?;
+    Ok(StagedDiff {
+        omitted,
+        ..truncate(diff_text.trim(), max_chars, marker)
+    })
+}
+
+/// Gets the per-file statistics of the changes HEAD made since a commit
+pub fn get_file_stats_since(repo: &Repository, base: Oid) -> Result<Vec<FileStat>> {
+    let diff = diff_since(repo, base)?;
+    (0..diff.deltas().len())
+        .map(|idx| file_stat(&diff, idx, Patch::from_diff(&diff, idx)?.as_ref()))
+        .collect()
+}
+
+/// Gets the subjects of the commits on HEAD since a commit, oldest first
+pub fn get_subjects_since(repo: &Repository, base: Oid) -> Result<Vec<String>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+    revwalk.hide(base)?;
+    let mut subjects = revwalk
+        .map(|oid| Ok(repo.find_commit(oid?)?.summary().unwrap_or_default().to_string()))
+        .collect::<Result<Vec<_>>>()?;
+    subjects.reverse();
+    Ok(subjects)
+}
+
+/// Stages the changes from a commit to a tree again onto HEAD, merged with the changes HEAD has
+/// gained since the commit
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `base` - The commit the changes were made on, or `None` for an unborn branch
+/// * `tree` - The tree with the changes
+///
+/// # Returns
+/// The ID of the tree now in the index, replacing whatever it held, or an error if the changes
+/// conflict with those of HEAD
+pub fn restage(repo: &Repository, base: Option<Oid>, tree: Oid) -> Result<Oid> {
+    let ancestor = match base {
+        Some(oid) => repo.find_commit(oid)?.tree()?,
+        None => repo.find_tree(repo.treebuilder(None)?.write()?)?,
+    };
+    let head = repo.head()?.peel_to_tree()?;
+    let mut merged = repo.merge_trees(&ancestor, &head, &repo.find_tree(tree)?, None)?;
+    if merged.has_conflicts() {
+        bail!("The changes conflict with those committed since");
+    }
+    let tree_id = merged.write_tree_to(repo)?;
+    let mut index = repo.index()?;
+    index.read_tree(&repo.find_tree(tree_id)?)?;
+    index.write()?;
+    Ok(tree_id)
+}
+
+/// Gets a summary of the currently staged changes for diffs too large to include in full
+///
+/// The summary lists each file with its status, insertion and deletion counts, and hunk headers,
+/// followed by the complete patches of the most changed files.
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `full_patches` - The number of most changed files to include the complete patch of
+/// * `max_chars` - The number of characters kept before the rest of the summary is dropped
+/// * `marker` - The text appended when the summary is truncated
+/// * `omit` - Decides from its path relative to the working directory whether a file is left out
+///
+/// # Returns
+/// The summary, truncated to `max_chars` characters if too long.
+/// Returns an error if the diff cannot be generated.
+pub fn get_staged_summary(
+    repo: &Repository,
+    full_patches: usize,
+    max_chars: usize,
+    marker: &str,
+    omit: impl Fn(&Path) -> bool,
+) -> Result<StagedDiff> {
+    let diff = diff_staged(repo)?;
+    let mut files = Vec::new();
+    let mut omitted = 0;
+    for idx in 0..diff.deltas().len() {
+        if diff.get_delta(idx).as_ref().and_then(delta_path).is_some_and(&omit) {
+            omitted += 1;
+            continue;
+        }
+        let patch = Patch::from_diff(&diff, idx)?;
+        let stat = file_stat(&diff, idx, patch.as_ref())?;
+        files.push((stat, patch));
+    }
+
+    let mut summary = format!("Summary of changes in {} files:\n", files.len());
+    for (stat, patch) in &files {
+        let path = match &stat.old_path {
+            Some(old_path) => format!("{old_path} -> {}", stat.path),
+            None => stat.path.clone(),
+        };
+        summary.push_str(&format!(
+            "{} {path} (+{}/-{})\n",
+            stat.status_code(),
+            stat.insertions,
+            stat.deletions
+        ));
+        for hunk_idx in 0..patch.as_ref().map_or(0, |patch| patch.num_hunks()) {
+            let (hunk, _) = patch.as_ref().expect("patch has hunks").hunk(hunk_idx)?;
+            summary.push_str(&format!("  {}", String::from_utf8_lossy(hunk.header())));
+        }
+    }
+
+    files.sort_by_key(|(stat, _)| std::cmp::Reverse(stat.insertions + stat.deletions));
+    let largest = files
+        .iter_mut()
+        .filter_map(|(_, patch)| patch.as_mut())
+        .take(full_patches);
+    for patch in largest {
+        summary.push('\n');
+        summary.push_str(&String::from_utf8_lossy(&patch.to_buf()?));
+    }
+
+    Ok(StagedDiff {
+        omitted,
+        ..truncate(summary.trim(), max_chars, marker)
     })
 }
 
+/// Truncates the text to `max_chars` characters, appending the marker if anything was dropped
+fn truncate(text: &str, max_chars: usize, marker: &str) -> StagedDiff {
+    let total = text.chars().count();
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => StagedDiff {
+            content: format!("{}\n\n{marker}", &text[..end]),
+            dropped: total - max_chars,
+            total,
+            omitted: 0,
+        },
+        None => StagedDiff {
+            content: text.to_string(),
+            dropped: 0,
+            total,
+            omitted: 0,
+        },
+    }
+}
+
+/// An operation in progress that the next commit concludes
+pub enum PendingOperation {
+    /// A merge stopped for conflict resolution
+    Merge {
+        /// The commits being merged into HEAD, which become the additional parents
+        heads: Vec<Oid>,
+        /// The message prepared by git in `MERGE_MSG`
+        message: String,
+    },
+    /// A revert stopped for conflict resolution
+    Revert { message: String },
+    /// A cherry-pick stopped for conflict resolution
+    CherryPick { message: String },
+    /// Any other operation, such as a rebase or bisect, which is never committed automatically
+    Other(RepositoryState),
+}
+
+impl Display for PendingOperation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PendingOperation::Merge { .. } => f.write_str("merge"),
+            PendingOperation::Revert { .. } => f.write_str("revert"),
+            PendingOperation::CherryPick { .. } => f.write_str("cherry-pick"),
+            PendingOperation::Other(state) => write!(f, "{state:?}"),
+        }
+    }
+}
+
+/// Gets the operation in progress in the repository, if any
+///
+/// # Arguments
+/// * `repo` - The git repository
+///
+/// # Returns
+/// The operation with the message git prepared for it (without comment lines), or `None` if the
+/// repository is in a clean state
+pub fn get_pending_operation(repo: &Repository) -> Result<Option<PendingOperation>> {
+    let state = repo.state();
+    if state == RepositoryState::Clean {
+        return Ok(None);
+    }
+
+    let message = repo
+        .message()
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string();
+    Ok(Some(match state {
+        RepositoryState::Merge => {
+            let heads = std::fs::read_to_string(repo.path().join("MERGE_HEAD"))?
+                .lines()
+                .map(Oid::from_str)
+                .collect::<Result<_, _>>()?;
+            PendingOperation::Merge { heads, message }
+        }
+        RepositoryState::Revert | RepositoryState::RevertSequence => {
+            PendingOperation::Revert { message }
+        }
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+            PendingOperation::CherryPick { message }
+        }
+        state => PendingOperation::Other(state),
+    }))
+}
+
+/// Gets the operation in progress that nothing may be written to git during, as it moves HEAD
+/// itself
+///
+/// # Arguments
+/// * `repo` - The git repository
+///
+/// # Returns
+/// The name of the operation, e.g., "interactive rebase", or `None` if none is in progress
+pub fn get_blocking_operation(repo: &Repository) -> Option<&'static str> {
+    match repo.state() {
+        RepositoryState::Rebase | RepositoryState::RebaseMerge => Some("rebase"),
+        RepositoryState::RebaseInteractive => Some("interactive rebase"),
+        RepositoryState::Bisect => Some("bisect"),
+        RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => Some("git am"),
+        _ => None,
+    }
+}
+
+/// Stashes the changes to a tracked file, removing them from the working tree
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `path` - The path of the file relative to the working directory
+///
+/// # Returns
+/// The ID of the stash commit, or an error if the file has no changes to stash, e.g., as it is
+/// untracked: libgit2 would stash every untracked file along with it
+pub fn stash_file(repo: &Repository, path: &Path) -> Result<Oid> {
+    let stasher = create_signature(repo)?.to_owned();
+    // Stashing needs the repository mutably, which the shared one isn't
+    let mut repo = git2::Repository::open(repo.path())?;
+    let mut options = StashSaveOptions::new(stasher);
+    options.pathspec(path);
+    Ok(repo.stash_save_ext(Some(&mut options))?)
+}
+
 /// Creates a git commit with the given message
 ///
 /// # Arguments
 /// * `repo` - The git repository
 /// * `message` - The commit message
-pub fn create_commit(repo: &Repository, message: &str) -> Result<()> {
-    let signature = create_signature(repo)?;
-    let mut index = repo.index()?;
-    let tree_id = index.write_tree()?;
+/// * `merge_heads` - Parents in addition to HEAD, for a merge commit
+/// * `committer` - The name and email of the committer, or `None` to commit as the author
+///
+/// # Returns
+/// The ID of the commit. After a merge, revert, or cherry-pick, its state is cleared.
+pub fn create_commit(
+    repo: &Repository,
+    message: &str,
+    merge_heads: &[Oid],
+    committer: Option<(&str, &str)>,
+) -> Result<Oid> {
+    let tree_id = repo.index()?.write_tree()?;
+    commit_tree(repo, message, tree_id, merge_heads, committer)
+}
+
+/// Creates a git commit of the given tree on HEAD, leaving the index as it is
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `message` - The commit message
+/// * `tree_id` - The tree committed
+/// * `merge_heads` - Parents in addition to HEAD, for a merge commit
+/// * `committer` - The name and email of the committer, or `None` to commit as the author
+///
+/// # Returns
+/// The ID of the commit. After a merge, revert, or cherry-pick, its state is cleared.
+pub fn commit_tree(
+    repo: &Repository,
+    message: &str,
+    tree_id: Oid,
+    merge_heads: &[Oid],
+    committer: Option<(&str, &str)>,
+) -> Result<Oid> {
+    let author = create_signature(repo)?;
+    let committer = match committer {
+        Some((name, email)) => Signature::new(name, email, &author.when())?,
+        None => author.to_owned(),
+    };
     let tree = repo.find_tree(tree_id)?;
-    let parents: Vec<_> = repo
+    let mut parents: Vec<_> = repo
         .head()
         .ok()
         .and_then(|head| head.target())
         .and_then(|oid| repo.find_commit(oid).ok())
         .map(|commit| vec![commit])
         .unwrap_or_default();
+    for oid in merge_heads {
+        parents.push(repo.find_commit(*oid)?);
+    }
 
-    repo.commit(
+    let oid = repo.commit(
         Some("HEAD"),
-        &signature,
-        &signature,
+        &author,
+        &committer,
         message,
         &tree,
         &parents.iter().collect::<Vec<_>>(),
     )?;
 
-    Ok(())
+    if repo.state() != RepositoryState::Clean {
+        repo.cleanup_state()?;
+    }
+    Ok(oid)
+}
+
+/// Gets the name and email commits are authored with, resolved as for the commits themselves
+///
+/// # Arguments
+/// * `repo` - The git repository
+///
+/// # Returns
+/// The name and email, or an error if no identity is configured
+pub fn get_author_identity(repo: &Repository) -> Result<(String, String)> {
+    let signature = create_signature(repo)?;
+    Ok((
+        String::from_utf8_lossy(signature.name_bytes()).into_owned(),
+        String::from_utf8_lossy(signature.email_bytes()).into_owned(),
+    ))
 }
 
 /// Creates a git signature from git config with conditionally includes support
@@ -160,6 +755,96 @@ pub fn get_current_branch(repo: &Repository) -> Result<String> {
     }
 }
 
+/// Gets the subjects of the most recent commits on the current branch, leaving out those made
+/// with the fallback message
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `limit` - The maximum number of subjects
+/// * `fallback_subject` - The subject of fallback commits made before they had `FALLBACK_TRAILER`,
+///   which are left out too
+///
+/// # Returns
+/// The subjects, newest first. Returns an error if HEAD is unborn or history can't be walked.
+pub fn get_recent_subjects(
+    repo: &Repository,
+    limit: usize,
+    fallback_subject: Option<&str>,
+) -> Result<Vec<String>> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_head()?;
+
+    let mut subjects = Vec::new();
+    for oid in revwalk {
+        if subjects.len() >= limit {
+            break;
+        }
+        let commit = repo.find_commit(oid?)?;
+        let subject = commit.summary().unwrap_or_default();
+        let fallback = find_trailer(commit.message().unwrap_or_default(), FALLBACK_TRAILER)
+            .is_some()
+            || fallback_subject == Some(subject);
+        if !fallback {
+            subjects.push(subject.to_string());
+        }
+    }
+    Ok(subjects)
+}
+
+/// Guesses the branch the given branch was started from
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `branch` - The branch to find the base of
+/// * `candidates` - The names of the branches that may be the base, in order of preference
+///
+/// # Returns
+/// `branch` itself if it is one of the candidates; otherwise the existing candidate `branch` has
+/// the fewest commits ahead of, or an empty string if no candidate exists
+pub fn get_base_branch(repo: &Repository, branch: &str, candidates: &[String]) -> String {
+    if candidates.iter().any(|candidate| candidate == branch) {
+        return branch.to_string();
+    }
+
+    let Some(head) = repo.head().ok().and_then(|head| head.target()) else {
+        return String::new();
+    };
+    candidates
+        .iter()
+        .filter_map(|name| {
+            let base = repo.find_branch(name, git2::BranchType::Local).ok()?.get().target()?;
+            let merge_base = repo.merge_base(head, base).ok()?;
+            let (ahead, _) = repo.graph_ahead_behind(head, merge_base).ok()?;
+            Some((ahead, name))
+        })
+        .min_by_key(|(ahead, _)| *ahead)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_default()
+}
+
+/// Counts the commits on HEAD since it diverged from the given branch
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `base_branch` - The name of the local branch to count from
+///
+/// # Returns
+/// The number of commits reachable from HEAD but not from `base_branch`, or 0 if either can't be
+/// resolved
+pub fn count_commits_since(repo: &Repository, base_branch: &str) -> usize {
+    let count = || {
+        let head = repo.head().ok()?.target()?;
+        let base = repo
+            .find_branch(base_branch, git2::BranchType::Local)
+            .ok()?
+            .get()
+            .target()?;
+        let merge_base = repo.merge_base(head, base).ok()?;
+        repo.graph_ahead_behind(head, merge_base).ok().map(|(ahead, _)| ahead)
+    };
+    count().unwrap_or_default()
+}
+
 /// Creates a new session branch with timestamp
 ///
 /// # Arguments
@@ -171,7 +856,7 @@ pub fn get_current_branch(repo: &Repository) -> Result<String> {
 /// format: `session/{session_id}_{timestamp}`
 pub fn create_session_branch(repo: &Repository, session_id: &str) -> Result<()> {
     let timestamp = Zoned::now().strftime("%Y%m%d_%H%M%S");
-    let branch_name = format!("session/{}_{}", session_id, timestamp);
+    let branch_name = format!("{SESSION_BRANCH_PREFIX}{session_id}_{timestamp}");
     let head_commit = repo.head()?.peel_to_commit()?;
 
     repo.branch(&branch_name, &head_commit, false)?;
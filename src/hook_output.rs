@@ -0,0 +1,300 @@
+//! The JSON object a hook prints on stdout, which Claude Code shows the user and, for a session
+//! start, the model
+
+use git2::{Oid, Repository, Sort};
+use serde::Serialize;
+
+/// Length of the abbreviated commit IDs in messages
+const SHORT_ID_LEN: usize = 7;
+
+/// Maximum number of commits listed; the rest are summarized as a count
+const MAX_LISTED_COMMITS: usize = 10;
+
+/// What the hook tells Claude Code once it handled an event in the foreground
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HookOutput {
+    /// The message shown to the user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_message: Option<String>,
+    /// Whether to keep the output out of the transcript, as the message says it all
+    pub suppress_output: bool,
+    /// What the event adds to the context of the model
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hook_specific_output: Option<HookSpecificOutput>,
+}
+
+/// The part of the output specific to the kind of event
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HookSpecificOutput {
+    /// The name of the event, e.g., `SessionStart`
+    pub hook_event_name: &'static str,
+    /// Text added to the context of the model
+    pub additional_context: String,
+}
+
+/// The commit HEAD points at and the branch checked out, to tell what handling an event changed
+pub struct RepoState {
+    /// The commit, `None` on an unborn branch
+    pub head: Option<Oid>,
+    /// The short name of the branch, `None` when detached
+    pub branch: Option<String>,
+}
+
+impl RepoState {
+    /// Gets the state of the repository as of now
+    pub fn of(repo: &Repository) -> Self {
+        let head = repo.head().ok();
+        Self {
+            head: head.as_ref().and_then(|head| head.target()),
+            branch: head
+                .filter(|head| head.is_branch())
+                .and_then(|head| head.shorthand().map(str::to_string)),
+        }
+    }
+}
+
+/// A commit made while handling an event
+pub struct Committed {
+    /// The number of files the commit changes
+    pub files: usize,
+    /// The abbreviated commit ID
+    pub id: String,
+    /// The first line of the commit message
+    pub subject: String,
+}
+
+impl Committed {
+    /// Lists the commits HEAD moved on by since the commit it pointed at before
+    ///
+    /// # Arguments
+    /// * `repo` - The git repository
+    /// * `before` - The commit HEAD pointed at before, `None` on an unborn branch
+    ///
+    /// # Returns
+    /// The commits, newest first, none if HEAD didn't move to another commit, e.g., only to
+    /// another branch
+    pub fn since(repo: &Repository, before: Option<Oid>) -> Vec<Self> {
+        let Some(head) = repo.head().ok().and_then(|head| head.target()) else {
+            return Vec::new();
+        };
+        let Ok(mut walk) = repo.revwalk() else {
+            return Vec::new();
+        };
+        let _ = walk.set_sorting(Sort::TOPOLOGICAL);
+        if walk.push(head).is_err() || before.is_some_and(|before| walk.hide(before).is_err()) {
+            return Vec::new();
+        }
+        walk.filter_map(|oid| Self::of(repo, oid.ok()?)).collect()
+    }
+
+    /// Gets the commit of the ID, with the number of files it changes from its first parent
+    pub fn of(repo: &Repository, oid: Oid) -> Option<Self> {
+        let commit = repo.find_commit(oid).ok()?;
+        let parent = commit.parent(0).ok().and_then(|parent| parent.tree().ok());
+        let diff = repo
+            .diff_tree_to_tree(parent.as_ref(), Some(&commit.tree().ok()?), None)
+            .ok()?;
+        Some(Self {
+            files: diff.deltas().len(),
+            id: oid.to_string()[..SHORT_ID_LEN].to_string(),
+            subject: commit.summary().unwrap_or_default().to_string(),
+        })
+    }
+}
+
+impl HookOutput {
+    /// Composes the output telling about what handling an event changed in the repository and the
+    /// warnings logged meanwhile
+    ///
+    /// # Arguments
+    /// * `repo` - The git repository
+    /// * `before` - The state of the repository before the event
+    /// * `event_name` - The name of the event; a `SessionStart` also tells the model about the
+    ///   commits of the previous session and the session branch
+    /// * `warnings` - The messages of the warnings and errors logged, in order
+    ///
+    /// # Returns
+    /// The output, or `None` if there is nothing to tell
+    pub fn since(
+        repo: &Repository,
+        before: &RepoState,
+        event_name: &'static str,
+        warnings: &[String],
+    ) -> Option<Self> {
+        let commits = Committed::since(repo, before.head);
+        let context = (event_name == "SessionStart")
+            .then(|| session_context(&commits, before, &RepoState::of(repo)))
+            .flatten()
+            .map(|additional_context| HookSpecificOutput {
+                hook_event_name: event_name,
+                additional_context,
+            });
+        Self::new(&commits, warnings, context)
+    }
+
+    /// Composes the output telling about the commits and the warnings logged handling an event
+    ///
+    /// # Arguments
+    /// * `commits` - The commits made, newest first
+    /// * `warnings` - The messages of the warnings and errors logged, in order; the first is quoted
+    ///   and the rest counted, as the log has them all
+    /// * `hook_specific_output` - What the event adds to the context of the model, if anything
+    ///
+    /// # Returns
+    /// The output, or `None` if there is nothing to tell
+    pub fn new(
+        commits: &[Committed],
+        warnings: &[String],
+        hook_specific_output: Option<HookSpecificOutput>,
+    ) -> Option<Self> {
+        let mut lines = commits
+            .iter()
+            .rev()
+            .map(|Committed { files, id, subject }| {
+                let plural = if *files == 1 { "" } else { "s" };
+                format!("auto-committed {files} file{plural} as {id}: {subject}")
+            })
+            .collect::<Vec<_>>();
+        if let Some((first, rest)) = warnings.split_first() {
+            let mut line = format!("auto-commit warning: {first}");
+            if !rest.is_empty() {
+                line.push_str(&format!(" (+{} more in the log)", rest.len()));
+            }
+            lines.push(line);
+        }
+        if lines.is_empty() && hook_specific_output.is_none() {
+            return None;
+        }
+        Some(Self {
+            system_message: (!lines.is_empty()).then(|| lines.join("\n")),
+            suppress_output: true,
+            hook_specific_output,
+        })
+    }
+}
+
+/// Describes for the model starting a session the commits made of the changes the previous
+/// session left, and the session branch it works on
+///
+/// # Arguments
+/// * `commits` - The commits made, newest first
+/// * `before` - The state of the repository before the session start
+/// * `after` - The state of the repository after it
+///
+/// # Returns
+/// The description, or `None` if neither a commit nor a branch was made
+fn session_context(commits: &[Committed], before: &RepoState, after: &RepoState) -> Option<String> {
+    let mut paragraphs = Vec::new();
+    if !commits.is_empty() {
+        let mut lines = vec![
+            "The changes the previous session left uncommitted were committed automatically, so \
+             there is no need to redo them:"
+                .to_string(),
+        ];
+        lines.extend(
+            commits
+                .iter()
+                .take(MAX_LISTED_COMMITS)
+                .map(|commit| format!("- {} {}", commit.id, commit.subject)),
+        );
+        if commits.len() > MAX_LISTED_COMMITS {
+            lines.push(format!("- ... {} more", commits.len() - MAX_LISTED_COMMITS));
+        }
+        paragraphs.push(lines.join("\n"));
+    }
+    if let Some(branch) = &after.branch
+        && after.branch != before.branch
+    {
+        let from = before
+            .branch
+            .as_deref()
+            .map_or(String::new(), |branch| format!(" from {branch}"));
+        paragraphs.push(format!(
+            "This session works on the branch {branch}, created{from} for it; its changes are \
+             committed there automatically."
+        ));
+    }
+    (!paragraphs.is_empty()).then(|| paragraphs.join("\n\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Creates a commit of the number of files, with a fixed ID and subject
+    fn committed(files: usize) -> Committed {
+        Committed {
+            files,
+            id: "a1b2c3d".to_string(),
+            subject: "feat(parser): accept trailing commas".to_string(),
+        }
+    }
+
+    /// Creates the state of a repository on the branch
+    fn on(branch: &str) -> RepoState {
+        RepoState { head: None, branch: Some(branch.to_string()) }
+    }
+
+    #[test]
+    fn commit_is_reported() {
+        let output = HookOutput::new(&[committed(2)], &[], None).unwrap();
+        assert_eq!(
+            serde_json::to_value(&output).unwrap(),
+            json!({
+                "systemMessage": "auto-committed 2 files as a1b2c3d: feat(parser): accept trailing commas",
+                "suppressOutput": true,
+            })
+        );
+        let output = HookOutput::new(&[committed(1)], &[], None).unwrap();
+        assert!(output.system_message.unwrap().starts_with("auto-committed 1 file as"));
+    }
+
+    #[test]
+    fn first_warning_is_quoted_and_the_rest_counted() {
+        let warnings = ["NOT COMMITTED: the staged changes contain 1 likely secrets", "  .env:1"]
+            .map(str::to_string);
+        let output = HookOutput::new(&[], &warnings, None).unwrap();
+        assert_eq!(
+            output.system_message.unwrap(),
+            "auto-commit warning: NOT COMMITTED: the staged changes contain 1 likely secrets \
+             (+1 more in the log)"
+        );
+        let output = HookOutput::new(&[committed(1)], &warnings[..1], None).unwrap();
+        assert_eq!(output.system_message.unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn nothing_to_tell_is_no_output() {
+        assert_eq!(HookOutput::new(&[], &[], None), None);
+        assert_eq!(session_context(&[], &on("feature"), &on("feature")), None);
+    }
+
+    #[test]
+    fn session_start_adds_context() {
+        let context = session_context(&[committed(2)], &on("main"), &on("session/abc_1")).unwrap();
+        assert_eq!(
+            context,
+            "The changes the previous session left uncommitted were committed automatically, so \
+             there is no need to redo them:\n\
+             - a1b2c3d feat(parser): accept trailing commas\n\n\
+             This session works on the branch session/abc_1, created from main for it; its \
+             changes are committed there automatically."
+        );
+        let output = HookOutput::new(
+            &[],
+            &[],
+            Some(HookSpecificOutput {
+                hook_event_name: "SessionStart",
+                additional_context: context,
+            }),
+        )
+        .unwrap();
+        let json = serde_json::to_value(&output).unwrap();
+        assert_eq!(json["hookSpecificOutput"]["hookEventName"], "SessionStart");
+        assert!(json.get("systemMessage").is_none());
+    }
+}
@@ -0,0 +1,1031 @@
+//! Installation of the hook into the Claude Code settings files
+
+use std::{
+    env::{current_exe, set_current_dir, split_paths, var_os},
+    fs::{
+        File, canonicalize, copy, create_dir_all, metadata, read_dir, read_link, read_to_string,
+        remove_file, rename, set_permissions,
+    },
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use clap::ValueEnum;
+use directories::BaseDirs;
+use git2::Repository;
+use jiff::Zoned;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{Serializer, Value, from_str, ser::PrettyFormatter};
+use similar::TextDiff;
+
+use crate::{
+    claude_settings::{ClaudeSettings, HookCommand, MatcherGroup},
+    config::{CONFIG, LoadedConfig, first_match},
+};
+
+#[derive(clap::Args)]
+pub struct InstallArgs {
+    /// Argument to append to the hook command, after `install.extra_args`; repeatable
+    #[arg(long = "extra-arg", value_name = "ARG", allow_hyphen_values = true)]
+    extra_args: Vec<String>,
+    /// Events to register the hook for
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "session-start,post-tool-use")]
+    events: Vec<HookKind>,
+    /// Also register the hook for the Stop event, committing at the end of each response; the
+    /// same as adding `stop` to --events
+    #[arg(long)]
+    on_stop: bool,
+    /// Tools whose use triggers the PostToolUse hook, as a Claude Code matcher
+    #[arg(long, alias = "tools", default_value = "Edit|MultiEdit|Write", value_parser = parse_matcher)]
+    matcher: String,
+    /// Seconds Claude Code waits for the hooks [default: 60, or 10 for Stop]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..=MAX_TIMEOUT_SECS))]
+    timeout: Option<u64>,
+    /// Add the hooks even if this binary already has hooks for the events
+    #[arg(long)]
+    force: bool,
+    /// Install into the user-global ~/.claude/settings.json, for every project
+    #[arg(long)]
+    global: bool,
+    /// Instead of installing, point the hooks running a binary of the same name at another path,
+    /// e.g., before an upgrade moved it, to this binary
+    #[arg(long, conflicts_with = "force")]
+    update: bool,
+    /// Print the diff of the change to the settings file instead of writing it
+    #[arg(long)]
+    dry_run: bool,
+    /// How the hook command refers to this binary [default: path for the shared settings file,
+    /// abs otherwise]
+    #[arg(long, value_enum)]
+    command_style: Option<CommandStyle>,
+    /// Settings file of the repository to install into
+    #[arg(long, value_enum, default_value = "local", conflicts_with = "global")]
+    settings: SettingsFile,
+    /// Install into every repository under the directory instead of the current one
+    #[arg(long, value_name = "DIR", conflicts_with = "global")]
+    recursive: Option<PathBuf>,
+    /// Only install into the repositories whose path matches the glob pattern; repeatable
+    #[arg(long, value_name = "GLOB", requires = "recursive")]
+    filter: Vec<String>,
+}
+
+/// Maximum hook timeout, in seconds, which Claude Code accepts
+const MAX_TIMEOUT_SECS: u64 = 600;
+
+/// Checks that the matcher is a plausible regular expression of tool names, e.g., `Write|Edit`
+fn parse_matcher(matcher: &str) -> Result<String, String> {
+    // Claude Code matches every tool for `*`, which is no regular expression
+    if matcher == "*" {
+        return Ok(matcher.to_string());
+    }
+    if let Some(c) = matcher
+        .chars()
+        .find(|&c| !c.is_ascii_alphanumeric() && !"_-|.*+?()[]^$\\".contains(c))
+    {
+        return Err(format!("{c:?} is not part of a tool name pattern"));
+    }
+    Regex::new(matcher).map_err(|e| e.to_string())?;
+    Ok(matcher.to_string())
+}
+
+impl InstallArgs {
+    /// Gets the events to register the hook for
+    fn events(&self) -> Vec<HookKind> {
+        let mut events = self.events.clone();
+        if self.on_stop && !events.contains(&HookKind::Stop) {
+            events.push(HookKind::Stop);
+        }
+        events
+    }
+
+    /// Gets the settings file to install into
+    fn target(&self) -> SettingsFile {
+        if self.global { SettingsFile::Global } else { self.settings }
+    }
+}
+
+/// A Claude Code settings file the hook can be installed into
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SettingsFile {
+    /// <repository_root>/.claude/settings.local.json, for yourself only
+    Local,
+    /// <repository_root>/.claude/settings.json, committed and shared with everyone working on the
+    /// repository
+    Shared,
+    /// ~/.claude/settings.json, for every project
+    #[value(skip)]
+    Global,
+}
+
+impl SettingsFile {
+    /// The settings files, in the order `status` lists them
+    pub const ALL: [SettingsFile; 3] =
+        [SettingsFile::Local, SettingsFile::Shared, SettingsFile::Global];
+
+    /// Gets the path of the settings file
+    pub fn path(self) -> Result<PathBuf> {
+        if self == SettingsFile::Global {
+            return Ok(BaseDirs::new()
+                .ok_or_else(|| anyhow!("Home directory not found"))?
+                .home_dir()
+                .join(".claude")
+                .join("settings.json"));
+        }
+        Ok(Repository::discover(".")?
+            .workdir()
+            .ok_or_else(|| anyhow!("Repository has no working directory (bare repo?)"))?
+            .join(".claude")
+            .join(match self {
+                SettingsFile::Shared => "settings.json",
+                _ => "settings.local.json",
+            }))
+    }
+
+    /// Gets the `install` flag selecting the settings file
+    pub fn install_flag(self) -> &'static str {
+        match self {
+            SettingsFile::Local => "",
+            SettingsFile::Shared => " --settings shared",
+            SettingsFile::Global => " --global",
+        }
+    }
+}
+
+/// A Claude Code hook event `install` can register the binary for
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HookKind {
+    /// Commits the previous session on `/clear`, `/compact`, and resume
+    SessionStart,
+    /// Commits the files changed by the matched tools
+    PostToolUse,
+    /// Commits the changes at the end of each response
+    Stop,
+}
+
+impl HookKind {
+    /// Gets the name of the event in the settings file
+    fn event_name(self) -> &'static str {
+        match self {
+            HookKind::SessionStart => "SessionStart",
+            HookKind::PostToolUse => "PostToolUse",
+            HookKind::Stop => "Stop",
+        }
+    }
+
+    /// Creates the hook entry running the command
+    ///
+    /// # Arguments
+    /// * `command` - The hook command
+    /// * `matcher` - The matcher of the tools triggering a PostToolUse hook
+    /// * `timeout` - The timeout in seconds, or `None` for the default of the event
+    fn entry(self, command: &str, matcher: &str, timeout: Option<u64>) -> MatcherGroup {
+        let hook = HookCommand::new(command, timeout.unwrap_or(self.default_timeout()));
+        match self {
+            HookKind::SessionStart | HookKind::Stop => MatcherGroup::new(None, hook),
+            HookKind::PostToolUse => MatcherGroup::new(Some(matcher), hook),
+        }
+    }
+
+    /// Gets the seconds Claude Code waits for the hook unless `--timeout` is given
+    fn default_timeout(self) -> u64 {
+        match self {
+            // Handled in the hook process, which may commit the previous session before printing
+            // the context it adds, within `foreground_deadline_secs`
+            HookKind::SessionStart => 60,
+            // The hook returns right after forking, but leave room for slow starts
+            HookKind::PostToolUse => 60,
+            HookKind::Stop => 10,
+        }
+    }
+}
+
+/// Reads a Claude Code settings file, or empty settings if it doesn't exist
+///
+/// # Returns
+/// * `Result<ClaudeSettings>` - The settings, or an error if the file isn't shaped like settings,
+///   so that writing it back doesn't discard its content
+fn read_settings(path: &Path) -> Result<ClaudeSettings> {
+    let content = match read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(ClaudeSettings::default()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    if content.trim().is_empty() {
+        return Ok(ClaudeSettings::default());
+    }
+    let settings = from_str::<Value>(&content)
+        .with_context(|| format!("Failed to parse {}; fix it first", path.display()))?;
+    ClaudeSettings::from_value(settings)
+        .map_err(|e| anyhow!("Unexpected content in {}: {e}; fix it first", path.display()))
+}
+
+/// Serializes Claude Code settings in the indentation of the current content of the file
+///
+/// # Arguments
+/// * `original` - The current content of the file, if it exists
+/// * `settings` - The settings to serialize
+fn render_settings(original: Option<&str>, settings: &ClaudeSettings) -> Result<String> {
+    let indent = original
+        .and_then(|content| {
+            content.lines().find_map(|line| {
+                let trimmed = line.trim_start();
+                (!trimmed.is_empty() && trimmed.len() < line.len())
+                    .then(|| &line[..line.len() - trimmed.len()])
+            })
+        })
+        .unwrap_or("  ");
+
+    let mut content = Vec::new();
+    settings.to_value().serialize(&mut Serializer::with_formatter(
+        &mut content,
+        PrettyFormatter::with_indent(indent.as_bytes()),
+    ))?;
+    if original.is_none_or(|original| original.ends_with('\n')) {
+        content.push(b'\n');
+    }
+    Ok(String::from_utf8(content)?)
+}
+
+/// Writes a Claude Code settings file in the indentation of its current content
+///
+/// # Arguments
+/// * `path` - The settings file
+/// * `settings` - The settings to write
+/// * `dry_run` - Whether to print the diff of the change instead of writing it
+fn write_settings(path: &Path, settings: &ClaudeSettings, dry_run: bool) -> Result<()> {
+    let original = read_to_string(path).ok();
+    let content = render_settings(original.as_deref(), settings)?;
+    if dry_run {
+        let path = path.display().to_string();
+        print!(
+            "{}",
+            TextDiff::from_lines(original.as_deref().unwrap_or_default(), &content)
+                .unified_diff()
+                .header(&path, &path)
+        );
+        println!("Dry run: {path} is left unchanged");
+        return Ok(());
+    }
+    replace_file(path, &content)
+}
+
+/// Appends the suffix to the file name of the path
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Replaces the content of the file atomically, keeping the previous content as
+/// `<file>.bak.<timestamp>`, of which the last [`MAX_BACKUPS`] are kept
+///
+/// A temporary file in the same directory is written and synced first and then renamed over the
+/// file, so that a failed write leaves the file intact.
+fn replace_file(path: &Path, content: &str) -> Result<()> {
+    create_dir_all(path.parent().expect("the settings file is in a directory"))?;
+    let temp_path = with_suffix(path, ".tmp");
+    let write_temp = || -> Result<()> {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        if path.exists() {
+            set_permissions(&temp_path, metadata(path)?.permissions())?;
+        }
+        Ok(())
+    };
+    if let Err(e) = write_temp() {
+        let _ = remove_file(&temp_path);
+        return Err(e.context(format!("Failed to write {}; it is left unchanged", path.display())));
+    }
+
+    if path.exists() {
+        let timestamp = Zoned::now().strftime("%Y%m%d_%H%M%S").to_string();
+        copy(path, with_suffix(path, &format!(".bak.{timestamp}")))?;
+        for (_, backup) in backups(path)?.into_iter().skip(MAX_BACKUPS) {
+            remove_file(backup)?;
+        }
+    }
+    rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// Maximum number of backups kept per settings file
+const MAX_BACKUPS: usize = 3;
+
+/// Lists the backups of the file, newest first
+///
+/// # Returns
+/// * `Result<Vec<(String, PathBuf)>>` - The timestamp and the path of each backup
+fn backups(path: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let prefix = format!("{}.bak.", path.file_name().unwrap_or_default().to_string_lossy());
+    let Ok(entries) = read_dir(path.parent().expect("the settings file is in a directory")) else {
+        return Ok(Vec::new());
+    };
+    let mut backups = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let timestamp = entry.file_name().to_str()?.strip_prefix(&prefix)?.to_string();
+            Some((timestamp, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    backups.sort_by(|a, b| b.0.cmp(&a.0));
+    Ok(backups)
+}
+
+/// Lists the backups of the settings file, or restores one of them
+///
+/// # Arguments
+/// * `file` - The settings file
+/// * `timestamp` - The timestamp of the backup to restore, or `None` to list them
+pub fn restore_settings(file: SettingsFile, timestamp: Option<&str>) -> Result<()> {
+    let path = file.path()?;
+    let backups = backups(&path)?;
+    let Some(timestamp) = timestamp else {
+        if backups.is_empty() {
+            println!("No backups of {}", path.display());
+        } else {
+            println!("Backups of {}, newest first:", path.display());
+            backups.iter().for_each(|(timestamp, _)| println!("  {timestamp}"));
+        }
+        return Ok(());
+    };
+
+    let (_, backup) = backups
+        .iter()
+        .find(|(t, _)| t == timestamp)
+        .ok_or_else(|| anyhow!("No backup of {} at {timestamp}", path.display()))?;
+    let content = read_to_string(backup)?;
+    replace_file(&path, &content)?;
+    println!("Restored {} from {}", path.display(), backup.display());
+    Ok(())
+}
+
+/// Quotes the argument for a POSIX shell, unless it consists of safe characters only
+pub fn shell_quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,@%+".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Finds the program on PATH, as the shell running the hook command would
+fn find_on_path(program: &str) -> Option<PathBuf> {
+    split_paths(&var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// The reference to the project directory in hook commands, which Claude Code sets
+const PROJECT_DIR: &str = "\"$CLAUDE_PROJECT_DIR\"";
+
+/// The references to the project directory at the start of an unquoted program
+const PROJECT_DIR_VARS: [&str; 2] = ["${CLAUDE_PROJECT_DIR}", "$CLAUDE_PROJECT_DIR"];
+
+/// How an installed hook command refers to the binary
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CommandStyle {
+    /// By its absolute path
+    Abs,
+    /// By its name, found on PATH
+    Path,
+    /// By a symlink to it at <repository_root>/.claude/bin, relative to $CLAUDE_PROJECT_DIR
+    ProjectRelative,
+}
+
+impl CommandStyle {
+    /// Detects the style of the unquoted program of a hook command
+    fn of(program: &str) -> Self {
+        if PROJECT_DIR_VARS.iter().any(|var| program.starts_with(var)) {
+            CommandStyle::ProjectRelative
+        } else if program.contains('/') {
+            CommandStyle::Abs
+        } else {
+            CommandStyle::Path
+        }
+    }
+
+    /// Gets the `install` flag selecting the style
+    fn install_flag(self) -> &'static str {
+        match self {
+            CommandStyle::Abs => "--command-style abs",
+            CommandStyle::Path => "--command-style path",
+            CommandStyle::ProjectRelative => "--command-style project-relative",
+        }
+    }
+}
+
+/// Gets the project directory hook commands run in
+fn project_dir() -> Option<PathBuf> {
+    var_os("CLAUDE_PROJECT_DIR")
+        .map(PathBuf::from)
+        .or_else(|| Repository::discover(".").ok()?.workdir().map(Path::to_path_buf))
+}
+
+/// Resolves the unquoted program of a hook command to the file it runs, as the shell running it
+/// would
+fn resolve_program(program: &str) -> Option<PathBuf> {
+    match CommandStyle::of(program) {
+        CommandStyle::Abs => Some(PathBuf::from(program)),
+        CommandStyle::Path => find_on_path(program),
+        CommandStyle::ProjectRelative => {
+            let relative = PROJECT_DIR_VARS.iter().find_map(|var| program.strip_prefix(var))?;
+            Some(project_dir()?.join(relative.trim_start_matches('/')))
+        }
+    }
+}
+
+/// Splits the hook command into its program, unquoted as a POSIX shell would but with variable
+/// references kept, and the rest of the command
+fn split_program(command: &str) -> Option<(String, &str)> {
+    let command = command.trim_start();
+    let mut program = String::new();
+    let mut quote = None;
+    let mut chars = command.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (None, c) if c.is_whitespace() => return Some((program, &command[i..])),
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '\\') => program.extend(chars.next().map(|(_, c)| c)),
+            // In double quotes, a backslash only escapes the characters special there
+            (Some('"'), '\\') => match chars.peek() {
+                Some(&(_, next @ ('$' | '`' | '"' | '\\'))) => {
+                    program.push(next);
+                    chars.next();
+                }
+                _ => program.push(c),
+            },
+            _ => program.push(c),
+        }
+    }
+    (!program.is_empty()).then_some((program, ""))
+}
+
+/// Checks whether the hook command runs the binary, directly, through a symlink, by its name on
+/// PATH, or relative to the project directory
+fn runs_binary(command: &str, binary: &Path) -> bool {
+    let Some(program) = split_program(command).and_then(|(program, _)| resolve_program(&program))
+    else {
+        return false;
+    };
+    program == binary
+        || canonicalize(program)
+            .is_ok_and(|program| canonicalize(binary).is_ok_and(|b| b == program))
+}
+
+/// Checks whether the hook runs the binary
+fn runs(hook: &HookCommand, binary: &Path) -> bool {
+    hook.command
+        .as_deref()
+        .is_some_and(|command| runs_binary(command, binary))
+}
+
+/// Checks whether the hook command runs another binary named like this one by its absolute path
+///
+/// # Returns
+/// * `Option<&str>` - The rest of the command after the program, if it does
+fn moved_program<'a>(command: &'a str, binary: &Path) -> Option<&'a str> {
+    let (program, rest) = split_program(command)?;
+    (CommandStyle::of(&program) == CommandStyle::Abs
+        && Path::new(&program).file_name() == binary.file_name()
+        && !runs_binary(command, binary))
+    .then_some(rest)
+}
+
+/// A hook command running a binary named like this one that doesn't resolve on this machine
+pub struct DanglingCommand {
+    /// The name of the event
+    pub event: String,
+    /// The hook command
+    pub command: String,
+    /// How the command refers to the binary
+    pub style: CommandStyle,
+}
+
+impl DanglingCommand {
+    /// Gets the advice to fix the command
+    ///
+    /// # Arguments
+    /// * `file` - The settings file the command is in
+    pub fn advice(&self, file: SettingsFile) -> Result<String> {
+        let binary = current_exe()?;
+        let name = binary.file_name().unwrap_or_default().to_string_lossy();
+        Ok(match self.style {
+            CommandStyle::Abs => format!(
+                "run `{} install --update{}` to point it at this binary",
+                binary.display(),
+                file.install_flag()
+            ),
+            CommandStyle::Path => format!(
+                "put `{name}` on PATH, or run `{} install {}{}`",
+                binary.display(),
+                CommandStyle::Abs.install_flag(),
+                file.install_flag()
+            ),
+            CommandStyle::ProjectRelative => format!(
+                "run `{} install {}{}` to link .claude/bin/{name} to this binary",
+                binary.display(),
+                self.style.install_flag(),
+                file.install_flag()
+            ),
+        })
+    }
+}
+
+/// Finds the hook commands in the settings file that run a binary named like this one that
+/// doesn't exist on this machine
+pub fn dangling_commands(settings_path: &Path) -> Result<Vec<DanglingCommand>> {
+    let binary = current_exe()?;
+    let settings = read_settings(settings_path)?;
+    Ok(settings
+        .commands()
+        .filter_map(|(event, hook)| {
+            let command = hook.command.as_deref()?;
+            let (program, _) = split_program(command)?;
+            (Path::new(&program).file_name() == binary.file_name()
+                && resolve_program(&program).is_none_or(|path| !path.is_file()))
+            .then(|| DanglingCommand {
+                event: event.to_string(),
+                command: command.to_string(),
+                style: CommandStyle::of(&program),
+            })
+        })
+        .collect())
+}
+
+/// A hook of this binary in a settings file
+pub struct InstalledHook {
+    /// The name of the event
+    pub event: &'static str,
+    /// The hook command
+    pub command: String,
+    /// The matcher of the tools triggering the hook, if any
+    pub matcher: Option<String>,
+    /// The timeout in seconds, if set
+    pub timeout: Option<u64>,
+}
+
+/// Finds the hooks of this binary in the settings file, one per event
+pub fn installed_hooks(settings_path: &Path) -> Result<Vec<InstalledHook>> {
+    let binary = current_exe()?;
+    let settings = read_settings(settings_path)?;
+    let Some(hooks) = &settings.hooks else {
+        return Ok(Vec::new());
+    };
+    Ok(HookKind::value_variants()
+        .iter()
+        .filter_map(|kind| {
+            hooks.event(kind.event_name()).iter().find_map(|group| {
+                let hook = group.hooks.first()?;
+                let command = hook.command.as_deref()?;
+                runs_binary(command, &binary).then(|| InstalledHook {
+                    event: kind.event_name(),
+                    command: command.to_string(),
+                    matcher: group.matcher.clone(),
+                    timeout: hook.timeout,
+                })
+            })
+        })
+        .collect())
+}
+
+/// Removes the hooks of this binary from the settings files
+///
+/// # Arguments
+/// * `global` - Whether to remove them from the user-global settings file instead of the
+///   repository's ones
+/// * `dry_run` - Whether to print the diff of the change instead of writing it
+pub fn uninstall_hook(global: bool, dry_run: bool) -> Result<()> {
+    let files: &[SettingsFile] =
+        if global { &[SettingsFile::Global] } else { &[SettingsFile::Local, SettingsFile::Shared] };
+    for file in files {
+        remove_hooks(&file.path()?, dry_run)?;
+    }
+    Ok(())
+}
+
+/// Removes the hooks of this binary from the settings file
+fn remove_hooks(settings_path: &Path, dry_run: bool) -> Result<()> {
+    let binary = current_exe()?;
+    let mut settings = read_settings(settings_path)?;
+
+    let mut removed = 0;
+    if let Some(hooks) = &mut settings.hooks {
+        for (_, groups) in hooks.iter_mut() {
+            removed += remove_binary_hooks(groups, &binary, None);
+        }
+        hooks.remove_empty();
+    }
+
+    if removed == 0 {
+        println!("No hooks of {} in {}", binary.display(), settings_path.display());
+        return Ok(());
+    }
+    println!("Removed {removed} hooks from {}", settings_path.display());
+    write_settings(settings_path, &settings, dry_run)
+}
+
+/// Removes the hooks running the binary from the matcher groups, then the groups they leave
+/// without hooks, keeping the other hooks of the groups
+///
+/// # Arguments
+/// * `groups` - The matcher groups of an event
+/// * `binary` - The binary whose hooks to remove
+/// * `keep` - The group and position in it of a hook of the binary to keep, if any
+///
+/// # Returns
+/// The number of hooks removed
+fn remove_binary_hooks(
+    groups: &mut Vec<MatcherGroup>,
+    binary: &Path,
+    keep: Option<(usize, usize)>,
+) -> usize {
+    let mut removed = 0;
+    let mut index = 0;
+    groups.retain_mut(|group| {
+        let before = group.hooks.len();
+        let mut position = 0;
+        group.hooks.retain(|hook| {
+            let kept = !runs(hook, binary) || keep == Some((index, position));
+            position += 1;
+            kept
+        });
+        removed += before - group.hooks.len();
+        index += 1;
+        group.hooks.len() == before || !group.hooks.is_empty()
+    });
+    removed
+}
+
+/// Points the hooks running another binary named like this one at this binary, keeping their
+/// arguments and timeouts
+///
+/// # Arguments
+/// * `settings_path` - The settings file to update
+/// * `dry_run` - Whether to print the diff of the change instead of writing it
+fn update_hooks(settings_path: &Path, dry_run: bool) -> Result<InstallOutcome> {
+    let binary = current_exe()?;
+    let mut settings = read_settings(settings_path)?;
+
+    let mut updated = 0;
+    for (event, groups) in settings.hooks.iter_mut().flat_map(|hooks| hooks.iter_mut()) {
+        for hook in groups.iter_mut().flat_map(|group| &mut group.hooks) {
+            let Some(command) = &hook.command else {
+                continue;
+            };
+            let Some(rest) = moved_program(command, &binary) else {
+                continue;
+            };
+            let new_command = format!("{}{rest}", shell_quote(&binary.to_string_lossy()));
+            println!(
+                "{event} hook updated in {}: {command} -> {new_command}",
+                settings_path.display()
+            );
+            hook.command = Some(new_command);
+            updated += 1;
+        }
+    }
+
+    if updated == 0 {
+        println!(
+            "No hooks of other {} binaries in {}",
+            binary.file_name().unwrap_or_default().to_string_lossy(),
+            settings_path.display()
+        );
+        return Ok(InstallOutcome::AlreadyInstalled);
+    }
+    write_settings(settings_path, &settings, dry_run)?;
+    Ok(InstallOutcome::Installed)
+}
+
+/// Links `<repository_root>/.claude/bin/<name>` to the binary for project-relative hook commands
+///
+/// # Arguments
+/// * `binary` - The binary to link to
+/// * `name` - The file name of the binary
+/// * `dry_run` - Whether to only print the link that would be made
+fn link_binary(binary: &Path, name: &str, dry_run: bool) -> Result<()> {
+    let link = Repository::discover(".")?
+        .workdir()
+        .ok_or_else(|| anyhow!("Repository has no working directory (bare repo?)"))?
+        .join(".claude")
+        .join("bin")
+        .join(name);
+    if read_link(&link).is_ok_and(|target| target == binary) {
+        return Ok(());
+    }
+    if dry_run {
+        println!("Dry run: {} would be linked to {}", link.display(), binary.display());
+        return Ok(());
+    }
+    create_dir_all(link.parent().expect("the link is in .claude/bin"))?;
+    if link.symlink_metadata().is_ok() {
+        remove_file(&link)?;
+    }
+    symlink(binary, &link)?;
+    println!("Linked {} to {}", link.display(), binary.display());
+    Ok(())
+}
+
+/// Creates a symlink to the binary
+#[cfg(unix)]
+fn symlink(binary: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(binary, link)
+}
+
+/// Creates a symlink to the binary, which requires Developer Mode or administrator rights
+#[cfg(windows)]
+fn symlink(binary: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(binary, link)
+}
+
+/// What installing the hooks into a repository did
+#[derive(Clone, Copy)]
+enum InstallOutcome {
+    /// The settings file was changed
+    Installed,
+    /// The hooks were already installed as requested
+    AlreadyInstalled,
+}
+
+/// Depth down to which `install --recursive` looks for repositories
+const RECURSIVE_MAX_DEPTH: usize = 4;
+
+/// Directories `install --recursive` doesn't look into, besides hidden ones
+const RECURSIVE_SKIPPED_DIRS: [&str; 2] = ["node_modules", "target"];
+
+/// Finds the git work trees in the directory, not looking into the work trees themselves
+///
+/// # Arguments
+/// * `dir` - The directory to look in
+/// * `depth` - How many more levels of subdirectories to look into
+/// * `repos` - The work trees found so far
+fn find_repos(dir: &Path, depth: usize, repos: &mut Vec<PathBuf>) {
+    if dir.join(".git").exists() {
+        repos.push(dir.to_path_buf());
+        return;
+    }
+    let Ok(entries) = read_dir(dir) else {
+        return;
+    };
+    let mut subdirs = entries
+        .filter_map(Result::ok)
+        // Symlinks are not followed, so that cycles end
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            !name.starts_with('.') && !RECURSIVE_SKIPPED_DIRS.contains(&name.as_str())
+        })
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    subdirs.sort();
+    if depth > 0 {
+        subdirs.iter().for_each(|subdir| find_repos(subdir, depth - 1, repos));
+    }
+}
+
+/// Adds the hooks of this binary to the settings file of each repository under the directory
+///
+/// A failure in one repository is reported in the summary and doesn't stop the others.
+fn install_recursive(
+    language: Option<&str>,
+    profile: Option<&str>,
+    install: &InstallArgs,
+    dir: &Path,
+) -> Result<()> {
+    let dir = canonicalize(dir).with_context(|| format!("Failed to open {}", dir.display()))?;
+    let mut repos = Vec::new();
+    find_repos(&dir, RECURSIVE_MAX_DEPTH, &mut repos);
+
+    let mut results = Vec::new();
+    for repo in repos {
+        if !install.filter.is_empty() && first_match(&install.filter, &repo)?.is_none() {
+            continue;
+        }
+        println!("{}:", repo.display());
+        let outcome = set_current_dir(&repo)
+            .map_err(Into::into)
+            .and_then(|_| install_into(language, profile, install));
+        results.push((repo, outcome));
+    }
+
+    println!();
+    let width = results
+        .iter()
+        .map(|(repo, _)| repo.as_os_str().len())
+        .max()
+        .unwrap_or(0);
+    for (repo, outcome) in &results {
+        let result = match outcome {
+            Ok(InstallOutcome::Installed) => "installed".to_string(),
+            Ok(InstallOutcome::AlreadyInstalled) => "already installed".to_string(),
+            Err(e) => format!("failed: {e:#}"),
+        };
+        println!("{:width$}  {result}", repo.display());
+    }
+    println!(
+        "{} repositories, {} failed",
+        results.len(),
+        results.iter().filter(|(_, outcome)| outcome.is_err()).count()
+    );
+    Ok(())
+}
+
+/// Adds the hooks of this binary to the settings file
+///
+/// # Arguments
+/// * `language` - The language to bake into the hook command, if given on the command line
+/// * `profile` - The profile to bake into the hook command, if given on the command line
+/// * `install` - The arguments of the `install` command
+pub fn install_hook(
+    language: Option<&str>,
+    profile: Option<&str>,
+    install: &InstallArgs,
+) -> Result<()> {
+    match &install.recursive {
+        Some(dir) => install_recursive(language, profile, install, dir),
+        None => install_into(language, profile, install).map(|_| ()),
+    }
+}
+
+/// Adds the hooks of this binary to the settings file of the current repository, or the
+/// user-global one
+fn install_into(
+    language: Option<&str>,
+    profile: Option<&str>,
+    install: &InstallArgs,
+) -> Result<InstallOutcome> {
+    let target = install.target();
+    let settings_path = target.path()?;
+    if install.update {
+        return update_hooks(&settings_path, install.dry_run);
+    }
+
+    // Read existing settings or create an empty object
+    let mut settings = read_settings(&settings_path)?;
+
+    let binary = current_exe()?;
+    let name = binary
+        .file_name()
+        .ok_or_else(|| anyhow!("Binary has no file name"))?
+        .to_string_lossy()
+        .to_string();
+    // The shared file is committed, so by default it runs the binary by name rather than by a path
+    // under one developer's home directory
+    let style = install.command_style.unwrap_or(if target == SettingsFile::Shared {
+        CommandStyle::Path
+    } else {
+        CommandStyle::Abs
+    });
+    let mut command = match style {
+        CommandStyle::Abs => shell_quote(&binary.to_string_lossy()),
+        CommandStyle::Path => {
+            if !find_on_path(&name)
+                .is_some_and(|path| runs_binary(&shell_quote(&path.to_string_lossy()), &binary))
+            {
+                println!(
+                    "Warning: `{name}` on PATH is not {}; the hooks run whatever `{name}` is on \
+                     PATH",
+                    binary.display()
+                );
+            }
+            shell_quote(&name)
+        }
+        CommandStyle::ProjectRelative => {
+            if target == SettingsFile::Global {
+                bail!("The project-relative command style needs a repository settings file");
+            }
+            link_binary(&binary, &name, install.dry_run)?;
+            format!("{PROJECT_DIR}/.claude/bin/{}", shell_quote(&name))
+        }
+    };
+    // Only an explicit language and profile are baked into the command, so that the config files
+    // can set them
+    if let Some(language) = language {
+        command.push_str(&format!(" --language {}", shell_quote(language)));
+    }
+    if let Some(profile) = profile {
+        command.push_str(&format!(" --profile {}", shell_quote(profile)));
+    }
+    for arg in CONFIG.install.extra_args.iter().chain(&install.extra_args) {
+        command.push_str(&format!(" {}", shell_quote(arg)));
+    }
+
+    let mut changed = false;
+    for kind in install.events() {
+        // Create the new hook entry
+        let new_hook = kind.entry(&command, &install.matcher, install.timeout);
+
+        // Check if there's already a hook for this binary
+        let groups = settings.hooks_mut().event_mut(kind.event_name());
+
+        // Look for existing hooks running this binary, by group and position in it
+        let existing = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(index, group)| {
+                group
+                    .hooks
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, hook)| runs(hook, &binary))
+                    .map(move |(position, _)| (index, position))
+            })
+            .collect::<Vec<_>>();
+
+        let event = kind.event_name();
+        match existing.first() {
+            Some(&(index, position)) if !install.force => {
+                // Check if the command, matcher, and timeout are already correct, leaving a timeout
+                // alone unless one is given
+                let mut keep = (index, position);
+                let group = &mut groups[index];
+                let hook = &mut group.hooks[position];
+                if hook.command.as_deref() == Some(&command)
+                    && group.matcher == new_hook.matcher
+                    && install.timeout.is_none_or(|timeout| hook.timeout == Some(timeout))
+                {
+                    println!("{event} hook already installed in {}", settings_path.display());
+                } else {
+                    changed = true;
+                    // Update the hook with the new command and timeout, leaving the other hooks of
+                    // its group alone
+                    hook.command = Some(command.clone());
+                    if let Some(timeout) = install.timeout {
+                        hook.timeout = Some(timeout);
+                    }
+                    if new_hook.matcher.is_some() && group.matcher != new_hook.matcher {
+                        if group.hooks.len() == 1 {
+                            group.matcher = new_hook.matcher;
+                        } else {
+                            // The other hooks of the group keep their matcher
+                            let hook = group.hooks.remove(position);
+                            groups.push(MatcherGroup::new(new_hook.matcher.as_deref(), hook));
+                            keep = (groups.len() - 1, 0);
+                        }
+                    }
+                    println!("{event} hook updated in {}", settings_path.display());
+                }
+                // Running the hook more than once per event would race on the index
+                let duplicates = remove_binary_hooks(groups, &binary, Some(keep));
+                if duplicates > 0 {
+                    changed = true;
+                    println!("Removed {duplicates} duplicate {event} hooks");
+                }
+            }
+            _ => {
+                // Add new hook
+                changed = true;
+                groups.push(new_hook);
+                println!("{event} hook installed successfully to {}", settings_path.display());
+            }
+        }
+    }
+
+    if !changed {
+        return Ok(InstallOutcome::AlreadyInstalled);
+    }
+    write_settings(&settings_path, &settings, install.dry_run)?;
+    if install.dry_run {
+        return Ok(InstallOutcome::Installed);
+    }
+
+    // Outside a repository, there are no repository settings files to check
+    for other in SettingsFile::ALL.into_iter().filter(|&file| file != target) {
+        if let Ok(other) = other.path()
+            && !installed_hooks(&other)?.is_empty()
+        {
+            println!(
+                "Warning: {} also has hooks of this binary, which then run twice per event",
+                other.display()
+            );
+        }
+    }
+    if target == SettingsFile::Global {
+        println!(
+            "The hooks now run in every project. To limit them to some repositories, set \
+             [repos] allow or deny in {}",
+            LoadedConfig::user_path()
+                .map_or("the user config".to_string(), |p| p.display().to_string())
+        );
+    }
+
+    Ok(InstallOutcome::Installed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn session_start_timeout_exceeds_the_foreground_deadline() {
+        let config =
+            toml::from_str::<Config>(include_str!("../assets/commit-config.toml")).unwrap();
+        assert!(config.foreground_deadline_secs < HookKind::SessionStart.default_timeout());
+    }
+}
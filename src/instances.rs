@@ -0,0 +1,270 @@
+use std::{
+    fs::{create_dir_all, metadata, read_dir, read_to_string, remove_file, write},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+/// How long `kill` waits for a process to exit after SIGTERM before sending SIGKILL
+const TERM_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// How often `kill` checks whether a process has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The PID file this process registered, if any
+static REGISTERED: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// A background process handling a hook event, as recorded in its PID file
+#[derive(Serialize, Deserialize)]
+pub struct Instance {
+    /// The process ID
+    pub pid: u32,
+    /// When the process started, as `process_start` reports it, to tell it from a later process
+    /// reusing the ID
+    pub started: String,
+    /// When the PID file was written, in RFC 3339 format
+    pub timestamp: String,
+    /// The name of the hook event the process handles
+    pub event: String,
+    /// Since when the process may hold the index lock, in RFC 3339 format, while it is staging or
+    /// committing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub holding_index: Option<String>,
+}
+
+impl Instance {
+    /// Describes this process as handling the hook event
+    ///
+    /// # Arguments
+    /// * `event` - The name of the hook event
+    pub fn current(event: &str) -> Self {
+        let pid = std::process::id();
+        Self {
+            pid,
+            started: process_start(pid).unwrap_or_default(),
+            timestamp: Timestamp::now().to_string(),
+            event: event.to_string(),
+            holding_index: None,
+        }
+    }
+
+    /// Checks whether the process is still running, rather than another one reusing its ID
+    pub fn is_alive(&self) -> bool {
+        process_start(self.pid).is_some_and(|started| started == self.started)
+    }
+}
+
+/// Gets when the process started, as `ps -o lstart=` reports it
+#[cfg(unix)]
+fn process_start(pid: u32) -> Option<String> {
+    let output = Command::new("ps")
+        .args(["-o", "lstart=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let started = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !started.is_empty()).then_some(started)
+}
+
+/// Gets when the process started, as PowerShell's `Get-Process` reports it
+#[cfg(windows)]
+fn process_start(pid: u32) -> Option<String> {
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-Command"])
+        .arg(format!("(Get-Process -Id {pid}).StartTime.ToString('o')"))
+        .output()
+        .ok()?;
+    let started = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !started.is_empty()).then_some(started)
+}
+
+/// Asks the process to exit with SIGTERM, sending SIGKILL if it is still running after a grace
+/// period
+#[cfg(unix)]
+fn terminate(instance: &Instance) {
+    let pid = instance.pid as libc::pid_t;
+    // SAFETY: `kill` has no memory safety preconditions. The process was checked to be the
+    // recorded one right before.
+    unsafe { libc::kill(pid, libc::SIGTERM) };
+    let deadline = Instant::now() + TERM_GRACE_PERIOD;
+    while instance.is_alive() {
+        if Instant::now() >= deadline {
+            // SAFETY: As above
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+            break;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Terminates the process and the processes it spawned, as Windows has no signal to ask it to exit
+#[cfg(windows)]
+fn terminate(instance: &Instance) {
+    let _ = Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &instance.pid.to_string()])
+        .output();
+    let deadline = Instant::now() + TERM_GRACE_PERIOD;
+    while instance.is_alive() && Instant::now() < deadline {
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Removes the PID file of this process when dropped
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.path);
+    }
+}
+
+/// The PID files of the background processes handling hook events in a repository
+pub struct Instances {
+    dir: PathBuf,
+}
+
+impl Instances {
+    /// Creates the PID files stored in the given directory
+    ///
+    /// # Arguments
+    /// * `dir` - The directory of the PID files; created on first registration
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Records this process as handling the hook event
+    ///
+    /// # Arguments
+    /// * `event` - The name of the hook event
+    ///
+    /// # Returns
+    /// The PID file, removed when dropped at the end of the process
+    pub fn register(&self, event: &str) -> Result<PidFile> {
+        let instance = Instance::current(event);
+        create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{}.json", instance.pid));
+        write(&path, serde_json::to_string(&instance)?)?;
+        if let Ok(mut registered) = REGISTERED.lock() {
+            *registered = Some(path.clone());
+        }
+        Ok(PidFile { path })
+    }
+
+    /// Lists the processes that are still running, removing the PID files of the others
+    pub fn live(&self) -> Vec<Instance> {
+        let Ok(entries) = read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut instances = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let instance = read_to_string(&path)
+                    .ok()
+                    .and_then(|content| serde_json::from_str::<Instance>(&content).ok());
+                match instance {
+                    Some(instance) if instance.is_alive() => Some(instance),
+                    _ => {
+                        let _ = remove_file(&path);
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        instances.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        instances
+    }
+
+    /// Terminates a running process and removes its PID file, along with the index lock of the
+    /// repository if the process left it behind
+    ///
+    /// # Arguments
+    /// * `instance` - The process to terminate
+    /// * `index_lock` - The path of the index lock of the repository
+    pub fn kill(&self, instance: &Instance, index_lock: &Path) -> Result<()> {
+        terminate(instance);
+        // Still there only if the process didn't shut down gracefully, recording whether it was
+        // holding the index lock then
+        let path = self.dir.join(format!("{}.json", instance.pid));
+        let holding = read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Instance>(&content).ok())
+            .and_then(|instance| instance.holding_index);
+        let _ = remove_file(&path);
+        remove_own_lock(index_lock, holding.map(|since| since.parse()).transpose()?)
+    }
+}
+
+/// Records in the PID file of this process, if it registered one, since when it may hold the index
+/// lock, so that `ccc kill` knows whether a lock left behind is its own
+///
+/// # Arguments
+/// * `since` - When the process started staging or committing, or `None` once it is done
+pub fn record_holding_index(since: Option<Timestamp>) {
+    let Some(path) = REGISTERED.lock().ok().and_then(|registered| registered.clone()) else {
+        return;
+    };
+    let Some(mut instance) = read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<Instance>(&content).ok())
+    else {
+        return;
+    };
+    instance.holding_index = since.map(|since| since.to_string());
+    if let Ok(content) = serde_json::to_string(&instance) {
+        let _ = write(&path, content);
+    }
+}
+
+/// Removes the index lock of the repository if a process left it behind: only one the process was
+/// holding, i.e., created since it started staging or committing, as git removes its locks and
+/// another process may hold one
+///
+/// # Arguments
+/// * `index_lock` - The path of the index lock of the repository
+/// * `holding_since` - When the process started staging or committing, or `None` if it wasn't
+pub fn remove_own_lock(index_lock: &Path, holding_since: Option<Timestamp>) -> Result<()> {
+    let Some(since) = holding_since else {
+        return Ok(());
+    };
+    if metadata(index_lock)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| Timestamp::try_from(modified).is_ok_and(|m| m >= since))
+    {
+        remove_file(index_lock)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_dir_all, process};
+
+    use super::*;
+
+    #[test]
+    fn only_a_lock_taken_while_holding_the_index_is_removed() {
+        let dir = temp_dir().join(format!("ccc-test-own-lock-{}", process::id()));
+        create_dir_all(&dir).unwrap();
+        let index_lock = dir.join("index.lock");
+        write(&index_lock, "").unwrap();
+
+        // Neither while not staging or committing, nor one another process took before
+        remove_own_lock(&index_lock, None).unwrap();
+        assert!(index_lock.exists());
+        let later = Timestamp::now() + Duration::from_secs(60);
+        remove_own_lock(&index_lock, Some(later)).unwrap();
+        assert!(index_lock.exists());
+
+        let earlier = Timestamp::now() - Duration::from_secs(60);
+        remove_own_lock(&index_lock, Some(earlier)).unwrap();
+        assert!(!index_lock.exists());
+        remove_dir_all(dir).unwrap();
+    }
+}
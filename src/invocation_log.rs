@@ -0,0 +1,135 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    fs::{OpenOptions, create_dir_all, read_to_string, write},
+    io::Write,
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Number of stderr characters kept per invocation
+const MAX_STDERR_CHARS: usize = 2000;
+
+/// Number of invocations kept in the log; older ones are dropped when it grows to twice as many
+const MAX_ENTRIES: usize = 100;
+
+/// How an invocation of the generator command ended
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// Exited successfully
+    Succeeded,
+    /// The command couldn't be found
+    NotFound,
+    /// Ran, but exited unsuccessfully
+    Failed,
+    /// Killed after the timeout
+    TimedOut,
+    /// Killed on a shutdown request
+    Interrupted,
+    /// Couldn't be started for another reason
+    Error,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Succeeded => "succeeded",
+            Outcome::NotFound => "not found",
+            Outcome::Failed => "failed",
+            Outcome::TimedOut => "timed out",
+            Outcome::Interrupted => "interrupted",
+            Outcome::Error => "could not be started",
+        })
+    }
+}
+
+/// A single invocation of the generator command
+#[derive(Serialize, Deserialize)]
+pub struct Invocation {
+    /// When the invocation finished, in RFC 3339 format
+    pub timestamp: String,
+    /// The program that was run
+    pub command: String,
+    pub outcome: Outcome,
+    /// The exit code, if the command exited normally
+    pub exit_code: Option<i32>,
+    /// Wall-clock duration in milliseconds
+    pub duration_ms: u64,
+    /// The stderr of the command, or the reason it couldn't be run, truncated
+    pub stderr: String,
+}
+
+impl Invocation {
+    /// Creates a record of an invocation that just finished
+    ///
+    /// # Arguments
+    /// * `command` - The program that was run
+    /// * `outcome` - How the invocation ended
+    /// * `exit_code` - The exit code, if the command exited normally
+    /// * `duration_ms` - Wall-clock duration in milliseconds
+    /// * `stderr` - The stderr of the command or the error; truncated to `MAX_STDERR_CHARS`
+    pub fn new(
+        command: &str,
+        outcome: Outcome,
+        exit_code: Option<i32>,
+        duration_ms: u64,
+        stderr: &str,
+    ) -> Self {
+        let stderr = stderr.trim();
+        let stderr = match stderr.char_indices().nth(MAX_STDERR_CHARS) {
+            Some((end, _)) => format!("{}[... truncated ...]", &stderr[..end]),
+            None => stderr.to_string(),
+        };
+        Self {
+            timestamp: jiff::Timestamp::now().to_string(),
+            command: command.to_string(),
+            outcome,
+            exit_code,
+            duration_ms,
+            stderr,
+        }
+    }
+}
+
+/// Per-repository log of generator command invocations, one JSON object per line
+pub struct InvocationLog {
+    path: PathBuf,
+}
+
+impl InvocationLog {
+    /// Creates a log stored in the given file
+    ///
+    /// # Arguments
+    /// * `path` - The log file; created on first write
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Appends an invocation, dropping the oldest ones once the log grows too long
+    pub fn append(&self, invocation: &Invocation) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(invocation)?)?;
+
+        let content = read_to_string(&self.path)?;
+        let lines = content.lines().collect::<Vec<_>>();
+        if lines.len() >= MAX_ENTRIES * 2 {
+            write(&self.path, lines[lines.len() - MAX_ENTRIES..].join("\n") + "\n")?;
+        }
+        Ok(())
+    }
+
+    /// Gets the most recent invocation that didn't succeed
+    pub fn last_failure(&self) -> Option<Invocation> {
+        read_to_string(&self.path)
+            .ok()?
+            .lines()
+            .rev()
+            .filter_map(|line| serde_json::from_str::<Invocation>(line).ok())
+            .find(|invocation| invocation.outcome != Outcome::Succeeded)
+    }
+}
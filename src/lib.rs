@@ -0,0 +1,52 @@
+//! Commits the changes Claude Code makes, with messages generated from the staged diff
+//!
+//! The `ccc` binary is a thin command line wrapper around this library: it reads the hook event
+//! from stdin and hands it to a [`Committer`], which stages the changed files and commits them
+//! with a message from a [`MessageGenerator`], by default the configured generator command.
+
+pub mod audit_log;
+mod batch;
+mod binary;
+mod cache;
+pub mod ci;
+mod claude_settings;
+pub mod commit_message_generator;
+pub mod committer;
+pub mod config;
+mod conflicts;
+mod daemon_log;
+mod debounce;
+mod fallback;
+mod file_lock;
+pub mod git_ops;
+pub mod hook_output;
+pub mod install;
+mod instances;
+pub mod invocation_log;
+mod locale;
+pub mod logging;
+mod notify;
+pub mod opt_in;
+mod output;
+mod process;
+mod proposals;
+pub mod pull_request;
+mod rate_limit;
+mod redact;
+mod secrets;
+mod sessions;
+pub mod shutdown;
+mod staged_paths;
+pub mod statusline;
+mod template;
+pub mod tokens;
+pub mod trailers;
+pub mod transcript;
+pub mod types;
+pub mod watchdog;
+mod webhook;
+#[cfg(unix)]
+pub mod worker;
+
+pub use commit_message_generator::{CommitMessageGenerator, MessageGenerator};
+pub use committer::Committer;
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// The tool's own fixed strings in a language, each of which can be overridden in config
+#[derive(Deserialize, JsonSchema, Default, Clone)]
+pub struct LocalizedStrings {
+    /// Subject of the fallback commit message when `generator.default_commit_message` is unset
+    pub fallback_subject: Option<String>,
+    /// Appended to a diff cut short to fit the prompt
+    pub truncation_marker: Option<String>,
+    /// Appended to a diff when files were dropped to fit the prompt. Placeholder: {count}.
+    pub omitted_files: Option<String>,
+    /// Appended to the message of a commit of changes the user hand-edited before they were
+    /// applied
+    pub user_modified_note: Option<String>,
+    /// Appended to the message of a commit of binary files with `binary_policy = "warn"`.
+    /// Placeholder: {files}.
+    pub binary_files_note: Option<String>,
+    /// Appended to a diff when files matching `paths` were left out of it. Placeholder: {count}.
+    pub excluded_files: Option<String>,
+}
+
+/// The tool's own fixed strings, resolved for a language
+pub struct Strings {
+    pub fallback_subject: String,
+    pub truncation_marker: String,
+    pub omitted_files: String,
+    pub user_modified_note: String,
+    pub binary_files_note: String,
+    pub excluded_files: String,
+}
+
+impl Strings {
+    /// Resolves the strings for the language, preferring overrides from config
+    ///
+    /// # Arguments
+    /// - `language` - The language messages are generated in, e.g., "Japanese" or "ja"
+    /// - `overrides` - Strings from config, keyed by normalized language (e.g., "ja")
+    ///
+    /// # Returns
+    /// The strings, with those missing from both the overrides and the built-in table for the
+    /// language taken from English
+    pub fn new(language: &str, overrides: &HashMap<String, LocalizedStrings>) -> Self {
+        let code = normalize(language);
+        let english = builtin("en");
+        let native = builtin(&code);
+        let configured = overrides.get(&code).cloned().unwrap_or_default();
+        let pick = |get: fn(&LocalizedStrings) -> &Option<String>| {
+            get(&configured)
+                .clone()
+                .or_else(|| get(&native).clone())
+                .or_else(|| get(&english).clone())
+                .unwrap_or_default()
+        };
+
+        Self {
+            fallback_subject: pick(|s| &s.fallback_subject),
+            truncation_marker: pick(|s| &s.truncation_marker),
+            omitted_files: pick(|s| &s.omitted_files),
+            user_modified_note: pick(|s| &s.user_modified_note),
+            binary_files_note: pick(|s| &s.binary_files_note),
+            excluded_files: pick(|s| &s.excluded_files),
+        }
+    }
+}
+
+/// Normalizes a language name to a short code for looking up strings
+///
+/// # Arguments
+/// - `language` - A language name in English or natively, or a locale such as "ja_JP.UTF-8"
+///
+/// # Returns
+/// The ISO 639-1 code for known languages, otherwise the name in lowercase
+pub fn normalize(language: &str) -> String {
+    let language = language.trim().to_lowercase();
+    let base = language.split(['_', '-', '.']).next().unwrap_or_default();
+    match base {
+        "english" | "en" => "en",
+        "japanese" | "ja" | "日本語" => "ja",
+        _ => return language,
+    }
+    .to_string()
+}
+
+/// Languages detected from the system locale, by language code
+const LOCALE_LANGUAGES: &[(&str, &str)] = &[
+    ("en", "English"),
+    ("ja", "Japanese"),
+    ("zh", "Chinese"),
+    ("ko", "Korean"),
+    ("de", "German"),
+    ("fr", "French"),
+    ("es", "Spanish"),
+    ("it", "Italian"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+];
+
+/// Maps a locale, e.g., "ja_JP.UTF-8", to the name of its language
+///
+/// # Arguments
+/// - `locale` - The value of `LC_ALL`, `LC_MESSAGES`, or `LANG`
+///
+/// # Returns
+/// The name of the language, English for the "C" and "POSIX" locales, or `None` for unknown
+/// languages
+pub fn language_of_locale(locale: &str) -> Option<&'static str> {
+    let locale = locale.trim();
+    let code = locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    match code.as_str() {
+        "c" | "posix" => Some("English"),
+        _ => LOCALE_LANGUAGES
+            .iter()
+            .find(|(known, _)| *known == code)
+            .map(|(_, name)| *name),
+    }
+}
+
+/// Gets the built-in strings for the normalized language, all `None` for unknown ones
+fn builtin(code: &str) -> LocalizedStrings {
+    let strings = |fallback_subject: &str,
+                   truncation_marker: &str,
+                   omitted_files: &str,
+                   user_modified_note: &str,
+                   binary_files_note: &str,
+                   excluded_files: &str| LocalizedStrings {
+        fallback_subject: Some(fallback_subject.to_string()),
+        truncation_marker: Some(truncation_marker.to_string()),
+        omitted_files: Some(omitted_files.to_string()),
+        user_modified_note: Some(user_modified_note.to_string()),
+        binary_files_note: Some(binary_files_note.to_string()),
+        excluded_files: Some(excluded_files.to_string()),
+    };
+    match code {
+        "en" => strings(
+            "WARNING: commit message generation failure",
+            "[... truncated ...]",
+            "[... {count} more files omitted ...]",
+            "Note: the user hand-edited the change before it was applied.",
+            "Note: this commit includes binary files: {files}",
+            "[... {count} vendored or generated files excluded ...]",
+        ),
+        "ja" => strings(
+            "警告: コミットメッセージの生成に失敗しました",
+            "[... 以下省略 ...]",
+            "[... 他 {count} ファイル省略 ...]",
+            "注: ユーザーが適用前に変更を手作業で編集しました。",
+            "注: このコミットにはバイナリファイルが含まれます: {files}",
+            "[... ベンダー・生成ファイル {count} 件を除外 ...]",
+        ),
+        _ => LocalizedStrings::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages_are_normalized_to_codes() {
+        for (language, code) in [
+            ("en", "en"),
+            ("English", "en"),
+            (" ENGLISH ", "en"),
+            ("en_US.UTF-8", "en"),
+            ("ja", "ja"),
+            ("Japanese", "ja"),
+            ("日本語", "ja"),
+            ("ja-JP", "ja"),
+            ("ja_JP.UTF-8", "ja"),
+            ("French", "french"),
+            ("pt_BR", "pt_br"),
+        ] {
+            assert_eq!(normalize(language), code, "{language}");
+        }
+    }
+
+    #[test]
+    fn locales_are_mapped_to_language_names() {
+        for (locale, language) in [
+            ("ja_JP.UTF-8", Some("Japanese")),
+            ("de_DE@euro", Some("German")),
+            ("zh-Hans", Some("Chinese")),
+            ("C", Some("English")),
+            ("POSIX", Some("English")),
+            ("C.UTF-8", Some("English")),
+            ("xx_YY", None),
+            ("", None),
+        ] {
+            assert_eq!(language_of_locale(locale), language, "{locale}");
+        }
+    }
+
+    #[test]
+    fn strings_are_built_in_for_english_and_japanese() {
+        let english = Strings::new("English", &HashMap::new());
+        assert_eq!(english.fallback_subject, "WARNING: commit message generation failure");
+        assert_eq!(english.truncation_marker, "[... truncated ...]");
+
+        let japanese = Strings::new("ja_JP.UTF-8", &HashMap::new());
+        assert_eq!(japanese.fallback_subject, "警告: コミットメッセージの生成に失敗しました");
+        assert_eq!(japanese.omitted_files, "[... 他 {count} ファイル省略 ...]");
+
+        // Other languages fall back to English
+        let french = Strings::new("French", &HashMap::new());
+        assert_eq!(french.fallback_subject, english.fallback_subject);
+    }
+
+    #[test]
+    fn overrides_take_precedence_string_by_string() {
+        let overrides = HashMap::from([
+            (
+                "ja".to_string(),
+                LocalizedStrings {
+                    fallback_subject: Some("警告: 生成失敗".to_string()),
+                    ..Default::default()
+                },
+            ),
+            (
+                "french".to_string(),
+                LocalizedStrings {
+                    truncation_marker: Some("[... tronqué ...]".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ]);
+
+        let japanese = Strings::new("Japanese", &overrides);
+        assert_eq!(japanese.fallback_subject, "警告: 生成失敗");
+        assert_eq!(japanese.truncation_marker, "[... 以下省略 ...]");
+
+        let french = Strings::new("French", &overrides);
+        assert_eq!(french.truncation_marker, "[... tronqué ...]");
+        assert_eq!(french.fallback_subject, "WARNING: commit message generation failure");
+    }
+}
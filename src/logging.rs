@@ -0,0 +1,156 @@
+//! Structured logging of the hook, filtered by `CC_AUTO_COMMIT_LOG`
+
+use std::{
+    fmt::Debug,
+    fs::File,
+    io::{self, Write, stderr},
+    path::Path,
+    sync::{Mutex, OnceLock},
+};
+
+use jiff::Timestamp;
+use tracing::{
+    Event, Level, Span, Subscriber,
+    field::{Field, Visit},
+    info_span,
+};
+use tracing_subscriber::{
+    EnvFilter, Layer,
+    fmt::{self, MakeWriter},
+    layer::{Context, SubscriberExt},
+    util::SubscriberInitExt,
+};
+
+use crate::types::HookEvent;
+
+/// Environment variable holding the filter of the logged records, in the `EnvFilter` syntax,
+/// e.g., `debug` or `info,ccc::commit_message_generator=trace`
+pub const LOG_ENV: &str = "CC_AUTO_COMMIT_LOG";
+
+/// Filter used when `LOG_ENV` is unset or invalid
+const DEFAULT_FILTER: &str = "info";
+
+/// The log file of the repository the records also go to, once set by `log_to_file`
+static LOG_FILE: OnceLock<File> = OnceLock::new();
+
+/// The messages of the warning and error records since `collect_warnings`, if called
+static WARNINGS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+/// Writes the records to stderr and to `LOG_FILE` once it is set
+struct Output;
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(mut file) = LOG_FILE.get() {
+            file.write_all(buf)?;
+        }
+        stderr().write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(mut file) = LOG_FILE.get() {
+            file.flush()?;
+        }
+        stderr().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for Output {
+    type Writer = Output;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Output
+    }
+}
+
+/// Starts logging compact records to stderr, which is the background log in the background
+/// process, and to the file set by `log_to_file`
+pub fn init() {
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_env(LOG_ENV).unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER)))
+        .with(
+            fmt::layer()
+                .compact()
+                .with_ansi(false)
+                .with_target(false)
+                .with_writer(Output),
+        )
+        .with(WarningCollector)
+        .init();
+}
+
+/// Adds the messages of warning and error records to `WARNINGS` while collecting
+struct WarningCollector;
+
+impl<S: Subscriber> Layer<S> for WarningCollector {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        if *event.metadata().level() > Level::WARN {
+            return;
+        }
+        if let Ok(mut warnings) = WARNINGS.lock()
+            && let Some(warnings) = warnings.as_mut()
+        {
+            let mut message = Message(String::new());
+            event.record(&mut message);
+            warnings.push(message.0);
+        }
+    }
+}
+
+/// The message of a record
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+/// Starts collecting the messages of warning and error records, e.g., to tell the user about
+/// them in the hook output
+pub fn collect_warnings() {
+    if let Ok(mut warnings) = WARNINGS.lock() {
+        *warnings = Some(Vec::new());
+    }
+}
+
+/// Takes the messages of the warning and error records since `collect_warnings`, in order
+pub fn take_warnings() -> Vec<String> {
+    WARNINGS
+        .lock()
+        .ok()
+        .and_then(|mut warnings| warnings.replace(Vec::new()))
+        .unwrap_or_default()
+}
+
+/// Creates the span of handling a hook event, whose fields tell its records from those of others
+///
+/// # Arguments
+/// * `event` - The hook event
+/// * `root` - The root directory of the repository of the event, if any
+pub fn event_span(event: &HookEvent, root: Option<&Path>) -> Span {
+    info_span!(
+        "event",
+        id = %event_id(),
+        session = event.session_id(),
+        repo = %root.unwrap_or(Path::new("")).display(),
+    )
+}
+
+/// Makes an ID telling the records of one hook event from those of others in the log
+fn event_id() -> String {
+    let nanos = Timestamp::now().subsec_nanosecond() as u32;
+    format!("{:08x}", nanos ^ std::process::id().rotate_left(16))
+}
+
+/// Makes the records also go to the file, e.g., the background log of the repository when
+/// handling an event in the foreground
+///
+/// # Arguments
+/// * `file` - The file, opened for appending
+pub fn log_to_file(file: File) {
+    let _ = LOG_FILE.set(file);
+}
@@ -1,24 +1,58 @@
 use std::{
     env::{current_exe, var},
-    fs::{File, create_dir_all, read_to_string},
-    io::{Read, Write, stdin},
+    fs::File,
+    io::{BufRead, IsTerminal, Read, stdin},
+    path::{Path, PathBuf, absolute},
+    process::exit,
+    sync::mpsc::{RecvTimeoutError, channel},
+    thread::spawn,
+    time::Duration,
 };
 
-use anyhow::{Result, anyhow, bail};
-use clap::{Parser, Subcommand};
+use anyhow::{Context, Result, anyhow, bail};
+#[cfg(unix)]
+use ccc::worker;
+use ccc::{
+    audit_log::Action,
+    ci,
+    commit_message_generator::{CommitContext, CommitMessageGenerator, EnvSource, PromptVariables},
+    committer::{
+        Committer, audit_log, daemon_log, instances, invocation_log, message_cache, proposals,
+        rate_limiter, sessions,
+    },
+    config::{self, CONFIG, Language, LanguageSource, LoadedConfig, Mode},
+    git_ops,
+    hook_output::{HookOutput, RepoState},
+    install::{
+        InstallArgs, SettingsFile, dangling_commands, install_hook, installed_hooks,
+        restore_settings, shell_quote, uninstall_hook,
+    },
+    invocation_log::Outcome,
+    logging,
+    opt_in::OptIns,
+    pull_request::{self, PullRequest},
+    shutdown, statusline, tokens,
+    trailers::{SESSION_TRAILER, TRANSCRIPT_TRAILER, find_trailer},
+    transcript::{expand_home, find_in_projects, is_transcript},
+    types::{self, HookEvent, SessionStartSource, ToolInput, ToolName, ToolResponse},
+    watchdog,
+};
+use clap::{
+    CommandFactory, FromArgMatches, Parser, Subcommand, builder::FalseyValueParser,
+    parser::ValueSource,
+};
+#[cfg(unix)]
 use daemonize::Daemonize;
 use git2::Repository;
-use serde_json::{Value, from_str, json, to_string_pretty};
-
-mod commit_message_generator;
-mod committer;
-mod git_ops;
-mod types;
+use serde_json::{Value, from_str, to_string, to_string_pretty};
+use tracing::{debug, error, info, info_span, warn};
 
-use commit_message_generator::CommitMessageGenerator;
-use committer::Committer;
+/// How long to wait for the first data on stdin, as Claude Code writes the event right away
+const STDIN_TIMEOUT: Duration = Duration::from_secs(5);
 
-use crate::types::HookEvent;
+/// Set for the detached process handling a hook event on Windows, which must not re-spawn itself
+#[cfg(windows)]
+const DETACHED_ENV: &str = "CLAUDE_AUTO_COMMIT_DETACHED";
 
 /// Command line arguments for the auto-commit application
 #[derive(Parser)]
@@ -27,15 +61,173 @@ pub struct Args {
     #[command(subcommand)]
     pub command: Option<Commands>,
 
-    /// Language to use for commit messages
-    #[arg(short, long, default_value = "Japanese", env = "CC_AUTO_COMMIT_LANGUAGE")]
-    pub language: String,
+    /// Language to use for commit messages [default: the `language` setting, or the system locale]
+    #[arg(short, long, global = true, env = "CC_AUTO_COMMIT_LANGUAGE")]
+    pub language: Option<String>,
+
+    /// Profile of the user config file to apply [default: the one the repository config pins]
+    #[arg(long, global = true, env = "CC_AUTO_COMMIT_PROFILE")]
+    pub profile: Option<String>,
+
+    /// Handle hook events in this process instead of a background one, with real exit codes
+    #[arg(long, env = "CC_AUTO_COMMIT_FOREGROUND", value_parser = FalseyValueParser::new())]
+    pub foreground: bool,
+
+    /// What the changes are meant to achieve, passed to the prompt when generating from stdin
+    #[arg(long)]
+    pub intent: Option<String>,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
-    /// Add a hook configuration to <repository_root>/.claude/settings.local.json
-    Install,
+    /// Add a hook configuration to <repository_root>/.claude/settings.local.json, or the file
+    /// selected with --settings
+    Install(InstallArgs),
+    /// Remove the hook configuration of this binary from <repository_root>/.claude/settings.json
+    /// and settings.local.json
+    Uninstall {
+        /// Remove it from the user-global ~/.claude/settings.json instead
+        #[arg(long)]
+        global: bool,
+        /// Print the diff of the change to the settings files instead of writing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// List the backups `install` and `uninstall` keep of a settings file, or restore one
+    RestoreSettings {
+        /// Timestamp of the backup to restore, as listed without it
+        timestamp: Option<String>,
+        /// Restore the user-global ~/.claude/settings.json
+        #[arg(long)]
+        global: bool,
+        /// Settings file of the repository to restore
+        #[arg(long, value_enum, default_value = "local", conflicts_with = "global")]
+        settings: SettingsFile,
+    },
+    /// Diagnose the setup for the current repository
+    Doctor,
+    /// Allow the repository config file to set the commands that are run
+    Trust,
+    /// Opt the current repository in, for `require_opt_in`, recorded in the user-level state
+    Enable,
+    /// Opt the current repository out, recorded in the user-level state
+    Disable,
+    /// Show what the hook does in the current repository
+    Status,
+    /// Print the auto-commit state of the repository as one line, for the Claude Code status
+    /// line, which passes its JSON on stdin
+    Statusline,
+    /// Terminate the background processes handling hook events in the current repository, e.g.,
+    /// when they hang
+    Kill {
+        /// Process ID of the one to terminate, as `status` lists them [default: all]
+        pid: Option<u32>,
+    },
+    /// Commit the currently staged changes with a generated message
+    Commit {
+        /// End the message with a `Signed-off-by` trailer of the author, as `message.signoff`
+        /// does
+        #[arg(long)]
+        signoff: bool,
+    },
+    /// Run the hook in the foreground for a synthetic event in the current repository
+    Simulate {
+        #[command(subcommand)]
+        event: SimulatedEvent,
+    },
+    /// List the commits proposed in review mode (`mode = "review"`), or act on one
+    Review {
+        #[command(subcommand)]
+        command: Option<ReviewCommands>,
+    },
+    /// Open the transcript of the Claude Code session a commit was made in, as linked by its
+    /// trailers (`message.transcript_trailer`)
+    Open {
+        /// The commit, e.g., its SHA or HEAD
+        commit: String,
+        /// Print the path of the transcript instead of opening it
+        #[arg(long)]
+        print: bool,
+    },
+    /// Push the current session branch and open a GitHub pull request of it, titled and described
+    /// by the generator from its changes
+    Pr {
+        /// The branch to merge into, the base branch of the session branch by default
+        #[arg(long)]
+        base: Option<String>,
+        /// Open the pull request as a draft
+        #[arg(long)]
+        draft: bool,
+    },
+    /// Print the prompt that would be sent for the currently staged changes
+    Prompt {
+        /// Render the prompt for a per-file commit instead of a session-end commit
+        #[arg(long)]
+        per_file: bool,
+    },
+    /// Inspect the effective configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SimulatedEvent {
+    /// A session start, which commits the previous session unless the source is `startup`
+    SessionStart {
+        /// How the session started
+        #[arg(long, value_enum, default_value = "startup")]
+        source: SessionStartSource,
+        /// The session ID used for the session branch name
+        #[arg(long, default_value = "simulated")]
+        session_id: String,
+    },
+    /// A successful use of a tool on a file, which commits it if the tool is in `triggers`
+    PostToolUse {
+        /// The file the tool changed
+        file: PathBuf,
+        /// The tool used, as named in `triggers`
+        #[arg(long, default_value = "Edit")]
+        tool: ToolName,
+        /// Report the change as hand-edited by the user before it was applied
+        #[arg(long)]
+        user_modified: bool,
+        /// The session ID used for the session branch name
+        #[arg(long, default_value = "simulated")]
+        session_id: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ReviewCommands {
+    /// Show the message and diff of a proposal
+    Show {
+        /// ID of the proposal, as listed
+        id: String,
+    },
+    /// Commit a proposal, or stage its changes again onto HEAD if HEAD moved since it was made
+    Apply {
+        /// ID of the proposal, as listed
+        id: String,
+    },
+    /// Drop a proposal, leaving the index and working tree as they are
+    Discard {
+        /// ID of the proposal, as listed
+        id: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Show the effective settings for the current repository
+    Show {
+        /// Annotate each setting with the config file it comes from
+        #[arg(long)]
+        origin: bool,
+    },
+    /// Print the JSON Schema of the config files
+    Schema,
 }
 
 fn main() -> Result<()> {
@@ -44,30 +236,97 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    let args = Args::parse();
+    logging::init();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let language = Language::from_arg(args.language.as_deref(), matches.value_source("language"));
+    // The status line loads the config of the directory it is given on stdin
+    if args
+        .command
+        .as_ref()
+        .is_some_and(|command| !matches!(command, Commands::Statusline))
+    {
+        config::init(Repository::discover(".").ok().as_ref(), args.profile.as_deref())?;
+    }
 
     match args.command {
-        Some(Commands::Install) => install_hook(&args.language),
+        Some(Commands::Install(install)) => install_hook(
+            (language.source == LanguageSource::CommandLine).then_some(language.name.as_str()),
+            args.profile
+                .as_deref()
+                .filter(|_| matches.value_source("profile") == Some(ValueSource::CommandLine)),
+            &install,
+        ),
+        Some(Commands::Uninstall { global, dry_run }) => uninstall_hook(global, dry_run),
+        Some(Commands::RestoreSettings { timestamp, global, settings }) => restore_settings(
+            if global { SettingsFile::Global } else { settings },
+            timestamp.as_deref(),
+        ),
+        Some(Commands::Trust) => trust(),
+        Some(Commands::Enable) => set_enabled(true),
+        Some(Commands::Disable) => set_enabled(false),
+        Some(Commands::Status) => status(),
+        Some(Commands::Statusline) => print_statusline(args.profile.as_deref()),
+        Some(Commands::Kill { pid }) => kill(pid),
+        Some(Commands::Commit { signoff }) => Committer::new(Path::new("."))?
+            .with_signoff(signoff || CONFIG.message.signoff)
+            .commit_staged_changes(&language),
+        Some(Commands::Simulate { event }) => simulate(event, &language),
+        Some(Commands::Review { command }) => review(command),
+        Some(Commands::Open { commit, print }) => open_transcript(&commit, print),
+        Some(Commands::Pr { base, draft }) => open_pull_request(base, draft, &language),
+        Some(Commands::Config { command: ConfigCommands::Show { origin } }) => {
+            show_config(&language, origin)
+        }
+        Some(Commands::Config { command: ConfigCommands::Schema }) => {
+            println!("{}", to_string_pretty(&config::schema())?);
+            Ok(())
+        }
+        Some(Commands::Doctor) => doctor(&language),
+        Some(Commands::Prompt { per_file }) => print_prompt(&language, per_file),
         None => {
-            // Default behavior - run as a hook or commit message generator
-            let mut input = String::new();
-            stdin().read_to_string(&mut input)?;
-
-            match from_str::<HookEvent>(&input) {
-                Ok(hook_event) => {
-                    match Daemonize::new()
-                        .working_directory(hook_event.cwd())
-                        .umask(0o027)
-                        .start()
-                    {
-                        Ok(_) => Committer::new().handle_event(hook_event, &args.language),
-                        Err(e) => bail!("Error starting daemon: {e}"),
-                    }
+            // Default behavior - run as a hook or commit message generator, unless run by hand
+            if stdin().is_terminal() {
+                Args::command().print_help()?;
+                return Ok(());
+            }
+            let input = read_input()?;
+
+            match info_span!("parse").in_scope(|| from_str::<HookEvent>(&input)) {
+                Ok(hook_event) => handle_hook(
+                    hook_event,
+                    &input,
+                    args.profile.as_deref(),
+                    args.foreground,
+                    language,
+                ),
+                // Events this binary doesn't handle, e.g., from hooks installed by a newer one
+                Err(_)
+                    if from_str::<Value>(&input)
+                        .is_ok_and(|value| value.get("hook_event_name").is_some()) =>
+                {
+                    Ok(())
                 }
                 Err(_) => {
                     // If the input is not a valid HookEvent, assume it's a diff content and
                     // generate a commit message from it.
-                    println!("{}", CommitMessageGenerator::new(&args.language)?.generate(&input));
+                    config::init(Repository::discover(".").ok().as_ref(), args.profile.as_deref())?;
+                    let language = language.resolve();
+                    let mut generator = CommitMessageGenerator::new(&CONFIG, &language.name)?;
+                    if let Ok(repo) = Repository::discover(".") {
+                        generator = generator.with_log(invocation_log(&repo));
+                    }
+                    println!(
+                        "{}",
+                        generator.generate(
+                            &PromptVariables {
+                                diff_content: input,
+                                user_intent: args.intent.unwrap_or_default(),
+                                ..Default::default()
+                            },
+                            CommitContext::Standalone
+                        )
+                    );
                     Ok(())
                 }
             }
@@ -75,87 +334,811 @@ fn main() -> Result<()> {
     }
 }
 
-fn install_hook(language: &str) -> Result<()> {
-    let repo_root = Repository::discover(".")?
-        .workdir()
-        .ok_or_else(|| anyhow!("Repository has no working directory (bare repo?)"))?
-        .to_path_buf();
+/// Reads the hook event or diff from stdin, giving up after `STDIN_TIMEOUT` without any data
+///
+/// # Returns
+/// The input, or an error if none arrives in time or stdin is empty
+fn read_input() -> Result<String> {
+    let (sender, receiver) = channel();
+    let reader = spawn(move || {
+        let mut stdin = stdin().lock();
+        // The first data is awaited with a timeout, while the rest of a large diff may take longer
+        let started = stdin.fill_buf().map(|_| ());
+        let _ = sender.send(());
+        let mut input = String::new();
+        started.and_then(|()| stdin.read_to_string(&mut input)).map(|_| input)
+    });
+    match receiver.recv_timeout(STDIN_TIMEOUT) {
+        Ok(()) => {}
+        Err(RecvTimeoutError::Timeout) => {
+            bail!("No hook event received: stdin had no data within {} s", STDIN_TIMEOUT.as_secs())
+        }
+        Err(RecvTimeoutError::Disconnected) => bail!("Reading stdin panicked"),
+    }
+    let input = reader.join().map_err(|_| anyhow!("Reading stdin panicked"))??;
+    if input.is_empty() {
+        bail!("No hook event received: stdin is empty");
+    }
+    Ok(input)
+}
 
-    let claude_dir = repo_root.join(".claude");
-    create_dir_all(&claude_dir)?;
-    let settings_path = claude_dir.join("settings.local.json");
-
-    // Read existing settings or create an empty object
-    let mut settings = settings_path
-        .exists()
-        .then(|| read_to_string(&settings_path).ok())
-        .flatten()
-        .and_then(|content| from_str::<Value>(&content).ok())
-        .filter(|v| v.is_object())
-        .unwrap_or_else(|| json!({}));
-    let settings = settings.as_object_mut().unwrap();
-
-    let binary_path = current_exe()?.display().to_string();
-
-    // Create the new hook entry
-    let new_hook = json!({ "hooks": [ { "type": "command", "command": format!("{binary_path} --language {language}"), "timeout": 10 } ] });
-
-    // Check if there's already a hook for this binary
-    let session_start_array = settings
-        .entry("hooks".to_string())
-        .or_insert_with(|| json!({}))
-        .as_object_mut()
-        .unwrap()
-        .entry("SessionStart".to_string())
-        .or_insert_with(|| json!([]))
-        .as_array_mut()
-        .unwrap();
-
-    // Look for existing hook with the same binary path
-    let mut existing_hook_index = None;
-    for (i, existing_hook) in session_start_array.iter().enumerate() {
-        if let Some(hooks_array) = existing_hook.get("hooks")
-            && let Some(hooks) = hooks_array.as_array()
-            && let Some(first_hook) = hooks.first()
-            && let Some(command) = first_hook.get("command")
-            && let Some(command_str) = command.as_str()
-            && command_str.starts_with(&binary_path)
+/// Handles the hook event in the background, or in this process with `--foreground`
+///
+/// # Arguments
+/// * `hook_event` - The parsed event
+/// * `input` - The event JSON, passed on to the background process where it is re-spawned
+/// * `profile` - The profile selected by the `--profile` flag, if any
+/// * `foreground` - Whether to handle the event in this process
+/// * `language` - Language to use for commit messages
+fn handle_hook(
+    hook_event: HookEvent,
+    input: &str,
+    profile: Option<&str>,
+    foreground: bool,
+    language: Language,
+) -> Result<()> {
+    // The event may come from a different directory than the process runs in
+    let repo = Repository::discover(hook_event.cwd()).ok();
+    config::init(repo.as_ref(), profile)?;
+    if stands_down_in_ci() {
+        return Ok(());
+    }
+    // Checked before forking, as most events of a disabled repository end here
+    if let Some(root) = repo.as_ref().and_then(Repository::workdir)
+        && let Some(reason) = CONFIG.repos.disabled_reason(root)?
+    {
+        info!("Skipping {}: it {reason}", root.display());
+        return Ok(());
+    }
+    if let Some(root) = repo.as_ref().and_then(Repository::workdir)
+        && let Some(reason) = opt_out_reason(root)
+    {
+        debug!("Skipping {}: it {reason}", root.display());
+        return Ok(());
+    }
+    // For features reading the transcript of the session later
+    if let Some(repo) = &repo
+        && let Some(transcript_path) = hook_event.transcript_path()
+        && let Err(e) = sessions(repo).record(hook_event.session_id(), transcript_path)
+    {
+        warn!("Failed to record the transcript of the session: {e:#}");
+    }
+    if let Some(log) = repo.as_ref().and_then(audit_log) {
+        log.record(Action::received(&hook_event));
+    }
+    // Checked before forking too, as most events are of tools that change no files
+    if repo.is_none() || !CONFIG.acts_on(&hook_event) {
+        match &hook_event {
+            HookEvent::PostToolUse { tool_name, tool_response, .. } if !tool_response.success() => {
+                let error = tool_response.error().unwrap_or("no error given");
+                info!("Ignoring the failed use of {tool_name}: {error}");
+            }
+            _ => debug!("Ignoring the {} event, which can't result in a commit", hook_event.name()),
+        }
+        return Ok(());
+    }
+
+    let span = logging::event_span(&hook_event, repo.as_ref().and_then(Repository::workdir));
+    let _entered = span.enter();
+    let cwd = hook_event.cwd().to_path_buf();
+    // A session start is handled in this process too, as the context it adds for the model only
+    // reaches Claude Code from the output of the hook process
+    if foreground || matches!(hook_event, HookEvent::SessionStart { .. }) {
+        if let Some(log) = open_daemon_log(repo.as_ref()) {
+            logging::log_to_file(log);
+        }
+        shutdown::install_handlers();
+        let before = repo.as_ref().map(RepoState::of);
+        let event_name = hook_event.name();
+        logging::collect_warnings();
+        let result = handle_in_foreground(repo.as_ref(), hook_event, language);
+        if result.is_ok()
+            && let Some((repo, before)) = repo.as_ref().zip(before)
+            && let Some(output) =
+                HookOutput::since(repo, &before, event_name, &logging::take_warnings())
         {
-            existing_hook_index = Some(i);
-            break;
+            println!("{}", to_string(&output)?);
         }
+        return report(result);
+    }
+    // A running worker takes the event over, so that this invocation exits right away
+    #[cfg(unix)]
+    if CONFIG.worker.enabled
+        && let Some(repo) = &repo
+        && worker::forward(repo, input)
+    {
+        return Ok(());
     }
+    if !start_background(&cwd, repo.as_ref(), input)? {
+        return Ok(());
+    }
+    shutdown::install_handlers();
+    #[cfg(unix)]
+    if CONFIG.worker.enabled
+        && let Some(repo) = &repo
+    {
+        let _pid_file = instances(repo).register("worker")?;
+        info!(pid = std::process::id(), "Starting the worker with {}", hook_event.name());
+        start_watchdog(repo);
+        // The worker logs each event in its own span
+        drop(_entered);
+        return report(worker::serve(repo, hook_event, input, &language));
+    }
+
+    let result = {
+        let _pid_file = repo
+            .as_ref()
+            .map(|repo| instances(repo).register(hook_event.name()))
+            .transpose()?;
+        info!(pid = std::process::id(), "Handling {}", hook_event.name());
+        if let Some(repo) = &repo {
+            start_watchdog(repo);
+            watchdog::arm(Duration::from_secs(CONFIG.deadline_secs), "deadline_secs");
+        }
+        Committer::new(hook_event.cwd())
+            .map(|committer| committer.with_debounce(Duration::from_secs(CONFIG.debounce_secs)))
+            .and_then(|committer| committer.handle_event(hook_event, &language))
+    };
+    report(result)
+}
 
-    if let Some(index) = existing_hook_index {
-        // Check if the language is already correct
-        let existing_command = session_start_array[index]
-            .get("hooks")
-            .and_then(|h| h.as_array())
-            .and_then(|h| h.first())
-            .and_then(|h| h.get("command"))
-            .and_then(|c| c.as_str())
-            .unwrap_or("");
-        let expected_command = format!("{binary_path} --language {language}");
+/// Logs the full chain of the error handling the hook event, as nobody sees the error returned
+/// from the background process, and exits with a non-zero code
+fn report(result: Result<()>) -> Result<()> {
+    if let Err(e) = result {
+        error!("{e:#}");
+        exit(1);
+    }
+    Ok(())
+}
 
-        if existing_command == expected_command {
-            println!("Hook configuration already exists in {}", settings_path.display());
+/// Opens the background log of the repository, as errors of the background process would go
+/// nowhere without it
+fn open_daemon_log(repo: Option<&Repository>) -> Option<File> {
+    daemon_log(repo?)
+        .open()
+        .inspect_err(|e| warn!("Failed to open the background log: {e}"))
+        .ok()
+}
+
+/// Moves the handling of the hook event to the background by daemonizing
+///
+/// # Arguments
+/// * `cwd` - The directory of the event, which the background process runs in
+/// * `repo` - The repository of the event, whose background log gets the output
+/// * `_input` - The hook event JSON, already read by this process
+///
+/// # Returns
+/// Whether this process is the background one and goes on handling the event
+#[cfg(unix)]
+fn start_background(cwd: &Path, repo: Option<&Repository>, _input: &str) -> Result<bool> {
+    let mut daemon = Daemonize::new().working_directory(cwd).umask(0o027);
+    if let Some(log) = open_daemon_log(repo) {
+        daemon = daemon.stdout(log.try_clone()?).stderr(log);
+    }
+    daemon.start().map_err(|e| anyhow!("Error starting daemon: {e}"))?;
+    Ok(true)
+}
+
+/// Moves the handling of the hook event to the background by re-spawning this binary detached,
+/// as there is no fork on Windows
+///
+/// # Arguments
+/// * `cwd` - The directory of the event, which the background process runs in
+/// * `repo` - The repository of the event, whose background log gets the output
+/// * `input` - The hook event JSON, already read by this process and piped to the other one
+///
+/// # Returns
+/// Whether this process is the background one and goes on handling the event
+#[cfg(windows)]
+fn start_background(cwd: &Path, repo: Option<&Repository>, input: &str) -> Result<bool> {
+    use std::{
+        env::args_os,
+        io::Write,
+        os::windows::process::CommandExt,
+        process::{Command, Stdio},
+    };
+
+    const DETACHED_PROCESS: u32 = 0x0000_0008;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    if var(DETACHED_ENV).is_ok() {
+        return Ok(true);
+    }
+    let mut command = Command::new(current_exe()?);
+    command
+        .args(args_os().skip(1))
+        .current_dir(cwd)
+        .env(DETACHED_ENV, "1")
+        .creation_flags(DETACHED_PROCESS | CREATE_NO_WINDOW)
+        .stdin(Stdio::piped());
+    match open_daemon_log(repo) {
+        Some(log) => command.stdout(log.try_clone()?).stderr(log),
+        None => command.stdout(Stdio::null()).stderr(Stdio::null()),
+    };
+    let mut child = command
+        .spawn()
+        .map_err(|e| anyhow!("Error starting background process: {e}"))?;
+    // Dropping stdin at the end closes the pipe, signaling EOF
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())?;
+    Ok(false)
+}
+
+/// Starts the watchdog stopping the background process `deadline_secs` after it is armed,
+/// warning if that may cut a commit short
+fn start_watchdog(repo: &Repository) {
+    let min_deadline = CONFIG.min_deadline_secs();
+    if CONFIG.deadline_secs <= min_deadline {
+        warn!(
+            "`deadline_secs` ({}) doesn't exceed the {min_deadline} s handling an event may take \
+             with the generator timing out; raise it",
+            CONFIG.deadline_secs
+        );
+    }
+    watchdog::start(repo.path().join("index.lock"));
+}
+
+/// Handles the hook event in this process, stopped by the watchdog after
+/// `foreground_deadline_secs` as the background process is after `deadline_secs`
+fn handle_in_foreground(
+    repo: Option<&Repository>,
+    hook_event: HookEvent,
+    language: Language,
+) -> Result<()> {
+    if let Some(repo) = repo {
+        watchdog::start(repo.path().join("index.lock"));
+        watchdog::arm(
+            Duration::from_secs(CONFIG.foreground_deadline_secs),
+            "foreground_deadline_secs",
+        );
+    }
+    Committer::new(hook_event.cwd())?.handle_event(hook_event, &language)
+}
+
+/// Prints the status line of the repository of the directory Claude Code passes on stdin, or
+/// an empty line outside a repository, with the config of that repository
+///
+/// # Arguments
+/// * `profile` - The profile selected by the `--profile` flag, if any
+fn print_statusline(profile: Option<&str>) -> Result<()> {
+    let mut input = String::new();
+    if !stdin().is_terminal() {
+        stdin().read_to_string(&mut input)?;
+    }
+    let input = from_str::<Value>(&input).unwrap_or_default();
+    let cwd = input["workspace"]["current_dir"]
+        .as_str()
+        .or(input["cwd"].as_str())
+        .unwrap_or(".");
+    // Claude Code may run the status line from another directory than the session's
+    let Ok(repo) = types::Repository::discover_from(Path::new(cwd)) else {
+        println!();
+        return Ok(());
+    };
+    config::init(Some(&repo), profile)?;
+    let line = statusline::render(&repo);
+    println!("{line}");
+    Ok(())
+}
+
+/// Terminates the background processes handling hook events in the current repository
+///
+/// # Arguments
+/// * `pid` - The process ID of the one to terminate, or `None` for all
+fn kill(pid: Option<u32>) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let instances = instances(&repo);
+    let running = instances
+        .live()
+        .into_iter()
+        .filter(|instance| pid.is_none_or(|pid| pid == instance.pid))
+        .collect::<Vec<_>>();
+    if running.is_empty() {
+        match pid {
+            Some(pid) => bail!("No running instance with PID {pid}; see `ccc status`"),
+            None => println!("No running instances"),
+        }
+    }
+    for instance in running {
+        instances.kill(&instance, &repo.path().join("index.lock"))?;
+        println!("Terminated PID {}, which was handling {}", instance.pid, instance.event);
+    }
+    Ok(())
+}
+
+/// Records the command settings of the repository config file as trusted
+fn trust() -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let keys = config::trust(&repo)?;
+    if keys.is_empty() {
+        println!("{} sets no commands", LoadedConfig::REPO_PATH);
+    } else {
+        println!("Trusted {} in {}", keys.join(", "), LoadedConfig::REPO_PATH);
+        println!("Run `ccc trust` again after they change");
+    }
+    Ok(())
+}
+
+/// Checks whether the hook runs in CI without `allow_in_ci`, where it writes nothing to git,
+/// logging why if so
+fn stands_down_in_ci() -> bool {
+    if CONFIG.allow_in_ci {
+        return false;
+    }
+    let Some(variable) = ci::detect(&CONFIG.ci_variables, |name| var(name).ok()) else {
+        return false;
+    };
+    info!("Standing down in CI, as {variable} is set; set allow_in_ci = true to commit anyway");
+    true
+}
+
+/// Records the current repository as opted in or out in the user-level state
+fn set_enabled(enabled: bool) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let root = repo
+        .workdir()
+        .ok_or_else(|| anyhow!("Repository has no working directory (bare repo?)"))?;
+    let opt_ins = OptIns::user().ok_or_else(|| anyhow!("Failed to find the home directory"))?;
+    opt_ins.set(root, enabled)?;
+    println!("{} the hook in {}", if enabled { "Enabled" } else { "Disabled" }, root.display());
+    Ok(())
+}
+
+/// Checks whether the repository is opted in, by `ccc enable` or `ccc disable` first, then by
+/// `enabled` and `require_opt_in`
+///
+/// # Returns
+/// `None` if the repository is opted in, the reason why not otherwise
+fn opt_out_reason(root: &Path) -> Option<&'static str> {
+    CONFIG.opt_out_reason(OptIns::user().and_then(|opt_ins| opt_ins.get(root)))
+}
+
+/// Prints what the hook does in the current repository
+fn status() -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let branch = git_ops::get_current_branch(Committer::new(Path::new("."))?.repo())?;
+
+    if let Some(root) = repo.workdir() {
+        match CONFIG.repos.disabled_reason(root)? {
+            Some(reason) => println!("Repository: disabled, as it {reason}"),
+            None => match opt_out_reason(root) {
+                Some(reason) => println!("Repository: disabled, as it {reason}"),
+                None => println!("Repository: enabled"),
+            },
+        }
+    }
+    let mut installed = Vec::new();
+    for file in SettingsFile::ALL {
+        let path = file.path()?;
+        for hook in installed_hooks(&path)? {
+            installed.push(format!(
+                "Installed {} hook in {}: {}",
+                hook.event,
+                path.display(),
+                hook.command
+            ));
+            if let Some(matcher) = hook.matcher {
+                installed.push(format!("  Matcher: {matcher}"));
+            }
+            installed.push(format!(
+                "  Timeout: {}",
+                hook.timeout.map_or("default".to_string(), |secs| format!("{secs} s"))
+            ));
+        }
+    }
+    if installed.is_empty() {
+        println!("Installed hooks: none for {}", current_exe()?.display());
+    }
+    installed.iter().for_each(|line| println!("{line}"));
+    let running = instances(&repo).live();
+    if running.is_empty() {
+        println!("Running instances: none");
+    }
+    for instance in &running {
+        println!(
+            "Running instance: PID {}, handling {} since {}",
+            instance.pid, instance.event, instance.timestamp
+        );
+    }
+    match sessions(&repo).latest() {
+        Some(session) => println!(
+            "Last session: {}, transcript at {} (recorded {})",
+            session.id, session.transcript_path, session.recorded
+        ),
+        None => println!("Last session: none recorded"),
+    }
+    if CONFIG.mode == Mode::Review {
+        println!(
+            "Mode: review, {} proposals pending; `ccc review` lists them",
+            proposals(&repo).list().len()
+        );
+    } else {
+        println!("Mode: commit");
+    }
+    let levels = rate_limiter(&repo).levels();
+    if levels.is_empty() {
+        println!("Rate limit: none");
+    } else {
+        let left = levels
+            .iter()
+            .map(|level| format!("{} of {} this {}", level.tokens.floor(), level.max, level.window))
+            .collect::<Vec<_>>();
+        println!("Rate limit: commits left {}", left.join(", "));
+    }
+    let protected = CONFIG.is_protected(&branch);
+    println!("Current branch: {branch}{}", if protected { " (protected)" } else { "" });
+    println!(
+        "Session branching: {}",
+        match (CONFIG.branching.enabled, CONFIG.branching.protect_current) {
+            (false, _) => "disabled".to_string(),
+            (true, true) => format!("active on {}", CONFIG.base_branches.join(", ")),
+            (true, false) => "active on every branch".to_string(),
+        }
+    );
+    println!(
+        "  A session starting now {}",
+        if CONFIG.branches_from(&branch) {
+            "switches to a new session branch"
         } else {
-            // Update the existing hook with the new language
-            if let Some(hooks_array) = session_start_array[index].get_mut("hooks")
-                && let Some(hooks) = hooks_array.as_array_mut()
-                && let Some(first_hook) = hooks.first_mut()
-            {
-                first_hook["command"] = json!(expected_command);
+            "stays on the current branch"
+        }
+    );
+    if protected && !CONFIG.branching.enabled {
+        println!(
+            "WARNING: {branch} is protected and session branching is disabled, so nothing is \
+             committed here; switch to another branch or set branching.enabled = true"
+        );
+    }
+
+    Ok(())
+}
+
+/// Pushes the current session branch and opens a pull request of it, printing its URL
+///
+/// # Arguments
+/// * `base` - The branch to merge into, or `None` for the base branch of the session branch
+/// * `draft` - Whether to open it as a draft
+/// * `language` - Language of the generated title and body
+fn open_pull_request(base: Option<String>, draft: bool, language: &Language) -> Result<()> {
+    let language = language.resolve();
+    let committer = Committer::new(Path::new("."))?;
+    let repo = committer.repo();
+    let branch = git_ops::get_current_branch(repo)?;
+    if !branch.starts_with(git_ops::SESSION_BRANCH_PREFIX) {
+        bail!(
+            "{branch} isn't a session branch; `ccc pr` only publishes the {}* branches of sessions",
+            git_ops::SESSION_BRANCH_PREFIX
+        );
+    }
+    let base =
+        base.unwrap_or_else(|| git_ops::get_base_branch(repo, &branch, &CONFIG.base_branches));
+    if base.is_empty() {
+        bail!("Found none of the base branches {:?}; pass --base", CONFIG.base_branches);
+    }
+
+    let (title, body) = committer.describe_branch(&base, &language.name)?;
+    let config = &CONFIG.pull_request;
+    pull_request::push(repo, config, &branch)?;
+    // The base as named on the remote, if given as its remote-tracking branch
+    let remote_prefix = format!("{}/", config.remote);
+    let opened = pull_request::open(
+        repo,
+        config,
+        &PullRequest {
+            head: &branch,
+            base: base.strip_prefix(&remote_prefix).unwrap_or(&base),
+            title: &title,
+            body: &body,
+            draft,
+        },
+    )?;
+    if opened.existed {
+        eprintln!("A pull request of {branch} is open already");
+    }
+    println!("{}", opened.url);
+    Ok(())
+}
+
+/// Opens the transcript of the session the commit was made in with the default application, or
+/// prints its path
+///
+/// # Arguments
+/// * `commit` - The commit, as git revision syntax has it
+/// * `print` - Whether to print the path instead
+fn open_transcript(commit: &str, print: bool) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let commit = repo
+        .revparse_single(commit)
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("No commit {commit}"))?;
+    let message = commit.message().unwrap_or_default();
+    let path =
+        match (find_trailer(message, TRANSCRIPT_TRAILER), find_trailer(message, SESSION_TRAILER)) {
+            (Some(path), _) => expand_home(path),
+            // Only the session is named when the path is kept out of the history
+            (None, Some(session_id)) => sessions(&repo)
+                .get(session_id)
+                .map(|session| PathBuf::from(session.transcript_path))
+                .or_else(|| find_in_projects(session_id))
+                .ok_or_else(|| {
+                    anyhow!("The transcript of session {session_id} isn't on this machine")
+                })?,
+            (None, None) => bail!(
+                "Commit {} has no {SESSION_TRAILER} or {TRANSCRIPT_TRAILER} trailer; set \
+             message.transcript_trailer to add them",
+                commit.id()
+            ),
+        };
+    if !path.is_file() {
+        warn!("The transcript {} doesn't exist (anymore)", path.display());
+    }
+    if print {
+        println!("{}", path.display());
+        return Ok(());
+    }
+    // The path comes from history anyone may have pushed to, and the opener opens URLs and runs
+    // programs as well
+    if !is_transcript(&path) {
+        warn!("Not opening {}, which is no transcript in ~/.claude/projects", path.display());
+        println!("{}", path.display());
+        return Ok(());
+    }
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    let status = std::process::Command::new(opener)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Failed to open {}; --print prints its path", path.display()))?;
+    // Explorer exits with 1 even once it opened the file
+    if !status.success() && !cfg!(windows) {
+        bail!("Failed to open {} ({status}); --print prints its path", path.display());
+    }
+    Ok(())
+}
+
+/// Lists the proposals of review mode in the current repository, or shows, applies, or discards
+/// one
+fn review(command: Option<ReviewCommands>) -> Result<()> {
+    let committer = Committer::new(Path::new("."))?;
+    let proposals = proposals(committer.repo());
+    match command {
+        None => {
+            let pending = proposals.list();
+            if pending.is_empty() {
+                println!("No pending proposals");
+            }
+            for proposal in &pending {
+                println!(
+                    "{}  {}  on {}  {}",
+                    proposal.id,
+                    proposal.created,
+                    proposal.branch,
+                    proposal.subject()
+                );
             }
-            println!("Hook configuration updated in {}", settings_path.display());
         }
+        Some(ReviewCommands::Show { id }) => {
+            let proposal = proposals.get(&id)?;
+            println!("Proposal {} on {}", proposal.id, proposal.branch);
+            println!("Base: {}", proposal.base.as_deref().unwrap_or("none (unborn branch)"));
+            println!("Proposed: {}\n", proposal.created);
+            println!("{}\n", proposal.message.trim_end());
+            println!("{}", proposal.diff);
+        }
+        Some(ReviewCommands::Apply { id }) => match committer.apply_proposal(&id)? {
+            Some(oid) => println!("Committed proposal {id} as {oid}"),
+            None => println!("Re-staged proposal {id} onto HEAD; review it again"),
+        },
+        Some(ReviewCommands::Discard { id }) => {
+            proposals.remove(&id)?;
+            println!("Discarded proposal {id}; the index and working tree are left as they are");
+        }
+    }
+    Ok(())
+}
+
+/// Runs the hook for a synthetic event without daemonizing
+fn simulate(event: SimulatedEvent, language: &Language) -> Result<()> {
+    if stands_down_in_ci() {
+        return Ok(());
+    }
+    let cwd = Repository::discover(".")?
+        .workdir()
+        .ok_or_else(|| anyhow!("Repository has no working directory (bare repo?)"))?
+        .to_path_buf();
+    let hook_event = match event {
+        SimulatedEvent::SessionStart { source, session_id } => HookEvent::SessionStart {
+            session_id,
+            cwd,
+            source: Some(source),
+            transcript_path: None,
+            permission_mode: None,
+        },
+        SimulatedEvent::PostToolUse { file, tool, user_modified, session_id } => {
+            HookEvent::PostToolUse {
+                session_id,
+                cwd,
+                transcript_path: None,
+                permission_mode: None,
+                tool_name: tool,
+                tool_input: ToolInput::of_file(absolute(file)?.display().to_string()),
+                tool_response: ToolResponse::succeeded(user_modified),
+            }
+        }
+    };
+    Committer::new(hook_event.cwd())?.handle_event(hook_event, language)
+}
+
+fn show_config(language: &Language, with_origin: bool) -> Result<()> {
+    let language = language.resolve();
+
+    if let Some(profile) = &config::loaded().profile {
+        println!("# profile: {profile}");
+    }
+    println!("language = {:?}  # {}", language.name, language.source);
+    config::loaded()
+        .lines(with_origin)
+        .iter()
+        .filter(|line| !line.starts_with("language = "))
+        .for_each(|line| println!("{line}"));
+
+    Ok(())
+}
+
+fn print_prompt(language: &Language, per_file: bool) -> Result<()> {
+    let language = language.resolve();
+    let committer = Committer::new(Path::new("."))?;
+    let context = if per_file { CommitContext::PerFile } else { CommitContext::SessionEnd };
+
+    let max_chars = CONFIG.max_diff_chars();
+    let diff = committer.staged_diff(&language.name)?;
+    let (dropped, total) = (diff.dropped, diff.total);
+    let stats = git_ops::get_staged_file_stats(committer.repo())?;
+    let variables = committer.prompt_variables(diff.content, &stats, "", &language.name)?;
+    let generator = CommitMessageGenerator::new(&CONFIG, &language.name)?;
+
+    let prompt = generator.render_prompt(&variables, context);
+    println!("{prompt}");
+    eprintln!(
+        "Diff: {} of {total} characters included, {dropped} dropped (limit: {max_chars})",
+        total - dropped
+    );
+    eprintln!(
+        "Prompt: about {} tokens (limit: {})",
+        tokens::estimate(&prompt),
+        CONFIG
+            .generator
+            .max_prompt_tokens
+            .map_or("none".to_string(), |max| max.to_string())
+    );
+    eprintln!(
+        "Command: {}",
+        std::iter::once(&CONFIG.generator.command)
+            .chain(generator.command_args())
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    eprintln!("Generator environment:");
+    command_env_lines(&generator)
+        .iter()
+        .for_each(|line| eprintln!("{line}"));
+
+    Ok(())
+}
+
+/// Formats the generator command environment for display, with the values redacted
+fn command_env_lines(generator: &CommitMessageGenerator) -> Vec<String> {
+    generator
+        .command_env()
+        .into_iter()
+        .map(|(key, (_, source))| {
+            let source = match source {
+                EnvSource::Inherited => "inherited",
+                EnvSource::Configured => "generator.env",
+                EnvSource::MaxTokens => "generator.max_tokens",
+                EnvSource::Guard => "recursion guard",
+            };
+            format!("  {}=<redacted> ({source})", key.to_string_lossy())
+        })
+        .collect()
+}
+
+/// Number of lines of the background log `doctor` prints
+const DOCTOR_LOG_LINES: usize = 20;
+
+fn doctor(language: &Language) -> Result<()> {
+    let repo = Repository::discover(".")?;
+    let language = language.resolve();
+
+    for shadowing in &config::loaded().shadowings {
+        // Multi-line strings would otherwise be printed verbatim
+        let value = match shadowing.value.as_str() {
+            Some(value) => format!("{value:?}"),
+            None => shadowing.value.to_string(),
+        };
+        println!(
+            "Warning: {} is set to an empty string by {}, hiding {value} from {}",
+            shadowing.key, shadowing.origin, shadowing.shadowed
+        );
+    }
+
+    for file in SettingsFile::ALL {
+        let path = file.path()?;
+        for dangling in dangling_commands(&path)? {
+            println!(
+                "Warning: the {} hook in {} runs a binary that doesn't exist on this machine: {}",
+                dangling.event,
+                path.display(),
+                dangling.command
+            );
+            println!("  Advice: {}", dangling.advice(file)?);
+        }
+    }
+
+    if CONFIG.cache.enabled {
+        let stats = message_cache(&repo).stats();
+        println!("Message cache: {} entries, {} bytes", stats.entries, stats.bytes);
     } else {
-        // Add new hook
-        session_start_array.push(new_hook);
-        println!("Hook installed successfully to {}", settings_path.display());
+        println!("Message cache: disabled");
+    }
+
+    match invocation_log(&repo).last_failure() {
+        Some(failure) => {
+            println!(
+                "Last generator failure: {} at {} (exit code: {}, {} ms)",
+                failure.outcome,
+                failure.timestamp,
+                failure.exit_code.map_or("none".to_string(), |code| code.to_string()),
+                failure.duration_ms
+            );
+            if !failure.stderr.is_empty() {
+                println!("  {}", failure.stderr.replace('\n', "\n  "));
+            }
+            println!(
+                "  Advice: {}",
+                match failure.outcome {
+                    Outcome::NotFound => format!(
+                        "`{}` is not on PATH; install it or set `generator.command`",
+                        failure.command
+                    ),
+                    Outcome::TimedOut => "raise `generator.timeout_secs`".to_string(),
+                    Outcome::Interrupted => {
+                        "none; the hook was stopped, e.g., by Claude Code exiting".to_string()
+                    }
+                    _ => format!(
+                        "run `{}` manually to check that it works, e.g., that you are logged in",
+                        failure.command
+                    ),
+                }
+            );
+        }
+        None => println!("Last generator failure: none"),
+    }
+
+    let log = daemon_log(&repo);
+    match log.tail(DOCTOR_LOG_LINES) {
+        Some(lines) => {
+            println!("Background log ({}), last lines:", log.path().display());
+            lines.iter().for_each(|line| println!("  {line}"));
+        }
+        None => println!("Background log: empty"),
     }
 
-    File::create(&settings_path)?.write_all(to_string_pretty(&settings)?.as_bytes())?;
+    println!("Generator environment:");
+    let generator = CommitMessageGenerator::new(&CONFIG, &language.name)?;
+    command_env_lines(&generator)
+        .iter()
+        .for_each(|line| println!("{line}"));
 
     Ok(())
 }
@@ -0,0 +1,364 @@
+//! Notifications telling the user about commits made in the background and about changes left
+//! uncommitted for a reason worth knowing
+
+use std::{
+    fs::{create_dir_all, read_to_string, rename, write},
+    path::PathBuf,
+    process::Command,
+    time::Duration,
+};
+
+use anyhow::{Result, bail};
+use jiff::{SignedDuration, Timestamp};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::{
+    config::{Notify, NotifyMethod},
+    file_lock::FileLock,
+    process::run,
+    template::render,
+};
+
+/// Time after which a notification command is killed
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Length of the abbreviated commit IDs in desktop notifications
+const SHORT_ID_LEN: usize = 7;
+
+/// Something the user is notified about
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Notification {
+    /// What happened: "commit", "secrets" (nothing committed as the changes contain likely
+    /// secrets), or "rate-limited" (the commit deferred)
+    pub event: String,
+    /// The ID of the commit, empty unless committed
+    pub sha: String,
+    /// The first line of the commit message, or else why nothing was committed
+    pub subject: String,
+    /// The branch checked out
+    pub branch: String,
+    /// The number of files committed, or left staged
+    pub files: usize,
+}
+
+/// When the last notification was sent, as persisted
+#[derive(Serialize, Deserialize, Default)]
+struct State {
+    /// When it was sent, in RFC 3339 format
+    last: String,
+    /// The notifications left out since, as they came too soon after it
+    coalesced: usize,
+    /// The last of them, sent with the number of the others once the window is over
+    #[serde(default)]
+    pending: Option<Notification>,
+}
+
+/// Sends the notifications configured in `[notify]`, leaving out those coming too soon after the
+/// last one so that a burst of commits doesn't flood the user
+pub struct Notifier<'a> {
+    config: &'a Notify,
+    /// The JSON file of the last notification, shared by the processes handling hook events
+    path: PathBuf,
+}
+
+impl<'a> Notifier<'a> {
+    /// Creates the notifier persisting when it last notified in the given file
+    ///
+    /// # Arguments
+    /// * `config` - The notification settings
+    /// * `path` - The JSON file of the last notification, created on the first one
+    pub fn new(config: &'a Notify, path: PathBuf) -> Self {
+        Self { config, path }
+    }
+
+    /// Sends the notification, unless it comes too soon after the last one; failures are only
+    /// logged, as they must never affect the commit
+    pub fn notify(&self, notification: &Notification) {
+        if self.config.method == NotifyMethod::Off {
+            return;
+        }
+        let Some(coalesced) = self.coalesce_at(notification, Timestamp::now()) else {
+            debug!("Leaving out a notification coming too soon after the last one");
+            return;
+        };
+        self.send(notification, coalesced);
+    }
+
+    /// Sends the last notification left out, once the window of the one before is over, so that
+    /// the end of a burst isn't lost when nothing is notified after it
+    pub fn flush(&self) {
+        if self.config.method == NotifyMethod::Off {
+            return;
+        }
+        if let Some((notification, coalesced)) = self.flush_at(Timestamp::now()) {
+            debug!("Sending the last of {} notifications left out", coalesced + 1);
+            self.send(&notification, coalesced);
+        }
+    }
+
+    /// Sends the notification with the number of those left out before it, logging failures
+    fn send(&self, notification: &Notification, coalesced: usize) {
+        let result = match self.config.method {
+            NotifyMethod::Off => return,
+            NotifyMethod::Desktop => desktop(notification, coalesced),
+            NotifyMethod::Command => self.run_command(notification, coalesced),
+        };
+        if let Err(e) = result {
+            warn!("Failed to send the notification: {e:#}");
+        }
+    }
+
+    /// Records a notification at the time, unless it comes within `coalesce_secs` of the last one,
+    /// in which case it is kept for the next one instead
+    ///
+    /// # Returns
+    /// The number of notifications left out since the last one, or `None` to leave this one out
+    fn coalesce_at(&self, notification: &Notification, now: Timestamp) -> Option<usize> {
+        self.update(|state| match state.last.parse::<Timestamp>() {
+            Ok(last) if now.duration_since(last) < self.window() => {
+                state.coalesced += 1;
+                state.pending = Some(notification.clone());
+                None
+            }
+            _ => {
+                let coalesced = state.coalesced;
+                *state = State { last: now.to_string(), ..State::default() };
+                Some(coalesced)
+            }
+        })
+        .unwrap_or_else(|e| {
+            warn!("Failed to record the notification: {e:#}");
+            Some(0)
+        })
+    }
+
+    /// Takes the last notification left out, once the window of the one before is over at the time
+    ///
+    /// # Returns
+    /// The notification with the number of those left out before it, or `None` if there is none
+    /// or it is still too soon
+    fn flush_at(&self, now: Timestamp) -> Option<(Notification, usize)> {
+        self.update(|state| {
+            let last = state.last.parse::<Timestamp>().ok()?;
+            if state.pending.is_none() || now.duration_since(last) < self.window() {
+                return None;
+            }
+            let notification = state.pending.take()?;
+            let coalesced = state.coalesced - 1;
+            *state = State { last: now.to_string(), ..State::default() };
+            Some((notification, coalesced))
+        })
+        .unwrap_or_else(|e| {
+            warn!("Failed to record the notification: {e:#}");
+            None
+        })
+    }
+
+    /// Gets the window within which notifications are coalesced
+    fn window(&self) -> SignedDuration {
+        SignedDuration::from_secs(self.config.coalesce_secs as i64)
+    }
+
+    /// Changes the state while holding the lock, so that processes notifying together see each
+    /// other's notifications, writing it back through a temporary file
+    ///
+    /// # Returns
+    /// What the change returns, or an error if the state couldn't be locked or written
+    fn update<T>(&self, change: impl FnOnce(&mut State) -> T) -> Result<T> {
+        let _lock = FileLock::acquire(&self.path.with_extension("lock"))?;
+        let mut state = read_to_string(&self.path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<State>(&content).ok())
+            .unwrap_or_default();
+        let value = change(&mut state);
+        self.write(&state)?;
+        Ok(value)
+    }
+
+    /// Writes the state through a temporary file, so that readers never see a partial one,
+    /// creating the directory of the file
+    fn write(&self, state: &State) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+        let temp_path = self.path.with_extension("tmp");
+        write(&temp_path, serde_json::to_string(state)?)?;
+        rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+
+    /// Runs the configured command with the notification in its arguments and environment
+    ///
+    /// # Arguments
+    /// * `notification` - The notification
+    /// * `coalesced` - The number of notifications left out since the last one
+    fn run_command(&self, notification: &Notification, coalesced: usize) -> Result<()> {
+        let Some(program) = &self.config.command else {
+            bail!("notify.command is unset");
+        };
+        let files = notification.files.to_string();
+        let coalesced = coalesced.to_string();
+        let variables = [
+            ("event", notification.event.as_str()),
+            ("sha", notification.sha.as_str()),
+            ("subject", notification.subject.as_str()),
+            ("branch", notification.branch.as_str()),
+            ("files", files.as_str()),
+            ("coalesced", coalesced.as_str()),
+        ];
+        let mut command = Command::new(program);
+        command.args(self.config.args.iter().map(|arg| render(arg, &variables)));
+        for (name, value) in variables {
+            command.env(format!("CCC_NOTIFY_{}", name.to_uppercase()), value);
+        }
+        let output = run(&mut command, None, NOTIFY_TIMEOUT)?;
+        if !output.status.success() {
+            bail!("{program} failed ({}): {}", output.status, output.stderr.trim());
+        }
+        Ok(())
+    }
+}
+
+/// Shows the notification on the desktop, through `notify-send` on Linux and `osascript` on
+/// macOS
+///
+/// # Arguments
+/// * `notification` - The notification
+/// * `coalesced` - The number of notifications left out since the last one, mentioned in the body
+fn desktop(notification: &Notification, coalesced: usize) -> Result<()> {
+    let Notification { event, sha, subject, branch, files } = notification;
+    let plural = if *files == 1 { "" } else { "s" };
+    let title = match event.as_str() {
+        "commit" => format!("Auto-committed {files} file{plural} on {branch}"),
+        "rate-limited" => format!("Auto-commit deferred on {branch}"),
+        _ => format!("Nothing auto-committed on {branch}"),
+    };
+    let mut body = if sha.is_empty() {
+        subject.clone()
+    } else {
+        format!("{} {subject}", &sha[..SHORT_ID_LEN.min(sha.len())])
+    };
+    if coalesced > 0 {
+        body.push_str(&format!("\n(+{coalesced} more since the last notification)"));
+    }
+
+    let mut command = if cfg!(target_os = "macos") {
+        // Passed as arguments rather than in the script, so that they need no escaping
+        let mut command = Command::new("osascript");
+        command.args([
+            "-e",
+            "on run argv",
+            "-e",
+            "display notification (item 2 of argv) with title (item 1 of argv)",
+            "-e",
+            "end run",
+        ]);
+        command
+    } else if cfg!(unix) {
+        let mut command = Command::new("notify-send");
+        command.arg("--app-name=ccc");
+        command
+    } else {
+        bail!(
+            "desktop notifications aren't supported on this platform; use notify.method = \"command\""
+        );
+    };
+    let output = run(command.args([&title, &body]), None, NOTIFY_TIMEOUT)?;
+    if !output.status.success() {
+        bail!("the notifier failed ({}): {}", output.status, output.stderr.trim());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_dir_all, process::id};
+
+    use super::*;
+
+    /// Creates a notifier with the coalescing window, persisted in a fresh temporary directory
+    fn notifier<'a>(name: &str, config: &'a Notify) -> (PathBuf, Notifier<'a>) {
+        let dir = temp_dir().join(format!("ccc-test-notify-{name}-{}", id()));
+        let _ = remove_dir_all(&dir);
+        let notifier = Notifier::new(config, dir.join("notified.json"));
+        (dir, notifier)
+    }
+
+    /// Creates the settings running no command, with the coalescing window
+    fn config(coalesce_secs: u64) -> Notify {
+        Notify {
+            method: NotifyMethod::Command,
+            command: None,
+            args: Vec::new(),
+            coalesce_secs,
+        }
+    }
+
+    /// Creates the notification of a commit with the subject
+    fn commit(subject: &str) -> Notification {
+        Notification {
+            event: "commit".to_string(),
+            sha: "0123456789abcdef".to_string(),
+            subject: subject.to_string(),
+            branch: "main".to_string(),
+            files: 1,
+        }
+    }
+
+    #[test]
+    fn burst_is_coalesced_into_the_next_notification() {
+        let config = config(10);
+        let (dir, notifier) = notifier("burst", &config);
+        let start = Timestamp::now();
+
+        let at = |secs| start + SignedDuration::from_secs(secs);
+        let commit = commit("feat: add file");
+
+        assert_eq!(notifier.coalesce_at(&commit, at(0)), Some(0));
+        assert_eq!(notifier.coalesce_at(&commit, at(1)), None);
+        assert_eq!(notifier.coalesce_at(&commit, at(9)), None);
+        assert_eq!(notifier.coalesce_at(&commit, at(10)), Some(2));
+        assert_eq!(notifier.coalesce_at(&commit, at(30)), Some(0));
+        assert!(!dir.join("notified.lock").exists());
+        assert!(!dir.join("notified.tmp").exists());
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn no_window_coalesces_nothing() {
+        let config = config(0);
+        let (dir, notifier) = notifier("no-window", &config);
+        let now = Timestamp::now();
+
+        let commit = commit("feat: add file");
+
+        assert_eq!(notifier.coalesce_at(&commit, now), Some(0));
+        assert_eq!(notifier.coalesce_at(&commit, now), Some(0));
+        assert_eq!(notifier.flush_at(now), None);
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn end_of_a_burst_is_flushed_once_the_window_is_over() {
+        let config = config(10);
+        let (dir, notifier) = notifier("flush", &config);
+        let start = Timestamp::now();
+        let at = |secs| start + SignedDuration::from_secs(secs);
+
+        assert_eq!(notifier.coalesce_at(&commit("feat: one"), at(0)), Some(0));
+        assert_eq!(notifier.flush_at(at(20)), None);
+        assert_eq!(notifier.coalesce_at(&commit("feat: two"), at(21)), Some(0));
+        assert_eq!(notifier.coalesce_at(&commit("feat: three"), at(22)), None);
+        assert_eq!(notifier.coalesce_at(&commit("feat: four"), at(23)), None);
+        assert_eq!(notifier.flush_at(at(30)), None);
+        assert_eq!(notifier.flush_at(at(31)), Some((commit("feat: four"), 1)));
+        assert_eq!(notifier.flush_at(at(50)), None);
+        // The flushed notification starts a window of its own
+        assert_eq!(notifier.coalesce_at(&commit("feat: five"), at(40)), None);
+
+        remove_dir_all(dir).unwrap();
+    }
+}
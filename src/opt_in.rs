@@ -0,0 +1,72 @@
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use directories::ProjectDirs;
+
+/// The repositories the user enabled or disabled the hook in with `ccc enable` and `ccc disable`,
+/// recorded in a user-level file rather than in the repositories
+pub struct OptIns {
+    path: PathBuf,
+}
+
+impl OptIns {
+    /// Gets the record of the user, e.g., in `~/.local/share/c/repos.json` on Linux, or `None` if
+    /// the home directory can't be determined
+    pub fn user() -> Option<Self> {
+        ProjectDirs::from("", "", "c")
+            .map(|dirs| Self::new(dirs.data_local_dir().join("repos.json")))
+    }
+
+    /// Creates the record stored in the given file
+    ///
+    /// # Arguments
+    /// * `path` - The JSON file mapping repository roots to whether the hook is enabled in them;
+    ///   created on first change
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Gets whether the hook was enabled or disabled in the repository last
+    ///
+    /// # Arguments
+    /// * `root` - The root directory of the repository
+    ///
+    /// # Returns
+    /// `None` if neither was recorded, or the file can't be read
+    pub fn get(&self, root: &Path) -> Option<bool> {
+        self.read().ok()?.get(&key(root)).copied()
+    }
+
+    /// Records that the hook is enabled or disabled in the repository
+    ///
+    /// # Arguments
+    /// * `root` - The root directory of the repository
+    /// * `enabled` - Whether the hook acts in it
+    pub fn set(&self, root: &Path, enabled: bool) -> Result<()> {
+        let mut repos = self.read()?;
+        repos.insert(key(root), enabled);
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+        write(&self.path, serde_json::to_string_pretty(&repos)?)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// Reads the recorded repositories, none if the file doesn't exist yet
+    fn read(&self) -> Result<BTreeMap<String, bool>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        serde_json::from_str(&read_to_string(&self.path)?)
+            .with_context(|| format!("Failed to parse {}", self.path.display()))
+    }
+}
+
+/// Gets the key of the repository in the record, its root without a trailing separator
+fn key(root: &Path) -> String {
+    root.components().as_path().to_string_lossy().to_string()
+}
@@ -0,0 +1,164 @@
+use serde_json::Value;
+use tracing::warn;
+
+/// Text and metadata extracted from the output of the generator command
+pub struct Generation {
+    /// The generated message, trimmed
+    pub text: String,
+    /// The model that generated the message, if reported
+    pub model: Option<String>,
+    /// Why the model stopped generating, if reported
+    pub stop_reason: Option<String>,
+}
+
+/// Extracts the generated message from the stdout of the generator command
+///
+/// Understands the JSON envelope printed by `claude --output-format json`, also when banners or
+/// warnings precede it on their own lines. Output that isn't JSON is taken as the message itself,
+/// whereas output that looks like a broken or unsuccessful envelope is rejected, so that no JSON
+/// ever ends up as a commit message. A code fence around the message is removed.
+///
+/// # Arguments
+/// - `stdout` - The standard output of the command
+///
+/// # Returns
+/// The message with its metadata, or `None` if there is no usable message
+pub fn parse(stdout: &str) -> Option<Generation> {
+    let stdout = stdout.trim();
+    if stdout.is_empty() {
+        return None;
+    }
+
+    let envelope = serde_json::from_str::<Value>(stdout).ok().or_else(|| {
+        stdout
+            .lines()
+            .rev()
+            .filter(|line| line.trim_start().starts_with('{'))
+            .find_map(|line| serde_json::from_str::<Value>(line).ok())
+    });
+    let Some(envelope) = envelope.filter(Value::is_object) else {
+        if stdout.starts_with('{') || stdout.contains(r#""type":"result""#) {
+            warn!("Discarded malformed JSON output of the generator command");
+            return None;
+        }
+        let text = unfence(stdout);
+        return (!text.is_empty()).then(|| Generation {
+            text: text.to_string(),
+            model: None,
+            stop_reason: None,
+        });
+    };
+
+    if envelope["is_error"].as_bool().unwrap_or_default() {
+        warn!(
+            "Generator command reported an error: {}",
+            envelope["result"].as_str().unwrap_or("(no details)")
+        );
+        return None;
+    }
+    let Some(text) = envelope["result"]
+        .as_str()
+        .map(|text| unfence(text.trim()))
+        .filter(|text| !text.is_empty())
+    else {
+        warn!("Discarded JSON output of the generator command without a result");
+        return None;
+    };
+
+    let model = envelope["model"]
+        .as_str()
+        .map(str::to_string)
+        .or_else(|| envelope["modelUsage"].as_object()?.keys().next().cloned());
+    let stop_reason = envelope["stop_reason"]
+        .as_str()
+        .or_else(|| envelope["subtype"].as_str())
+        .map(str::to_string);
+    Some(Generation { text: text.to_string(), model, stop_reason })
+}
+
+/// Removes the code fence the model may have wrapped the message in, e.g., "```text" and "```"
+fn unfence(text: &str) -> &str {
+    text.strip_prefix("```")
+        .and_then(|rest| rest.split_once('\n'))
+        .and_then(|(_, rest)| rest.trim_end().strip_suffix("```"))
+        .map_or(text, str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn result_of_the_envelope_is_the_message() {
+        for (stdout, text, model, stop_reason) in [
+            (
+                r#"{"type":"result","subtype":"success","is_error":false,"result":" feat: add the parser\n","model":"claude-sonnet","stop_reason":"end_turn"}"#,
+                "feat: add the parser",
+                Some("claude-sonnet"),
+                Some("end_turn"),
+            ),
+            (
+                r#"{"type":"result","subtype":"success","result":"feat: add the parser","modelUsage":{"claude-haiku":{}}}"#,
+                "feat: add the parser",
+                Some("claude-haiku"),
+                Some("success"),
+            ),
+            (
+                "Update available: run `claude update`\n{\"type\":\"result\",\"result\":\"feat: add the parser\"}\n",
+                "feat: add the parser",
+                None,
+                None,
+            ),
+            (
+                r#"{"type":"result","result":"```\nfeat: add the parser\n```"}"#,
+                "feat: add the parser",
+                None,
+                None,
+            ),
+        ] {
+            let generation = parse(stdout).unwrap();
+            assert_eq!(generation.text, text, "{stdout}");
+            assert_eq!(generation.model.as_deref(), model, "{stdout}");
+            assert_eq!(generation.stop_reason.as_deref(), stop_reason, "{stdout}");
+        }
+    }
+
+    #[test]
+    fn plain_text_is_the_message() {
+        for (stdout, text) in [
+            ("feat: add the parser\n", "feat: add the parser"),
+            (
+                "feat: add the parser\n\nSplits the tokens.\n",
+                "feat: add the parser\n\nSplits the tokens.",
+            ),
+            ("```\nfeat: add the parser\n```\n", "feat: add the parser"),
+            (
+                "```text\nfeat: add the parser\n\nSplits the tokens.\n```",
+                "feat: add the parser\n\nSplits the tokens.",
+            ),
+            ("```\nfeat: add the parser", "```\nfeat: add the parser"),
+            ("[1, 2]", "[1, 2]"),
+        ] {
+            let generation = parse(stdout).unwrap();
+            assert_eq!(generation.text, text, "{stdout}");
+            assert_eq!(generation.model, None);
+        }
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        for stdout in [
+            "",
+            " \n",
+            "```\n```",
+            "{not json",
+            r#"{"type":"result","result":"feat: add"#,
+            r#"banner {"type":"result","result":"#,
+            r#"{"type":"result","is_error":true,"result":"Credit balance too low"}"#,
+            r#"{"type":"result","result":"  "}"#,
+            r#"{"type":"result","result":42}"#,
+        ] {
+            assert!(parse(stdout).is_none(), "{stdout}");
+        }
+    }
+}
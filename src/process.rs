@@ -0,0 +1,189 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{ErrorKind, Read, Write},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{
+        Mutex,
+        mpsc::{Receiver, channel},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, bail};
+
+use crate::shutdown;
+
+/// Interval between checks whether a running command has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Process IDs of the commands currently run by `run`, for `kill_children`
+static CHILDREN: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Records a running command in `CHILDREN` until dropped
+struct Running(u32);
+
+impl Running {
+    fn new(pid: u32) -> Self {
+        if let Ok(mut children) = CHILDREN.lock() {
+            children.push(pid);
+        }
+        Self(pid)
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Ok(mut children) = CHILDREN.lock() {
+            children.retain(|&pid| pid != self.0);
+        }
+    }
+}
+
+/// Error returned when a command is killed after the timeout
+#[derive(Debug)]
+pub struct TimedOut(pub Duration);
+
+impl Display for TimedOut {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Timed out after {} seconds", self.0.as_secs())
+    }
+}
+
+impl Error for TimedOut {}
+
+/// Output of a command that ran to completion
+pub struct CommandOutput {
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs a command to completion, killing it if it takes too long
+///
+/// # Arguments
+/// * `command` - The command to run. Its stdin, stdout, and stderr are overridden.
+/// * `input` - Text written to the command's stdin, which is closed afterwards. Without input,
+///   stdin is connected to `/dev/null`.
+/// * `timeout` - The time after which the command is killed
+///
+/// # Returns
+/// The exit status and output of the command; if processes it spawned keep its output open
+/// after it exits, only what it printed until the timeout. Returns an error if it can't be started
+/// or is killed after the timeout, in which case the error is `TimedOut`, or on a shutdown request,
+/// in which case it is `Interrupted`.
+pub fn run(command: &mut Command, input: Option<&str>, timeout: Duration) -> Result<CommandOutput> {
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(command, 0);
+    let mut child = command
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let _running = Running::new(child.id());
+
+    // Talk to the command from other threads so that a command producing output before reading
+    // all of its input can't deadlock. They are detached, as processes spawned by the command may
+    // keep the pipes open after it is killed.
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+        let input = input.to_string();
+        // Dropping `stdin` at the end closes the pipe, signaling EOF
+        thread::spawn(move || stdin.write_all(input.as_bytes()));
+    }
+    let stdout = read_lossy(child.stdout.take().expect("stdout is piped"));
+    let stderr = read_lossy(child.stderr.take().expect("stderr is piped"));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            kill(&mut child)?;
+            bail!(TimedOut(timeout));
+        }
+        if let Err(e) = shutdown::check() {
+            kill(&mut child)?;
+            return Err(e);
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    // Processes spawned by the command may keep the pipes open after it exits, so the output is
+    // only awaited until the deadline
+    Ok(CommandOutput {
+        status,
+        stdout: collect(&stdout, deadline),
+        stderr: collect(&stderr, deadline),
+    })
+}
+
+/// Kills the child along with the processes it spawned, where supported, and reaps it
+fn kill(child: &mut Child) -> Result<()> {
+    #[cfg(unix)]
+    kill_group(child.id());
+    child.kill()?;
+    child.wait()?;
+    Ok(())
+}
+
+/// Kills the commands currently run by `run` along with the processes they spawned, e.g., when
+/// the process is about to exit while they still run
+pub fn kill_children() {
+    let children = CHILDREN.lock().map(|children| children.clone()).unwrap_or_default();
+    for pid in children {
+        kill_group(pid);
+    }
+}
+
+/// Kills the process group led by the process
+#[cfg(unix)]
+fn kill_group(pid: u32) {
+    // SAFETY: `kill` has no memory safety preconditions. The process leads its own group, as
+    // `run` spawns it so.
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+/// Kills the process along with the processes it spawned
+#[cfg(windows)]
+fn kill_group(pid: u32) {
+    let _ = Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &pid.to_string()])
+        .output();
+}
+
+/// Reads everything from the reader on another thread, sending it in chunks as it is read
+///
+/// # Returns
+/// The receiver of the chunks, disconnected once the reader reaches the end
+fn read_lossy(mut reader: impl Read + Send + 'static) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let mut buf = [0; 8192];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => {
+                    if sender.send(buf[..read].to_vec()).is_err() {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+    });
+    receiver
+}
+
+/// Gathers the chunks read by `read_lossy` until the end or the deadline, replacing invalid UTF-8
+fn collect(chunks: &Receiver<Vec<u8>>, deadline: Instant) -> String {
+    let mut buf = Vec::new();
+    while let Ok(chunk) = chunks.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        buf.extend(chunk);
+    }
+    String::from_utf8_lossy(&buf).to_string()
+}
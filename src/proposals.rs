@@ -0,0 +1,110 @@
+use std::{
+    fs::{create_dir_all, read_dir, read_to_string, remove_file, write},
+    io::ErrorKind,
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+/// A commit prepared in review mode, for a human to apply or discard, as recorded in its file
+#[derive(Serialize, Deserialize)]
+pub struct Proposal {
+    /// The ID, the abbreviated ID of the tree first proposed
+    pub id: String,
+    /// The generated commit message
+    pub message: String,
+    /// The commit the changes are staged on, or `None` on an unborn branch
+    pub base: Option<String>,
+    /// The staged tree committed when the proposal is applied
+    pub tree: String,
+    /// Parents in addition to the base, for a merge commit
+    pub merge_heads: Vec<String>,
+    /// The branch checked out when the commit was proposed
+    pub branch: String,
+    /// When the commit was proposed or last re-staged, in RFC 3339 format
+    pub created: String,
+    /// The patch text of the changes from the base to the tree
+    pub diff: String,
+}
+
+impl Proposal {
+    /// Gets the first line of the message
+    pub fn subject(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+}
+
+/// The commits proposed in a repository in review mode, one file per proposal, kept until applied
+/// or discarded
+pub struct Proposals {
+    dir: PathBuf,
+}
+
+impl Proposals {
+    /// Creates the proposals stored in the given directory
+    ///
+    /// # Arguments
+    /// * `dir` - The directory of the proposal files; created on first save
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Saves the proposal, replacing the one of the same ID
+    pub fn save(&self, proposal: &Proposal) -> Result<()> {
+        create_dir_all(&self.dir)?;
+        let path = self.path(&proposal.id)?;
+        write(&path, serde_json::to_string_pretty(proposal)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Gets the proposal of the ID
+    ///
+    /// # Returns
+    /// The proposal, or an error if there is none of the ID or its file can't be read
+    pub fn get(&self, id: &str) -> Result<Proposal> {
+        let path = self.path(id)?;
+        let content = match read_to_string(&path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                bail!("No pending proposal {id}; `ccc review` lists them")
+            }
+            result => result.with_context(|| format!("Failed to read {}", path.display()))?,
+        };
+        serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Gets the pending proposals, oldest first, leaving out files that can't be read
+    pub fn list(&self) -> Vec<Proposal> {
+        let Ok(entries) = read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut proposals = entries
+            .filter_map(|entry| {
+                let content = read_to_string(entry.ok()?.path()).ok()?;
+                serde_json::from_str::<Proposal>(&content).ok()
+            })
+            .collect::<Vec<_>>();
+        proposals.sort_by(|a, b| a.created.cmp(&b.created));
+        proposals
+    }
+
+    /// Removes the proposal of the ID
+    pub fn remove(&self, id: &str) -> Result<()> {
+        let path = self.path(id)?;
+        match remove_file(&path) {
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                bail!("No pending proposal {id}; `ccc review` lists them")
+            }
+            result => result.with_context(|| format!("Failed to remove {}", path.display())),
+        }
+    }
+
+    /// Gets the path of the file of the proposal, rejecting IDs other than hexadecimal ones
+    fn path(&self, id: &str) -> Result<PathBuf> {
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("Invalid proposal ID {id:?}; `ccc review` lists them");
+        }
+        Ok(self.dir.join(format!("{id}.json")))
+    }
+}
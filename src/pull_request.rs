@@ -0,0 +1,261 @@
+//! Publishing of a session branch as a GitHub pull request, through `gh` or the REST API
+
+use std::{env::var, path::Path, process::Command, time::Duration};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::{Value, json};
+use tracing::{debug, info};
+use ureq::Agent;
+
+use crate::{
+    config::{PullRequestConfig, PullRequestMethod},
+    process::run,
+    types::Repository,
+};
+
+/// Time after which pushing the branch is given up
+const PUSH_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Time after which a `gh` command or a request to the REST API is given up
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Environment variables holding the token of the REST API, in order of preference, as `gh`
+/// reads them
+const TOKEN_ENVS: &[&str] = &["GH_TOKEN", "GITHUB_TOKEN"];
+
+/// A pull request to open
+pub struct PullRequest<'a> {
+    /// The branch with the changes, as pushed
+    pub head: &'a str,
+    /// The branch the changes are merged into
+    pub base: &'a str,
+    pub title: &'a str,
+    pub body: &'a str,
+    /// Whether it is opened as a draft
+    pub draft: bool,
+}
+
+/// A pull request opened
+pub struct Opened {
+    pub url: String,
+    /// Whether it was open already, rather than opened now
+    pub existed: bool,
+}
+
+/// Pushes the branch to the remote, setting it as the upstream, with the credentials git uses
+/// for the remote (its credential helper or SSH keys)
+///
+/// # Arguments
+/// * `repo` - The git repository
+/// * `config` - The settings of pull requests, naming the remote
+/// * `branch` - The branch to push
+pub fn push(repo: &Repository, config: &PullRequestConfig, branch: &str) -> Result<()> {
+    let workdir = repo.workdir().context("The repository has no working directory")?;
+    info!("Pushing {branch} to {}", config.remote);
+    let mut command = Command::new("git");
+    command
+        .args(["push", "--set-upstream", &config.remote, branch])
+        .current_dir(workdir);
+    let output = run(&mut command, None, PUSH_TIMEOUT)?;
+    if !output.status.success() {
+        bail!("Failed to push {branch} to {}: {}", config.remote, output.stderr.trim());
+    }
+    Ok(())
+}
+
+/// Opens the pull request, or finds the one open already for its branch
+///
+/// # Arguments
+/// * `repo` - The git repository, whose remote is on GitHub
+/// * `config` - The settings of pull requests
+/// * `request` - The pull request
+pub fn open(
+    repo: &Repository,
+    config: &PullRequestConfig,
+    request: &PullRequest,
+) -> Result<Opened> {
+    let workdir = repo.workdir().context("The repository has no working directory")?;
+    let use_gh = match config.method {
+        PullRequestMethod::Gh => true,
+        PullRequestMethod::Api => false,
+        PullRequestMethod::Auto => {
+            let found = run(Command::new("gh").arg("--version"), None, REQUEST_TIMEOUT)
+                .is_ok_and(|output| output.status.success());
+            debug!("gh is {}", if found { "installed" } else { "not installed" });
+            found
+        }
+    };
+    if use_gh {
+        return open_with_gh(workdir, request);
+    }
+    let url = repo
+        .config()?
+        .get_string(&format!("remote.{}.url", config.remote))
+        .with_context(|| format!("The remote {} has no URL", config.remote))?;
+    open_with_api(config, &github_repository(&url)?, request)
+}
+
+/// Opens the pull request with `gh pr create`, which uses the login of `gh`
+fn open_with_gh(workdir: &Path, request: &PullRequest) -> Result<Opened> {
+    let mut command = Command::new("gh");
+    command.current_dir(workdir).args([
+        "pr",
+        "create",
+        "--head",
+        request.head,
+        "--base",
+        request.base,
+        "--title",
+        request.title,
+        "--body",
+        request.body,
+    ]);
+    if request.draft {
+        command.arg("--draft");
+    }
+    let output = run(&mut command, None, REQUEST_TIMEOUT)?;
+    if output.status.success() {
+        let url = output.stdout.lines().rfind(|line| line.starts_with("http"));
+        return Ok(Opened {
+            url: url.unwrap_or(output.stdout.trim()).to_string(),
+            existed: false,
+        });
+    }
+    if !output.stderr.contains("already exists") {
+        bail!("gh pr create failed ({}): {}", output.status, output.stderr.trim());
+    }
+    // It names the existing pull request on the line after the error
+    if let Some(url) = output.stderr.lines().find(|line| line.starts_with("http")) {
+        return Ok(Opened { url: url.trim().to_string(), existed: true });
+    }
+    let mut command = Command::new("gh");
+    command.current_dir(workdir).args([
+        "pr",
+        "view",
+        request.head,
+        "--json",
+        "url",
+        "--jq",
+        ".url",
+    ]);
+    let output = run(&mut command, None, REQUEST_TIMEOUT)?;
+    if !output.status.success() {
+        bail!("gh pr view failed ({}): {}", output.status, output.stderr.trim());
+    }
+    Ok(Opened {
+        url: output.stdout.trim().to_string(),
+        existed: true,
+    })
+}
+
+/// Opens the pull request through the REST API of GitHub, with the token of `pull_request.token`
+/// or the environment
+///
+/// # Arguments
+/// * `config` - The settings of pull requests
+/// * `repository` - The repository on GitHub, as `owner/name`
+/// * `request` - The pull request
+fn open_with_api(
+    config: &PullRequestConfig,
+    repository: &str,
+    request: &PullRequest,
+) -> Result<Opened> {
+    let token = config
+        .token
+        .clone()
+        .or_else(|| TOKEN_ENVS.iter().find_map(|name| var(name).ok()))
+        .filter(|token| !token.is_empty())
+        .ok_or_else(|| {
+            anyhow!(
+                "Opening a pull request without gh needs a token in pull_request.token or {}",
+                TOKEN_ENVS.join(" or ")
+            )
+        })?;
+    let agent: Agent = Agent::config_builder()
+        .timeout_global(Some(REQUEST_TIMEOUT))
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let pulls = format!("{}/repos/{repository}/pulls", config.api_url.trim_end_matches('/'));
+    let authorization = format!("Bearer {token}");
+    let mut response = agent
+        .post(&pulls)
+        .header("Authorization", &authorization)
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", "ccc")
+        .send_json(json!({
+            "title": request.title,
+            "body": request.body,
+            "head": request.head,
+            "base": request.base,
+            "draft": request.draft,
+        }))?;
+    let status = response.status().as_u16();
+    let answer = response.body_mut().read_json::<Value>().unwrap_or_default();
+    match status {
+        201 => Ok(Opened { url: html_url(&answer)?, existed: false }),
+        422 if answer.to_string().contains("already exists") => {
+            let owner = repository.split('/').next().unwrap_or_default();
+            let open = agent
+                .get(&pulls)
+                .query("head", format!("{owner}:{}", request.head))
+                .query("state", "open")
+                .header("Authorization", &authorization)
+                .header("Accept", "application/vnd.github+json")
+                .header("User-Agent", "ccc")
+                .call()?
+                .body_mut()
+                .read_json::<Value>()?;
+            Ok(Opened { url: html_url(&open[0])?, existed: true })
+        }
+        _ => bail!(
+            "GitHub answered {status}: {}",
+            answer["message"].as_str().unwrap_or("no message")
+        ),
+    }
+}
+
+/// Gets the web URL of the pull request from its JSON
+fn html_url(pull: &Value) -> Result<String> {
+    pull["html_url"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("GitHub answered without the URL of the pull request"))
+}
+
+/// Gets the repository on GitHub a remote URL points to
+///
+/// # Arguments
+/// * `url` - The URL, e.g., `https://github.com/owner/name.git` or `git@github.com:owner/name`
+///
+/// # Returns
+/// The repository as `owner/name`, or an error if the URL doesn't end with one
+fn github_repository(url: &str) -> Result<String> {
+    let path = url.trim_end_matches('/').trim_end_matches(".git");
+    let mut segments = path.rsplit(['/', ':']);
+    match (segments.next(), segments.next()) {
+        (Some(name), Some(owner)) if !name.is_empty() && !owner.is_empty() => {
+            Ok(format!("{owner}/{name}"))
+        }
+        _ => bail!("The remote URL {url} doesn't name a GitHub repository"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repository_is_read_from_remote_urls() {
+        for url in [
+            "https://github.com/acme/widgets.git",
+            "https://github.com/acme/widgets",
+            "git@github.com:acme/widgets.git",
+            "ssh://git@github.com/acme/widgets.git",
+            "https://github.example.com/acme/widgets/",
+        ] {
+            assert_eq!(github_repository(url).unwrap(), "acme/widgets", "{url}");
+        }
+        assert!(github_repository("widgets").is_err());
+    }
+}
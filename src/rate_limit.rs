@@ -0,0 +1,258 @@
+use std::{
+    collections::BTreeMap,
+    fs::{File, create_dir_all, read_to_string, rename},
+    io::Write,
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::Result;
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+use crate::file_lock::FileLock;
+
+/// A bucket of commits, as persisted
+#[derive(Serialize, Deserialize, Clone)]
+struct Bucket {
+    /// The commits left, refilled continuously up to the maximum of the window
+    tokens: f64,
+    /// When `tokens` was computed, in RFC 3339 format
+    updated: String,
+}
+
+/// The fill of the bucket of a window
+pub struct Level {
+    /// The window, "minute" or "hour"
+    pub window: &'static str,
+    /// The commits left, refilled continuously
+    pub tokens: f64,
+    /// The maximum number of commits per window
+    pub max: u32,
+}
+
+/// Why a commit has to wait
+pub struct Throttle {
+    /// The window whose limit is reached, "minute" or "hour"
+    pub window: &'static str,
+    /// The maximum number of commits per window
+    pub max: u32,
+    /// How long until the bucket holds a commit again
+    pub wait: Duration,
+}
+
+/// Token buckets limiting the commits in a repository per minute and per hour, persisted so that
+/// the short-lived processes handling hook events share them
+pub struct RateLimiter {
+    path: PathBuf,
+    /// The windows with their length and maximum number of commits, 0 for no limit
+    limits: [(&'static str, Duration, u32); 2],
+}
+
+impl RateLimiter {
+    /// Creates the rate limiter persisted in the given file
+    ///
+    /// # Arguments
+    /// * `path` - The JSON file of the buckets, created on the first commit, next to which a lock
+    ///   file serializes processes updating it
+    /// * `per_minute` - The maximum number of commits per minute, 0 for no limit
+    /// * `per_hour` - The maximum number of commits per hour, 0 for no limit
+    pub fn new(path: PathBuf, per_minute: u32, per_hour: u32) -> Self {
+        Self {
+            path,
+            limits: [
+                ("minute", Duration::from_secs(60), per_minute),
+                ("hour", Duration::from_secs(60 * 60), per_hour),
+            ],
+        }
+    }
+
+    /// Checks whether a commit may be made, leaving the buckets as they are until it is recorded
+    ///
+    /// # Returns
+    /// `None` if the commit may be made, the limit it would exceed otherwise
+    pub fn check(&self) -> Option<Throttle> {
+        self.check_at(Timestamp::now())
+    }
+
+    /// Checks whether a commit may be made at the given time
+    fn check_at(&self, now: Timestamp) -> Option<Throttle> {
+        if self.limits.iter().all(|(_, _, max)| *max == 0) {
+            return None;
+        }
+        self.levels_at(&self.read(), now)
+            .iter()
+            .zip(&self.limits)
+            .filter(|(level, _)| level.max > 0 && level.tokens < 1.0)
+            .map(|(level, (_, period, _))| Throttle {
+                window: level.window,
+                max: level.max,
+                wait: period.mul_f64((1.0 - level.tokens) / f64::from(level.max)),
+            })
+            .max_by_key(|throttle| throttle.wait)
+    }
+
+    /// Takes a commit made from every bucket, once it is made, so that commits checked but not
+    /// made take nothing
+    ///
+    /// # Returns
+    /// An error if the buckets can't be locked, read, or written
+    pub fn record(&self) -> Result<()> {
+        self.record_at(Timestamp::now())
+    }
+
+    /// Takes a commit made at the given time from every bucket; processes that passed the check
+    /// together may take a bucket below empty, making the next commits wait longer
+    fn record_at(&self, now: Timestamp) -> Result<()> {
+        if self.limits.iter().all(|(_, _, max)| *max == 0) {
+            return Ok(());
+        }
+        let _lock = FileLock::acquire(&self.path.with_extension("lock"))?;
+        let mut buckets = self.read();
+        for level in self.levels_at(&buckets, now).iter().filter(|level| level.max > 0) {
+            buckets.insert(
+                level.window.to_string(),
+                Bucket {
+                    tokens: level.tokens - 1.0,
+                    updated: now.to_string(),
+                },
+            );
+        }
+        self.write(&buckets)
+    }
+
+    /// Gets the fill of the bucket of each window with a limit, as of now
+    pub fn levels(&self) -> Vec<Level> {
+        self.levels_at(&self.read(), Timestamp::now())
+            .into_iter()
+            .filter(|level| level.max > 0)
+            .collect()
+    }
+
+    /// Gets the fill of the bucket of each window at the given time, refilled since last updated
+    fn levels_at(&self, buckets: &BTreeMap<String, Bucket>, now: Timestamp) -> Vec<Level> {
+        self.limits
+            .iter()
+            .map(|&(window, period, max)| {
+                let tokens = buckets
+                    .get(window)
+                    .and_then(|bucket| {
+                        let updated = bucket.updated.parse::<Timestamp>().ok()?;
+                        let elapsed = now.duration_since(updated).as_secs_f64().max(0.0);
+                        Some(bucket.tokens + elapsed * f64::from(max) / period.as_secs_f64())
+                    })
+                    .unwrap_or(f64::from(max));
+                Level { window, tokens: tokens.min(f64::from(max)), max }
+            })
+            .collect()
+    }
+
+    /// Reads the buckets, all full if the file is missing or unreadable
+    fn read(&self) -> BTreeMap<String, Bucket> {
+        read_to_string(&self.path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the buckets through a temporary file, so that readers never see a partial one
+    fn write(&self, buckets: &BTreeMap<String, Bucket>) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+        let temp_path = self.path.with_extension("tmp");
+        File::create(&temp_path)?.write_all(serde_json::to_string(buckets)?.as_bytes())?;
+        rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_dir_all, process::id, sync::Arc, thread};
+
+    use jiff::SignedDuration;
+
+    use super::*;
+
+    /// Creates a rate limiter persisted in a fresh temporary directory
+    fn limiter(name: &str, per_minute: u32, per_hour: u32) -> (PathBuf, RateLimiter) {
+        let dir = temp_dir().join(format!("ccc-test-rate-limit-{name}-{}", id()));
+        let _ = remove_dir_all(&dir);
+        let limiter = RateLimiter::new(dir.join("rate-limit.json"), per_minute, per_hour);
+        (dir, limiter)
+    }
+
+    #[test]
+    fn empty_bucket_refills_over_its_window() {
+        let (dir, limiter) = limiter("refill", 2, 0);
+        let start = Timestamp::now();
+
+        for _ in 0..2 {
+            assert!(limiter.check_at(start).is_none());
+            limiter.record_at(start).unwrap();
+        }
+        let throttle = limiter.check_at(start).unwrap();
+        assert_eq!((throttle.window, throttle.max), ("minute", 2));
+        assert_eq!(throttle.wait, Duration::from_secs(30));
+
+        let later = start + SignedDuration::from_secs(30);
+        assert!(limiter.check_at(later).is_none());
+        limiter.record_at(later).unwrap();
+        assert!(limiter.check_at(later).is_some());
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn checked_commits_take_nothing_until_recorded() {
+        let (dir, limiter) = limiter("check", 1, 0);
+        let start = Timestamp::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at(start).is_none());
+        }
+        assert!(!dir.join("rate-limit.json").exists());
+        limiter.record_at(start).unwrap();
+        assert!(limiter.check_at(start).is_some());
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn longest_wait_is_reported() {
+        let (dir, limiter) = limiter("windows", 10, 1);
+        let start = Timestamp::now();
+
+        limiter.record_at(start).unwrap();
+        let throttle = limiter.check_at(start).unwrap();
+        assert_eq!((throttle.window, throttle.max), ("hour", 1));
+        assert_eq!(throttle.wait, Duration::from_secs(60 * 60));
+        let levels = limiter.levels_at(&limiter.read(), start);
+        assert_eq!(levels[0].tokens, 9.0);
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_processes_share_the_buckets() {
+        let (dir, limiter) = limiter("concurrent", 100, 0);
+        let limiter = Arc::new(limiter);
+
+        let threads = (0..20)
+            .map(|_| {
+                let limiter = Arc::clone(&limiter);
+                thread::spawn(move || limiter.record().unwrap())
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // Refilling adds a fraction of a commit at most while the threads run
+        assert_eq!(limiter.levels()[0].tokens.floor(), 80.0);
+        assert!(!dir.join("rate-limit.lock").exists());
+
+        remove_dir_all(dir).unwrap();
+    }
+}
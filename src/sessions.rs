@@ -0,0 +1,91 @@
+use std::{
+    fs::{create_dir_all, read_dir, read_to_string, write},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use jiff::Timestamp;
+use serde::{Deserialize, Serialize};
+
+/// What is known about a Claude Code session working in the repository, as recorded in its file
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    /// The session ID
+    pub id: String,
+    /// The JSON Lines transcript of the session
+    pub transcript_path: String,
+    /// When the transcript path was recorded, in RFC 3339 format
+    pub recorded: String,
+}
+
+/// The metadata of the Claude Code sessions seen in a repository, one file per session
+pub struct Sessions {
+    dir: PathBuf,
+}
+
+impl Sessions {
+    /// Creates the session metadata stored in the given directory
+    ///
+    /// # Arguments
+    /// * `dir` - The directory of the session files; created on first record
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// Records the transcript of the session, unless it is recorded already
+    ///
+    /// # Arguments
+    /// * `id` - The session ID
+    /// * `transcript_path` - The path of its transcript
+    pub fn record(&self, id: &str, transcript_path: &str) -> Result<()> {
+        let path = self.path(id);
+        if read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Session>(&content).ok())
+            .is_some_and(|session| session.transcript_path == transcript_path)
+        {
+            return Ok(());
+        }
+        let session = Session {
+            id: id.to_string(),
+            transcript_path: transcript_path.to_string(),
+            recorded: Timestamp::now().to_string(),
+        };
+        create_dir_all(&self.dir)?;
+        write(path, serde_json::to_string(&session)?)?;
+        Ok(())
+    }
+
+    /// Gets the session of the ID, if recorded
+    pub fn get(&self, id: &str) -> Option<Session> {
+        let content = read_to_string(self.path(id)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Gets the session recorded last, if any
+    pub fn latest(&self) -> Option<Session> {
+        self.latest_except("")
+    }
+
+    /// Gets the session recorded last but the one of the ID, e.g., the one a session started by
+    /// `/clear` follows
+    pub fn latest_except(&self, id: &str) -> Option<Session> {
+        read_dir(&self.dir)
+            .ok()?
+            .filter_map(|entry| {
+                let content = read_to_string(entry.ok()?.path()).ok()?;
+                serde_json::from_str::<Session>(&content).ok()
+            })
+            .filter(|session| session.id != id)
+            .max_by(|a, b| a.recorded.cmp(&b.recorded))
+    }
+
+    /// Gets the path of the file of the session, with characters unsafe in file names replaced
+    fn path(&self, id: &str) -> PathBuf {
+        let name = id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect::<String>();
+        self.dir.join(format!("{name}.json"))
+    }
+}
@@ -0,0 +1,86 @@
+//! Graceful shutdown on SIGTERM and SIGINT, checked between the phases of handling a hook event
+//! so that the repository is left as it was or fully committed
+
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    sync::atomic::{AtomicI32, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use crate::watchdog::Phase;
+
+/// Interval between checks whether a shutdown was requested while waiting
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The signal requesting the shutdown, or 0 if none was received
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// Error returned when handling the event stops early because a shutdown was requested
+#[derive(Debug)]
+pub struct Interrupted {
+    signal: i32,
+    phase: Phase,
+}
+
+impl Display for Interrupted {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let signal = match self.signal {
+            2 => "SIGINT".to_string(),
+            15 => "SIGTERM".to_string(),
+            signal => format!("signal {signal}"),
+        };
+        write!(f, "Stopped by {signal} while {}", self.phase)
+    }
+}
+
+impl Error for Interrupted {}
+
+/// Sets the shutdown flag; only async-signal-safe operations are allowed here
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    SIGNAL.store(signal, Ordering::Relaxed);
+}
+
+/// Makes SIGTERM and SIGINT request a shutdown instead of killing the process on the spot
+#[cfg(unix)]
+pub fn install_handlers() {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    for signal in [libc::SIGTERM, libc::SIGINT] {
+        // SAFETY: The handler only stores to an atomic, which is async-signal-safe
+        unsafe { libc::signal(signal, handler) };
+    }
+}
+
+/// Does nothing, as Windows has no signals to handle
+#[cfg(windows)]
+pub fn install_handlers() {}
+
+/// Checks whether a shutdown was requested
+///
+/// # Returns
+/// `Ok(())` to go on, or an `Interrupted` error naming the signal and the current phase
+pub fn check() -> Result<()> {
+    match SIGNAL.load(Ordering::Relaxed) {
+        0 => Ok(()),
+        signal => Err(Interrupted { signal, phase: Phase::current() }.into()),
+    }
+}
+
+/// Sleeps for the duration, waking up early if a shutdown is requested
+///
+/// # Arguments
+/// * `duration` - The time to sleep
+pub fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while check().is_ok() {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
@@ -0,0 +1,126 @@
+//! The record of the paths the tool staged and hasn't committed yet, shared by the processes
+//! handling hook events, so that the changes the user staged are told apart from them
+
+use std::{
+    collections::BTreeSet,
+    fs::{create_dir_all, read_to_string, rename, write},
+    path::PathBuf,
+};
+
+use anyhow::{Context, Result};
+
+use crate::file_lock::FileLock;
+
+/// The paths the tool staged in a repository and hasn't committed yet, e.g., as a commit was
+/// deferred, which tells them apart from the changes the user staged
+pub struct StagedPaths {
+    path: PathBuf,
+}
+
+impl StagedPaths {
+    /// Creates the record of staged paths persisted in the given file
+    ///
+    /// # Arguments
+    /// * `path` - The JSON file of the paths, created when the first one is recorded, next to which
+    ///   a lock file serializes processes updating it
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Gets the recorded paths, none if the file is missing or unreadable
+    pub fn list(&self) -> BTreeSet<String> {
+        read_to_string(&self.path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Records the paths in addition to those recorded already
+    pub fn add(&self, paths: impl IntoIterator<Item = String>) -> Result<()> {
+        self.update(|recorded| recorded.extend(paths))
+    }
+
+    /// Forgets the recorded paths but those the predicate keeps, e.g., once committed
+    pub fn retain(&self, keep: impl Fn(&str) -> bool) -> Result<()> {
+        self.update(|recorded| recorded.retain(|path| keep(path)))
+    }
+
+    /// Changes the recorded paths while holding the lock, so that no other process's change is
+    /// lost, writing them back if changed
+    fn update(&self, change: impl FnOnce(&mut BTreeSet<String>)) -> Result<()> {
+        let _lock = FileLock::acquire(&self.path.with_extension("lock"))?;
+        let mut recorded = self.list();
+        let before = recorded.clone();
+        change(&mut recorded);
+        if recorded == before {
+            return Ok(());
+        }
+        self.write(&recorded)
+    }
+
+    /// Writes the paths through a temporary file, so that readers never see a partial one,
+    /// creating the directory of the file
+    fn write(&self, paths: &BTreeSet<String>) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            create_dir_all(dir)?;
+        }
+        let temp_path = self.path.with_extension("tmp");
+        write(&temp_path, serde_json::to_string(paths)?)?;
+        rename(&temp_path, &self.path)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs::remove_dir_all, process::id, sync::Arc, thread};
+
+    use super::*;
+
+    /// Creates the record of staged paths in a fresh temporary directory
+    fn staged_paths(name: &str) -> (PathBuf, StagedPaths) {
+        let dir = temp_dir().join(format!("ccc-test-staged-paths-{name}-{}", id()));
+        let _ = remove_dir_all(&dir);
+        let staged = StagedPaths::new(dir.join("staged.json"));
+        (dir, staged)
+    }
+
+    #[test]
+    fn paths_are_added_and_forgotten() {
+        let (dir, staged) = staged_paths("update");
+        assert!(staged.list().is_empty());
+
+        staged
+            .add(["src/main.rs".to_string(), "README.md".to_string()])
+            .unwrap();
+        staged.add(Some("src/main.rs".to_string())).unwrap();
+        assert_eq!(staged.list(), BTreeSet::from(["README.md".into(), "src/main.rs".into()]));
+
+        staged.retain(|path| path.starts_with("src/")).unwrap();
+        assert_eq!(staged.list(), BTreeSet::from(["src/main.rs".into()]));
+        assert!(!dir.join("staged.lock").exists());
+        assert!(!dir.join("staged.tmp").exists());
+
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_processes_keep_each_others_paths() {
+        let (dir, staged) = staged_paths("concurrent");
+        let staged = Arc::new(staged);
+
+        let threads = (0..20)
+            .map(|n| {
+                let staged = Arc::clone(&staged);
+                thread::spawn(move || staged.add(Some(format!("file{n}.txt"))).unwrap())
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(staged.list().len(), 20);
+
+        remove_dir_all(dir).unwrap();
+    }
+}
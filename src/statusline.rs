@@ -0,0 +1,103 @@
+//! The compact line of auto-commit state `ccc statusline` prints for the Claude Code status line
+
+use jiff::Timestamp;
+
+use crate::{
+    config::CONFIG,
+    git_ops::{count_commits_since, get_base_branch, get_current_branch},
+    types::Repository,
+};
+
+/// Maximum number of commits walked to find the last auto-commit, keeping the line fast
+const MAX_WALKED_COMMITS: usize = 200;
+
+/// Maximum number of characters of a subject shown when it has no conventional commit type
+const MAX_LABEL_CHARS: usize = 24;
+
+/// Renders the state of the repository as one line, reading git only, so that it returns fast
+///
+/// # Arguments
+/// * `repo` - The git repository
+///
+/// # Returns
+/// The line, e.g., `⎇ session/ab12_0712 · 7 commits · last: fix(parser) 3m ago`: the current
+/// branch, the commits on it since its base branch, and the last auto-commit, found by the
+/// committer of `[committer]`, or else the last commit. Empty on an unborn branch.
+pub fn render(repo: &Repository) -> String {
+    let Ok(branch) = get_current_branch(repo) else {
+        return String::new();
+    };
+    let mut parts = vec![format!("⎇ {branch}")];
+    let base_branch = get_base_branch(repo, &branch, &CONFIG.base_branches);
+    if base_branch != branch {
+        match count_commits_since(repo, &base_branch) {
+            0 => {}
+            1 => parts.push("1 commit".to_string()),
+            count => parts.push(format!("{count} commits")),
+        }
+    }
+    if let Some(last) = last_auto_commit(repo) {
+        parts.push(last);
+    }
+    parts.join(" · ")
+}
+
+/// Describes the last auto-commit on the current branch as its label and age
+fn last_auto_commit(repo: &Repository) -> Option<String> {
+    let mut walk = repo.revwalk().ok()?;
+    walk.push_head().ok()?;
+    let committer = CONFIG.committer_identity().map(|(name, _)| name);
+    let commit = walk
+        .take(MAX_WALKED_COMMITS)
+        .filter_map(|oid| repo.find_commit(oid.ok()?).ok())
+        .find(|commit| committer.is_none_or(|name| commit.committer().name() == Some(name)))?;
+    let secs = Timestamp::now().as_second() - commit.time().seconds();
+    Some(format!("last: {} {} ago", label(commit.summary().unwrap_or_default()), age(secs)))
+}
+
+/// Shortens the subject to its conventional commit type and scope, e.g., `fix(parser)`, or else
+/// to its first characters
+fn label(subject: &str) -> String {
+    if let Some((prefix, _)) = subject.split_once(": ")
+        && !prefix.is_empty()
+        && !prefix.contains(' ')
+    {
+        return prefix.trim_end_matches('!').to_string();
+    }
+    match subject.char_indices().nth(MAX_LABEL_CHARS) {
+        Some((end, _)) => format!("{}…", &subject[..end]),
+        None => subject.to_string(),
+    }
+}
+
+/// Formats the age in seconds in its largest unit, e.g., `3m`
+fn age(secs: i64) -> String {
+    match secs.max(0) {
+        secs @ ..60 => format!("{secs}s"),
+        secs @ ..3600 => format!("{}m", secs / 60),
+        secs @ ..86400 => format!("{}h", secs / 3600),
+        secs => format!("{}d", secs / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subject_is_shortened_to_its_type() {
+        assert_eq!(label("fix(parser): accept trailing commas"), "fix(parser)");
+        assert_eq!(label("feat!: drop the v1 API"), "feat");
+        assert_eq!(label("Update the notes"), "Update the notes");
+        assert_eq!(label("Rework everything: the parser"), "Rework everything: the p…");
+    }
+
+    #[test]
+    fn age_is_in_its_largest_unit() {
+        assert_eq!(age(-5), "0s");
+        assert_eq!(age(59), "59s");
+        assert_eq!(age(3 * 60 + 20), "3m");
+        assert_eq!(age(2 * 3600), "2h");
+        assert_eq!(age(3 * 86400 + 5), "3d");
+    }
+}
@@ -0,0 +1,120 @@
+use tracing::warn;
+
+/// Renders a template by replacing each `{name}` placeholder with its value
+///
+/// The template is scanned once from left to right, so values are never scanned again. A
+/// placeholder-looking text inside a value (e.g., `{language}` in a diff) is therefore inserted
+/// verbatim. `{{` and `}}` produce literal braces. Placeholders with names not in `variables` are
+/// left untouched and reported as a warning.
+///
+/// # Arguments
+/// - `template` - The template containing the placeholders
+/// - `variables` - Pairs of placeholder names (without braces) and their values
+///
+/// # Returns
+/// The rendered template
+pub fn render(template: &str, variables: &[(&str, &str)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(pos) = rest.find(['{', '}']) {
+        rendered.push_str(&rest[..pos]);
+        let tail = &rest[pos..];
+
+        if tail.starts_with("{{") || tail.starts_with("}}") {
+            rendered.push_str(&tail[..1]);
+            rest = &tail[2..];
+            continue;
+        }
+
+        match placeholder_name(tail) {
+            Some(name) => {
+                match variables.iter().find(|(n, _)| *n == name) {
+                    Some((_, value)) => rendered.push_str(value),
+                    None => {
+                        warn!("Unknown placeholder `{{{name}}}` left as is");
+                        rendered.push_str(&tail[..name.len() + 2]);
+                    }
+                }
+                rest = &tail[name.len() + 2..];
+            }
+            None => {
+                rendered.push_str(&tail[..1]);
+                rest = &tail[1..];
+            }
+        }
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Gets the name of the placeholder at the start of `text`, if `text` starts with one
+fn placeholder_name(text: &str) -> Option<&str> {
+    let end = text.strip_prefix('{')?.find('}')? + 1;
+    let name = &text[1..end];
+    (!name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_')).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values of the placeholders used by the tests, one of them empty
+    const VARIABLES: &[(&str, &str)] =
+        &[("branch", "main"), ("session_id", "0b6c2f1e"), ("user_intent", "")];
+
+    #[test]
+    fn placeholders_are_replaced_by_their_values() {
+        for (template, expected) in [
+            ("{branch}", "main"),
+            ("on {branch} in {session_id}.", "on main in 0b6c2f1e."),
+            ("{branch}{branch}", "mainmain"),
+            ("[{user_intent}]", "[]"),
+            ("no placeholders", "no placeholders"),
+            ("", ""),
+        ] {
+            assert_eq!(render(template, VARIABLES), expected, "{template}");
+        }
+    }
+
+    #[test]
+    fn placeholders_without_values_are_left_as_is() {
+        for (template, variables, expected) in [
+            ("{unknown}", VARIABLES, "{unknown}"),
+            ("{branch} {unknown}", VARIABLES, "main {unknown}"),
+            ("{branch} {session_id}", &[][..], "{branch} {session_id}"),
+            ("{Branch}", VARIABLES, "{Branch}"),
+            ("{branch name}", VARIABLES, "{branch name}"),
+            ("{}", VARIABLES, "{}"),
+            ("{branch", VARIABLES, "{branch"),
+            ("branch}", VARIABLES, "branch}"),
+        ] {
+            assert_eq!(render(template, variables), expected, "{template}");
+        }
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        for (template, expected) in [
+            ("{{branch}}", "{branch}"),
+            ("{{{branch}}}", "{main}"),
+            ("{{", "{"),
+            ("}}", "}"),
+            ("{{}}", "{}"),
+            ("fn main() {{ {branch} }}", "fn main() { main }"),
+        ] {
+            assert_eq!(render(template, VARIABLES), expected, "{template}");
+        }
+    }
+
+    #[test]
+    fn values_are_inserted_verbatim() {
+        let diff = "+ {diff_content} {branch} {{literal}} }}";
+        let variables = [("diff_content", diff), ("branch", "main")];
+
+        let rendered = render("{branch}\n{diff_content}\n{branch}", &variables);
+
+        assert_eq!(rendered, format!("main\n{diff}\nmain"));
+    }
+}
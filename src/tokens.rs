@@ -0,0 +1,91 @@
+/// Estimates the number of tokens a model needs to read the text
+///
+/// This approximates typical BPE tokenizers without shipping one: ASCII text averages about four
+/// characters per token, while other scripts (e.g., Japanese) take about one token per character.
+///
+/// # Arguments
+/// - `text` - The text to estimate
+///
+/// # Returns
+/// The estimated number of tokens
+pub fn estimate(text: &str) -> usize {
+    let ascii = text.chars().filter(char::is_ascii).count();
+    let other = text.chars().count() - ascii;
+    ascii.div_ceil(4) + other
+}
+
+/// Splits a unified diff into the text before the first file and one section per file
+///
+/// # Arguments
+/// - `diff_content` - A unified diff as produced by `git diff`
+///
+/// # Returns
+/// The leading text and the per-file sections, which concatenated give the input back
+pub fn split_files(diff_content: &str) -> (&str, Vec<&str>) {
+    let mut starts = diff_content
+        .match_indices("diff --git ")
+        .map(|(pos, _)| pos)
+        .filter(|&pos| pos == 0 || diff_content[..pos].ends_with('\n'))
+        .collect::<Vec<_>>();
+    let Some(&first) = starts.first() else {
+        return (diff_content, Vec::new());
+    };
+
+    starts.push(diff_content.len());
+    let sections = starts.windows(2).map(|w| &diff_content[w[0]..w[1]]).collect();
+    (&diff_content[..first], sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates the section of a diff adding a line of the length to the file
+    fn section(file: &str, len: usize) -> String {
+        format!(
+            "diff --git a/{file} b/{file}\n--- a/{file}\n+++ b/{file}\n@@ -0,0 +1 @@\n+{}\n",
+            "x".repeat(len)
+        )
+    }
+
+    #[test]
+    fn tokens_are_estimated_by_script() {
+        for (text, tokens) in [
+            ("", 0),
+            ("abcd", 1),
+            ("abcde", 2),
+            ("abcdefgh", 2),
+            ("日本語", 3),
+            ("abcd日本語", 4),
+            ("a日", 2),
+        ] {
+            assert_eq!(estimate(text), tokens, "{text}");
+        }
+    }
+
+    #[test]
+    fn diff_is_split_at_each_file() {
+        let diff = format!("lead\n{}{}", section("a.rs", 10), section("b.rs", 10));
+
+        let (lead, files) = split_files(&diff);
+
+        assert_eq!(lead, "lead\n");
+        assert_eq!(files, [section("a.rs", 10), section("b.rs", 10)]);
+        assert_eq!(format!("{lead}{}", files.concat()), diff);
+    }
+
+    #[test]
+    fn header_inside_a_line_does_not_split() {
+        let diff = format!("{}+see diff --git a/c.rs b/c.rs\n", section("a.rs", 10));
+
+        let (lead, files) = split_files(&diff);
+
+        assert_eq!(lead, "");
+        assert_eq!(files, [diff.as_str()]);
+    }
+
+    #[test]
+    fn text_without_files_is_all_lead() {
+        assert_eq!(split_files("no diff here\n"), ("no diff here\n", Vec::new()));
+    }
+}
@@ -0,0 +1,140 @@
+//! Assembly of the trailers at the end of commit messages, such as `Signed-off-by`
+
+/// Key of the trailer naming the Claude Code session a commit was made in
+pub const SESSION_TRAILER: &str = "Claude-Session";
+
+/// Key of the trailer giving the path of the transcript of that session
+pub const TRANSCRIPT_TRAILER: &str = "Claude-Transcript";
+
+/// Key of the trailer marking a commit made with the fallback message, as generating one failed
+/// or is disabled, which is left out of the recent commits the generator is shown
+pub const FALLBACK_TRAILER: &str = "Auto-Commit-Fallback";
+
+/// Appends the trailers to the trailer block ending the message, starting one if it has none
+///
+/// # Arguments
+/// * `message` - The commit message, whose last paragraph other than the subject is its trailer
+///   block if every line of it is a `Key: value` trailer
+/// * `trailers` - The keys and values to append, in order; those the message has already, with the
+///   key in any case, are left out
+///
+/// # Returns
+/// The message with the trailers, separated from the rest by a blank line as git expects
+pub fn append_trailers(message: &str, trailers: &[(&str, String)]) -> String {
+    let (body, block) = split_trailer_block(message);
+    let mut lines = block.lines().map(str::to_string).collect::<Vec<_>>();
+    for (key, value) in trailers {
+        let exists = lines.iter().any(|line| {
+            line.split_once(':')
+                .is_some_and(|(k, v)| k.eq_ignore_ascii_case(key) && v.trim() == value.trim())
+        });
+        if !exists {
+            lines.push(format!("{key}: {value}"));
+        }
+    }
+    if lines.is_empty() {
+        return body.to_string();
+    }
+    format!("{body}\n\n{}", lines.join("\n"))
+}
+
+/// Gets the value of the trailer of the key, in any case, from the trailer block ending the
+/// message
+///
+/// # Returns
+/// The value of the last trailer of the key, or `None` if the message has none
+pub fn find_trailer<'m>(message: &'m str, key: &str) -> Option<&'m str> {
+    let (_, block) = split_trailer_block(message);
+    block
+        .lines()
+        .rev()
+        .filter_map(|line| line.split_once(": "))
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.trim())
+}
+
+/// Splits the message into the part before its trailer block and the block, which is empty if
+/// the last paragraph other than the subject isn't all trailers
+fn split_trailer_block(message: &str) -> (&str, &str) {
+    let message = message.trim_end();
+    match message.rsplit_once("\n\n") {
+        Some((body, last)) if last.lines().all(is_trailer) => (body, last),
+        _ => (message, ""),
+    }
+}
+
+/// Checks whether the line is a trailer: a key of letters, digits, and dashes, a colon, and a
+/// value
+fn is_trailer(line: &str) -> bool {
+    line.split_once(": ").is_some_and(|(key, value)| {
+        !key.is_empty()
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && !value.trim().is_empty()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The sign-off of the test identity
+    fn signoff() -> [(&'static str, String); 1] {
+        [("Signed-off-by", "Test <test@example.com>".to_string())]
+    }
+
+    #[test]
+    fn trailers_start_a_block_after_the_body() {
+        assert_eq!(
+            append_trailers("feat: add notes\n", &signoff()),
+            "feat: add notes\n\nSigned-off-by: Test <test@example.com>"
+        );
+        assert_eq!(
+            append_trailers("feat: add notes\n\nExplain why: it helps.\n", &signoff()),
+            "feat: add notes\n\nExplain why: it helps.\n\nSigned-off-by: Test <test@example.com>"
+        );
+    }
+
+    #[test]
+    fn trailers_join_the_existing_block_last() {
+        let message = "fix: handle errors\n\nBody.\n\nCo-authored-by: Pat <pat@example.com>\n";
+        let trailers = [
+            ("Session-Id", "0b6c2f1e".to_string()),
+            ("Signed-off-by", "Test <test@example.com>".to_string()),
+        ];
+        assert_eq!(
+            append_trailers(message, &trailers),
+            "fix: handle errors\n\nBody.\n\nCo-authored-by: Pat <pat@example.com>\n\
+             Session-Id: 0b6c2f1e\nSigned-off-by: Test <test@example.com>"
+        );
+    }
+
+    #[test]
+    fn existing_trailers_are_not_duplicated() {
+        let message = "feat: add notes\n\nsigned-off-by: Test <test@example.com>";
+        assert_eq!(append_trailers(message, &signoff()), message);
+        // Another identity's sign-off is kept, followed by this one
+        let message = "feat: add notes\n\nSigned-off-by: Pat <pat@example.com>";
+        assert_eq!(
+            append_trailers(message, &signoff()),
+            format!("{message}\nSigned-off-by: Test <test@example.com>")
+        );
+    }
+
+    #[test]
+    fn trailer_is_found_in_the_block_only() {
+        let message = "feat: add notes\n\nClaude-Session: in the body\nas prose\n\n\
+                       claude-session: 0b6c2f1e\nSigned-off-by: Test <test@example.com>\n";
+        assert_eq!(find_trailer(message, SESSION_TRAILER), Some("0b6c2f1e"));
+        assert_eq!(find_trailer(message, TRANSCRIPT_TRAILER), None);
+        assert_eq!(find_trailer("Claude-Session: 0b6c2f1e", SESSION_TRAILER), None);
+    }
+
+    #[test]
+    fn subject_alone_is_no_trailer_block() {
+        assert_eq!(
+            append_trailers("docs: update README", &signoff()),
+            "docs: update README\n\nSigned-off-by: Test <test@example.com>"
+        );
+        assert_eq!(append_trailers("docs: update README\n", &[]), "docs: update README");
+    }
+}
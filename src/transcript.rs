@@ -0,0 +1,226 @@
+//! Reading of the messages the user typed from the Claude Code transcript of a session, and
+//! finding the transcript of a session
+
+use std::{
+    collections::VecDeque,
+    ffi::OsStr,
+    fs::{File, read_dir},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use directories::BaseDirs;
+use serde_json::Value;
+
+/// Prefixes of the user records Claude Code writes for slash commands and their output, which the
+/// user didn't type as such
+const COMMAND_PREFIXES: &[&str] = &["<command-", "<local-command-"];
+
+/// Gets the last messages the user typed in the session, within the limits
+///
+/// Lines that aren't JSON, records of other types, tool results, and meta records are skipped, as
+/// the format of the transcript changes between Claude Code versions.
+///
+/// # Arguments
+/// * `path` - The JSON Lines transcript of the session
+/// * `max_messages` - The maximum number of messages, the latest ones kept
+/// * `max_chars` - The maximum number of characters of the messages together; older messages are
+///   left out first, and the latest one is cut if it is longer alone
+///
+/// # Returns
+/// The messages, oldest first, or none if the transcript can't be read
+pub fn user_messages(path: &Path, max_messages: usize, max_chars: usize) -> Vec<String> {
+    let Ok(file) = File::open(path) else {
+        return Vec::new();
+    };
+    let mut latest = VecDeque::new();
+    for line in BufReader::new(file).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let Some(text) = serde_json::from_str::<Value>(&line)
+            .ok()
+            .and_then(|record| typed_text(&record))
+        else {
+            continue;
+        };
+        latest.push_back(text);
+        if latest.len() > max_messages {
+            latest.pop_front();
+        }
+    }
+
+    let mut messages = Vec::new();
+    let mut chars = 0;
+    for text in latest.into_iter().rev() {
+        let len = text.chars().count();
+        if chars + len > max_chars {
+            if messages.is_empty() {
+                messages.push(text.chars().take(max_chars).collect());
+            }
+            break;
+        }
+        chars += len;
+        messages.push(text);
+    }
+    messages.reverse();
+    messages
+}
+
+/// Writes the path with the home directory as `~`, so that it is the same for the user whatever
+/// their home directory is on the machine
+pub fn shorten_home(path: &str) -> String {
+    let home = BaseDirs::new().map(|dirs| dirs.home_dir().to_path_buf());
+    match home.and_then(|home| Path::new(path).strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.to_string(),
+    }
+}
+
+/// Resolves a leading `~/` of the path to the home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), BaseDirs::new()) {
+        (Some(rest), Some(dirs)) => dirs.home_dir().join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Gets the directory of the projects of Claude Code, `~/.claude/projects`
+fn projects_dir() -> Option<PathBuf> {
+    Some(BaseDirs::new()?.home_dir().join(".claude").join("projects"))
+}
+
+/// Finds the transcript of the session among the projects of Claude Code in
+/// `~/.claude/projects`, where each project directory holds the transcripts of its sessions
+pub fn find_in_projects(session_id: &str) -> Option<PathBuf> {
+    read_dir(projects_dir()?)
+        .ok()?
+        .filter_map(|entry| Some(entry.ok()?.path().join(format!("{session_id}.jsonl"))))
+        .find(|path| path.is_file())
+}
+
+/// Checks whether the path is that of a transcript of Claude Code, an existing `.jsonl` file in
+/// `~/.claude/projects` once links are resolved, as a path read from shared history may be any
+/// file or URL
+pub fn is_transcript(path: &Path) -> bool {
+    projects_dir().is_some_and(|projects| is_transcript_in(path, &projects))
+}
+
+/// Checks whether the path is that of an existing `.jsonl` file in the directory of projects once
+/// links are resolved
+fn is_transcript_in(path: &Path, projects: &Path) -> bool {
+    let (Ok(path), Ok(projects)) = (path.canonicalize(), projects.canonicalize()) else {
+        return false;
+    };
+    path.is_file() && path.extension() == Some(OsStr::new("jsonl")) && path.starts_with(projects)
+}
+
+/// Gets the text the user typed in the record, if it is a user message
+fn typed_text(record: &Value) -> Option<String> {
+    if record["type"] != "user" || record["isMeta"].as_bool().unwrap_or_default() {
+        return None;
+    }
+    let content = &record["message"]["content"];
+    let text = match content {
+        Value::String(text) => text.clone(),
+        // Tool results come back as user records with blocks of other types
+        Value::Array(blocks) => blocks
+            .iter()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => return None,
+    };
+    let text = text.trim();
+    if text.is_empty() || COMMAND_PREFIXES.iter().any(|prefix| text.starts_with(prefix)) {
+        return None;
+    }
+    Some(text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env::temp_dir,
+        fs::{create_dir_all, remove_dir_all, write},
+        process::id,
+    };
+
+    use super::*;
+
+    /// Writes the transcript lines to a fresh temporary file
+    fn transcript(name: &str, lines: &[&str]) -> std::path::PathBuf {
+        let path = temp_dir().join(format!("ccc-test-transcript-{name}-{}.jsonl", id()));
+        write(&path, lines.join("\n")).unwrap();
+        path
+    }
+
+    #[test]
+    fn typed_messages_are_read_and_the_rest_skipped() {
+        let path = transcript(
+            "skip",
+            &[
+                r#"{"type":"summary","summary":"Parser work"}"#,
+                r#"{"type":"user","message":{"role":"user","content":"Make the parser accept trailing commas"}}"#,
+                r#"{"type":"assistant","message":{"role":"assistant","content":[{"type":"text","text":"Sure"}]}}"#,
+                r#"{"type":"user","message":{"role":"user","content":[{"type":"tool_result","tool_use_id":"t1","content":"ok"}]}}"#,
+                r#"{"type":"user","isMeta":true,"message":{"role":"user","content":"Caveat: local commands"}}"#,
+                r#"{"type":"user","message":{"role":"user","content":"<command-name>/clear</command-name>"}}"#,
+                "not JSON at all",
+                r#"{"type":"progress","data":{"future":"field"}}"#,
+                r#"{"type":"user","message":{"role":"user","content":[{"type":"text","text":"Also add tests"},{"type":"image"}]}}"#,
+            ],
+        );
+        assert_eq!(
+            user_messages(&path, 10, 1000),
+            ["Make the parser accept trailing commas", "Also add tests"]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn latest_messages_are_kept_within_the_limits() {
+        let lines = (1..=5)
+            .map(|n| format!(r#"{{"type":"user","message":{{"content":"message {n}"}}}}"#))
+            .collect::<Vec<_>>();
+        let path = transcript("limits", &lines.iter().map(String::as_str).collect::<Vec<_>>());
+        assert_eq!(user_messages(&path, 2, 1000), ["message 4", "message 5"]);
+        assert_eq!(user_messages(&path, 5, 20), ["message 4", "message 5"]);
+        assert_eq!(user_messages(&path, 5, 4), ["mess"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn missing_transcript_has_no_messages() {
+        assert!(user_messages(Path::new("/nonexistent/transcript.jsonl"), 10, 1000).is_empty());
+    }
+
+    #[test]
+    fn only_jsonl_files_in_the_projects_are_transcripts() {
+        let dir = temp_dir().join(format!("ccc-test-transcript-projects-{}", id()));
+        let projects = dir.join("projects");
+        create_dir_all(projects.join("-home-dev-project")).unwrap();
+        for file in
+            ["projects/-home-dev-project/0b6c2f1e.jsonl", "projects/notes.txt", "other.jsonl"]
+        {
+            write(dir.join(file), "").unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("other.jsonl"), projects.join("link.jsonl")).unwrap();
+
+        for (path, expected) in [
+            ("projects/-home-dev-project/0b6c2f1e.jsonl", true),
+            ("projects/-home-dev-project/../-home-dev-project/0b6c2f1e.jsonl", true),
+            ("projects/-home-dev-project/missing.jsonl", false),
+            ("projects/notes.txt", false),
+            ("projects/-home-dev-project", false),
+            ("projects/../other.jsonl", false),
+            ("other.jsonl", false),
+            ("projects/link.jsonl", false),
+        ] {
+            assert_eq!(is_transcript_in(&dir.join(path), &projects), expected, "{path}");
+        }
+        remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,6 +1,14 @@
-use std::ops::Deref;
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::Deref,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
-use serde::Deserialize;
+use anyhow::{Context, Result, anyhow};
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Map, Value, json};
 
 pub struct Repository {
     inner: git2::Repository,
@@ -13,57 +21,157 @@ impl Deref for Repository {
     }
 }
 
-impl Default for Repository {
-    fn default() -> Self {
-        Self { inner: git2::Repository::discover(".").unwrap() }
+impl Repository {
+    /// Discovers the repository containing the directory, e.g., the working directory of a hook
+    /// event rather than that of the process
+    ///
+    /// # Arguments
+    /// * `path` - The directory to search from, upwards
+    ///
+    /// # Returns
+    /// The repository, or an error if the directory isn't in one
+    pub fn discover_from(path: &Path) -> Result<Self> {
+        let inner = git2::Repository::discover(path)
+            .with_context(|| format!("No git repository found at {}", path.display()))?;
+        Ok(Self { inner })
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
 #[serde(tag = "hook_event_name")]
 pub enum HookEvent {
     SessionStart {
         session_id: String,
-        cwd: String,
-        #[serde(default)]
+        cwd: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
         source: Option<SessionStartSource>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transcript_path: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        permission_mode: Option<PermissionMode>,
     },
     PostToolUse {
-        cwd: String,
+        #[serde(default)]
+        session_id: String,
+        cwd: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transcript_path: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        permission_mode: Option<PermissionMode>,
         tool_name: ToolName,
         tool_input: ToolInput,
         tool_response: ToolResponse,
     },
+    Stop {
+        #[serde(default)]
+        session_id: String,
+        cwd: PathBuf,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        transcript_path: Option<String>,
+    },
 }
 
 impl HookEvent {
     /// Gets the current working directory from the hook event
     ///
     /// # Returns
-    /// The working directory path as a string slice
-    pub fn cwd(&self) -> &str {
+    /// The working directory path, kept as a path so that no conversion loses bytes
+    pub fn cwd(&self) -> &Path {
         match self {
-            HookEvent::SessionStart { cwd, .. } | HookEvent::PostToolUse { cwd, .. } => cwd,
+            HookEvent::SessionStart { cwd, .. }
+            | HookEvent::PostToolUse { cwd, .. }
+            | HookEvent::Stop { cwd, .. } => cwd,
+        }
+    }
+
+    /// Gets the Claude Code session the hook event belongs to
+    pub fn session_id(&self) -> &str {
+        match self {
+            HookEvent::SessionStart { session_id, .. }
+            | HookEvent::PostToolUse { session_id, .. }
+            | HookEvent::Stop { session_id, .. } => session_id,
         }
     }
-}
 
-#[derive(Debug, Deserialize)]
-pub struct ToolInput {
-    pub file_path: String,
+    /// Gets the transcript of the session, if the event names it
+    pub fn transcript_path(&self) -> Option<&str> {
+        match self {
+            HookEvent::SessionStart { transcript_path, .. }
+            | HookEvent::PostToolUse { transcript_path, .. }
+            | HookEvent::Stop { transcript_path, .. } => transcript_path.as_deref(),
+        }
+    }
+
+    /// Gets the name of the hook event, as in `hook_event_name`
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::SessionStart { .. } => "SessionStart",
+            HookEvent::PostToolUse { .. } => "PostToolUse",
+            HookEvent::Stop { .. } => "Stop",
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-pub struct ToolResponse {
-    #[serde(default = "default_success")]
-    pub success: bool,
+/// The input of the tool, whose fields depend on the tool, e.g., `file_path` for the native edit
+/// tools and `path` for the `write_file` tool of the MCP filesystem server
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ToolInput(Map<String, Value>);
+
+impl ToolInput {
+    /// Creates the input of a native edit tool changing the file
+    pub fn of_file(file_path: String) -> Self {
+        Self(Map::from_iter([("file_path".to_string(), Value::String(file_path))]))
+    }
+
+    /// Gets the string field of the input, e.g., `file_path`
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.0.get(field).and_then(Value::as_str)
+    }
 }
 
-fn default_success() -> bool {
-    true
+/// The response of the tool, kept whole and read through the methods below. MCP tools respond
+/// with a list of content blocks instead, read as a success.
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct ToolResponse(Value);
+
+impl ToolResponse {
+    /// Creates the response of a native edit tool that succeeded
+    ///
+    /// # Arguments
+    /// * `user_modified` - Whether the user hand-edited the change before it was applied
+    pub fn succeeded(user_modified: bool) -> Self {
+        Self(json!({ "success": true, "userModified": user_modified }))
+    }
+
+    /// Checks whether the tool succeeded, assumed unless `success` is false or an `error` is given
+    pub fn success(&self) -> bool {
+        self.0
+            .get("success")
+            .and_then(Value::as_bool)
+            .unwrap_or(self.error().is_none())
+    }
+
+    /// Gets why the tool failed
+    pub fn error(&self) -> Option<&str> {
+        self.0
+            .get("error")
+            .and_then(Value::as_str)
+            .filter(|error| !error.is_empty())
+    }
+
+    /// Gets the file the tool changed (`filePath`), more reliable than the one in its input
+    pub fn file_path(&self) -> Option<&str> {
+        self.0.get("filePath").and_then(Value::as_str)
+    }
+
+    /// Checks whether the user hand-edited the proposed change before it was applied
+    /// (`userModified`)
+    pub fn user_modified(&self) -> bool {
+        self.0.get("userModified").and_then(Value::as_bool).unwrap_or(false)
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ValueEnum)]
 #[serde(rename_all = "snake_case")]
 pub enum SessionStartSource {
     Clear,
@@ -71,21 +179,344 @@ pub enum SessionStartSource {
     Resume,
     Startup,
     #[serde(other)] // fallback
+    #[value(skip)]
     Unknown,
 }
 
-#[derive(Debug, Deserialize)]
+/// How Claude Code asks for permission to use tools, as set with Shift+Tab or `--permission-mode`
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum PermissionMode {
+    Default,
+    AcceptEdits,
+    BypassPermissions,
+    /// Planning without changing files
+    Plan,
+    #[serde(other)] // fallback
+    Unknown,
+}
+
+/// The tools of Claude Code, as named in `tool_name` and the `triggers` table
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ToolName {
     Task,
     Bash,
+    BashOutput,
+    KillShell,
     Glob,
     Grep,
     Read,
     Edit,
     MultiEdit,
     Write,
+    NotebookEdit,
     WebFetch,
     WebSearch,
-    #[serde(other)] // fallback
-    Unknown,
+    TodoWrite,
+    ExitPlanMode,
+    SlashCommand,
+    /// A tool of an MCP server
+    Mcp(McpTool),
+    /// A tool added after this binary, by its name
+    Unknown(String),
+}
+
+impl ToolName {
+    /// The native tools known to this binary
+    pub const KNOWN: [ToolName; 16] = [
+        ToolName::Task,
+        ToolName::Bash,
+        ToolName::BashOutput,
+        ToolName::KillShell,
+        ToolName::Glob,
+        ToolName::Grep,
+        ToolName::Read,
+        ToolName::Edit,
+        ToolName::MultiEdit,
+        ToolName::Write,
+        ToolName::NotebookEdit,
+        ToolName::WebFetch,
+        ToolName::WebSearch,
+        ToolName::TodoWrite,
+        ToolName::ExitPlanMode,
+        ToolName::SlashCommand,
+    ];
+}
+
+impl Display for ToolName {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            ToolName::Task => "Task",
+            ToolName::Bash => "Bash",
+            ToolName::BashOutput => "BashOutput",
+            ToolName::KillShell => "KillShell",
+            ToolName::Glob => "Glob",
+            ToolName::Grep => "Grep",
+            ToolName::Read => "Read",
+            ToolName::Edit => "Edit",
+            ToolName::MultiEdit => "MultiEdit",
+            ToolName::Write => "Write",
+            ToolName::NotebookEdit => "NotebookEdit",
+            ToolName::WebFetch => "WebFetch",
+            ToolName::WebSearch => "WebSearch",
+            ToolName::TodoWrite => "TodoWrite",
+            ToolName::ExitPlanMode => "ExitPlanMode",
+            ToolName::SlashCommand => "SlashCommand",
+            ToolName::Mcp(tool) => return write!(f, "{MCP_PREFIX}{}__{}", tool.server, tool.tool),
+            ToolName::Unknown(name) => name,
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for ToolName {
+    type Err = anyhow::Error;
+
+    /// Parses the name of a known tool or an MCP tool, e.g., in the `triggers` table or
+    /// `simulate --tool`
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        if let Some(tool) = McpTool::parse(name) {
+            return Ok(ToolName::Mcp(tool));
+        }
+        ToolName::KNOWN
+            .into_iter()
+            .find(|tool| tool.to_string() == name)
+            .ok_or_else(|| {
+                let known = ToolName::KNOWN.map(|tool| tool.to_string()).join(", ");
+                anyhow!("Unknown tool {name:?}, expected one of {known}, or mcp__<server>__<tool>")
+            })
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        // Tools added after this binary are ignored like the other tools not changing files
+        Ok(name.parse().unwrap_or(ToolName::Unknown(name)))
+    }
+}
+
+impl Serialize for ToolName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Prefix of the names of MCP tools, followed by the server name, `__`, and the tool name
+const MCP_PREFIX: &str = "mcp__";
+
+/// A tool provided by an MCP server, named `mcp__<server>__<tool>` by Claude Code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct McpTool {
+    pub server: String,
+    pub tool: String,
+}
+
+impl McpTool {
+    /// Parses the name of an MCP tool
+    ///
+    /// # Returns
+    /// The server and tool names, or `None` if the name doesn't follow `mcp__<server>__<tool>`
+    pub fn parse(name: &str) -> Option<Self> {
+        let (server, tool) = name.strip_prefix(MCP_PREFIX)?.split_once("__")?;
+        (!server.is_empty() && !tool.is_empty())
+            .then(|| Self { server: server.to_string(), tool: tool.to_string() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{from_str, from_value};
+
+    use super::*;
+
+    /// The `tool_name` values sent by current Claude Code versions
+    const TOOL_NAMES: [&str; 16] = [
+        "Task",
+        "Bash",
+        "BashOutput",
+        "KillShell",
+        "Glob",
+        "Grep",
+        "Read",
+        "Edit",
+        "MultiEdit",
+        "Write",
+        "NotebookEdit",
+        "WebFetch",
+        "WebSearch",
+        "TodoWrite",
+        "ExitPlanMode",
+        "SlashCommand",
+    ];
+
+    #[test]
+    fn tool_names_round_trip() {
+        for name in TOOL_NAMES {
+            let tool = from_value::<ToolName>(Value::String(name.to_string())).unwrap();
+            assert!(!matches!(tool, ToolName::Unknown(_)), "{name}");
+            assert_eq!(tool.to_string(), name);
+            assert_eq!(name.parse::<ToolName>().unwrap(), tool);
+        }
+    }
+
+    #[test]
+    fn unknown_tool_names_fall_back() {
+        let tool = from_value::<ToolName>(Value::String("FutureTool".to_string())).unwrap();
+        assert_eq!(tool, ToolName::Unknown("FutureTool".to_string()));
+        assert_eq!(tool.to_string(), "FutureTool");
+        assert!("FutureTool".parse::<ToolName>().is_err());
+        assert!("Unknown".parse::<ToolName>().is_err());
+    }
+
+    /// Parses a fixture under `tests/fixtures/tool_response`
+    fn tool_response(fixture: &str) -> ToolResponse {
+        from_str(fixture).unwrap()
+    }
+
+    #[test]
+    fn successful_write_reports_its_file() {
+        let response =
+            tool_response(include_str!("../tests/fixtures/tool_response/write_success.json"));
+        assert!(response.success());
+        assert_eq!(response.error(), None);
+        assert_eq!(response.file_path(), Some("/home/dev/project/src/lib.rs"));
+        assert!(!response.user_modified());
+    }
+
+    #[test]
+    fn user_modified_edit_is_flagged() {
+        let response =
+            tool_response(include_str!("../tests/fixtures/tool_response/edit_user_modified.json"));
+        assert!(response.success());
+        assert_eq!(response.file_path(), Some("/home/dev/project/src/main.rs"));
+        assert!(response.user_modified());
+    }
+
+    #[test]
+    fn failure_carries_its_error() {
+        let response = tool_response(include_str!("../tests/fixtures/tool_response/failure.json"));
+        assert!(!response.success());
+        assert_eq!(
+            response.error(),
+            Some("File has not been read yet. Read it first before writing to it.")
+        );
+        assert_eq!(response.file_path(), None);
+    }
+
+    #[test]
+    fn mcp_content_blocks_are_a_success() {
+        let response = tool_response(include_str!("../tests/fixtures/tool_response/mcp.json"));
+        assert!(response.success());
+        assert_eq!(response.file_path(), None);
+        assert!(!response.user_modified());
+    }
+
+    #[test]
+    fn session_start_carries_the_transcript() {
+        let event = from_str::<HookEvent>(include_str!(
+            "../tests/fixtures/hook_events/session_start_startup.json"
+        ))
+        .unwrap();
+        assert_eq!(
+            event.transcript_path(),
+            Some(
+                "/home/dev/.claude/projects/-home-dev-project/\
+                 9f3c2a4e-6b1d-4c8e-a2f7-5d0e1b3c4a6f.jsonl"
+            )
+        );
+        assert!(matches!(
+            event,
+            HookEvent::SessionStart { permission_mode: Some(PermissionMode::Default), .. }
+        ));
+    }
+
+    #[test]
+    fn post_tool_use_carries_the_permission_mode() {
+        let event = from_str::<HookEvent>(include_str!(
+            "../tests/fixtures/hook_events/post_tool_use_plan.json"
+        ))
+        .unwrap();
+        assert!(matches!(
+            event,
+            HookEvent::PostToolUse { permission_mode: Some(PermissionMode::Plan), .. }
+        ));
+        assert!(event.transcript_path().is_some());
+    }
+
+    #[test]
+    fn missing_and_unknown_fields_are_tolerated() {
+        let event = from_str::<HookEvent>(
+            r#"{"session_id":"s","cwd":"/tmp","hook_event_name":"SessionStart","permission_mode":"futureMode"}"#,
+        )
+        .unwrap();
+        assert_eq!(event.transcript_path(), None);
+        assert!(matches!(
+            event,
+            HookEvent::SessionStart { permission_mode: Some(PermissionMode::Unknown), .. }
+        ));
+    }
+
+    #[test]
+    fn mcp_tool_names_are_parsed() {
+        let name = "mcp__filesystem__write_file";
+        let tool = from_value::<ToolName>(Value::String(name.to_string())).unwrap();
+        assert_eq!(
+            tool,
+            ToolName::Mcp(McpTool {
+                server: "filesystem".to_string(),
+                tool: "write_file".to_string()
+            })
+        );
+        assert_eq!(tool.to_string(), name);
+        for malformed in ["mcp__filesystem", "mcp____write_file", "mcp__filesystem__"] {
+            assert!(McpTool::parse(malformed).is_none(), "{malformed}");
+        }
+    }
+
+    #[test]
+    fn every_fixture_round_trips() {
+        let dir = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/hook_events"));
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let content = std::fs::read_to_string(&path).unwrap();
+            let event = from_str::<HookEvent>(&content)
+                .unwrap_or_else(|error| panic!("{}: {error}", path.display()));
+            let value = serde_json::to_value(&event).unwrap();
+            assert_eq!(from_value::<HookEvent>(value).unwrap(), event, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn unknown_tools_and_missing_fields_round_trip() {
+        let event = from_str::<HookEvent>(include_str!(
+            "../tests/fixtures/hook_events/post_tool_use_unknown_tool.json"
+        ))
+        .unwrap();
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["hook_event_name"], "PostToolUse");
+        assert_eq!(value["tool_name"], "FutureTool");
+
+        let event = from_str::<HookEvent>(
+            r#"{"cwd":"/tmp","hook_event_name":"SessionStart","session_id":"s"}"#,
+        )
+        .unwrap();
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({"hook_event_name":"SessionStart","session_id":"s","cwd":"/tmp"})
+        );
+        assert_eq!(from_value::<HookEvent>(value).unwrap(), event);
+    }
+
+    #[test]
+    fn empty_errors_are_a_success() {
+        let event = from_str::<HookEvent>(include_str!(
+            "../tests/fixtures/hook_events/post_tool_use_notebook_edit.json"
+        ))
+        .unwrap();
+        let HookEvent::PostToolUse { tool_response, .. } = event else { panic!("{event:?}") };
+        assert!(tool_response.success());
+        assert_eq!(tool_response.error(), None);
+    }
 }
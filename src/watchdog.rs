@@ -0,0 +1,164 @@
+//! Deadline for the background process handling a hook event, so that a wedged one can't hold the
+//! repository forever
+
+use std::{
+    fmt::{self, Display, Formatter},
+    path::PathBuf,
+    process::exit,
+    sync::{
+        Mutex,
+        atomic::{AtomicU8, Ordering},
+    },
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+
+use jiff::Timestamp;
+use tracing::{error, info};
+
+use crate::{
+    instances::{record_holding_index, remove_own_lock},
+    process::kill_children,
+};
+
+/// Exit code of a process stopped by the watchdog, as `timeout` uses
+pub const TIMED_OUT_EXIT_CODE: i32 = 124;
+
+/// Interval between checks whether the deadline expired
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// The deadline set by `arm`, if any
+static DEADLINE: Mutex<Option<Deadline>> = Mutex::new(None);
+
+/// A deadline for handling an event
+#[derive(Clone, Copy)]
+struct Deadline {
+    /// When the process is stopped
+    expires: Instant,
+    /// The time from `armed` to `expires`
+    duration: Duration,
+    /// The setting the duration is taken from, named when it expires
+    setting: &'static str,
+}
+
+/// The phase the current process is in, stored as a `Phase` discriminant
+static PHASE: AtomicU8 = AtomicU8::new(Phase::Starting as u8);
+
+/// When the current process entered the phase in which it may hold the index lock, if it is in one
+static HOLDING_INDEX: Mutex<Option<Timestamp>> = Mutex::new(None);
+
+/// What the process handling a hook event is doing, reported when the deadline expires
+#[derive(Clone, Copy, Debug)]
+#[repr(u8)]
+pub enum Phase {
+    Starting,
+    Staging,
+    Generating,
+    Committing,
+    /// Reporting the commit made, e.g., to the webhook
+    Finishing,
+}
+
+impl Phase {
+    /// Records that the current process entered this phase, and whether it may hold the index lock
+    /// in it, i.e., while staging or committing
+    pub fn enter(self) {
+        PHASE.store(self as u8, Ordering::Relaxed);
+        let since = matches!(self, Phase::Staging | Phase::Committing).then(Timestamp::now);
+        if let Ok(mut holding) = HOLDING_INDEX.lock() {
+            *holding = since;
+        }
+        record_holding_index(since);
+    }
+
+    /// Gets the phase the current process is in
+    pub fn current() -> Self {
+        match PHASE.load(Ordering::Relaxed) {
+            1 => Phase::Staging,
+            2 => Phase::Generating,
+            3 => Phase::Committing,
+            4 => Phase::Finishing,
+            _ => Phase::Starting,
+        }
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Phase::Starting => write!(f, "starting"),
+            Phase::Staging => write!(f, "staging"),
+            Phase::Generating => write!(f, "generating"),
+            Phase::Committing => write!(f, "committing"),
+            Phase::Finishing => write!(f, "finishing"),
+        }
+    }
+}
+
+/// Starts a thread exiting the process once the deadline set by `arm` expires, after logging the
+/// phase it was in, killing the generator commands still running, and removing the index lock
+/// left behind. A commit under way is let finish first, so as not to leave it half made.
+///
+/// # Arguments
+/// * `index_lock` - The path of the index lock of the repository
+pub fn start(index_lock: PathBuf) {
+    spawn(move || {
+        let mut committing = false;
+        loop {
+            sleep(POLL_INTERVAL);
+            let expired = DEADLINE
+                .lock()
+                .ok()
+                .and_then(|deadline| *deadline)
+                .filter(|deadline| Instant::now() >= deadline.expires);
+            let Some(deadline) = expired else {
+                committing = false;
+                continue;
+            };
+            let phase = Phase::current();
+            if !committing {
+                error!(
+                    "Timed out after {} s while {phase}; raise `{}` if it needs more time",
+                    deadline.duration.as_secs(),
+                    deadline.setting,
+                );
+            }
+            if matches!(phase, Phase::Committing) {
+                if !committing {
+                    info!("Stopping once the commit is made");
+                    committing = true;
+                }
+                continue;
+            }
+            kill_children();
+            let holding = HOLDING_INDEX.lock().ok().and_then(|holding| *holding);
+            if let Err(e) = remove_own_lock(&index_lock, holding) {
+                error!("Failed to remove {}: {e}", index_lock.display());
+            }
+            exit(TIMED_OUT_EXIT_CODE);
+        }
+    });
+}
+
+/// Sets the deadline for handling an event, replacing any previous one
+///
+/// # Arguments
+/// * `duration` - The time after which the process is stopped, measured from now
+/// * `setting` - The setting the duration is taken from, e.g., `deadline_secs`
+pub fn arm(duration: Duration, setting: &'static str) {
+    Phase::Starting.enter();
+    if let Ok(mut deadline) = DEADLINE.lock() {
+        *deadline = Some(Deadline {
+            expires: Instant::now() + duration,
+            duration,
+            setting,
+        });
+    }
+}
+
+/// Clears the deadline, e.g., while the worker waits for events
+pub fn disarm() {
+    if let Ok(mut deadline) = DEADLINE.lock() {
+        *deadline = None;
+    }
+}
@@ -0,0 +1,341 @@
+//! HTTP POST of each commit to the webhook of `[webhook]`, e.g., a relay posting to a chat channel
+
+use std::{
+    collections::BTreeMap,
+    mem::take,
+    sync::Mutex,
+    thread::{JoinHandle, sleep, spawn},
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde_json::Value;
+use tracing::{debug, warn};
+use ureq::Agent;
+
+use crate::{
+    config::{CONFIG, Webhook},
+    redact::Redactor,
+    template::render,
+};
+
+/// Pause before the single retry of a failed delivery
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// A commit the webhook is told about
+pub struct Delivery {
+    /// The ID of the commit
+    pub sha: String,
+    /// The first line of the commit message
+    pub subject: String,
+    /// The branch committed on
+    pub branch: String,
+    /// The name of the directory of the repository
+    pub repo: String,
+    /// The Claude Code session the commit was made for, empty if none
+    pub session_id: String,
+    /// The number of files the commit changes
+    pub files: usize,
+}
+
+/// Posts the commits to the webhook in the background, through one HTTP client kept for all of
+/// them, so that the commits of a batched run reuse its connections
+pub struct WebhookClient {
+    url: String,
+    headers: BTreeMap<String, String>,
+    /// The JSON body, whose strings are rendered for each commit
+    body: Value,
+    retry: bool,
+    agent: Agent,
+    /// The deliveries still running, waited for when the webhook is dropped
+    pending: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl WebhookClient {
+    /// Creates the webhook of the settings
+    ///
+    /// # Returns
+    /// The webhook, or `None` if `webhook.url` is unset
+    pub fn new(config: &Webhook) -> Option<Self> {
+        let url = config.url.clone().filter(|url| !url.is_empty())?;
+        let agent = Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(config.timeout_secs)))
+            .build()
+            .into();
+        Some(Self {
+            url,
+            headers: config.headers.clone(),
+            body: config.body.clone(),
+            retry: config.retry,
+            agent,
+            pending: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Posts the commit in the background; failures are only logged, as they must never affect
+    /// the commit
+    pub fn send(&self, delivery: &Delivery) {
+        let body = match render_body(&self.body, delivery) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Not calling the webhook: {e:#}");
+                return;
+            }
+        };
+        let agent = self.agent.clone();
+        let url = self.url.clone();
+        let headers = self.headers.clone();
+        let attempts = if self.retry { 2 } else { 1 };
+        let handle = spawn(move || {
+            for attempt in 1..=attempts {
+                match post(&agent, &url, &headers, &body) {
+                    Ok(()) => {
+                        debug!("Called the webhook");
+                        return;
+                    }
+                    Err(e) if attempt < attempts => {
+                        debug!("Failed to call the webhook, retrying: {e:#}");
+                        sleep(RETRY_DELAY);
+                    }
+                    Err(e) => warn!("Failed to call the webhook: {e:#}"),
+                }
+            }
+        });
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.retain(|handle| !handle.is_finished());
+            pending.push(handle);
+        }
+    }
+}
+
+impl Drop for WebhookClient {
+    /// Waits for the deliveries still running, each bounded by `webhook.timeout_secs`, so that
+    /// the process doesn't exit in the middle of them
+    fn drop(&mut self) {
+        let pending = self.pending.get_mut().map(take).unwrap_or_default();
+        for handle in pending {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Posts the JSON body to the URL with the headers
+fn post(agent: &Agent, url: &str, headers: &BTreeMap<String, String>, body: &str) -> Result<()> {
+    let mut request = agent.post(url).header("Content-Type", "application/json");
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    let response = request.send(body)?;
+    // Read to its end, so that the connection can be reused
+    response.into_body().read_to_vec()?;
+    Ok(())
+}
+
+/// Renders the strings of the body template with the commit, e.g., `{"text": "{subject}"}`
+///
+/// The values are redacted of likely secrets, and are inserted into JSON strings rather than into
+/// the JSON text, so that they need no escaping. The diff is never sent.
+///
+/// # Returns
+/// The JSON body, or an error if a redaction pattern is invalid
+fn render_body(template: &Value, delivery: &Delivery) -> Result<String> {
+    let redactor = Redactor::new(&CONFIG.redaction.patterns)?;
+    let redact = |value: &str| redactor.redact(value).0.into_owned();
+    let (subject, branch) = (redact(&delivery.subject), redact(&delivery.branch));
+    let files = delivery.files.to_string();
+    let variables = [
+        ("sha", delivery.sha.as_str()),
+        ("subject", subject.as_str()),
+        ("branch", branch.as_str()),
+        ("repo", delivery.repo.as_str()),
+        ("session_id", delivery.session_id.as_str()),
+        ("files", files.as_str()),
+    ];
+    Ok(render_strings(template, &variables).to_string())
+}
+
+/// Renders every string in the JSON value, keys included
+fn render_strings(value: &Value, variables: &[(&str, &str)]) -> Value {
+    match value {
+        Value::String(text) => Value::String(render(text, variables)),
+        Value::Array(items) => {
+            Value::Array(items.iter().map(|item| render_strings(item, variables)).collect())
+        }
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, value)| (render(key, variables), render_strings(value, variables)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{
+            Arc,
+            mpsc::{Receiver, Sender, channel},
+        },
+    };
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Creates a delivery of a commit whose subject contains a likely secret and JSON syntax
+    fn delivery() -> Delivery {
+        Delivery {
+            sha: "0123456789abcdef".to_string(),
+            subject: r#"fix: set api_key = "sk1234567890abcdef" in "config""#.to_string(),
+            branch: "session/ab12_0712".to_string(),
+            repo: "project".to_string(),
+            session_id: "ab12".to_string(),
+            files: 3,
+        }
+    }
+
+    #[test]
+    fn body_strings_are_rendered_and_redacted() {
+        let template = json!({
+            "text": "{repo}@{branch}: {subject}",
+            "commit": { "sha": "{sha}", "files": "{files}" },
+            "tags": ["{session_id}"],
+            "count": 1,
+        });
+
+        let body = render_body(&template, &delivery()).unwrap();
+
+        let body = serde_json::from_str::<Value>(&body).unwrap();
+        let text = body["text"].as_str().unwrap();
+        assert!(text.starts_with("project@session/ab12_0712: fix: set api_key = "), "{text}");
+        assert!(text.ends_with(r#" in "config""#), "{text}");
+        assert!(!text.contains("sk1234567890abcdef"), "{text}");
+        assert_eq!(body["commit"], json!({ "sha": "0123456789abcdef", "files": "3" }));
+        assert_eq!(body["tags"], json!(["ab12"]));
+        assert_eq!(body["count"], 1);
+    }
+
+    /// Serves HTTP requests on a local port, answering with the statuses in turn, then 200, and
+    /// sending the body of each request and the number of the connection it came on
+    fn stub_server(statuses: Vec<u16>) -> (String, Receiver<(usize, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (sender, receiver) = channel();
+        let statuses = Arc::new(Mutex::new(statuses.into_iter()));
+        spawn(move || {
+            for (connection, stream) in listener.incoming().enumerate() {
+                let (stream, sender, statuses) =
+                    (stream.unwrap(), sender.clone(), statuses.clone());
+                // Connections are served at once, as clients keep theirs open while idle
+                spawn(move || {
+                    while let Some(body) = read_request(&stream) {
+                        let status = statuses.lock().unwrap().next().unwrap_or(200);
+                        respond(&stream, status, connection, body, &sender);
+                    }
+                });
+            }
+        });
+        (url, receiver)
+    }
+
+    /// Reads a request from the connection, returning its body, or `None` once it is closed
+    fn read_request(stream: &TcpStream) -> Option<String> {
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':')
+                && name.eq_ignore_ascii_case("content-length")
+            {
+                length = value.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).ok()?;
+        Some(String::from_utf8(body).unwrap())
+    }
+
+    /// Answers the request with the status and an empty body, keeping the connection open
+    fn respond(
+        mut stream: &TcpStream,
+        status: u16,
+        connection: usize,
+        body: String,
+        sender: &Sender<(usize, String)>,
+    ) {
+        // Sent first, so that it is received by the time the client has the response
+        sender.send((connection, body)).unwrap();
+        // Clients close the connection after a failure as they like
+        let _ = write!(stream, "HTTP/1.1 {status} Status\r\nContent-Length: 0\r\n\r\n");
+    }
+
+    /// Waits for the deliveries still running
+    fn wait(client: &WebhookClient) {
+        for handle in take(&mut *client.pending.lock().unwrap()) {
+            handle.join().unwrap();
+        }
+    }
+
+    /// Creates the settings posting the subject to the URL
+    fn config(url: String, retry: bool) -> Webhook {
+        Webhook {
+            url: Some(url),
+            headers: BTreeMap::from([("X-Token".to_string(), "t0ken".to_string())]),
+            body: json!({ "text": "{subject}" }),
+            timeout_secs: 5,
+            retry,
+        }
+    }
+
+    #[test]
+    fn commits_of_a_run_share_the_connection() {
+        let (url, received) = stub_server(Vec::new());
+        let client = WebhookClient::new(&config(url, false)).unwrap();
+
+        client.send(&Delivery { subject: "feat: one".to_string(), ..delivery() });
+        wait(&client);
+        client.send(&Delivery { subject: "feat: two".to_string(), ..delivery() });
+        wait(&client);
+
+        let received = received.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            received,
+            [
+                (0, r#"{"text":"feat: one"}"#.to_string()),
+                (0, r#"{"text":"feat: two"}"#.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_delivery_is_retried_once_if_configured() {
+        let (url, received) = stub_server(vec![500, 500, 500]);
+
+        let client = WebhookClient::new(&config(url.clone(), false)).unwrap();
+        client.send(&delivery());
+        wait(&client);
+        assert_eq!(received.try_iter().count(), 1);
+
+        let client = WebhookClient::new(&config(url, true)).unwrap();
+        client.send(&delivery());
+        wait(&client);
+        assert_eq!(received.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn unset_url_posts_nothing() {
+        let config = Webhook { url: None, ..config(String::new(), false) };
+        assert!(WebhookClient::new(&config).is_none());
+    }
+}
@@ -0,0 +1,310 @@
+//! Long-running process handling the hook events of a repository one after another, fed by the
+//! hook invocations through a Unix socket
+
+use std::{
+    env::temp_dir,
+    fs::{Permissions, metadata, remove_file, set_permissions},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::Shutdown,
+    os::unix::{
+        fs::{MetadataExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        mpsc::{RecvTimeoutError, Sender, channel},
+    },
+    thread::spawn,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use git2::Repository;
+use serde_json::from_str;
+use tracing::{error, info, warn};
+
+use crate::{
+    committer::Committer,
+    config::{CONFIG, Granularity, Language},
+    git_ops::state_dir,
+    logging, shutdown,
+    types::HookEvent,
+    watchdog,
+};
+
+/// Name of the socket in the state directory of the repository
+const SOCKET_NAME: &str = "worker.sock";
+
+/// Longest socket path fitting in `sockaddr_un` on every Unix, beyond which the socket is put in
+/// the temporary directory
+const MAX_SOCKET_PATH: usize = 100;
+
+/// How long the forwarder and the worker wait for each other on the socket
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interval between checks of the worker for shutdown requests, idleness, and its socket
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Most edits committed together, so that a steady stream of them still gets committed
+const MAX_BATCH: usize = 50;
+
+/// Reply of the worker to an event it queued
+const ACCEPTED: &str = "ok";
+
+/// The sender of the event queue, or `None` once the worker exits
+type Queue = Arc<Mutex<Option<Sender<HookEvent>>>>;
+
+/// Gets the path of the socket of the worker of the repository
+fn socket_path(repo: &Repository) -> PathBuf {
+    let path = state_dir(repo).join(SOCKET_NAME);
+    if path.as_os_str().len() <= MAX_SOCKET_PATH {
+        return path;
+    }
+    // Collisions of the hash are caught by the repository in the handshake
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    temp_dir().join(format!("ccc-{:016x}.sock", hasher.finish()))
+}
+
+/// Makes the first line sent to the worker, naming the version of the binary and the repository,
+/// so that a worker of another version or repository turns the event down
+fn handshake(repo: &Repository) -> String {
+    format!("ccc {} {}\n", env!("CARGO_PKG_VERSION"), repo.path().display())
+}
+
+/// Forwards the hook event to the worker of the repository
+///
+/// # Arguments
+/// * `repo` - The repository of the event
+/// * `input` - The event JSON
+///
+/// # Returns
+/// Whether the worker queued the event; `false` if none is running or it turned the event down,
+/// e.g., being of another version or exiting
+pub fn forward(repo: &Repository, input: &str) -> bool {
+    let path = socket_path(repo);
+    match send(&path, &handshake(repo), input) {
+        Ok(reply) if reply == ACCEPTED => true,
+        Ok(reply) => {
+            info!("The worker turned the event down: {reply}");
+            false
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => false,
+        // Nothing listens on a socket left behind by a worker that crashed
+        Err(e) if e.kind() == ErrorKind::ConnectionRefused => {
+            info!("Removing the stale socket {}", path.display());
+            let _ = remove_file(&path);
+            false
+        }
+        Err(e) => {
+            warn!("Failed to forward the event to the worker: {e}");
+            false
+        }
+    }
+}
+
+/// Sends the handshake and the event JSON to the socket
+///
+/// # Returns
+/// The reply of the worker
+fn send(path: &Path, handshake: &str, input: &str) -> io::Result<String> {
+    let mut stream = UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    stream.write_all(handshake.as_bytes())?;
+    stream.write_all(input.as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply)?;
+    Ok(reply.trim().to_string())
+}
+
+/// Listens on the socket of the repository, replacing a stale one
+///
+/// # Returns
+/// The listener, or `None` if another worker listens on it already
+fn bind(path: &Path) -> Result<Option<UnixListener>> {
+    match UnixListener::bind(path) {
+        Ok(listener) => Ok(Some(listener)),
+        Err(e) if e.kind() == ErrorKind::AddrInUse => {
+            // Either another worker started meanwhile, or one crashed and left its socket behind
+            if UnixStream::connect(path).is_ok() {
+                return Ok(None);
+            }
+            info!("Removing the stale socket {}", path.display());
+            remove_file(path)?;
+            Ok(Some(UnixListener::bind(path)?))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Handles the hook events of the repository until no more arrive for `worker.idle_timeout_secs`,
+/// starting with the one of this invocation
+///
+/// # Arguments
+/// * `repo` - The repository of the event
+/// * `first` - The event of this invocation
+/// * `input` - Its JSON, forwarded to another worker that started meanwhile
+/// * `language` - Language to use for commit messages
+pub fn serve(repo: &Repository, first: HookEvent, input: &str, language: &Language) -> Result<()> {
+    let path = socket_path(repo);
+    let listener = match bind(&path) {
+        Ok(Some(listener)) => listener,
+        result => {
+            if let Err(e) = result {
+                warn!("Failed to listen on {}: {e:#}", path.display());
+            }
+            if forward(repo, input) {
+                return Ok(());
+            }
+            // Handled by this process alone, as by one started without the worker
+            let _span = logging::event_span(&first, repo.workdir()).entered();
+            watchdog::arm(Duration::from_secs(CONFIG.deadline_secs), "deadline_secs");
+            return Committer::new(first.cwd())?
+                .with_debounce(Duration::from_secs(CONFIG.debounce_secs))
+                .handle_event(first, language);
+        }
+    };
+    // Only the user may feed events to the worker
+    set_permissions(&path, Permissions::from_mode(0o600))?;
+    let inode = metadata(&path)?.ino();
+    info!("Serving hook events on {}", path.display());
+
+    let committer = Committer::new(first.cwd())?;
+    let (sender, receiver) = channel();
+    sender.send(first)?;
+    let queue: Queue = Arc::new(Mutex::new(Some(sender)));
+    let expected = handshake(repo);
+    let accepting = Arc::clone(&queue);
+    spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| accept(&stream, &expected, &accepting));
+            if let Err(e) = result {
+                warn!("Failed to receive an event: {e}");
+            }
+        }
+    });
+
+    let idle_timeout = Duration::from_secs(CONFIG.worker.idle_timeout_secs);
+    let quiet_period = match CONFIG.granularity {
+        Granularity::Batched => Duration::from_secs(CONFIG.quiet_period_secs),
+        _ => Duration::ZERO,
+    };
+    let mut pending = Vec::new();
+    let mut last_event = Instant::now();
+    loop {
+        if shutdown::check().is_err() {
+            close(&queue);
+            let dropped = pending.len() + receiver.try_iter().count();
+            warn!("Exiting on a shutdown request, dropping {dropped} queued events");
+            break;
+        }
+        // Another worker took over after the socket was removed as stale
+        if metadata(&path).map_or(true, |metadata| metadata.ino() != inode)
+            || (pending.is_empty() && last_event.elapsed() >= idle_timeout)
+        {
+            close(&queue);
+        }
+
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(event) => {
+                last_event = Instant::now();
+                if matches!(event, HookEvent::PostToolUse { .. }) {
+                    pending.push(event);
+                    if pending.len() >= MAX_BATCH {
+                        handle_edits(&committer, &mut pending, repo, language);
+                    }
+                    continue;
+                }
+                handle_edits(&committer, &mut pending, repo, language);
+                let _span = logging::event_span(&event, repo.workdir()).entered();
+                watchdog::arm(Duration::from_secs(CONFIG.deadline_secs), "deadline_secs");
+                if let Err(e) = committer.handle_event(event, language) {
+                    error!("{e:#}");
+                }
+                watchdog::disarm();
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if last_event.elapsed() >= quiet_period {
+                    handle_edits(&committer, &mut pending, repo, language);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                handle_edits(&committer, &mut pending, repo, language);
+                break;
+            }
+        }
+    }
+
+    if metadata(&path).is_ok_and(|metadata| metadata.ino() == inode) {
+        remove_file(&path)?;
+    }
+    info!("Exiting the worker");
+    Ok(())
+}
+
+/// Commits the pending edits together, if any
+fn handle_edits(
+    committer: &Committer,
+    pending: &mut Vec<HookEvent>,
+    repo: &Repository,
+    language: &Language,
+) {
+    let Some(last) = pending.last() else {
+        return;
+    };
+    let _span = logging::event_span(last, repo.workdir()).entered();
+    watchdog::arm(Duration::from_secs(CONFIG.deadline_secs), "deadline_secs");
+    if let Err(e) = committer.handle_edits(pending, language) {
+        error!("{e:#}");
+    }
+    watchdog::disarm();
+    pending.clear();
+}
+
+/// Stops queueing events, so that the worker exits once it handled the queued ones
+fn close(queue: &Queue) {
+    if let Ok(mut sender) = queue.lock() {
+        sender.take();
+    }
+}
+
+/// Receives an event from a forwarder and queues it, replying whether it did
+///
+/// # Arguments
+/// * `stream` - The connection of the forwarder
+/// * `expected` - The handshake of this worker
+/// * `queue` - The event queue
+fn accept(stream: &UnixStream, expected: &str, queue: &Queue) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut handshake = String::new();
+    reader.read_line(&mut handshake)?;
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+
+    let version = |handshake: &str| handshake.split(' ').nth(1).map(str::to_string);
+    let reply = if handshake != expected {
+        if version(&handshake) != version(expected) {
+            // Make way for a worker of the binary now installed
+            info!("Exiting for a forwarder of another version: {}", handshake.trim());
+            close(queue);
+        }
+        format!("expected {}", expected.trim())
+    } else {
+        match from_str::<HookEvent>(&input) {
+            Ok(event) => match queue.lock().ok().and_then(|sender| sender.clone()) {
+                Some(sender) if sender.send(event).is_ok() => ACCEPTED.to_string(),
+                _ => "exiting".to_string(),
+            },
+            Err(e) => format!("invalid event: {e}"),
+        }
+    };
+    let mut stream = stream;
+    stream.write_all(reply.as_bytes())?;
+    stream.write_all(b"\n")
+}
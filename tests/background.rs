@@ -0,0 +1,397 @@
+//! Tests of the background process: stopping a wedged or interrupted one, not forking for
+//! ignorable events, the worker, debouncing edits, and batching them
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{read_dir, read_to_string, remove_dir_all, write},
+    io::{Read, Write},
+    net::Shutdown,
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use common::{ccc, send, wait_for};
+use git2::Repository;
+
+/// Creates a repository in a fresh temporary directory, with a committed file changed since
+fn temp_repo(name: &str) -> (PathBuf, Repository) {
+    let (dir, repo) = common::temp_repo_with(name, None, &[("a.txt", "a\n")]);
+    write(dir.join("a.txt"), "a\nb\n").unwrap();
+    (dir, repo)
+}
+
+/// Runs the hook for an edit of the file and waits for it to exit
+///
+/// # Arguments
+/// * `dir` - The repository
+/// * `file` - The edited file, relative to the repository
+/// * `envs` - Extra environment variables, e.g., settings
+fn send_edit(dir: &Path, file: &str, envs: &[(&str, &str)]) {
+    let mut child = ccc(dir)
+        .env_remove("CC_AUTO_COMMIT_FOREGROUND")
+        .envs(envs.iter().copied())
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let event = edit_event(dir, file);
+    child.stdin.take().unwrap().write_all(event.as_bytes()).unwrap();
+    assert!(child.wait().unwrap().success());
+}
+
+/// Creates the event of an edit of the file, relative to the repository
+fn edit_event(dir: &Path, file: &str) -> String {
+    format!(
+        r#"{{"session_id":"s","transcript_path":"/dev/null","cwd":"{}","hook_event_name":"PostToolUse","tool_name":"Edit","tool_input":{{"file_path":"{}"}},"tool_response":{{}}}}"#,
+        dir.display(),
+        dir.join(file).display()
+    )
+}
+
+/// Runs the hook for an edit of the changed file, with a fake generator that records its process
+/// ID and sleeps
+///
+/// # Returns
+/// The path of the file the generator writes its process ID to
+fn run_hook(dir: &Path, envs: &[(&str, &str)]) -> PathBuf {
+    let pid_path = dir.join("generator.pid");
+    let args = format!("-c,echo $$ > '{}'; exec sleep 30", pid_path.display());
+    let mut all = vec![
+        ("CC_AUTO_COMMIT_GENERATOR_COMMAND", "/bin/sh"),
+        ("CC_AUTO_COMMIT_GENERATOR_ARGS", args.as_str()),
+    ];
+    all.extend_from_slice(envs);
+    send_edit(dir, "a.txt", &all);
+    pid_path
+}
+
+/// Checks whether the process is running
+fn is_alive(pid: &str) -> bool {
+    Command::new("kill").args(["-0", pid]).status().unwrap().success()
+}
+
+/// Reads the background log of the repository
+fn daemon_log(dir: &Path) -> String {
+    read_to_string(dir.join(".git").join("claude-auto-commit").join("daemon.log"))
+        .unwrap_or_default()
+}
+
+#[test]
+fn deadline_stops_a_wedged_generator() {
+    let (dir, repo) = temp_repo("watchdog");
+    let pid_path = run_hook(
+        &dir,
+        &[("CC_AUTO_COMMIT_DEADLINE_SECS", "2"), ("CC_AUTO_COMMIT_DEBOUNCE_SECS", "0")],
+    );
+
+    assert!(wait_for(Duration::from_secs(15), || {
+        daemon_log(&dir).contains("Timed out after 2 s while generating")
+    }));
+
+    // The generator is killed along with the background process
+    let pid = read_to_string(&pid_path).unwrap().trim().to_string();
+    assert!(wait_for(Duration::from_secs(5), || !is_alive(&pid)));
+    assert_eq!(repo.head().unwrap().peel_to_commit().unwrap().message(), Some("init"));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn foreground_deadline_stops_a_wedged_generator() {
+    let (dir, repo) = temp_repo("foreground-watchdog");
+    let pid_path = dir.join("generator.pid");
+    let args = format!("-c,echo $$ > '{}'; exec sleep 30", pid_path.display());
+
+    let output = send(
+        ccc(&dir)
+            .env("CC_AUTO_COMMIT_GENERATOR_ARGS", args)
+            .env("CC_AUTO_COMMIT_FOREGROUND_DEADLINE_SECS", "2"),
+        &edit_event(&dir, "a.txt"),
+    );
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(124), "{stderr}");
+    assert!(
+        stderr.contains("Timed out after 2 s while generating; raise `foreground_deadline_secs`"),
+        "{stderr}"
+    );
+    let pid = read_to_string(&pid_path).unwrap().trim().to_string();
+    assert!(wait_for(Duration::from_secs(5), || !is_alive(&pid)));
+    assert!(!dir.join(".git").join("index.lock").exists());
+    assert_eq!(repo.head().unwrap().peel_to_commit().unwrap().message(), Some("init"));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn sigterm_restores_the_index() {
+    let (dir, repo) = temp_repo("sigterm");
+    let pid_path = run_hook(&dir, &[]);
+
+    // Once the edited file is staged and the generator is running, stop the background process
+    assert!(wait_for(Duration::from_secs(15), || pid_path.exists()));
+    let instances = dir.join(".git").join("claude-auto-commit").join("instances");
+    let entry = read_dir(&instances).unwrap().next().unwrap().unwrap().path();
+    let daemon_pid = entry.file_stem().unwrap().to_string_lossy().to_string();
+    assert!(
+        Command::new("kill")
+            .args(["-TERM", &daemon_pid])
+            .status()
+            .unwrap()
+            .success()
+    );
+
+    assert!(wait_for(Duration::from_secs(15), || {
+        daemon_log(&dir).contains("Stopped by SIGTERM while generating")
+    }));
+    let pid = read_to_string(&pid_path).unwrap().trim().to_string();
+    assert!(wait_for(Duration::from_secs(5), || !is_alive(&pid) && !is_alive(&daemon_pid)));
+
+    // Nothing is committed, and the edit is no longer staged
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("init"));
+    let mut index = repo.index().unwrap();
+    index.read(true).unwrap();
+    assert_eq!(index.write_tree().unwrap(), head.tree_id());
+    assert!(!repo.path().join("index.lock").exists());
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ignorable_event_returns_without_forking() {
+    let (dir, _repo) = temp_repo("ignorable");
+    let event = format!(
+        r#"{{"session_id":"s","cwd":"{}","hook_event_name":"PostToolUse","tool_name":"Read","tool_input":{{"file_path":"{}"}},"tool_response":{{}}}}"#,
+        dir.display(),
+        dir.join("a.txt").display()
+    );
+
+    let runs = 20;
+    let started = Instant::now();
+    for _ in 0..runs {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_ccc"))
+            .current_dir(&dir)
+            .env_clear()
+            .env("HOME", dir.join("home"))
+            .env("XDG_CONFIG_HOME", dir.join("config"))
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(event.as_bytes()).unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+    let per_run = started.elapsed() / runs;
+    println!("{per_run:?} per ignored event");
+
+    // A forked background process would have opened its log
+    assert!(
+        !dir.join(".git")
+            .join("claude-auto-commit")
+            .join("daemon.log")
+            .exists()
+    );
+    assert!(per_run < Duration::from_secs(1));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn worker_handles_forwarded_events() {
+    let (dir, repo) = temp_repo("worker");
+    write(dir.join("b.txt"), "b\n").unwrap();
+    let state_dir = dir.join(".git").join("claude-auto-commit");
+    let socket = state_dir.join("worker.sock");
+    // As left behind by a worker that crashed
+    std::fs::create_dir_all(&state_dir).unwrap();
+    drop(UnixListener::bind(&socket).unwrap());
+
+    let envs = [
+        ("CC_AUTO_COMMIT_WORKER_ENABLED", "true"),
+        ("CC_AUTO_COMMIT_WORKER_IDLE_TIMEOUT_SECS", "3"),
+        ("CC_AUTO_COMMIT_GENERATOR_COMMAND", "/bin/sh"),
+        ("CC_AUTO_COMMIT_GENERATOR_ARGS", "-c,echo 'Update the files'"),
+    ];
+    send_edit(&dir, "a.txt", &envs);
+    assert!(wait_for(Duration::from_secs(15), || {
+        daemon_log(&dir).contains("Serving hook events")
+    }));
+    send_edit(&dir, "b.txt", &envs);
+
+    // Both edits are committed by the one worker, which removes its socket once idle
+    assert!(wait_for(Duration::from_secs(30), || {
+        daemon_log(&dir).contains("Exiting the worker")
+    }));
+    let log = daemon_log(&dir);
+    assert_eq!(log.matches("Serving hook events").count(), 1);
+    assert!(!socket.exists());
+    let tree = repo.head().unwrap().peel_to_tree().unwrap();
+    assert!(tree.get_name("b.txt").is_some());
+    let a = tree.get_name("a.txt").unwrap().to_object(&repo).unwrap();
+    assert_eq!(a.as_blob().unwrap().content(), b"a\nb\n");
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn worker_leaves_edits_to_the_session_end_with_session_granularity() {
+    let (dir, repo) = common::temp_repo("worker-session");
+    let envs = [
+        ("CC_AUTO_COMMIT_WORKER_ENABLED", "true"),
+        ("CC_AUTO_COMMIT_WORKER_IDLE_TIMEOUT_SECS", "3"),
+        ("CC_AUTO_COMMIT_GRANULARITY", "session"),
+    ];
+    // Started by the end of a response, with nothing to commit yet
+    let mut child = ccc(&dir)
+        .env_remove("CC_AUTO_COMMIT_FOREGROUND")
+        .envs(envs)
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stop = format!(
+        r#"{{"session_id":"s","transcript_path":"/dev/null","cwd":"{}","hook_event_name":"Stop","stop_hook_active":false}}"#,
+        dir.display()
+    );
+    child.stdin.take().unwrap().write_all(stop.as_bytes()).unwrap();
+    assert!(child.wait().unwrap().success());
+    assert!(wait_for(Duration::from_secs(15), || {
+        daemon_log(&dir).contains("Serving hook events")
+    }));
+
+    // Forwarded as by a hook of another granularity, as the hook forwards no edits with this one
+    write(dir.join("b.txt"), "b\n").unwrap();
+    let socket = dir.join(".git").join("claude-auto-commit").join("worker.sock");
+    let mut stream = UnixStream::connect(socket).unwrap();
+    let handshake = format!("ccc {} {}\n", env!("CARGO_PKG_VERSION"), repo.path().display());
+    stream.write_all(handshake.as_bytes()).unwrap();
+    stream.write_all(edit_event(&dir, "b.txt").as_bytes()).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).unwrap();
+    assert_eq!(reply.trim(), "ok");
+
+    assert!(wait_for(Duration::from_secs(30), || {
+        daemon_log(&dir).contains("Exiting the worker")
+    }));
+    let log = daemon_log(&dir);
+    assert!(log.contains("Leaving 1 edits to be committed when the session ends"), "{log}");
+    assert_eq!(repo.head().unwrap().peel_to_commit().unwrap().message(), Some("init"));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn burst_of_edits_of_a_file_is_committed_once() {
+    let (dir, repo) = temp_repo("debounce");
+    let envs = [
+        ("CC_AUTO_COMMIT_DEBOUNCE_SECS", "2"),
+        ("CC_AUTO_COMMIT_GENERATOR_COMMAND", "/bin/sh"),
+        ("CC_AUTO_COMMIT_GENERATOR_ARGS", "-c,echo 'Update a.txt'"),
+    ];
+
+    // Each edit is handled by its own background process
+    send_edit(&dir, "a.txt", &envs);
+    write(dir.join("a.txt"), "a\nb\nc\n").unwrap();
+    send_edit(&dir, "a.txt", &envs);
+
+    let instances = dir.join(".git").join("claude-auto-commit").join("instances");
+    assert!(wait_for(Duration::from_secs(30), || {
+        daemon_log(&dir).contains("superseded by a newer edit")
+            && read_dir(&instances).is_ok_and(|mut entries| entries.next().is_none())
+    }));
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.parent(0).unwrap().message(), Some("init"));
+    let a = head
+        .tree()
+        .unwrap()
+        .get_name("a.txt")
+        .unwrap()
+        .to_object(&repo)
+        .unwrap();
+    assert_eq!(a.as_blob().unwrap().content(), b"a\nb\nc\n");
+
+    remove_dir_all(dir).unwrap();
+}
+
+/// The settings batching edits, committed with a fake generator
+const BATCHED: [(&str, &str); 4] = [
+    ("CC_AUTO_COMMIT_GRANULARITY", "batched"),
+    ("CC_AUTO_COMMIT_QUIET_PERIOD_SECS", "2"),
+    ("CC_AUTO_COMMIT_GENERATOR_COMMAND", "/bin/sh"),
+    ("CC_AUTO_COMMIT_GENERATOR_ARGS", "-c,echo 'Update the files'"),
+];
+
+/// Waits for the background log to contain the text, then for the background processes of the
+/// repository to exit
+fn wait_for_log_and_exit(dir: &Path, text: &str) -> bool {
+    let instances = dir.join(".git").join("claude-auto-commit").join("instances");
+    wait_for(Duration::from_secs(30), || daemon_log(dir).contains(text))
+        && wait_for(Duration::from_secs(30), || {
+            read_dir(&instances).is_ok_and(|mut entries| entries.next().is_none())
+        })
+}
+
+#[test]
+fn batch_reaching_the_file_cap_is_committed_at_once() {
+    let (dir, repo) = temp_repo("batch-cap");
+    write(dir.join("b.txt"), "b\n").unwrap();
+    let mut envs = BATCHED.to_vec();
+    envs.push(("CC_AUTO_COMMIT_MAX_BATCH_FILES", "2"));
+
+    send_edit(&dir, "a.txt", &envs);
+    send_edit(&dir, "b.txt", &envs);
+
+    assert!(wait_for_log_and_exit(&dir, "Committing the batch of 2 files at once"));
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.parent(0).unwrap().message(), Some("init"));
+    let tree = head.tree().unwrap();
+    assert!(tree.get_name("b.txt").is_some());
+    let a = tree.get_name("a.txt").unwrap().to_object(&repo).unwrap();
+    assert_eq!(a.as_blob().unwrap().content(), b"a\nb\n");
+    let state_dir = dir.join(".git").join("claude-auto-commit");
+    assert!(!state_dir.join("batch.json").exists());
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn batch_left_by_a_dead_process_is_committed_by_the_next_event() {
+    let (dir, repo) = temp_repo("batch-recovery");
+    write(dir.join("b.txt"), "b\n").unwrap();
+    // As left behind by a process stopped before committing, and which unstaged b.txt
+    let state_dir = dir.join(".git").join("claude-auto-commit");
+    std::fs::create_dir_all(&state_dir).unwrap();
+    write(
+        state_dir.join("batch.json"),
+        r#"{"owner":{"pid":1,"started":"","timestamp":"2026-01-01T00:00:00Z","event":"PostToolUse"},"token":"1-0","files":["b.txt"],"started":"2026-01-01T00:00:00Z"}"#,
+    )
+    .unwrap();
+
+    send_edit(&dir, "a.txt", &BATCHED);
+
+    assert!(wait_for_log_and_exit(
+        &dir,
+        "Committing the batch of 1 files left behind by process 1"
+    ));
+    // The batch left behind is committed alone, then the new one
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    let recovered = head.parent(0).unwrap();
+    assert_eq!(recovered.parent(0).unwrap().message(), Some("init"));
+    let tree = recovered.tree().unwrap();
+    assert!(tree.get_name("b.txt").is_some());
+    let a = tree.get_name("a.txt").unwrap().to_object(&repo).unwrap();
+    assert_eq!(a.as_blob().unwrap().content(), b"a\n");
+    let a = head
+        .tree()
+        .unwrap()
+        .get_name("a.txt")
+        .unwrap()
+        .to_object(&repo)
+        .unwrap();
+    assert_eq!(a.as_blob().unwrap().content(), b"a\nb\n");
+
+    remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,85 @@
+//! Tests of handling changed binary files as `binary_policy` says
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{copy, remove_dir_all},
+    path::Path,
+};
+
+use common::{ccc, generate, head_message, temp_repo};
+
+/// Copies the fixture of `tests/fixtures/binary` into the repository
+fn add_fixture(dir: &Path, name: &str) {
+    let fixture = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/binary")
+        .join(name);
+    copy(fixture, dir.join(name)).unwrap();
+}
+
+/// Runs the hook in the foreground for a Write of the file with the policy
+///
+/// # Returns
+/// What the hook logged to stderr
+fn run_hook(dir: &Path, file: &str, policy: &str) -> String {
+    let mut command = ccc(dir);
+    generate(&mut command, "chore: add file");
+    command.env("CC_AUTO_COMMIT_BINARY_POLICY", policy);
+    common::run_hook(&mut command, dir, file)
+}
+
+#[test]
+fn commit_policy_commits_binary_file() {
+    let (dir, repo) = temp_repo("binary-commit");
+    add_fixture(&dir, "pixel.png");
+
+    run_hook(&dir, "pixel.png", "commit");
+
+    assert_eq!(head_message(&repo), "chore: add file");
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn skip_policy_leaves_binary_file_unstaged() {
+    let (dir, repo) = temp_repo("binary-skip");
+    add_fixture(&dir, "pixel.png");
+
+    let log = run_hook(&dir, "pixel.png", "skip");
+
+    assert_eq!(head_message(&repo), "init");
+    assert!(repo.index().unwrap().get_path(Path::new("pixel.png"), 0).is_none());
+    assert!(log.contains("Not staging pixel.png: binary"), "{log}");
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn skip_policy_names_utf16_text() {
+    let (dir, repo) = temp_repo("binary-skip-utf16");
+    add_fixture(&dir, "notes-utf16le.txt");
+
+    let log = run_hook(&dir, "notes-utf16le.txt", "skip");
+
+    assert_eq!(head_message(&repo), "init");
+    assert!(log.contains("notes-utf16le.txt: UTF-16 text, handled as binary"), "{log}");
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn warn_policy_commits_binary_file_with_a_note() {
+    let (dir, repo) = temp_repo("binary-warn");
+    add_fixture(&dir, "pixel.png");
+
+    let log = run_hook(&dir, "pixel.png", "warn");
+
+    assert_eq!(
+        head_message(&repo),
+        "chore: add file\n\nNote: this commit includes binary files: pixel.png"
+    );
+    assert!(log.contains("Committing binary files: pixel.png"), "{log}");
+
+    remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,108 @@
+//! Tests of standing down in CI, unless `allow_in_ci` is set
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{remove_dir_all, write},
+    path::Path,
+};
+
+use common::{SESSION_ID, ccc, send, temp_repo, write_event};
+use git2::Repository;
+
+/// Runs the hook in the foreground for a session start, then a Write of `notes.md`
+///
+/// # Returns
+/// What the hook logged to stderr
+fn run_session(dir: &Path, envs: &[(&str, &str)]) -> String {
+    write(dir.join("notes.md"), "# Notes\n").unwrap();
+    let events = [
+        format!(
+            r#"{{"session_id":"{SESSION_ID}","transcript_path":"/dev/null","cwd":"{}","hook_event_name":"SessionStart","source":"startup"}}"#,
+            dir.display()
+        ),
+        write_event(dir, "notes.md"),
+    ];
+    let mut log = String::new();
+    for event in events {
+        let output = send(ccc(dir).envs(envs.iter().copied()), &event);
+        assert!(output.status.success());
+        log.push_str(&String::from_utf8_lossy(&output.stderr));
+    }
+    log
+}
+
+/// Checks that the hook neither switched branches nor committed
+fn assert_untouched(repo: &Repository) {
+    let head = repo.head().unwrap();
+    assert_eq!(head.shorthand(), Some("master"));
+    assert_eq!(head.peel_to_commit().unwrap().message(), Some("init"));
+    assert_eq!(repo.branches(None).unwrap().count(), 1);
+}
+
+#[test]
+fn ci_variable_stands_the_hook_down() {
+    for (variable, value) in [("CI", "true"), ("GITHUB_ACTIONS", "true"), ("TF_BUILD", "True")] {
+        let (dir, repo) = temp_repo(&format!("ci-{variable}"));
+
+        let log = run_session(&dir, &[(variable, value)]);
+
+        assert_untouched(&repo);
+        assert!(log.contains(&format!("Standing down in CI, as {variable} is set")), "{log}");
+
+        remove_dir_all(dir).unwrap();
+    }
+}
+
+#[test]
+fn configured_variable_stands_the_hook_down() {
+    let (dir, repo) = temp_repo("ci-configured");
+
+    let log = run_session(
+        &dir,
+        &[("CC_AUTO_COMMIT_CI_VARIABLES", "BUILDKITE,JENKINS_URL"), ("JENKINS_URL", "http://ci/")],
+    );
+
+    assert_untouched(&repo);
+    assert!(log.contains("as JENKINS_URL is set"), "{log}");
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn false_ci_variable_is_ignored() {
+    let (dir, repo) = temp_repo("ci-false");
+
+    run_session(&dir, &[("CI", "false")]);
+
+    assert_eq!(repo.head().unwrap().peel_to_commit().unwrap().message(), Some("docs: add notes"));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn allow_in_ci_overrides_detection() {
+    let (dir, repo) = temp_repo("ci-allowed");
+
+    let log = run_session(&dir, &[("CI", "true"), ("CC_AUTO_COMMIT_ALLOW_IN_CI", "true")]);
+
+    let head = repo.head().unwrap();
+    assert!(head.shorthand().unwrap().starts_with("session/0b6c2f1e_"));
+    assert_eq!(head.peel_to_commit().unwrap().message(), Some("docs: add notes"));
+    assert!(!log.contains("Standing down"), "{log}");
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn message_is_generated_from_a_diff_in_ci() {
+    let (dir, _repo) = temp_repo("ci-generate");
+
+    let output = send(ccc(&dir).env("CI", "true"), "diff --git a/notes.md b/notes.md\n+# Notes\n");
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "docs: add notes");
+
+    remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,155 @@
+//! Fixtures shared by the integration tests: temporary repositories, and running the binary in
+//! them as Claude Code runs the hook
+// Each test crate uses only some of them
+#![allow(dead_code)]
+
+use std::{
+    env::{temp_dir, var_os},
+    fs::{remove_dir_all, write},
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio, id},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use git2::{Repository, Signature};
+
+/// The session of the hook events
+pub const SESSION_ID: &str = "0b6c2f1e";
+
+/// Creates a repository with an initial commit in a fresh temporary directory
+pub fn temp_repo(name: &str) -> (PathBuf, Repository) {
+    temp_repo_with(name, None, &[])
+}
+
+/// Creates a repository with an initial commit of the files on the branch, in a fresh temporary
+/// directory
+///
+/// # Arguments
+/// * `name` - The name of the test, which the directory is named after
+/// * `branch` - The branch of the initial commit, HEAD's default one if `None`
+/// * `files` - The paths and contents of the files of the initial commit
+pub fn temp_repo_with(
+    name: &str,
+    branch: Option<&str>,
+    files: &[(&str, &str)],
+) -> (PathBuf, Repository) {
+    let dir = temp_dir().join(format!("ccc-test-{name}-{}", id()));
+    let _ = remove_dir_all(&dir);
+    let repo = init_repo(&dir, branch, files);
+    (dir, repo)
+}
+
+/// Creates a repository with a committer identity and an initial commit of the files on the
+/// branch in the directory
+pub fn init_repo(dir: &Path, branch: Option<&str>, files: &[(&str, &str)]) -> Repository {
+    let repo = Repository::init(dir).unwrap();
+    let mut config = repo.config().unwrap();
+    config.set_str("user.name", "Test").unwrap();
+    config.set_str("user.email", "test@example.com").unwrap();
+    let mut index = repo.index().unwrap();
+    for (path, content) in files {
+        write(dir.join(path), content).unwrap();
+        index.add_path(Path::new(path)).unwrap();
+    }
+    index.write().unwrap();
+    let tree_id = index.write_tree().unwrap();
+    let signature = Signature::now("Test", "test@example.com").unwrap();
+    let reference = branch.map(|branch| format!("refs/heads/{branch}"));
+    repo.commit(
+        Some(reference.as_deref().unwrap_or("HEAD")),
+        &signature,
+        &signature,
+        "init",
+        &repo.find_tree(tree_id).unwrap(),
+        &[],
+    )
+    .unwrap();
+    if let Some(reference) = reference {
+        repo.set_head(&reference).unwrap();
+    }
+    repo
+}
+
+/// Gets the message of the commit HEAD points at
+pub fn head_message(repo: &Repository) -> String {
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    head.message().unwrap().to_string()
+}
+
+/// Creates the command running the binary in the directory, isolated from the user's config and
+/// state, handling hook events in the foreground with a generator answering `docs: add notes`
+pub fn ccc(dir: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ccc"));
+    command
+        .current_dir(dir)
+        .env_clear()
+        .env("PATH", var_os("PATH").unwrap_or_default())
+        .env("HOME", dir.join("home"))
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .env("XDG_DATA_HOME", dir.join("data"))
+        .env("CC_AUTO_COMMIT_FOREGROUND", "1");
+    generate(&mut command, "docs: add notes");
+    command
+}
+
+/// Makes the command generate the message with a shell stand-in for the generator command
+pub fn generate(command: &mut Command, message: &str) {
+    command
+        .env("CC_AUTO_COMMIT_GENERATOR_COMMAND", "/bin/sh")
+        .env("CC_AUTO_COMMIT_GENERATOR_ARGS", format!("-c,echo '{message}'"));
+}
+
+/// Creates the event of a successful Write of the file, as Claude Code sends it
+///
+/// # Arguments
+/// * `dir` - The repository, the working directory of the event
+/// * `file` - The written file, relative to the repository
+pub fn write_event(dir: &Path, file: &str) -> String {
+    let path = dir.join(file);
+    format!(
+        r#"{{"session_id":"{SESSION_ID}","transcript_path":"/dev/null","cwd":"{}","permission_mode":"default","hook_event_name":"PostToolUse","tool_name":"Write","tool_input":{{"file_path":"{}","content":""}},"tool_response":{{"filePath":"{}","success":true}}}}"#,
+        dir.display(),
+        path.display(),
+        path.display()
+    )
+}
+
+/// Runs the command with the hook event on stdin, capturing its output
+pub fn send(command: &mut Command, event: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(event.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Runs the hook in the foreground for a Write of the file, expecting it to succeed
+///
+/// # Returns
+/// What the hook logged to stderr
+pub fn run_hook(command: &mut Command, dir: &Path, file: &str) -> String {
+    let output = send(command, &write_event(dir, file));
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    assert!(output.status.success(), "{stderr}");
+    stderr
+}
+
+/// Polls the condition until it holds or the timeout expires
+///
+/// # Returns
+/// Whether the condition held in time
+pub fn wait_for(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    while !condition() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    true
+}
@@ -0,0 +1,81 @@
+//! Tests of handling hook events from a process running outside the repository of the event
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{create_dir_all, remove_dir_all, write},
+    io::Write,
+    path::{Path, PathBuf},
+    process::Stdio,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use common::{ccc, head_message};
+use git2::Repository;
+
+/// Creates a repository with an initial commit and a `src` directory in a fresh temporary
+/// directory
+fn temp_repo(name: &str) -> (PathBuf, Repository) {
+    let (dir, repo) = common::temp_repo(name);
+    create_dir_all(dir.join("src")).unwrap();
+    write(dir.join("src").join("lib.rs"), "pub fn f() {}\n").unwrap();
+    (dir, repo)
+}
+
+/// Runs the hook from a directory outside any repository for an edit in the `src` directory of
+/// the repository
+///
+/// # Arguments
+/// * `dir` - The repository
+/// * `file_path` - The edited file, as in the event
+/// * `foreground` - Whether to handle the event in the hook process
+fn run_hook(dir: &Path, file_path: &str, foreground: bool) {
+    let neutral = dir.with_extension("neutral");
+    create_dir_all(&neutral).unwrap();
+    let mut child = ccc(&neutral)
+        .env("CC_AUTO_COMMIT_FOREGROUND", if foreground { "1" } else { "0" })
+        // Fails unless run in the repository
+        .env("CC_AUTO_COMMIT_GENERATOR_ARGS", "-c,test -d .git && echo 'feat: add f'")
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let event = format!(
+        r#"{{"session_id":"s","cwd":"{}","hook_event_name":"PostToolUse","tool_name":"Write","tool_input":{{"file_path":"{file_path}"}},"tool_response":{{}}}}"#,
+        dir.join("src").display(),
+    );
+    child.stdin.take().unwrap().write_all(event.as_bytes()).unwrap();
+    assert!(child.wait().unwrap().success());
+    remove_dir_all(neutral).unwrap();
+}
+
+#[test]
+fn relative_path_is_resolved_against_the_event_cwd() {
+    let (dir, repo) = temp_repo("cwd-relative");
+
+    run_hook(&dir, "lib.rs", true);
+
+    assert_eq!(head_message(&repo), "feat: add f");
+    let tree = repo.head().unwrap().peel_to_tree().unwrap();
+    assert!(tree.get_path(Path::new("src/lib.rs")).is_ok());
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn background_process_commits_in_the_event_repository() {
+    let (dir, repo) = temp_repo("cwd-background");
+
+    run_hook(&dir, &dir.join("src").join("lib.rs").display().to_string(), false);
+
+    let deadline = Instant::now() + Duration::from_secs(15);
+    while head_message(&repo) == "init" && Instant::now() < deadline {
+        sleep(Duration::from_millis(100));
+    }
+    assert_eq!(head_message(&repo), "feat: add f");
+    let tree = repo.head().unwrap().peel_to_tree().unwrap();
+    assert!(tree.get_path(Path::new("src/lib.rs")).is_ok());
+
+    remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,4 @@
+#!/bin/sh
+# Rewrites the type of the message, then exits leaving a process that keeps its output open
+sed 's/^feat:/fix:/'
+sleep 5 &
@@ -0,0 +1,6 @@
+#!/bin/sh
+# Fails after printing a rewritten message
+cat >/dev/null
+echo "feat: rewritten"
+echo "no rewriting today" >&2
+exit 1
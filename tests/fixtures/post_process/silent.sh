@@ -0,0 +1,3 @@
+#!/bin/sh
+# Succeeds without printing anything
+cat >/dev/null
@@ -0,0 +1,4 @@
+#!/bin/sh
+# Prints the message only after the timeout
+sleep 5
+cat
@@ -0,0 +1,4 @@
+#!/bin/sh
+# Appends a trailer to the message
+cat
+printf '\n\nReviewed-by: Bot\n'
@@ -0,0 +1,130 @@
+//! Tests of the history each `granularity` makes of the same edits, telling the prompt templates
+//! used apart by a generator answering with the first line of the prompt
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{read_dir, remove_dir_all, write},
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use common::{SESSION_ID, ccc, run_hook, send, temp_repo, wait_for, write_event};
+use git2::Repository;
+
+/// Creates the command running the binary in the repository with the granularity, generating the
+/// message from the template of each context
+fn ccc_with(dir: &Path, granularity: &str) -> Command {
+    let mut command = ccc(dir);
+    command
+        .env("CC_AUTO_COMMIT_GRANULARITY", granularity)
+        .env("CC_AUTO_COMMIT_GENERATOR_ARGS", "-c,head -n 1")
+        .env("CC_AUTO_COMMIT_PROMPT_TEMPLATE", "chore: update the files\n")
+        .env("CC_AUTO_COMMIT_PROMPT_PER_FILE_TEMPLATE", "chore: update a file\n")
+        .env("CC_AUTO_COMMIT_PROMPT_SESSION_TEMPLATE", "chore: end the session\n");
+    command
+}
+
+/// Writes the files, then runs the hook for the Write of each
+fn write_files(command: impl Fn() -> Command, dir: &Path) {
+    for file in ["a.txt", "b.txt"] {
+        write(dir.join(file), format!("{file}\n")).unwrap();
+        run_hook(&mut command(), dir, file);
+    }
+}
+
+/// Runs the hook for the end of the session
+fn end_session(command: &mut Command, dir: &Path) {
+    let event = format!(
+        r#"{{"session_id":"{SESSION_ID}","transcript_path":"/dev/null","cwd":"{}","hook_event_name":"Stop","stop_hook_active":false}}"#,
+        dir.display()
+    );
+    let output = send(command, &event);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+/// Gets the commits since the initial one, oldest first, by subject and the files they change
+fn history(repo: &Repository) -> Vec<(String, Vec<String>)> {
+    let mut commits = Vec::new();
+    let mut commit = repo.head().unwrap().peel_to_commit().unwrap();
+    while let Ok(parent) = commit.parent(0) {
+        let diff = repo
+            .diff_tree_to_tree(Some(&parent.tree().unwrap()), Some(&commit.tree().unwrap()), None)
+            .unwrap();
+        let files = diff
+            .deltas()
+            .map(|delta| delta.new_file().path().unwrap().display().to_string())
+            .collect();
+        commits.push((commit.summary().unwrap().to_string(), files));
+        commit = parent;
+    }
+    commits.reverse();
+    commits
+}
+
+#[test]
+fn per_file_commits_each_edit() {
+    let (dir, repo) = temp_repo("granularity-per-file");
+
+    write_files(|| ccc_with(&dir, "per-file"), &dir);
+    end_session(&mut ccc_with(&dir, "per-file"), &dir);
+
+    assert_eq!(
+        history(&repo),
+        [
+            ("chore: update a file".to_string(), vec!["a.txt".to_string()]),
+            ("chore: update a file".to_string(), vec!["b.txt".to_string()]),
+        ]
+    );
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn batched_commits_the_edits_made_together() {
+    let (dir, repo) = temp_repo("granularity-batched");
+    let command = || {
+        let mut command = ccc_with(&dir, "batched");
+        command
+            .env_remove("CC_AUTO_COMMIT_FOREGROUND")
+            .env("CC_AUTO_COMMIT_QUIET_PERIOD_SECS", "2");
+        command
+    };
+
+    // In the background, so that the process of the first edit is still waiting for more
+    for file in ["a.txt", "b.txt"] {
+        write(dir.join(file), format!("{file}\n")).unwrap();
+        let mut child = command().stdin(Stdio::piped()).spawn().unwrap();
+        let event = write_event(&dir, file);
+        child.stdin.take().unwrap().write_all(event.as_bytes()).unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+    let instances = dir.join(".git").join("claude-auto-commit").join("instances");
+    assert!(wait_for(Duration::from_secs(30), || {
+        repo.head().unwrap().peel_to_commit().unwrap().parent_count() > 0
+            && read_dir(&instances).is_ok_and(|mut entries| entries.next().is_none())
+    }));
+
+    assert_eq!(
+        history(&repo),
+        [("chore: update the files".to_string(), vec!["a.txt".to_string(), "b.txt".to_string()])]
+    );
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn session_commits_once_the_session_ends() {
+    let (dir, repo) = temp_repo("granularity-session");
+
+    write_files(|| ccc_with(&dir, "session"), &dir);
+    assert!(history(&repo).is_empty());
+    end_session(&mut ccc_with(&dir, "session"), &dir);
+
+    assert_eq!(
+        history(&repo),
+        [("chore: end the session".to_string(), vec!["a.txt".to_string(), "b.txt".to_string()])]
+    );
+    remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,263 @@
+//! Tests of `install` and `uninstall` editing existing Claude Code settings files
+
+use std::{
+    env::temp_dir,
+    ffi::OsStr,
+    fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, write},
+    path::{Path, PathBuf},
+    process::{Command, Output, id},
+};
+
+use git2::Repository;
+use serde_json::{Value, from_str, json};
+
+/// Settings of other tools, in a different indentation and key order than serde_json's defaults
+const SETTINGS: &str = r#"{
+    "permissions": {
+        "allow": [
+            "Bash(cargo test:*)"
+        ]
+    },
+    "hooks": {
+        "PostToolUse": [
+            {
+                "matcher": "Bash",
+                "hooks": [
+                    {
+                        "type": "command",
+                        "command": "other-tool --check"
+                    }
+                ]
+            }
+        ]
+    },
+    "zzUnknown": {
+        "b": 1,
+        "a": [
+            true,
+            null
+        ]
+    }
+}
+"#;
+
+/// Creates a repository in a fresh temporary directory, with its settings file at
+/// `.claude/settings.local.json`
+fn temp_repo(name: &str) -> (PathBuf, PathBuf) {
+    let dir = temp_dir().join(format!("ccc-test-{name}-{}", id()));
+    let _ = remove_dir_all(&dir);
+    Repository::init(&dir).unwrap();
+    create_dir_all(dir.join(".claude")).unwrap();
+    let settings_path = dir.join(".claude").join("settings.local.json");
+    (dir, settings_path)
+}
+
+/// Runs the program in the directory, isolated from the user's config and settings files
+fn run(program: impl AsRef<OsStr>, dir: &Path, args: &[&str]) -> Output {
+    Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .env_clear()
+        .env("HOME", dir.join("home"))
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .output()
+        .unwrap()
+}
+
+/// Runs the binary in the directory, isolated from the user's config and settings files
+fn ccc(dir: &Path, args: &[&str]) -> Output {
+    run(env!("CARGO_BIN_EXE_ccc"), dir, args)
+}
+
+/// Finds the newest backup of the settings file
+fn backup(dir: &Path) -> PathBuf {
+    let mut backups = read_dir(dir.join(".claude"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.to_string_lossy().contains("settings.local.json.bak."))
+        .collect::<Vec<_>>();
+    backups.sort();
+    backups.pop().unwrap()
+}
+
+#[test]
+fn install_and_uninstall_keep_other_settings() {
+    let (dir, settings_path) = temp_repo("keep");
+    write(&settings_path, SETTINGS).unwrap();
+
+    assert!(ccc(&dir, &["install"]).status.success());
+    let installed = read_to_string(&settings_path).unwrap();
+    assert!(installed.contains(env!("CARGO_BIN_EXE_ccc")));
+    assert!(installed.contains("other-tool --check"));
+    assert!(installed.starts_with("{\n    \"permissions\""));
+    assert_eq!(read_to_string(backup(&dir)).unwrap(), SETTINGS);
+
+    assert!(ccc(&dir, &["uninstall"]).status.success());
+    assert_eq!(read_to_string(&settings_path).unwrap(), SETTINGS);
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn reinstalling_keeps_one_hook_and_the_others_of_its_group() {
+    let (dir, settings_path) = temp_repo("reinstall");
+    let binary = env!("CARGO_BIN_EXE_ccc");
+    // The hook of the binary shares its group with the hook of another tool
+    let settings = json!({
+        "hooks": {
+            "PostToolUse": [{
+                "matcher": "Edit|MultiEdit|Write",
+                "hooks": [
+                    { "type": "command", "command": "other-tool --format" },
+                    { "type": "command", "command": binary, "timeout": 60 },
+                ],
+            }],
+        },
+    });
+    write(&settings_path, settings.to_string()).unwrap();
+
+    assert!(ccc(&dir, &["install"]).status.success());
+    assert!(ccc(&dir, &["install", "--timeout", "30"]).status.success());
+    let installed = from_str::<Value>(&read_to_string(&settings_path).unwrap()).unwrap();
+    for (event, groups) in installed["hooks"].as_object().unwrap() {
+        let commands = groups
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|group| group["hooks"].as_array().unwrap())
+            .filter(|hook| hook["command"] == binary)
+            .collect::<Vec<_>>();
+        assert_eq!(commands.len(), 1, "{event}: {installed:#}");
+    }
+    let groups = installed["hooks"]["PostToolUse"].as_array().unwrap();
+    assert_eq!(groups.len(), 1, "{installed:#}");
+    assert_eq!(groups[0]["hooks"][0]["command"], "other-tool --format");
+    assert_eq!(groups[0]["hooks"][0].get("timeout"), None);
+    assert_eq!(groups[0]["hooks"][1]["timeout"], 30);
+
+    assert!(ccc(&dir, &["uninstall"]).status.success());
+    let uninstalled = from_str::<Value>(&read_to_string(&settings_path).unwrap()).unwrap();
+    assert_eq!(
+        uninstalled["hooks"],
+        json!({
+            "PostToolUse": [{
+                "matcher": "Edit|MultiEdit|Write",
+                "hooks": [{ "type": "command", "command": "other-tool --format" }],
+            }],
+        })
+    );
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn dry_run_prints_the_diff_only() {
+    let (dir, settings_path) = temp_repo("dry-run");
+    write(&settings_path, SETTINGS).unwrap();
+
+    let output = ccc(&dir, &["install", "--dry-run"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&format!(
+        "+                        \"command\": \"{}\"",
+        env!("CARGO_BIN_EXE_ccc")
+    )));
+    assert_eq!(read_to_string(&settings_path).unwrap(), SETTINGS);
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn failed_write_leaves_settings_intact() {
+    let (dir, settings_path) = temp_repo("failed-write");
+    write(&settings_path, SETTINGS).unwrap();
+    // A directory in place of the temporary file makes writing it fail
+    create_dir_all(dir.join(".claude").join("settings.local.json.tmp")).unwrap();
+
+    let output = ccc(&dir, &["install"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("it is left unchanged"));
+    assert_eq!(read_to_string(&settings_path).unwrap(), SETTINGS);
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn restore_settings_restores_a_backup() {
+    let (dir, settings_path) = temp_repo("restore");
+    write(&settings_path, SETTINGS).unwrap();
+    assert!(ccc(&dir, &["install"]).status.success());
+
+    let backup = backup(&dir);
+    let timestamp = backup.to_string_lossy().rsplit_once(".bak.").unwrap().1.to_string();
+    let output = ccc(&dir, &["restore-settings"]);
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&timestamp));
+
+    assert!(ccc(&dir, &["restore-settings", &timestamp]).status.success());
+    assert_eq!(read_to_string(&settings_path).unwrap(), SETTINGS);
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn installed_command_quotes_paths_and_arguments() {
+    use std::fs::copy;
+
+    let (dir, settings_path) = temp_repo("quoting");
+    let binary = dir.join("with space").join("ccc");
+    create_dir_all(binary.parent().unwrap()).unwrap();
+    copy(env!("CARGO_BIN_EXE_ccc"), &binary).unwrap();
+
+    let output = run(&binary, &dir, &["--language", "Brazilian Portuguese", "install"]);
+    assert!(output.status.success());
+    let settings = from_str::<Value>(&read_to_string(&settings_path).unwrap()).unwrap();
+    let command = settings["hooks"]["SessionStart"][0]["hooks"][0]["command"]
+        .as_str()
+        .unwrap();
+
+    // The shell running the hook has to find the binary and pass the language as one argument
+    let output = run("/bin/sh", &dir, &["-c", &format!("{command} config show")]);
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("language = \"Brazilian Portuguese\"")
+    );
+
+    // The quoted command is recognized as this binary's
+    let output = run(&binary, &dir, &["--language", "Brazilian Portuguese", "install"]);
+    assert!(
+        String::from_utf8_lossy(&output.stdout).contains("SessionStart hook already installed")
+    );
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn install_refuses_unparsable_settings() {
+    let (dir, settings_path) = temp_repo("unparsable");
+    write(&settings_path, "{ \"hooks\": ").unwrap();
+
+    let output = ccc(&dir, &["install"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Failed to parse"));
+    assert_eq!(read_to_string(&settings_path).unwrap(), "{ \"hooks\": ");
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn install_names_unexpected_hooks() {
+    let (dir, settings_path) = temp_repo("unexpected");
+    let settings = "{ \"hooks\": { \"SessionStart\": { \"command\": \"other-tool\" } } }\n";
+    write(&settings_path, settings).unwrap();
+
+    let output = ccc(&dir, &["install"]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr)
+            .contains("expected hooks.SessionStart to be an array, found object")
+    );
+    assert_eq!(read_to_string(&settings_path).unwrap(), settings);
+
+    remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,56 @@
+//! Tests of staging files above `max_file_size` that match a `large_files` pattern
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{create_dir_all, remove_dir_all, write},
+    path::Path,
+};
+
+use common::{ccc, generate, temp_repo};
+
+/// Runs the hook in the foreground for a Write of the file, with files above 1 KiB oversized
+/// unless matching the `large_files` patterns
+fn run_hook(dir: &Path, file: &str, large_files: &str) {
+    let mut command = ccc(dir);
+    generate(&mut command, "chore: add data");
+    command
+        .env("CC_AUTO_COMMIT_MAX_FILE_SIZE", "1024")
+        .env("CC_AUTO_COMMIT_LARGE_FILES", large_files);
+    common::run_hook(&mut command, dir, file);
+}
+
+/// Writes rows of numbers to the file, over 1 KiB in all
+fn write_rows(path: &Path) {
+    create_dir_all(path.parent().unwrap()).unwrap();
+    let rows = (0..200).map(|i| format!("{i},{}\n", i * i)).collect::<String>();
+    write(path, rows).unwrap();
+}
+
+#[test]
+fn oversized_file_is_not_committed() {
+    let (dir, repo) = temp_repo("large-files-skipped");
+    write_rows(&dir.join("data/rows.csv"));
+
+    run_hook(&dir, "data/rows.csv", "fixtures/**");
+
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("init"));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn oversized_file_matching_large_files_is_committed() {
+    let (dir, repo) = temp_repo("large-files-allowed");
+    write_rows(&dir.join("data/rows.csv"));
+
+    run_hook(&dir, "data/rows.csv", "fixtures/**,data/*.csv");
+
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("chore: add data"));
+    assert!(head.tree().unwrap().get_path(Path::new("data/rows.csv")).is_ok());
+
+    remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,51 @@
+//! Tests of committing the files changed by MCP tools mapped in `mcp_tools`
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{remove_dir_all, write},
+    path::Path,
+};
+
+use common::{SESSION_ID, ccc, send, temp_repo};
+
+/// Runs the hook in the foreground for a use of the MCP tool writing `notes.md`, as sent by
+/// Claude Code for the filesystem server
+fn run_hook(dir: &Path, tool_name: &str) {
+    let path = dir.join("notes.md");
+    let event = format!(
+        r##"{{"session_id":"{SESSION_ID}","transcript_path":"/dev/null","cwd":"{}","permission_mode":"default","hook_event_name":"PostToolUse","tool_name":"{tool_name}","tool_input":{{"path":"{}","content":"# Notes\n"}},"tool_response":[{{"type":"text","text":"Successfully wrote to {}"}}]}}"##,
+        dir.display(),
+        path.display(),
+        path.display()
+    );
+    assert!(send(&mut ccc(dir), &event).status.success());
+}
+
+#[test]
+fn mapped_mcp_tool_commits_the_file() {
+    let (dir, repo) = temp_repo("mcp-mapped");
+    write(dir.join("notes.md"), "# Notes\n").unwrap();
+
+    run_hook(&dir, "mcp__filesystem__write_file");
+
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("docs: add notes"));
+    assert!(head.tree().unwrap().get_name("notes.md").is_some());
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unmapped_mcp_tool_commits_nothing() {
+    let (dir, repo) = temp_repo("mcp-unmapped");
+    write(dir.join("notes.md"), "# Notes\n").unwrap();
+
+    run_hook(&dir, "mcp__notes__save");
+
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    assert_eq!(head.message(), Some("init"));
+
+    remove_dir_all(dir).unwrap();
+}
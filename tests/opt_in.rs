@@ -0,0 +1,78 @@
+//! Tests of acting only in repositories opted in, with `require_opt_in`
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{create_dir_all, remove_dir_all, write},
+    path::Path,
+    process::Command,
+};
+
+use common::{head_message, temp_repo};
+
+/// Creates the command running the binary in the repository, with `require_opt_in` set and the
+/// user-level state kept in the repository's `home`
+fn ccc(dir: &Path) -> Command {
+    let mut command = common::ccc(dir);
+    command.env("CC_AUTO_COMMIT_REQUIRE_OPT_IN", "true");
+    command
+}
+
+/// Runs the subcommand in the repository
+///
+/// # Returns
+/// What it printed to stdout
+fn run(dir: &Path, subcommand: &str) -> String {
+    let output = ccc(dir).arg(subcommand).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+/// Writes `notes.md` and runs the hook in the foreground for the Write
+fn write_notes(dir: &Path, content: &str) {
+    write(dir.join("notes.md"), content).unwrap();
+    common::run_hook(&mut ccc(dir), dir, "notes.md");
+}
+
+#[test]
+fn repository_without_a_marker_is_ignored() {
+    let (dir, repo) = temp_repo("opt-in-none");
+
+    write_notes(&dir, "# Notes\n");
+
+    assert_eq!(head_message(&repo), "init");
+    assert!(run(&dir, "status").contains("Repository: disabled, as it isn't opted in"));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn repository_config_opts_in() {
+    let (dir, repo) = temp_repo("opt-in-config");
+    create_dir_all(dir.join(".claude")).unwrap();
+    write(dir.join(".claude/auto-commit.toml"), "enabled = true\n").unwrap();
+
+    write_notes(&dir, "# Notes\n");
+
+    assert_eq!(head_message(&repo), "docs: add notes");
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn enable_and_disable_opt_in_and_out() {
+    let (dir, repo) = temp_repo("opt-in-command");
+
+    assert!(run(&dir, "enable").starts_with("Enabled the hook in "));
+    assert!(run(&dir, "status").contains("Repository: enabled"));
+    write_notes(&dir, "# Notes\n");
+    assert_eq!(head_message(&repo), "docs: add notes");
+
+    assert!(run(&dir, "disable").starts_with("Disabled the hook in "));
+    assert!(run(&dir, "status").contains("Repository: disabled, as it was disabled with"));
+    write_notes(&dir, "# Notes\n\n- Opted out\n");
+    assert_eq!(repo.head().unwrap().peel_to_commit().unwrap().parent_count(), 1);
+
+    remove_dir_all(dir).unwrap();
+}
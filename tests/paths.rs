@@ -0,0 +1,138 @@
+//! Tests of the precedence of `paths.exclude`, `paths.include`, and the built-in
+//! `paths.default_exclude`
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{create_dir_all, remove_dir_all, write},
+    path::Path,
+};
+
+use ccc::config::Paths;
+use common::{ccc, generate, temp_repo};
+use git2::Repository;
+
+/// Creates the settings from comma-separated patterns
+fn paths(default_exclude: &str, exclude: &str, include: &str) -> Paths {
+    let patterns = |list: &str| {
+        list.split(',')
+            .filter(|pattern| !pattern.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    Paths {
+        default_exclude: patterns(default_exclude),
+        exclude: patterns(exclude),
+        include: patterns(include),
+    }
+}
+
+#[test]
+fn include_overrides_only_the_default_excludes() {
+    let paths = paths("**/vendor/**,**/Cargo.lock", "**/*.min.js", "Cargo.lock,vendor/**");
+
+    assert!(!paths.excludes(Path::new("Cargo.lock")));
+    assert!(paths.excludes(Path::new("crates/cli/Cargo.lock")));
+    assert!(!paths.excludes(Path::new("vendor/lib.js")));
+    assert!(paths.excludes(Path::new("vendor/lib.min.js")));
+    assert_eq!(paths.exclusion(Path::new("vendor/lib.min.js")), Some("matches paths.exclude"));
+    assert!(paths.excludes(Path::new("web/vendor/lib.js")));
+    assert!(!paths.excludes(Path::new("src/main.rs")));
+}
+
+#[test]
+fn empty_default_excludes_nothing() {
+    let paths = paths("", "", "");
+
+    assert!(!paths.excludes(Path::new("node_modules/left-pad/index.js")));
+    assert!(!paths.excludes(Path::new("Cargo.lock")));
+}
+
+#[test]
+fn patterns_match_whole_path_components() {
+    let paths = paths("**/target/**", "", "");
+
+    assert!(paths.excludes(Path::new("target/debug/ccc")));
+    assert!(paths.excludes(Path::new("crates/cli/target/debug/ccc")));
+    assert!(!paths.excludes(Path::new("src/target.rs")));
+    assert!(!paths.excludes(Path::new("targets/list.txt")));
+}
+
+/// Runs the hook in the foreground for a Write of the file, with the given settings overridden
+fn run_hook(dir: &Path, file: &str, envs: &[(&str, &str)]) {
+    let mut command = ccc(dir);
+    generate(&mut command, "chore: update lockfile");
+    common::run_hook(command.envs(envs.iter().copied()), dir, file);
+}
+
+/// Writes a lockfile at the path
+fn write_lockfile(path: &Path) {
+    create_dir_all(path.parent().unwrap()).unwrap();
+    write(path, "version = 4\n").unwrap();
+}
+
+/// Gets whether HEAD has the file, i.e., whether the hook committed it
+fn is_committed(repo: &Repository, file: &str) -> bool {
+    let tree = repo.head().unwrap().peel_to_tree().unwrap();
+    tree.get_path(Path::new(file)).is_ok()
+}
+
+#[test]
+fn lockfile_is_excluded_by_default() {
+    let (dir, repo) = temp_repo("paths-default");
+    write_lockfile(&dir.join("Cargo.lock"));
+
+    run_hook(&dir, "Cargo.lock", &[]);
+
+    assert!(!is_committed(&repo, "Cargo.lock"));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn included_lockfile_is_committed() {
+    let (dir, repo) = temp_repo("paths-include");
+    write_lockfile(&dir.join("Cargo.lock"));
+
+    run_hook(&dir, "Cargo.lock", &[("CC_AUTO_COMMIT_PATHS_INCLUDE", "Cargo.lock")]);
+
+    assert!(is_committed(&repo, "Cargo.lock"));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn exclude_wins_over_include() {
+    let (dir, repo) = temp_repo("paths-exclude");
+    write_lockfile(&dir.join("Cargo.lock"));
+
+    run_hook(
+        &dir,
+        "Cargo.lock",
+        &[
+            ("CC_AUTO_COMMIT_PATHS_INCLUDE", "Cargo.lock"),
+            ("CC_AUTO_COMMIT_PATHS_EXCLUDE", "*.lock"),
+        ],
+    );
+
+    assert!(!is_committed(&repo, "Cargo.lock"));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn empty_default_exclude_disables_it() {
+    let (dir, repo) = temp_repo("paths-no-default");
+    write_lockfile(&dir.join("web/node_modules/left-pad/package-lock.json"));
+
+    run_hook(
+        &dir,
+        "web/node_modules/left-pad/package-lock.json",
+        &[("CC_AUTO_COMMIT_PATHS_DEFAULT_EXCLUDE", "")],
+    );
+
+    assert!(is_committed(&repo, "web/node_modules/left-pad/package-lock.json"));
+
+    remove_dir_all(dir).unwrap();
+}
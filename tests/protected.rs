@@ -0,0 +1,96 @@
+//! Tests of leaving changes on protected branches uncommitted when not switching away from them
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{remove_dir_all, write},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use git2::Repository;
+
+/// Creates a repository with an initial commit on `main` in a fresh temporary directory
+fn temp_repo(name: &str) -> (PathBuf, Repository) {
+    common::temp_repo_with(name, Some("main"), &[])
+}
+
+/// Creates the command running the binary in the repository with the settings
+fn ccc(dir: &Path, envs: &[(&str, &str)]) -> Command {
+    let mut command = common::ccc(dir);
+    command.envs(envs.iter().copied());
+    command
+}
+
+/// Runs the hook in the foreground for a Write of `notes.md`
+fn run_hook(dir: &Path, envs: &[(&str, &str)]) {
+    common::run_hook(&mut ccc(dir, envs), dir, "notes.md");
+}
+
+/// Checks that HEAD is still the initial commit on `main`
+fn assert_uncommitted(repo: &Repository) {
+    let head = repo.head().unwrap();
+    assert_eq!(head.shorthand(), Some("main"));
+    assert_eq!(head.peel_to_commit().unwrap().message(), Some("init"));
+}
+
+#[test]
+fn skip_policy_leaves_changes_uncommitted() {
+    let (dir, repo) = temp_repo("protected-skip");
+    write(dir.join("notes.md"), "# Notes\n").unwrap();
+
+    run_hook(&dir, &[("CC_AUTO_COMMIT_BRANCHING_ON_PROTECTED", "skip")]);
+
+    assert_uncommitted(&repo);
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn disabled_branching_leaves_changes_uncommitted() {
+    let (dir, repo) = temp_repo("protected-disabled");
+    write(dir.join("notes.md"), "# Notes\n").unwrap();
+
+    run_hook(&dir, &[("CC_AUTO_COMMIT_BRANCHING_ENABLED", "false")]);
+
+    assert_uncommitted(&repo);
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unprotected_branch_is_committed_on() {
+    let (dir, repo) = temp_repo("protected-other");
+    write(dir.join("notes.md"), "# Notes\n").unwrap();
+
+    run_hook(
+        &dir,
+        &[
+            ("CC_AUTO_COMMIT_BRANCHING_ENABLED", "false"),
+            ("CC_AUTO_COMMIT_PROTECTED_BRANCHES", "release/*"),
+        ],
+    );
+
+    let head = repo.head().unwrap();
+    assert_eq!(head.shorthand(), Some("main"));
+    assert_eq!(head.peel_to_commit().unwrap().message(), Some("docs: add notes"));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn status_warns_of_a_protected_branch_without_branching() {
+    let (dir, _repo) = temp_repo("protected-status");
+
+    let output = ccc(&dir, &[("CC_AUTO_COMMIT_BRANCHING_ENABLED", "false")])
+        .arg("status")
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Current branch: main (protected)"), "{stdout}");
+    assert!(stdout.contains("WARNING: main is protected"), "{stdout}");
+
+    remove_dir_all(dir).unwrap();
+}
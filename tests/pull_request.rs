@@ -0,0 +1,245 @@
+//! Tests of `ccc pr`, pushing to a local bare repository standing in for GitHub and opening the
+//! pull request through a stub of its REST API
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    env::temp_dir,
+    fs::{remove_dir_all, write},
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Output, id},
+    sync::mpsc::{Receiver, channel},
+    thread::spawn,
+};
+
+use common::ccc;
+use git2::{Repository, Signature};
+use serde_json::{Value, json};
+
+/// The repository on GitHub the remote URL names
+const REMOTE_URL: &str = "https://github.com/acme/widgets.git";
+
+/// Creates a repository on a session branch with two commits, whose `origin` names GitHub but is
+/// a bare repository next to it
+fn session_repo(name: &str) -> (PathBuf, PathBuf, Repository) {
+    let dir = temp_dir().join(format!("ccc-test-pr-{name}-{}", id()));
+    let origin = dir.with_extension("origin.git");
+    let _ = remove_dir_all(&dir);
+    let _ = remove_dir_all(&origin);
+    Repository::init_bare(&origin).unwrap();
+    let repo = Repository::init(&dir).unwrap();
+    repo.remote("origin", REMOTE_URL).unwrap();
+    repo.config()
+        .unwrap()
+        .set_str(&format!("url.{}.insteadOf", origin.display()), REMOTE_URL)
+        .unwrap();
+
+    commit(&repo, "README.md", "# Widgets\n", "init");
+    let base = repo.head().unwrap().target().unwrap();
+    repo.branch("session/ab12_0712", &repo.find_commit(base).unwrap(), false)
+        .unwrap();
+    repo.set_head("refs/heads/session/ab12_0712").unwrap();
+    commit(&repo, "notes.md", "# Notes\n", "feat: add notes");
+    commit(&repo, "notes.md", "# Notes\n\nFixed.\n", "fix: correct the notes");
+    (dir, origin, repo)
+}
+
+/// Commits the file with the content on HEAD
+fn commit(repo: &Repository, path: &str, content: &str, subject: &str) {
+    write(repo.workdir().unwrap().join(path), content).unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new(path)).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let signature = Signature::now("Test", "test@example.com").unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        subject,
+        &tree,
+        parent.as_slice().iter().collect::<Vec<_>>().as_slice(),
+    )
+    .unwrap();
+}
+
+/// A request the stub API received
+struct Request {
+    /// The request line, e.g., `POST /repos/acme/widgets/pulls HTTP/1.1`
+    line: String,
+    /// The header lines, lowercase
+    headers: Vec<String>,
+    body: String,
+}
+
+/// Serves the REST API on a local port, answering the requests with the statuses and JSON bodies
+/// in turn
+fn stub_api(answers: Vec<(u16, Value)>) -> (String, Receiver<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = channel();
+    spawn(move || {
+        let mut answers = answers.into_iter();
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            while let Some(request) = read_request(&stream) {
+                let (status, body) = answers.next().unwrap();
+                sender.send(request).unwrap();
+                let body = body.to_string();
+                let _ = write!(
+                    &stream,
+                    "HTTP/1.1 {status} Status\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\n\r\n{body}",
+                    body.len()
+                );
+            }
+        }
+    });
+    (url, receiver)
+}
+
+/// Reads a request from the connection, or `None` once it is closed
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    if reader.read_line(&mut line).ok()? == 0 {
+        return None;
+    }
+    let mut headers = Vec::new();
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        let header = header.trim_end().to_lowercase();
+        if header.is_empty() {
+            break;
+        }
+        headers.push(header);
+    }
+    let length = headers
+        .iter()
+        .find_map(|header| header.strip_prefix("content-length:"))
+        .map_or(0, |length| length.trim().parse().unwrap());
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+    Some(Request {
+        line: line.trim_end().to_string(),
+        headers,
+        body: String::from_utf8(body).unwrap(),
+    })
+}
+
+/// Runs `ccc pr` with the arguments in the repository, opening pull requests through the API
+fn run_pr(dir: &Path, api_url: &str, args: &[&str]) -> Output {
+    ccc(dir)
+        .arg("pr")
+        .args(args)
+        .env("GH_TOKEN", "t0ken")
+        .env("CC_AUTO_COMMIT_PULL_REQUEST_METHOD", "api")
+        .env("CC_AUTO_COMMIT_PULL_REQUEST_API_URL", api_url)
+        .env(
+            "CC_AUTO_COMMIT_GENERATOR_ARGS",
+            r"-c,printf 'feat: add notes\n\nAdds the notes file.\n'",
+        )
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn session_branch_is_pushed_and_opened_as_a_pull_request() {
+    let (dir, origin, repo) = session_repo("open");
+    let (api_url, requests) =
+        stub_api(vec![(201, json!({ "html_url": "https://github.com/acme/widgets/pull/7" }))]);
+
+    let output = run_pr(&dir, &api_url, &["--draft"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "https://github.com/acme/widgets/pull/7\n"
+    );
+
+    let pushed = Repository::open_bare(&origin)
+        .unwrap()
+        .refname_to_id("refs/heads/session/ab12_0712")
+        .unwrap();
+    assert_eq!(pushed, repo.head().unwrap().target().unwrap());
+    let request = requests.try_recv().unwrap();
+    assert_eq!(request.line, "POST /repos/acme/widgets/pulls HTTP/1.1");
+    assert!(request.headers.contains(&"authorization: bearer t0ken".to_string()));
+    assert_eq!(
+        serde_json::from_str::<Value>(&request.body).unwrap(),
+        json!({
+            "title": "feat: add notes",
+            "body": "Adds the notes file.\n\n## Commits\n\n- feat: add notes\n- fix: correct the notes",
+            "head": "session/ab12_0712",
+            "base": "master",
+            "draft": true,
+        })
+    );
+
+    remove_dir_all(dir).unwrap();
+    remove_dir_all(origin).unwrap();
+}
+
+#[test]
+fn existing_pull_request_is_printed() {
+    let (dir, origin, _repo) = session_repo("exists");
+    let (api_url, requests) = stub_api(vec![
+        (
+            422,
+            json!({
+                "message": "Validation Failed",
+                "errors": [{ "message": "A pull request already exists for acme:session/ab12_0712." }],
+            }),
+        ),
+        (200, json!([{ "html_url": "https://github.com/acme/widgets/pull/3" }])),
+    ]);
+
+    let output = run_pr(&dir, &api_url, &[]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "https://github.com/acme/widgets/pull/3\n"
+    );
+    assert!(String::from_utf8(output.stderr).unwrap().contains("is open already"));
+
+    let lookup = requests.try_iter().nth(1).unwrap();
+    assert_eq!(
+        lookup.line,
+        "GET /repos/acme/widgets/pulls?head=acme%3Asession%2Fab12_0712&state=open HTTP/1.1"
+    );
+
+    remove_dir_all(dir).unwrap();
+    remove_dir_all(origin).unwrap();
+}
+
+#[test]
+fn other_branches_are_refused() {
+    let (dir, origin, repo) = session_repo("refused");
+    repo.set_head("refs/heads/master").unwrap();
+    let (api_url, requests) = stub_api(Vec::new());
+
+    let output = run_pr(&dir, &api_url, &[]);
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("master isn't a session branch")
+    );
+
+    assert!(
+        Repository::open_bare(&origin)
+            .unwrap()
+            .branches(None)
+            .unwrap()
+            .next()
+            .is_none()
+    );
+    assert!(requests.try_recv().is_err());
+
+    remove_dir_all(dir).unwrap();
+    remove_dir_all(origin).unwrap();
+}
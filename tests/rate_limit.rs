@@ -0,0 +1,69 @@
+//! Tests of deferring commits beyond `rate_limit` and folding them into the next one
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{remove_dir_all, remove_file, write},
+    path::Path,
+    process::Command,
+};
+
+use common::temp_repo;
+use git2::Repository;
+
+/// Creates the command running the binary in the repository, limited to a commit per minute
+fn ccc(dir: &Path) -> Command {
+    let mut command = common::ccc(dir);
+    command.env("CC_AUTO_COMMIT_RATE_LIMIT_PER_MINUTE", "1");
+    command
+}
+
+/// Writes the file and runs the hook in the foreground for the Write
+///
+/// # Returns
+/// What the hook logged to stderr
+fn write_file(dir: &Path, file: &str) -> String {
+    write(dir.join(file), format!("# {file}\n")).unwrap();
+    common::run_hook(&mut ccc(dir), dir, file)
+}
+
+/// Gets the names of the files the commit HEAD points at adds to its parent's tree
+fn head_files(repo: &Repository) -> Vec<String> {
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    let parent = head.parent(0).unwrap().tree().unwrap();
+    let diff = repo
+        .diff_tree_to_tree(Some(&parent), Some(&head.tree().unwrap()), None)
+        .unwrap();
+    diff.deltas()
+        .map(|delta| delta.new_file().path().unwrap().display().to_string())
+        .collect()
+}
+
+#[test]
+fn commit_beyond_the_limit_is_folded_into_the_next_one() {
+    let (dir, repo) = temp_repo("rate-limit");
+
+    write_file(&dir, "a.md");
+    assert_eq!(head_files(&repo), ["a.md"]);
+
+    let log = write_file(&dir, "b.md");
+    assert!(log.contains("Deferring the commit, as the limit of 1 commits per minute"), "{log}");
+    assert_eq!(head_files(&repo), ["a.md"]);
+    let mut index = repo.index().unwrap();
+    index.read(true).unwrap();
+    assert!(index.get_path(Path::new("b.md"), 0).is_some());
+    let status = ccc(&dir).arg("status").output().unwrap();
+    let status = String::from_utf8_lossy(&status.stdout);
+    assert!(
+        status.contains("Rate limit: commits left 0 of 1 this minute, 119 of 120 this hour"),
+        "{status}"
+    );
+
+    // A minute later, as far as the bucket knows
+    remove_file(repo.path().join("claude-auto-commit/rate-limit.json")).unwrap();
+    write_file(&dir, "c.md");
+    assert_eq!(head_files(&repo), ["b.md", "c.md"]);
+
+    remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,98 @@
+//! Tests of leaving git alone while a rebase or bisect is in progress
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{create_dir_all, read_to_string, remove_dir_all, write},
+    path::{Path, PathBuf},
+    process::Output,
+};
+
+use common::{ccc, generate, send, write_event};
+use git2::{Repository, RepositoryState};
+
+/// Creates a repository with an initial commit of `notes.md` on `feature` in a fresh temporary
+/// directory
+fn temp_repo(name: &str) -> (PathBuf, Repository) {
+    common::temp_repo_with(name, Some("feature"), &[("notes.md", "# Notes\n")])
+}
+
+/// Leaves the repository as `git rebase -i` does when it stops at a commit to edit
+fn start_interactive_rebase(dir: &Path, repo: &Repository) {
+    let rebase_dir = dir.join(".git/rebase-merge");
+    create_dir_all(&rebase_dir).unwrap();
+    write(rebase_dir.join("interactive"), "").unwrap();
+    write(rebase_dir.join("head-name"), "refs/heads/feature\n").unwrap();
+    assert_eq!(repo.state(), RepositoryState::RebaseInteractive);
+}
+
+/// Runs the hook in the foreground for a Write of `notes.md`, with the settings overridden
+fn run_hook(dir: &Path, envs: &[(&str, &str)]) -> Output {
+    let mut command = ccc(dir);
+    generate(&mut command, "docs: update notes");
+    let output = send(command.envs(envs.iter().copied()), &write_event(dir, "notes.md"));
+    assert!(output.status.success());
+    output
+}
+
+/// Checks that HEAD is still the initial commit on `feature` and nothing is staged
+fn assert_untouched(repo: &Repository) {
+    let head = repo.head().unwrap();
+    assert_eq!(head.shorthand(), Some("feature"));
+    assert_eq!(head.peel_to_commit().unwrap().message(), Some("init"));
+    let statuses = repo.statuses(None).unwrap();
+    assert!(statuses.iter().all(|entry| !entry.status().is_index_modified()));
+}
+
+#[test]
+fn edit_during_an_interactive_rebase_is_not_committed() {
+    let (dir, repo) = temp_repo("rebase-skip");
+    start_interactive_rebase(&dir, &repo);
+    write(dir.join("notes.md"), "# Notes\n\nEdited\n").unwrap();
+
+    let output = run_hook(&dir, &[]);
+
+    assert_untouched(&repo);
+    assert_eq!(read_to_string(dir.join("notes.md")).unwrap(), "# Notes\n\nEdited\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("during the interactive rebase in progress"), "{stderr}");
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn edit_during_a_bisect_is_not_committed() {
+    let (dir, repo) = temp_repo("rebase-bisect");
+    write(dir.join(".git/BISECT_LOG"), "git bisect start\n").unwrap();
+    write(dir.join("notes.md"), "# Notes\n\nEdited\n").unwrap();
+
+    run_hook(&dir, &[]);
+
+    assert_untouched(&repo);
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn edit_during_a_rebase_is_stashed_with_the_stash_policy() {
+    let (dir, mut repo) = temp_repo("rebase-stash");
+    start_interactive_rebase(&dir, &repo);
+    write(dir.join("notes.md"), "# Notes\n\nEdited\n").unwrap();
+    write(dir.join("scratch.txt"), "not the model's\n").unwrap();
+
+    run_hook(&dir, &[("CC_AUTO_COMMIT_PENDING_OPERATIONS_ON_BLOCKING", "stash")]);
+
+    assert_untouched(&repo);
+    assert_eq!(read_to_string(dir.join("notes.md")).unwrap(), "# Notes\n");
+    assert_eq!(read_to_string(dir.join("scratch.txt")).unwrap(), "not the model's\n");
+    let mut stashes = 0;
+    repo.stash_foreach(|_, _, _| {
+        stashes += 1;
+        true
+    })
+    .unwrap();
+    assert_eq!(stashes, 1);
+
+    remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,152 @@
+//! Tests of review mode, where commits are proposed for `ccc review apply` to make
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    fs::{remove_dir_all, write},
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use common::head_message;
+use git2::{Repository, Signature};
+
+/// Creates a repository with an initial commit on `feature` in a fresh temporary directory
+fn temp_repo(name: &str) -> (PathBuf, Repository) {
+    common::temp_repo_with(name, Some("feature"), &[])
+}
+
+/// Creates the command running the binary in the repository in review mode
+fn ccc(dir: &Path) -> Command {
+    let mut command = common::ccc(dir);
+    command.env("CC_AUTO_COMMIT_MODE", "review");
+    command
+}
+
+/// Runs the hook in the foreground for a Write of `notes.md`
+fn run_hook(dir: &Path) {
+    common::run_hook(&mut ccc(dir), dir, "notes.md");
+}
+
+/// Runs `ccc review` with the arguments, expecting success
+fn review(dir: &Path, args: &[&str]) -> Output {
+    let output = ccc(dir).arg("review").args(args).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+/// Gets the ID of the only pending proposal
+fn proposal_id(dir: &Path) -> String {
+    let stdout = String::from_utf8(review(dir, &[]).stdout).unwrap();
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1, "{stdout}");
+    lines[0].split_whitespace().next().unwrap().to_string()
+}
+
+/// Checks whether the file is in the index
+fn is_staged(repo: &Repository, file: &str) -> bool {
+    let mut index = repo.index().unwrap();
+    index.read(true).unwrap();
+    index.get_path(Path::new(file), 0).is_some()
+}
+
+#[test]
+fn edit_is_proposed_and_applied() {
+    let (dir, repo) = temp_repo("review-apply");
+    write(dir.join("notes.md"), "# Notes\n").unwrap();
+
+    run_hook(&dir);
+
+    assert_eq!(head_message(&repo).trim_end(), "init");
+    assert!(is_staged(&repo, "notes.md"));
+    let id = proposal_id(&dir);
+    assert!(
+        dir.join(format!(".git/claude-auto-commit/pending/{id}.json"))
+            .exists()
+    );
+    let shown = String::from_utf8(review(&dir, &["show", &id]).stdout).unwrap();
+    assert!(shown.contains("docs: add notes"), "{shown}");
+    assert!(shown.contains("+# Notes"), "{shown}");
+
+    review(&dir, &["apply", &id]);
+
+    assert_eq!(head_message(&repo).trim_end(), "docs: add notes");
+    let tree = repo.head().unwrap().peel_to_tree().unwrap();
+    assert!(tree.get_name("notes.md").is_some());
+    let stdout = review(&dir, &[]).stdout;
+    assert_eq!(String::from_utf8(stdout).unwrap().trim(), "No pending proposals");
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn later_edit_supersedes_the_proposal() {
+    let (dir, repo) = temp_repo("review-supersede");
+    write(dir.join("notes.md"), "# Notes\n").unwrap();
+    run_hook(&dir);
+    let first = proposal_id(&dir);
+
+    write(dir.join("notes.md"), "# Notes\n\nMore\n").unwrap();
+    run_hook(&dir);
+
+    let second = proposal_id(&dir);
+    assert_ne!(first, second);
+    review(&dir, &["apply", &second]);
+    let tree = repo.head().unwrap().peel_to_tree().unwrap();
+    let blob = tree.get_name("notes.md").unwrap().to_object(&repo).unwrap();
+    assert_eq!(blob.as_blob().unwrap().content(), b"# Notes\n\nMore\n");
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn proposal_on_a_moved_base_is_restaged() {
+    let (dir, repo) = temp_repo("review-moved");
+    write(dir.join("notes.md"), "# Notes\n").unwrap();
+    run_hook(&dir);
+    let id = proposal_id(&dir);
+    // Someone commits another file meanwhile, from an index without the proposed changes
+    write(dir.join("other.txt"), "other\n").unwrap();
+    let mut index = repo.index().unwrap();
+    index.remove_path(Path::new("notes.md")).unwrap();
+    index.add_path(Path::new("other.txt")).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let parent = repo.head().unwrap().peel_to_commit().unwrap();
+    let signature = Signature::now("Test", "test@example.com").unwrap();
+    repo.commit(Some("HEAD"), &signature, &signature, "other", &tree, &[&parent])
+        .unwrap();
+
+    let output = review(&dir, &["apply", &id]);
+
+    assert_eq!(head_message(&repo).trim_end(), "other");
+    assert!(is_staged(&repo, "notes.md") && is_staged(&repo, "other.txt"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("re-staged its changes onto HEAD"), "{stderr}");
+    assert_eq!(proposal_id(&dir), id);
+
+    review(&dir, &["apply", &id]);
+
+    assert_eq!(head_message(&repo).trim_end(), "docs: add notes");
+    let tree = repo.head().unwrap().peel_to_tree().unwrap();
+    assert!(tree.get_name("notes.md").is_some() && tree.get_name("other.txt").is_some());
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn discarded_proposal_leaves_the_index() {
+    let (dir, repo) = temp_repo("review-discard");
+    write(dir.join("notes.md"), "# Notes\n").unwrap();
+    run_hook(&dir);
+    let id = proposal_id(&dir);
+
+    review(&dir, &["discard", &id]);
+
+    assert_eq!(head_message(&repo).trim_end(), "init");
+    assert!(is_staged(&repo, "notes.md"));
+    assert!(!ccc(&dir).args(["review", "apply", &id]).status().unwrap().success());
+
+    remove_dir_all(dir).unwrap();
+}
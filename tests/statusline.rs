@@ -0,0 +1,107 @@
+//! Tests of the status line printed for Claude Code
+
+use std::{
+    env::{temp_dir, var_os},
+    fs::{create_dir_all, remove_dir_all, write},
+    io::Write,
+    path::Path,
+    process::{Command, Stdio, id},
+};
+
+use git2::{Repository, Signature, Time};
+use jiff::Timestamp;
+
+/// Runs `ccc statusline` outside the directory, with the status line JSON naming it on stdin
+fn run_statusline(dir: &Path) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ccc"))
+        .arg("statusline")
+        .current_dir(temp_dir())
+        .env_clear()
+        .env("PATH", var_os("PATH").unwrap_or_default())
+        .env("HOME", dir.join("home"))
+        .env("XDG_CONFIG_HOME", dir.join("config"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input = serde_json::json!({
+        "session_id": "ab12",
+        "cwd": dir,
+        "workspace": { "current_dir": dir, "project_dir": dir },
+        "model": { "id": "some-model", "display_name": "Some Model" },
+    });
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.to_string().as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Commits an empty change on HEAD, committed by the identity the given minutes ago
+fn commit(repo: &Repository, committer: &str, subject: &str, minutes_ago: i64) {
+    let time = Time::new(Timestamp::now().as_second() - minutes_ago * 60, 0);
+    let author = Signature::new("Test", "test@example.com", &time).unwrap();
+    let committer = Signature::new(committer, "committer@example.com", &time).unwrap();
+    let tree = repo.find_tree(repo.index().unwrap().write_tree().unwrap()).unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    repo.commit(
+        Some("HEAD"),
+        &author,
+        &committer,
+        subject,
+        &tree,
+        parent.as_slice().iter().collect::<Vec<_>>().as_slice(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn session_branch_shows_its_commits_and_the_last_auto_commit() {
+    let dir = temp_dir().join(format!("ccc-test-statusline-{}", id()));
+    let _ = remove_dir_all(&dir);
+    let repo = Repository::init(&dir).unwrap();
+    commit(&repo, "Test", "init", 60);
+    let base = repo.head().unwrap().peel_to_commit().unwrap();
+    repo.branch("session/ab12_0712", &base, false).unwrap();
+    repo.set_head("refs/heads/session/ab12_0712").unwrap();
+    commit(&repo, "Claude Auto Commit", "feat: add notes", 10);
+    commit(&repo, "Claude Auto Commit", "fix(parser): accept trailing commas", 3);
+    commit(&repo, "Test", "Tweak the notes by hand", 1);
+
+    assert_eq!(
+        run_statusline(&dir),
+        "⎇ session/ab12_0712 · 3 commits · last: fix(parser) 3m ago\n"
+    );
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn config_of_the_repository_on_stdin_is_loaded() {
+    let dir = temp_dir().join(format!("ccc-test-statusline-config-{}", id()));
+    let _ = remove_dir_all(&dir);
+    let repo = Repository::init(&dir).unwrap();
+    create_dir_all(dir.join(".claude")).unwrap();
+    write(dir.join(".claude/auto-commit.toml"), "[committer]\nname = \"Repo Bot\"\n").unwrap();
+    commit(&repo, "Repo Bot", "docs: add notes", 5);
+    commit(&repo, "Claude Auto Commit", "feat: add parser", 2);
+
+    assert!(run_statusline(&dir).ends_with(" · last: docs 5m ago\n"));
+
+    remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn outside_a_repository_the_line_is_empty() {
+    let dir = temp_dir().join(format!("ccc-test-statusline-none-{}", id()));
+    create_dir_all(&dir).unwrap();
+
+    assert_eq!(run_statusline(&dir), "\n");
+
+    remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,64 @@
+//! Tests of running the binary without a hook event on stdin
+
+use std::{
+    env::temp_dir,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+#[test]
+fn terminal_stdin_prints_the_help() {
+    use std::{fs::File, os::fd::FromRawFd, ptr::null_mut};
+
+    let (mut controller, mut terminal) = (0, 0);
+    // SAFETY: The out pointers are valid, and the optional arguments may be null
+    let status = unsafe {
+        libc::openpty(&mut controller, &mut terminal, null_mut(), null_mut(), null_mut())
+    };
+    assert_eq!(status, 0);
+    // SAFETY: `openpty` returned both descriptors open and owned by nobody else
+    let (_controller, terminal) =
+        unsafe { (File::from_raw_fd(controller), File::from_raw_fd(terminal)) };
+
+    let started = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_ccc"))
+        .current_dir(temp_dir())
+        .stdin(terminal)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Usage:"));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn silent_pipe_fails_without_a_hook_event() {
+    let started = Instant::now();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ccc"))
+        .current_dir(temp_dir())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The pipe stays open without data until the binary gives up
+    let stdin = child.stdin.take();
+    let output = child.wait_with_output().unwrap();
+    drop(stdin);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No hook event received"));
+    assert!(started.elapsed() < Duration::from_secs(15));
+}
+
+#[test]
+fn empty_stdin_fails_without_a_hook_event() {
+    let output = Command::new(env!("CARGO_BIN_EXE_ccc"))
+        .current_dir(temp_dir())
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No hook event received"));
+}
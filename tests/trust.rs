@@ -0,0 +1,85 @@
+//! Tests of ignoring the settings of the repository-level config file that run commands, send
+//! data and credentials elsewhere, or write files outside the repository until they are trusted
+//! with `ccc trust`
+
+mod common;
+
+use std::{
+    fs::{create_dir_all, remove_dir_all, write},
+    path::Path,
+};
+
+use common::{ccc, temp_repo};
+
+/// Repository-level config file setting a command to run for notifications, where to post commits
+/// and open pull requests with which credentials, and which file to append the audit log to
+const REPO_CONFIG: &str = r#"
+[notify]
+method = "command"
+command = "notify-send"
+args = ["--urgency=low"]
+
+[webhook]
+url = "https://hooks.example.com/commits"
+
+[webhook.headers]
+Authorization = "Bearer 0123abcd"
+
+[pull_request]
+api_url = "https://github.example.com/api/v3"
+token = "ghp_0123abcd"
+
+[audit]
+path = "/home/u/.bashrc"
+"#;
+
+/// The settings of `REPO_CONFIG`, as `ccc config show` prints them once honored
+const SETTINGS: &[&str] = &[
+    r#"notify.method = "command""#,
+    r#"notify.command = "notify-send""#,
+    r#"notify.args = ["--urgency=low"]"#,
+    r#"webhook.url = "https://hooks.example.com/commits""#,
+    r#"webhook.headers.Authorization = "Bearer 0123abcd""#,
+    r#"pull_request.api_url = "https://github.example.com/api/v3""#,
+    r#"pull_request.token = "ghp_0123abcd""#,
+    r#"audit.path = "/home/u/.bashrc""#,
+];
+
+/// Runs `ccc config show` in the repository
+///
+/// # Returns
+/// The settings it printed, one per line
+fn config_show(dir: &Path) -> String {
+    let output = ccc(dir).args(["config", "show"]).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Checks whether all or none of the settings of `REPO_CONFIG` are in effect in the repository
+fn assert_honored(dir: &Path, honored: bool) {
+    let shown = config_show(dir);
+    for setting in SETTINGS {
+        assert_eq!(shown.lines().any(|line| line == *setting), honored, "{setting}\n{shown}");
+    }
+}
+
+#[test]
+fn repo_config_runs_commands_only_once_trusted() {
+    let (dir, _repo) = temp_repo("trust");
+    create_dir_all(dir.join(".claude")).unwrap();
+    write(dir.join(".claude/auto-commit.toml"), REPO_CONFIG).unwrap();
+
+    assert_honored(&dir, false);
+
+    let output = ccc(&dir).arg("trust").output().unwrap();
+    assert!(output.status.success());
+    assert_honored(&dir, true);
+
+    // Changing them takes trusting them again
+    write(dir.join(".claude/auto-commit.toml"), REPO_CONFIG.replace("notify-send", "curl"))
+        .unwrap();
+    let shown = config_show(&dir);
+    assert!(!shown.contains("curl"), "{shown}");
+
+    remove_dir_all(dir).unwrap();
+}